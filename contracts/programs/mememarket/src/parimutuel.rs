@@ -1,9 +1,94 @@
 use anchor_lang::prelude::*;
-use anchor_lang::system_program::{transfer, Transfer};
+#[cfg(not(test))]
+use anchor_lang::solana_program::program::set_return_data;
+use anchor_lang::system_program::Transfer;
+#[cfg(not(test))]
+use anchor_lang::system_program::transfer;
+#[cfg(test)]
+use tests::{set_return_data, transfer};
 
-/// Market creation fee: 0.015 SOL in lamports
-/// Debug: Fee charged to any user creating a new market
-pub const MARKET_CREATION_FEE: u64 = 15_000_000; // 0.015 SOL
+/// Off-chain builds discard event logs, so tests record emitted events instead
+#[cfg(test)]
+macro_rules! emit {
+    ($event:expr) => {
+        tests::record_event(&$event)
+    };
+}
+
+/// Default market creation fee: 0.015 SOL in lamports
+/// Debug: Suggested initial value for `Config::creation_fee_lamports`
+pub const DEFAULT_MARKET_CREATION_FEE: u64 = 15_000_000; // 0.015 SOL
+
+/// Default allowed oracle timestamp skew: 5 minutes either side of the chain clock
+/// Debug: Used when a market is created with max_oracle_skew_secs <= 0
+pub const DEFAULT_MAX_ORACLE_SKEW_SECS: i64 = 300;
+
+/// Default shortest allowed market: deadline must be at least 5 minutes out
+/// Debug: Stops near-instant markets that are useless and grief crank automation
+pub const DEFAULT_MIN_MARKET_DURATION_SECS: i64 = 300;
+
+/// How long after the deadline the oracle has to resolve before anyone may force NO
+/// Debug: 7 days - keeps funds from being locked forever if the oracle disappears
+pub const FORCE_RESOLVE_GRACE_SECS: i64 = 7 * 24 * 60 * 60;
+
+/// How long after resolution winners have to claim before unclaimed rewards are forfeited
+/// Debug: 180 days - after this the creator may sweep the escrow residue
+pub const UNCLAIMED_SWEEP_GRACE_SECS: i64 = 180 * 24 * 60 * 60;
+
+/// Delay between proposing and executing an emergency escrow withdrawal
+/// Debug: 48 hours, fixed in code so a compromised authority cannot shorten it
+pub const EMERGENCY_TIMELOCK_SECS: i64 = 48 * 60 * 60;
+
+/// Switchboard V2 program that owns aggregator feed accounts
+pub const SWITCHBOARD_PROGRAM_ID: Pubkey = pubkey!("SW1TCH7qEPTdLsDHRgPuMQjbQxKdH2aBStViMFnt64f");
+
+/// Anchor discriminator of Switchboard's `AggregatorAccountData`
+/// sha256("account:AggregatorAccountData")[..8]
+pub const SWITCHBOARD_AGGREGATOR_DISCRIMINATOR: [u8; 8] = [217, 230, 65, 101, 201, 162, 27, 125];
+
+/// Byte offsets into the packed `AggregatorAccountData` (discriminator included)
+/// Debug: min_oracle_results sits in the header; the rest are in latest_confirmed_round
+const SWITCHBOARD_MIN_ORACLE_RESULTS_OFFSET: usize = 236;
+const SWITCHBOARD_NUM_SUCCESS_OFFSET: usize = 341;
+const SWITCHBOARD_ROUND_OPEN_TIMESTAMP_OFFSET: usize = 358;
+const SWITCHBOARD_RESULT_MANTISSA_OFFSET: usize = 366;
+const SWITCHBOARD_RESULT_SCALE_OFFSET: usize = 382;
+
+/// Longest human-readable market title, in bytes
+pub const MAX_TITLE_LEN: usize = 64;
+
+/// Longest off-chain metadata URI, in bytes
+pub const MAX_METADATA_URI_LEN: usize = 128;
+
+/// Most outcomes a categorical market may list
+/// Debug: Bounds `CategoricalMarket::outcome_pools` so the account size is fixed
+pub const MAX_OUTCOMES: usize = 8;
+
+/// Market caps are stored with 6 decimals
+const MARKET_CAP_DECIMALS: u32 = 6;
+
+/// Program-level configuration account (single PDA)
+/// Debug: Holds the creation fee and treasury so they can change without a redeploy
+#[account]
+pub struct Config {
+    pub authority: Pubkey,              // Admin allowed to update the config
+    pub treasury: Pubkey,               // Wallet that receives market creation fees
+    pub creation_fee_lamports: u64,     // Fee charged to create a market (in lamports)
+    pub min_market_duration_secs: i64,  // Deadline must be at least this far from creation
+    pub max_market_duration_secs: i64,  // Deadline must be at most this far from creation (0 = no limit)
+    pub paused: bool,                   // Kill-switch: blocks new markets and bets, exits stay open
+    pub market_count: u64,              // Markets created so far; the next market's registry index
+    pub block_bets_after_target: bool,  // Anti-sniping: reject bets once last_market_cap hits the target
+    pub bump: u8,                       // PDA bump seed
+}
+
+impl Config {
+    /// Calculate space needed for Config account
+    /// Debug: 8 (discriminator) + 32 (authority) + 32 (treasury) + 8 (creation_fee)
+    ///        + 8 (min_market_duration) + 8 (max_market_duration) + 1 (paused)
+    ///        + 8 (market_count) + 1 (block_bets_after_target) + 1 (bump)
+    pub const LEN: usize = 8 + 32 + 32 + 8 + 8 + 8 + 1 + 8 + 1 + 1;
+}
 
 /// Parimutuel betting market account structure with automated oracle resolution
 /// Debug: Stores pools, target market cap, deadline, and oracle data
@@ -20,6 +105,30 @@ pub struct Market {
     pub winner: Option<bool>,       // Winning side: Some(true) = YES, Some(false) = NO
     pub target_reached: bool,       // Whether target was reached before deadline
     pub resolved_at: i64,           // Timestamp when market was resolved
+    pub last_market_cap: u64,       // Latest market cap reported by the oracle
+    pub last_oracle_update: i64,    // Oracle timestamp of the latest report (0 = none yet)
+    pub creation_fee_held: u64,     // Creation fee still held in the fee escrow (in lamports)
+    pub total_claimed_lamports: u64, // Total rewards paid out of escrow
+    pub total_claimed_stake: u64,   // Sum of winning bet amounts that have claimed
+    pub max_oracle_skew_secs: i64,  // Max distance between oracle timestamp and chain clock
+    pub betting_closes_at: i64,     // Bets rejected from here on (deadline - grace)
+    pub unclaimed_swept_at: i64,    // When unclaimed rewards were forfeited (0 = never)
+    pub emergency_proposed_at: i64, // When an emergency withdrawal was proposed (0 = none)
+    pub emergency_recovery: Pubkey, // Recovery address named in the pending proposal
+    pub switchboard_feed: Pubkey,   // Switchboard aggregator trusted for resolution (default = none)
+    pub resolution_source: ResolutionSource, // Which path resolved the market
+    pub claim_window_secs: i64,     // Time winners have to claim after resolution (0 = no deadline)
+    pub claim_deadline: i64,        // resolved_at + claim_window_secs, set on resolution (0 = none)
+    pub is_void: bool,              // Resolved as a push: every bettor reclaims their principal
+    pub title: String,              // Human-readable market title (<= MAX_TITLE_LEN bytes)
+    pub metadata_uri: String,       // Off-chain JSON metadata URI (empty = none)
+    pub escrow_bump: u8,            // Canonical escrow PDA bump, recorded at creation
+    pub created_at: i64,            // Unix timestamp the market was created
+    pub last_bet_at: i64,           // Timestamp of the most recent bet (0 = none yet)
+    pub resolution_policy: ResolutionPolicy, // Whether a report at/after the deadline can still resolve YES
+    pub market_index: u64,          // Position in the market registry (see MarketRegistryEntry)
+    pub yes_bet_count: u64,         // Number of bets placed on YES
+    pub no_bet_count: u64,          // Number of bets placed on NO
     pub bump: u8,                   // PDA bump seed
 }
 
@@ -27,12 +136,96 @@ impl Market {
     /// Calculate space needed for Market account
     /// Debug: 8 (discriminator) + 32 (creator) + 32 (oracle) + 32 (token_mint) + 8 (yes_pool) + 8 (no_pool) 
     ///        + 8 (target_cap) + 8 (deadline) + 1 (is_resolved) + 2 (Option<bool>) + 1 (target_reached) 
-    ///        + 8 (resolved_at) + 1 (bump)
-    pub const LEN: usize = 8 + 32 + 32 + 32 + 8 + 8 + 8 + 8 + 1 + 2 + 1 + 8 + 1;
+    ///        + 8 (resolved_at) + 8 (last_market_cap) + 8 (last_oracle_update) + 8 (creation_fee_held)
+    ///        + 8 (total_claimed_lamports) + 8 (total_claimed_stake) + 8 (max_oracle_skew_secs)
+    ///        + 8 (betting_closes_at) + 8 (unclaimed_swept_at) + 8 (emergency_proposed_at)
+    ///        + 32 (emergency_recovery) + 32 (switchboard_feed) + 1 (resolution_source)
+    ///        + 8 (claim_window_secs) + 8 (claim_deadline) + 1 (is_void)
+    ///        + 4 + MAX_TITLE_LEN (title) + 4 + MAX_METADATA_URI_LEN (metadata_uri)
+    ///        + 1 (escrow_bump) + 8 (created_at) + 8 (last_bet_at) + 1 (resolution_policy)
+    ///        + 8 (market_index) + 8 (yes_bet_count) + 8 (no_bet_count) + 1 (bump)
+    pub const LEN: usize = 8 + 32 + 32 + 32 + 8 + 8 + 8 + 8 + 1 + 2 + 1 + 8 + 8 + 8 + 8 + 8 + 8 + 8 + 8 + 8 + 8 + 32 + 32 + 1 + 8 + 8 + 1
+        + 4 + MAX_TITLE_LEN + 4 + MAX_METADATA_URI_LEN + 1 + 8 + 8 + 1 + 8 + 8 + 8 + 1;
+}
+
+/// Registry slot mapping a creation index to its market
+/// Debug: PDA of [b"registry", index as u64 LE], so clients enumerate every market by
+/// walking 0..config.market_count instead of scanning with getProgramAccounts
+#[account]
+pub struct MarketRegistryEntry {
+    pub market: Pubkey,          // Market created at this index
+    pub index: u64,              // Registry index (matches market.market_index)
+    pub bump: u8,                // PDA bump seed
+}
+
+impl MarketRegistryEntry {
+    /// Calculate space needed for MarketRegistryEntry account
+    /// Debug: 8 (discriminator) + 32 (market) + 8 (index) + 1 (bump)
+    pub const LEN: usize = 8 + 32 + 8 + 1;
+}
+
+/// Categorical (N-outcome) parimutuel market
+/// Debug: Same pool mechanics as `Market`, with one pool per outcome and an oracle-picked winner
+#[account]
+pub struct CategoricalMarket {
+    pub creator: Pubkey,            // User who created the market (paid creation fee)
+    pub oracle_authority: Pubkey,   // Oracle/backend authority that picks the winning outcome
+    pub outcome_pools: Vec<u64>,    // Total SOL staked on each outcome (2..=MAX_OUTCOMES entries)
+    pub deadline: i64,              // Unix timestamp deadline for market resolution
+    pub betting_closes_at: i64,     // Bets rejected from here on (deadline - grace)
+    pub is_resolved: bool,          // Whether market has been resolved
+    pub winning_outcome: u8,        // Index of the winning outcome (valid once resolved)
+    pub is_void: bool,              // Nobody backed the winner: every bettor reclaims their principal
+    pub resolved_at: i64,           // Timestamp when market was resolved
+    pub total_claimed_lamports: u64, // Total rewards paid out of escrow
+    pub open_bet_count: u64,        // Bets placed and not yet claimed
+    pub unclaimed_swept_at: i64,    // When unclaimed rewards were forfeited (0 = never)
+    pub title: String,              // Human-readable market title (<= MAX_TITLE_LEN bytes)
+    pub escrow_bump: u8,            // Canonical escrow PDA bump, recorded at creation
+    pub created_at: i64,            // Unix timestamp the market was created
+    pub bump: u8,                   // PDA bump seed
+}
+
+impl CategoricalMarket {
+    /// Calculate space needed for CategoricalMarket account
+    /// Debug: 8 (discriminator) + 32 (creator) + 32 (oracle) + 4 + 8 * MAX_OUTCOMES (outcome_pools)
+    ///        + 8 (deadline) + 8 (betting_closes_at) + 1 (is_resolved) + 1 (winning_outcome)
+    ///        + 1 (is_void) + 8 (resolved_at) + 8 (total_claimed_lamports)
+    ///        + 8 (open_bet_count) + 8 (unclaimed_swept_at)
+    ///        + 4 + MAX_TITLE_LEN (title) + 1 (escrow_bump) + 8 (created_at) + 1 (bump)
+    pub const LEN: usize = 8 + 32 + 32 + 4 + 8 * MAX_OUTCOMES + 8 + 8 + 1 + 1 + 1 + 8 + 8 + 8 + 8
+        + 4 + MAX_TITLE_LEN + 1 + 8 + 1;
+    
+    /// Sum of every outcome pool
+    pub fn total_pool(&self) -> Result<u64> {
+        self.outcome_pools
+            .iter()
+            .try_fold(0u64, |total, pool| total.checked_add(*pool))
+            .ok_or(ParimutuelError::Overflow.into())
+    }
+}
+
+/// Bet on one outcome of a categorical market
+#[account]
+pub struct CategoricalBet {
+    pub user: Pubkey,            // User who placed the bet
+    pub market: Pubkey,          // CategoricalMarket this bet belongs to
+    pub amount: u64,             // Amount bet in lamports
+    pub outcome_index: u8,       // Outcome backed by this bet
+}
+
+impl CategoricalBet {
+    /// Calculate space needed for CategoricalBet account
+    /// Debug: 8 (discriminator) + 32 (user) + 32 (market) + 8 (amount) + 1 (outcome_index)
+    pub const LEN: usize = 8 + 32 + 32 + 8 + 1;
 }
 
 /// User bet account structure
 /// Debug: Tracks individual user's bet amount, side, and claim status
+/// PDA of [b"user_bet", market, user, bet_index as u64 LE] - a user can hold any number
+/// of independent bets on one market, each claimed or closed on its own
+/// A closed market's seed can be reused, so `market_index` ties the bet to the instance
+/// it was placed in and a stale bet can never claim against a re-created market
 #[account]
 pub struct UserBet {
     pub user: Pubkey,            // User who placed the bet
@@ -40,19 +233,63 @@ pub struct UserBet {
     pub amount: u64,             // Amount bet in lamports
     pub side: bool,              // Betting side: true = YES, false = NO
     pub claimed: bool,           // Whether reward has been claimed
+    pub bet_index: u64,          // Client-chosen index distinguishing this user's bets
+    pub market_index: u64,       // Registry index of the market instance the bet was placed in
 }
 
 impl UserBet {
     /// Calculate space needed for UserBet account
     /// Debug: 8 (discriminator) + 32 (user) + 32 (market) + 8 (amount) + 1 (side) + 1 (claimed)
-    pub const LEN: usize = 8 + 32 + 32 + 8 + 1 + 1;
+    ///        + 8 (bet_index) + 8 (market_index)
+    pub const LEN: usize = 8 + 32 + 32 + 8 + 1 + 1 + 8 + 8;
+}
+
+/// Create the program config (one-time)
+/// Debug: Signer becomes the config authority
+#[derive(Accounts)]
+pub struct InitializeConfig<'info> {
+    #[account(
+        init,
+        payer = authority,
+        space = Config::LEN,
+        seeds = [b"config"],
+        bump
+    )]
+    pub config: Account<'info, Config>,
+    
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+/// Update config values (authority only)
+/// Debug: Shared by all admin setters on the config
+#[derive(Accounts)]
+pub struct UpdateConfig<'info> {
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump = config.bump,
+        has_one = authority @ ParimutuelError::Unauthorized
+    )]
+    pub config: Account<'info, Config>,
+    
+    pub authority: Signer<'info>,
 }
 
 /// Initialize a new parimutuel market (permissionless)
-/// Debug: Any user can create a market by paying 0.015 SOL fee to treasury
+/// Debug: Any user can create a market by paying the configured fee into the fee escrow
 #[derive(Accounts)]
 #[instruction(market_seed: String)]
 pub struct InitializeMarket<'info> {
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, Config>,
+    
     #[account(
         init,
         payer = creator,
@@ -62,9 +299,31 @@ pub struct InitializeMarket<'info> {
     )]
     pub market: Account<'info, Market>,
     
-    /// CHECK: Treasury wallet that receives market creation fees
-    #[account(mut)]
-    pub treasury: AccountInfo<'info>,
+    /// CHECK: Market escrow PDA, funded with its rent-exempt reserve at creation
+    #[account(
+        mut,
+        seeds = [b"escrow", market.key().as_ref()],
+        bump
+    )]
+    pub escrow: AccountInfo<'info>,
+    
+    /// CHECK: Fee escrow PDA that holds the creation fee until it is refunded or collected
+    #[account(
+        mut,
+        seeds = [b"fee_escrow", market.key().as_ref()],
+        bump
+    )]
+    pub fee_escrow: AccountInfo<'info>,
+    
+    /// Debug: Next registry slot, indexed by the config's running market count
+    #[account(
+        init,
+        payer = creator,
+        space = MarketRegistryEntry::LEN,
+        seeds = [b"registry", config.market_count.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub registry_entry: Account<'info, MarketRegistryEntry>,
     
     #[account(mut)]
     pub creator: Signer<'info>,
@@ -75,8 +334,14 @@ pub struct InitializeMarket<'info> {
 /// Place a bet on the market
 /// Debug: Transfers SOL from user to market escrow PDA
 #[derive(Accounts)]
-#[instruction(market_seed: String)]
+#[instruction(market_seed: String, bet_index: u64)]
 pub struct PlaceBet<'info> {
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, Config>,
+    
     #[account(
         mut,
         seeds = [b"market", market_seed.as_bytes()],
@@ -88,16 +353,17 @@ pub struct PlaceBet<'info> {
         init,
         payer = user,
         space = UserBet::LEN,
-        seeds = [b"user_bet", market.key().as_ref(), user.key().as_ref()],
+        seeds = [b"user_bet", market.key().as_ref(), user.key().as_ref(), bet_index.to_le_bytes().as_ref()],
         bump
     )]
     pub user_bet: Account<'info, UserBet>,
     
     /// CHECK: Market escrow PDA that holds all bet funds
+    /// Debug: Bound to the bump recorded at market creation
     #[account(
         mut,
         seeds = [b"escrow", market.key().as_ref()],
-        bump
+        bump = market.escrow_bump
     )]
     pub escrow: AccountInfo<'info>,
     
@@ -123,10 +389,162 @@ pub struct ResolveMarket<'info> {
     pub oracle: Signer<'info>,
 }
 
+/// Resolve from the market's Switchboard aggregator feed
+/// Debug: Permissionless - the feed is pinned to `market.switchboard_feed`
+#[derive(Accounts)]
+#[instruction(market_seed: String)]
+pub struct ResolveFromSwitchboard<'info> {
+    #[account(
+        mut,
+        seeds = [b"market", market_seed.as_bytes()],
+        bump = market.bump
+    )]
+    pub market: Account<'info, Market>,
+    
+    /// CHECK: Switchboard aggregator, checked against market.switchboard_feed and decoded in the handler
+    #[account(
+        constraint = feed.key() == market.switchboard_feed @ ParimutuelError::InvalidSwitchboardFeed
+    )]
+    pub feed: AccountInfo<'info>,
+    
+    pub caller: Signer<'info>,
+}
+
+/// Force-resolve a market NO once the oracle has missed the deadline by the grace period
+/// Debug: Permissionless - any signer can crank it, no oracle data required
+#[derive(Accounts)]
+#[instruction(market_seed: String)]
+pub struct ForceResolveDeadline<'info> {
+    #[account(
+        mut,
+        seeds = [b"market", market_seed.as_bytes()],
+        bump = market.bump
+    )]
+    pub market: Account<'info, Market>,
+    
+    pub caller: Signer<'info>,
+}
+
+/// Record an oracle heartbeat without resolving
+/// Debug: Same accounts as resolution - oracle signer checked in handler
+#[derive(Accounts)]
+#[instruction(market_seed: String)]
+pub struct UpdateOracleHeartbeat<'info> {
+    #[account(
+        mut,
+        seeds = [b"market", market_seed.as_bytes()],
+        bump = market.bump
+    )]
+    pub market: Account<'info, Market>,
+    
+    /// CHECK: Oracle authority that reports the market cap
+    pub oracle: Signer<'info>,
+}
+
+/// Designate the Switchboard feed a market may be resolved from
+/// Debug: Same accounts as resolution - oracle signer checked in handler
+#[derive(Accounts)]
+#[instruction(market_seed: String)]
+pub struct SetSwitchboardFeed<'info> {
+    #[account(
+        mut,
+        seeds = [b"market", market_seed.as_bytes()],
+        bump = market.bump
+    )]
+    pub market: Account<'info, Market>,
+    
+    /// CHECK: Oracle authority that vouches for the feed
+    pub oracle: Signer<'info>,
+}
+
+/// Rotate the oracle authority of an unresolved market
+/// Debug: Signer must be the market creator or the config authority - checked in handler
+#[derive(Accounts)]
+#[instruction(market_seed: String)]
+pub struct SetOracleAuthority<'info> {
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, Config>,
+    
+    #[account(
+        mut,
+        seeds = [b"market", market_seed.as_bytes()],
+        bump = market.bump
+    )]
+    pub market: Account<'info, Market>,
+    
+    pub authority: Signer<'info>,
+}
+
+/// Refund the creation fee to the creator of a market that never saw a bet
+/// Debug: Only the creator can reclaim, and only after the deadline
+#[derive(Accounts)]
+#[instruction(market_seed: String)]
+pub struct RefundCreationFee<'info> {
+    #[account(
+        mut,
+        seeds = [b"market", market_seed.as_bytes()],
+        bump = market.bump,
+        has_one = creator @ ParimutuelError::Unauthorized
+    )]
+    pub market: Account<'info, Market>,
+    
+    /// CHECK: Fee escrow PDA holding the creation fee
+    #[account(
+        mut,
+        seeds = [b"fee_escrow", market.key().as_ref()],
+        bump
+    )]
+    pub fee_escrow: AccountInfo<'info>,
+    
+    #[account(mut)]
+    pub creator: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+/// Release the creation fee of an active market to the treasury (permissionless)
+/// Debug: Treasury is pinned to the configured wallet
+#[derive(Accounts)]
+#[instruction(market_seed: String)]
+pub struct CollectCreationFee<'info> {
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, Config>,
+    
+    #[account(
+        mut,
+        seeds = [b"market", market_seed.as_bytes()],
+        bump = market.bump
+    )]
+    pub market: Account<'info, Market>,
+    
+    /// CHECK: Fee escrow PDA holding the creation fee
+    #[account(
+        mut,
+        seeds = [b"fee_escrow", market.key().as_ref()],
+        bump
+    )]
+    pub fee_escrow: AccountInfo<'info>,
+    
+    /// CHECK: Treasury wallet that receives market creation fees, pinned to config
+    #[account(
+        mut,
+        constraint = treasury.key() == config.treasury @ ParimutuelError::InvalidTreasury
+    )]
+    pub treasury: AccountInfo<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
 /// Claim reward after market resolution
 /// Debug: Calculates proportional payout using u128 to prevent overflow
 #[derive(Accounts)]
-#[instruction(market_seed: String)]
+#[instruction(market_seed: String, bet_index: u64)]
 pub struct ClaimReward<'info> {
     #[account(
         mut,
@@ -135,20 +553,24 @@ pub struct ClaimReward<'info> {
     )]
     pub market: Account<'info, Market>,
     
+    /// Debug: Closed once the reward is paid, returning its rent to the bettor
     #[account(
         mut,
-        seeds = [b"user_bet", market.key().as_ref(), user.key().as_ref()],
+        close = user,
+        seeds = [b"user_bet", market.key().as_ref(), user.key().as_ref(), bet_index.to_le_bytes().as_ref()],
         bump,
         constraint = user_bet.user == user.key() @ ParimutuelError::Unauthorized,
-        constraint = user_bet.market == market.key() @ ParimutuelError::InvalidMarket
+        constraint = user_bet.market == market.key() @ ParimutuelError::InvalidMarket,
+        constraint = user_bet.market_index == market.market_index @ ParimutuelError::InvalidMarket
     )]
     pub user_bet: Account<'info, UserBet>,
     
     /// CHECK: Market escrow PDA that holds all bet funds
+    /// Debug: Bound to the bump recorded at market creation
     #[account(
         mut,
         seeds = [b"escrow", market.key().as_ref()],
-        bump
+        bump = market.escrow_bump
     )]
     pub escrow: AccountInfo<'info>,
     
@@ -158,88 +580,2107 @@ pub struct ClaimReward<'info> {
     pub system_program: Program<'info, System>,
 }
 
-/// Initialize a new parimutuel market with oracle-based resolution (permissionless)
-/// Debug: Any user can create a market by paying 0.015 SOL fee to treasury
-pub fn initialize_market(
-    ctx: Context<InitializeMarket>,
-    _market_seed: String,
-    oracle_authority: Pubkey,
-    token_mint: Pubkey,
-    target_market_cap: u64,
-    deadline: i64,
-) -> Result<()> {
-    let market = &mut ctx.accounts.market;
-    let current_time = Clock::get()?.unix_timestamp;
+/// Claim a resolved bet and re-bet the payout into another market
+/// Debug: Source side mirrors ClaimReward, target side mirrors PlaceBet
+#[derive(Accounts)]
+#[instruction(
+    source_market_seed: String,
+    target_market_seed: String,
+    source_bet_index: u64,
+    target_bet_index: u64
+)]
+pub struct ClaimAndBet<'info> {
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, Config>,
     
-    require!(deadline > current_time, ParimutuelError::InvalidDeadline);
-    require!(target_market_cap > 0, ParimutuelError::InvalidAmount);
+    #[account(
+        mut,
+        seeds = [b"market", source_market_seed.as_bytes()],
+        bump = source_market.bump
+    )]
+    pub source_market: Account<'info, Market>,
     
-    let creator_balance = ctx.accounts.creator.lamports();
-    let rent_exempt_balance = Rent::get()?.minimum_balance(Market::LEN);
-    let total_required = MARKET_CREATION_FEE
-        .checked_add(rent_exempt_balance)
-        .ok_or(ParimutuelError::Overflow)?;
+    /// Debug: Closed once the reward is paid, returning its rent to the bettor
+    #[account(
+        mut,
+        close = user,
+        seeds = [
+            b"user_bet",
+            source_market.key().as_ref(),
+            user.key().as_ref(),
+            source_bet_index.to_le_bytes().as_ref()
+        ],
+        bump,
+        constraint = source_user_bet.user == user.key() @ ParimutuelError::Unauthorized,
+        constraint = source_user_bet.market == source_market.key() @ ParimutuelError::InvalidMarket,
+        constraint = source_user_bet.market_index == source_market.market_index @ ParimutuelError::InvalidMarket
+    )]
+    pub source_user_bet: Account<'info, UserBet>,
     
-    require!(
-        creator_balance >= total_required,
-        ParimutuelError::InsufficientFunds
-    );
+    /// CHECK: Source market escrow PDA that pays the reward
+    /// Debug: Bound to the bump recorded at market creation
+    #[account(
+        mut,
+        seeds = [b"escrow", source_market.key().as_ref()],
+        bump = source_market.escrow_bump
+    )]
+    pub source_escrow: AccountInfo<'info>,
     
-    msg!("DEBUG: Transferring {} lamports creation fee to treasury", MARKET_CREATION_FEE);
+    #[account(
+        mut,
+        seeds = [b"market", target_market_seed.as_bytes()],
+        bump = target_market.bump
+    )]
+    pub target_market: Account<'info, Market>,
     
-    let cpi_context = CpiContext::new(
-        ctx.accounts.system_program.to_account_info(),
-        Transfer {
-            from: ctx.accounts.creator.to_account_info(),
-            to: ctx.accounts.treasury.to_account_info(),
-        },
-    );
-    transfer(cpi_context, MARKET_CREATION_FEE)?;
+    #[account(
+        init,
+        payer = user,
+        space = UserBet::LEN,
+        seeds = [
+            b"user_bet",
+            target_market.key().as_ref(),
+            user.key().as_ref(),
+            target_bet_index.to_le_bytes().as_ref()
+        ],
+        bump
+    )]
+    pub target_user_bet: Account<'info, UserBet>,
     
-    market.creator = ctx.accounts.creator.key();
-    market.oracle_authority = oracle_authority;
-    market.token_mint = token_mint;
-    market.total_yes_pool = 0;
-    market.total_no_pool = 0;
-    market.target_market_cap = target_market_cap;
-    market.deadline = deadline;
+    /// CHECK: Target market escrow PDA that receives the new stake
+    /// Debug: Bound to the bump recorded at market creation
+    #[account(
+        mut,
+        seeds = [b"escrow", target_market.key().as_ref()],
+        bump = target_market.escrow_bump
+    )]
+    pub target_escrow: AccountInfo<'info>,
+    
+    #[account(mut)]
+    pub user: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+/// Close a losing bet after resolution
+/// Debug: Nothing is paid out - the UserBet rent simply returns to the bettor
+#[derive(Accounts)]
+#[instruction(market_seed: String, bet_index: u64)]
+pub struct CloseLosingBet<'info> {
+    #[account(
+        seeds = [b"market", market_seed.as_bytes()],
+        bump = market.bump
+    )]
+    pub market: Account<'info, Market>,
+    
+    #[account(
+        mut,
+        close = user,
+        seeds = [b"user_bet", market.key().as_ref(), user.key().as_ref(), bet_index.to_le_bytes().as_ref()],
+        bump,
+        constraint = user_bet.user == user.key() @ ParimutuelError::Unauthorized,
+        constraint = user_bet.market == market.key() @ ParimutuelError::InvalidMarket,
+        constraint = user_bet.market_index == market.market_index @ ParimutuelError::InvalidMarket
+    )]
+    pub user_bet: Account<'info, UserBet>,
+    
+    #[account(mut)]
+    pub user: Signer<'info>,
+}
+
+/// Read-only market status for keeper bots
+#[derive(Accounts)]
+#[instruction(market_seed: String)]
+pub struct IsResolvable<'info> {
+    #[account(
+        seeds = [b"market", market_seed.as_bytes()],
+        bump = market.bump
+    )]
+    pub market: Account<'info, Market>,
+}
+
+/// Propose an emergency escrow withdrawal (config authority only)
+/// Debug: Starts the timelock; nothing moves until `emergency_withdraw`
+#[derive(Accounts)]
+#[instruction(market_seed: String)]
+pub struct ProposeEmergencyWithdraw<'info> {
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump,
+        has_one = authority @ ParimutuelError::Unauthorized
+    )]
+    pub config: Account<'info, Config>,
+    
+    #[account(
+        mut,
+        seeds = [b"market", market_seed.as_bytes()],
+        bump = market.bump
+    )]
+    pub market: Account<'info, Market>,
+    
+    pub authority: Signer<'info>,
+}
+
+/// Execute a proposed emergency withdrawal after the timelock (config authority only)
+/// Debug: Recovery account is pinned to the address named in the proposal
+#[derive(Accounts)]
+#[instruction(market_seed: String)]
+pub struct EmergencyWithdraw<'info> {
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump,
+        has_one = authority @ ParimutuelError::Unauthorized
+    )]
+    pub config: Account<'info, Config>,
+    
+    #[account(
+        mut,
+        seeds = [b"market", market_seed.as_bytes()],
+        bump = market.bump
+    )]
+    pub market: Account<'info, Market>,
+    
+    /// CHECK: Market escrow PDA being drained
+    #[account(
+        mut,
+        seeds = [b"escrow", market.key().as_ref()],
+        bump = market.escrow_bump
+    )]
+    pub escrow: AccountInfo<'info>,
+    
+    /// CHECK: Recovery wallet, must match the proposal
+    #[account(
+        mut,
+        constraint = recovery.key() == market.emergency_recovery @ ParimutuelError::InvalidRecoveryAddress
+    )]
+    pub recovery: AccountInfo<'info>,
+    
+    pub authority: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+/// Sweep forfeited rewards to the creator long after resolution
+/// Debug: Creator only, escrow keeps its rent reserve
+#[derive(Accounts)]
+#[instruction(market_seed: String)]
+pub struct SweepUnclaimed<'info> {
+    #[account(
+        mut,
+        seeds = [b"market", market_seed.as_bytes()],
+        bump = market.bump,
+        has_one = creator @ ParimutuelError::Unauthorized
+    )]
+    pub market: Account<'info, Market>,
+    
+    /// CHECK: Market escrow PDA holding the unclaimed rewards
+    #[account(
+        mut,
+        seeds = [b"escrow", market.key().as_ref()],
+        bump = market.escrow_bump
+    )]
+    pub escrow: AccountInfo<'info>,
+    
+    #[account(mut)]
+    pub creator: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+/// Sweep rewards left unclaimed past the market's claim deadline to the treasury
+/// Debug: Permissionless crank, escrow keeps its rent reserve
+#[derive(Accounts)]
+#[instruction(market_seed: String)]
+pub struct SweepExpiredClaims<'info> {
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, Config>,
+    
+    #[account(
+        mut,
+        seeds = [b"market", market_seed.as_bytes()],
+        bump = market.bump
+    )]
+    pub market: Account<'info, Market>,
+    
+    /// CHECK: Market escrow PDA holding the unclaimed rewards
+    #[account(
+        mut,
+        seeds = [b"escrow", market.key().as_ref()],
+        bump = market.escrow_bump
+    )]
+    pub escrow: AccountInfo<'info>,
+    
+    /// CHECK: Treasury wallet, must match config.treasury
+    #[account(
+        mut,
+        constraint = treasury.key() == config.treasury @ ParimutuelError::InvalidTreasury
+    )]
+    pub treasury: AccountInfo<'info>,
+    
+    pub caller: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+/// Close a fully-claimed market
+/// Debug: Rounding dust goes to treasury, rent goes back to the creator
+#[derive(Accounts)]
+#[instruction(market_seed: String)]
+pub struct CloseMarket<'info> {
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, Config>,
+    
+    #[account(
+        mut,
+        seeds = [b"market", market_seed.as_bytes()],
+        bump = market.bump,
+        has_one = creator @ ParimutuelError::Unauthorized,
+        close = creator
+    )]
+    pub market: Account<'info, Market>,
+    
+    /// CHECK: Market escrow PDA holding the rounding dust
+    #[account(
+        mut,
+        seeds = [b"escrow", market.key().as_ref()],
+        bump = market.escrow_bump
+    )]
+    pub escrow: AccountInfo<'info>,
+    
+    /// CHECK: Treasury wallet that receives the dust, pinned to config
+    #[account(
+        mut,
+        constraint = treasury.key() == config.treasury @ ParimutuelError::InvalidTreasury
+    )]
+    pub treasury: AccountInfo<'info>,
+    
+    /// CHECK: Market creator receives the market and escrow rent back
+    #[account(mut)]
+    pub creator: AccountInfo<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+/// Initialize a categorical market (permissionless)
+/// Debug: The creation fee goes straight to the treasury - there is no refund path
+#[derive(Accounts)]
+#[instruction(market_seed: String)]
+pub struct InitializeCategoricalMarket<'info> {
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, Config>,
+    
+    #[account(
+        init,
+        payer = creator,
+        space = CategoricalMarket::LEN,
+        seeds = [b"categorical_market", market_seed.as_bytes()],
+        bump
+    )]
+    pub market: Account<'info, CategoricalMarket>,
+    
+    /// CHECK: Market escrow PDA, funded with its rent-exempt reserve at creation
+    #[account(
+        mut,
+        seeds = [b"escrow", market.key().as_ref()],
+        bump
+    )]
+    pub escrow: AccountInfo<'info>,
+    
+    /// CHECK: Treasury wallet that receives the creation fee, pinned to config
+    #[account(
+        mut,
+        constraint = treasury.key() == config.treasury @ ParimutuelError::InvalidTreasury
+    )]
+    pub treasury: AccountInfo<'info>,
+    
+    #[account(mut)]
+    pub creator: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+/// Place a bet on one outcome of a categorical market
+#[derive(Accounts)]
+#[instruction(market_seed: String)]
+pub struct PlaceCategoricalBet<'info> {
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, Config>,
+    
+    #[account(
+        mut,
+        seeds = [b"categorical_market", market_seed.as_bytes()],
+        bump = market.bump
+    )]
+    pub market: Account<'info, CategoricalMarket>,
+    
+    #[account(
+        init,
+        payer = user,
+        space = CategoricalBet::LEN,
+        seeds = [b"categorical_bet", market.key().as_ref(), user.key().as_ref()],
+        bump
+    )]
+    pub user_bet: Account<'info, CategoricalBet>,
+    
+    /// CHECK: Market escrow PDA that holds all bet funds
+    #[account(
+        mut,
+        seeds = [b"escrow", market.key().as_ref()],
+        bump = market.escrow_bump
+    )]
+    pub escrow: AccountInfo<'info>,
+    
+    #[account(mut)]
+    pub user: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+/// Resolve a categorical market to one outcome
+/// Debug: Oracle signer checked in handler
+#[derive(Accounts)]
+#[instruction(market_seed: String)]
+pub struct ResolveCategoricalMarket<'info> {
+    #[account(
+        mut,
+        seeds = [b"categorical_market", market_seed.as_bytes()],
+        bump = market.bump
+    )]
+    pub market: Account<'info, CategoricalMarket>,
+    
+    /// CHECK: Oracle authority that picks the winning outcome
+    pub oracle: Signer<'info>,
+}
+
+/// Claim a categorical bet after resolution
+/// Debug: The bet is closed either way - losing bets only get their rent back
+#[derive(Accounts)]
+#[instruction(market_seed: String)]
+pub struct ClaimCategoricalReward<'info> {
+    #[account(
+        mut,
+        seeds = [b"categorical_market", market_seed.as_bytes()],
+        bump = market.bump
+    )]
+    pub market: Account<'info, CategoricalMarket>,
+    
+    #[account(
+        mut,
+        close = user,
+        seeds = [b"categorical_bet", market.key().as_ref(), user.key().as_ref()],
+        bump,
+        constraint = user_bet.user == user.key() @ ParimutuelError::Unauthorized,
+        constraint = user_bet.market == market.key() @ ParimutuelError::InvalidMarket
+    )]
+    pub user_bet: Account<'info, CategoricalBet>,
+    
+    /// CHECK: Market escrow PDA that holds all bet funds
+    #[account(
+        mut,
+        seeds = [b"escrow", market.key().as_ref()],
+        bump = market.escrow_bump
+    )]
+    pub escrow: AccountInfo<'info>,
+    
+    #[account(mut)]
+    pub user: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+/// Void a categorical market once the oracle has missed the deadline by the grace period
+/// Debug: Permissionless - any signer can crank it, no oracle data required
+#[derive(Accounts)]
+#[instruction(market_seed: String)]
+pub struct ForceVoidCategoricalMarket<'info> {
+    #[account(
+        mut,
+        seeds = [b"categorical_market", market_seed.as_bytes()],
+        bump = market.bump
+    )]
+    pub market: Account<'info, CategoricalMarket>,
+    
+    pub caller: Signer<'info>,
+}
+
+/// Sweep forfeited categorical rewards to the creator long after resolution
+/// Debug: Creator only, escrow keeps its rent reserve
+#[derive(Accounts)]
+#[instruction(market_seed: String)]
+pub struct SweepUnclaimedCategorical<'info> {
+    #[account(
+        mut,
+        seeds = [b"categorical_market", market_seed.as_bytes()],
+        bump = market.bump,
+        has_one = creator @ ParimutuelError::Unauthorized
+    )]
+    pub market: Account<'info, CategoricalMarket>,
+    
+    /// CHECK: Market escrow PDA holding the unclaimed rewards
+    #[account(
+        mut,
+        seeds = [b"escrow", market.key().as_ref()],
+        bump = market.escrow_bump
+    )]
+    pub escrow: AccountInfo<'info>,
+    
+    #[account(mut)]
+    pub creator: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+/// Close a categorical market once every bet has been claimed
+/// Debug: Rounding dust goes to treasury, rent goes back to the creator
+#[derive(Accounts)]
+#[instruction(market_seed: String)]
+pub struct CloseCategoricalMarket<'info> {
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, Config>,
+    
+    #[account(
+        mut,
+        seeds = [b"categorical_market", market_seed.as_bytes()],
+        bump = market.bump,
+        has_one = creator @ ParimutuelError::Unauthorized,
+        close = creator
+    )]
+    pub market: Account<'info, CategoricalMarket>,
+    
+    /// CHECK: Market escrow PDA holding the rounding dust
+    #[account(
+        mut,
+        seeds = [b"escrow", market.key().as_ref()],
+        bump = market.escrow_bump
+    )]
+    pub escrow: AccountInfo<'info>,
+    
+    /// CHECK: Treasury wallet that receives the dust, pinned to config
+    #[account(
+        mut,
+        constraint = treasury.key() == config.treasury @ ParimutuelError::InvalidTreasury
+    )]
+    pub treasury: AccountInfo<'info>,
+    
+    /// CHECK: Market creator receives the market and escrow rent back
+    #[account(mut)]
+    pub creator: AccountInfo<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+/// Check an oracle timestamp is within the market's allowed skew of the chain clock
+/// and not from before the market existed
+/// Debug: Rejects both future-dated and stale reports
+fn validate_oracle_timestamp(market: &Market, timestamp: i64, current_time: i64) -> Result<()> {
+    require!(timestamp >= market.created_at, ParimutuelError::StaleData);
+    require!(
+        timestamp <= current_time.saturating_add(market.max_oracle_skew_secs),
+        ParimutuelError::StaleData
+    );
+    require!(
+        timestamp >= current_time.saturating_sub(market.max_oracle_skew_secs),
+        ParimutuelError::StaleData
+    );
+    Ok(())
+}
+
+/// Latest confirmed round of a Switchboard aggregator
+struct SwitchboardRound {
+    min_oracle_results: u32,
+    num_success: u32,
+    round_open_timestamp: i64,
+    mantissa: i128,
+    scale: u32,
+}
+
+/// Decode the fields resolution needs from a Switchboard `AggregatorAccountData`
+/// Debug: Checks the owner and discriminator, then reads fixed offsets of the
+/// zero-copy layout instead of pulling in the Switchboard crate
+fn read_switchboard_round(feed: &AccountInfo) -> Result<SwitchboardRound> {
+    require!(feed.owner == &SWITCHBOARD_PROGRAM_ID, ParimutuelError::InvalidSwitchboardFeed);
+    let data = feed.try_borrow_data()?;
+    require!(
+        data.len() >= SWITCHBOARD_RESULT_SCALE_OFFSET + 4
+            && data[..8] == SWITCHBOARD_AGGREGATOR_DISCRIMINATOR,
+        ParimutuelError::InvalidSwitchboardFeed
+    );
+    
+    let field = |offset: usize, len: usize| &data[offset..offset + len];
+    Ok(SwitchboardRound {
+        min_oracle_results: u32::from_le_bytes(field(SWITCHBOARD_MIN_ORACLE_RESULTS_OFFSET, 4).try_into().unwrap()),
+        num_success: u32::from_le_bytes(field(SWITCHBOARD_NUM_SUCCESS_OFFSET, 4).try_into().unwrap()),
+        round_open_timestamp: i64::from_le_bytes(field(SWITCHBOARD_ROUND_OPEN_TIMESTAMP_OFFSET, 8).try_into().unwrap()),
+        mantissa: i128::from_le_bytes(field(SWITCHBOARD_RESULT_MANTISSA_OFFSET, 16).try_into().unwrap()),
+        scale: u32::from_le_bytes(field(SWITCHBOARD_RESULT_SCALE_OFFSET, 4).try_into().unwrap()),
+    })
+}
+
+/// Convert a Switchboard decimal (mantissa * 10^-scale dollars) to the 6-decimal market-cap scale
+/// Debug: Truncates extra precision; negative or oversized values are rejected
+fn switchboard_to_market_cap(mantissa: i128, scale: u32) -> Result<u64> {
+    require!(mantissa >= 0, ParimutuelError::InvalidOracleValue);
+    let value = if scale <= MARKET_CAP_DECIMALS {
+        mantissa
+            .checked_mul(10i128.pow(MARKET_CAP_DECIMALS - scale))
+            .ok_or(ParimutuelError::Overflow)?
+    } else {
+        let divisor = 10i128
+            .checked_pow(scale - MARKET_CAP_DECIMALS)
+            .ok_or(ParimutuelError::InvalidOracleValue)?;
+        mantissa / divisor
+    };
+    u64::try_from(value).map_err(|_| ParimutuelError::Overflow.into())
+}
+
+/// Parimutuel payout of a winning stake: (user_amount * total_pool) / winning_pool, rounded down
+/// Pure and context-free so off-chain clients can reproduce on-chain rounding exactly
+/// Flooring each payout keeps the market solvent: across all winners the payouts sum to
+/// at most total_pool, and the dust left in escrow is below the number of winners
+/// Debug: Computed in u128; errors on an empty winning pool or a payout above u64::MAX
+pub fn calculate_reward(user_amount: u64, winning_pool: u64, total_pool: u64) -> Result<u64> {
+    let reward = (user_amount as u128)
+        .checked_mul(total_pool as u128)
+        .ok_or(ParimutuelError::Overflow)?
+        .checked_div(winning_pool as u128)
+        .ok_or(ParimutuelError::DivisionByZero)?;
+    u64::try_from(reward).map_err(|_| ParimutuelError::Overflow.into())
+}
+
+/// Proportional reward owed to a winning bet: (user_amount * total_pool) / winning_pool
+/// Debug: Rejects losing bets and markets without a winner
+fn winning_reward_lamports(market: &Market, user_bet: &UserBet) -> Result<u64> {
+    // Validation: User must be on winning side
+    let winner = market.winner.ok_or(ParimutuelError::NoWinner)?;
+    require!(user_bet.side == winner, ParimutuelError::NotWinner);
+    
+    // Calculate proportional reward using u128 to prevent overflow
+    // Formula: Reward = (User's Bet / Winning Pool) × Total Pool
+    let winning_pool = if winner {
+        market.total_yes_pool
+    } else {
+        market.total_no_pool
+    };
+    
+    let total_pool = market.total_yes_pool
+        .checked_add(market.total_no_pool)
+        .ok_or(ParimutuelError::Overflow)?;
+    
+    // Debug: Use u128 for precise calculation with large numbers
+    msg!("DEBUG: Calculating reward - User bet: {}, Winning pool: {}, Total pool: {}", 
+        user_bet.amount, winning_pool, total_pool);
+    
+    require!(winning_pool > 0, ParimutuelError::EmptyPool);
+    
+    let reward_lamports = calculate_reward(user_bet.amount, winning_pool, total_pool)?;
+    
+    msg!("DEBUG: Calculated reward: {} lamports", reward_lamports);
+    
+    Ok(reward_lamports)
+}
+
+/// Number of bets paid out by a resolved market
+/// Debug: A push refunds both sides, so every bet counts as a winner
+fn winning_bet_count(market: &Market) -> Result<u64> {
+    if market.is_void {
+        return market.yes_bet_count
+            .checked_add(market.no_bet_count)
+            .ok_or(ParimutuelError::Overflow.into());
+    }
+    
+    if market.winner.ok_or(ParimutuelError::NoWinner)? {
+        Ok(market.yes_bet_count)
+    } else {
+        Ok(market.no_bet_count)
+    }
+}
+
+/// Clamp a claim to what the escrow holds above rent, absorbing only rounding dust
+/// Floored payouts lose under one lamport per winning bet, so a shortfall below the
+/// winning bet count is rounding; anything larger means the escrow was drained
+/// Debug: Errors with InsufficientEscrow past the dust and InvalidAmount on a zero payout
+fn clamp_to_escrow(market: &Market, computed_reward: u64, escrow_available: u64) -> Result<u64> {
+    let winning_bets = winning_bet_count(market)?;
+    let shortfall = computed_reward.saturating_sub(escrow_available);
+    require!(shortfall < winning_bets, ParimutuelError::InsufficientEscrow);
+    
+    let reward_lamports = computed_reward - shortfall;
+    require!(reward_lamports > 0, ParimutuelError::InvalidAmount);
+    
+    if shortfall > 0 {
+        msg!("DEBUG: Reward clamped from {} to {} lamports left in escrow",
+            computed_reward, reward_lamports);
+    }
+    
+    Ok(reward_lamports)
+}
+
+/// Start the claim window at resolution
+/// Debug: Leaves claim_deadline at 0 (no deadline) when the market has no window
+fn open_claim_window(market: &mut Market, resolved_at: i64) {
+    market.claim_deadline = if market.claim_window_secs > 0 {
+        resolved_at.saturating_add(market.claim_window_secs)
+    } else {
+        0
+    };
+}
+
+/// Resolve YES if the reported cap reached the target, NO if the deadline passed
+/// The target counts when `current_market_cap >= target_market_cap`; under
+/// `DeadlineWinsOnTie` the report's `timestamp` must also be `< deadline`
+/// `forced` lets the oracle declare a push; a forced Yes/No must match the derived outcome
+/// Debug: Shared by the signed-oracle and Switchboard paths once the report is validated
+fn apply_market_cap_resolution(
+    market: &mut Account<Market>,
+    current_market_cap: u64,
+    timestamp: i64,
+    current_time: i64,
+    source: ResolutionSource,
+    actor: Pubkey,
+    forced: Option<Resolution>,
+) -> Result<()> {
+    let target_hit = current_market_cap >= market.target_market_cap;
+    let target_reached = match market.resolution_policy {
+        ResolutionPolicy::TargetWinsOnTie => target_hit,
+        ResolutionPolicy::DeadlineWinsOnTie => target_hit && timestamp < market.deadline,
+    };
+    let deadline_passed = current_time >= market.deadline;
+    
+    require!(
+        target_reached || deadline_passed,
+        ParimutuelError::CannotResolveYet
+    );
+    
+    #[allow(clippy::needless_bool)]
+    let winner = if target_reached {
+        true
+    } else {
+        false
+    };
+    let derived = if winner { Resolution::Yes } else { Resolution::No };
+    let resolution = forced.unwrap_or(derived);
+    require!(
+        resolution == derived || resolution == Resolution::Push,
+        ParimutuelError::ResolutionMismatch
+    );
+    let is_push = resolution == Resolution::Push;
+    
+    market.is_resolved = true;
+    market.winner = if is_push { None } else { Some(winner) };
+    market.is_void = is_push;
+    market.target_reached = target_reached;
+    market.resolved_at = current_time;
+    market.last_market_cap = current_market_cap;
+    market.last_oracle_update = timestamp;
+    market.resolution_source = source;
+    open_claim_window(market, current_time);
+    
+    msg!("DEBUG: Market resolved by {:?}", source);
+    msg!("DEBUG: Current Market Cap: ${}", current_market_cap as f64 / 1_000_000.0);
+    msg!("DEBUG: Target Market Cap: ${}", market.target_market_cap as f64 / 1_000_000.0);
+    msg!("DEBUG: Target Reached: {}", target_reached);
+    msg!("DEBUG: Deadline Passed: {}", deadline_passed);
+    msg!("DEBUG: Outcome: {:?}", resolution);
+    msg!("DEBUG: Total YES pool: {} lamports", market.total_yes_pool);
+    msg!("DEBUG: Total NO pool: {} lamports", market.total_no_pool);
+    msg!("DEBUG: Claim deadline: {} (0 = none)", market.claim_deadline);
+    
+    emit!(MarketResolved {
+        market: market.key(),
+        winner,
+        target_reached,
+        last_market_cap: current_market_cap,
+        target_market_cap: market.target_market_cap,
+        total_yes_pool: market.total_yes_pool,
+        total_no_pool: market.total_no_pool,
+        resolved_at: current_time,
+        claim_deadline: market.claim_deadline,
+        is_void: is_push,
+    });
+    
+    let paid_pool = if is_push {
+        market.total_yes_pool.saturating_add(market.total_no_pool)
+    } else if winner {
+        market.total_yes_pool
+    } else {
+        market.total_no_pool
+    };
+    
+    emit!(MarketLifecycle {
+        market: market.key(),
+        phase: if is_push { MarketPhase::Voided } else { MarketPhase::Resolved },
+        actor,
+        amount: paid_pool,
+        total_yes_pool: market.total_yes_pool,
+        total_no_pool: market.total_no_pool,
+        timestamp: current_time,
+    });
+    
+    Ok(())
+}
+
+/// Check a creation fee can be held by the fee escrow
+/// The fee escrow is a plain system account, so a nonzero fee below its rent-exempt
+/// minimum could never be deposited and every market creation would fail
+/// Debug: Shared by initialize_config and set_creation_fee
+fn validate_creation_fee(creation_fee_lamports: u64) -> Result<()> {
+    require!(
+        creation_fee_lamports == 0 || creation_fee_lamports >= Rent::get()?.minimum_balance(0),
+        ParimutuelError::InvalidCreationFee
+    );
+    Ok(())
+}
+
+/// Create the program config with the initial fee and treasury
+/// Debug: Can only run once since the config PDA has a fixed seed
+pub fn initialize_config(
+    ctx: Context<InitializeConfig>,
+    treasury: Pubkey,
+    creation_fee_lamports: u64,
+) -> Result<()> {
+    validate_creation_fee(creation_fee_lamports)?;
+    
+    let config = &mut ctx.accounts.config;
+    
+    config.authority = ctx.accounts.authority.key();
+    config.treasury = treasury;
+    config.creation_fee_lamports = creation_fee_lamports;
+    config.min_market_duration_secs = DEFAULT_MIN_MARKET_DURATION_SECS;
+    config.max_market_duration_secs = 0;
+    config.paused = false;
+    config.market_count = 0;
+    config.block_bets_after_target = false;
+    config.bump = ctx.bumps.config;
+    
+    msg!("DEBUG: Config initialized");
+    msg!("DEBUG: Authority: {}", config.authority);
+    msg!("DEBUG: Treasury: {}", treasury);
+    msg!("DEBUG: Creation fee: {} lamports", creation_fee_lamports);
+    msg!("DEBUG: Min market duration: {}s", config.min_market_duration_secs);
+    
+    Ok(())
+}
+
+/// Update the allowed distance between market creation and deadline (config authority only)
+/// Debug: max_market_duration_secs = 0 removes the upper bound
+pub fn set_market_duration_bounds(
+    ctx: Context<UpdateConfig>,
+    min_market_duration_secs: i64,
+    max_market_duration_secs: i64,
+) -> Result<()> {
+    require!(
+        min_market_duration_secs >= 0 && max_market_duration_secs >= 0,
+        ParimutuelError::InvalidDurationBounds
+    );
+    require!(
+        max_market_duration_secs == 0 || max_market_duration_secs >= min_market_duration_secs,
+        ParimutuelError::InvalidDurationBounds
+    );
+    
+    let config = &mut ctx.accounts.config;
+    config.min_market_duration_secs = min_market_duration_secs;
+    config.max_market_duration_secs = max_market_duration_secs;
+    
+    msg!("DEBUG: Market duration bounds set to [{}s, {}s]",
+        min_market_duration_secs, max_market_duration_secs);
+    
+    Ok(())
+}
+
+/// Update the market creation fee (config authority only)
+/// Debug: Applies to markets created after this call; 0 or at least the escrow rent minimum
+pub fn set_creation_fee(
+    ctx: Context<UpdateConfig>,
+    creation_fee_lamports: u64,
+) -> Result<()> {
+    validate_creation_fee(creation_fee_lamports)?;
+    
+    let config = &mut ctx.accounts.config;
+    
+    let old_fee = config.creation_fee_lamports;
+    config.creation_fee_lamports = creation_fee_lamports;
+    
+    msg!("DEBUG: Creation fee updated from {} to {} lamports", old_fee, creation_fee_lamports);
+    
+    Ok(())
+}
+
+/// Pause or unpause market creation and betting across every market (config authority only)
+/// Debug: Claims, refunds and closes are never gated, so users can always exit
+pub fn set_paused(
+    ctx: Context<UpdateConfig>,
+    paused: bool,
+) -> Result<()> {
+    ctx.accounts.config.paused = paused;
+    
+    msg!("DEBUG: Protocol {}", if paused { "paused" } else { "unpaused" });
+    
+    Ok(())
+}
+
+/// Toggle anti-sniping across every market (config authority only)
+/// When on, bets are rejected once a heartbeat has recorded last_market_cap >= target:
+/// the outcome is effectively decided and only the resolve crank is outstanding
+pub fn set_block_bets_after_target(
+    ctx: Context<UpdateConfig>,
+    enabled: bool,
+) -> Result<()> {
+    ctx.accounts.config.block_bets_after_target = enabled;
+    
+    msg!("DEBUG: Bets after target hit {}", if enabled { "blocked" } else { "allowed" });
+    
+    Ok(())
+}
+
+/// Initialize a new parimutuel market with oracle-based resolution (permissionless)
+/// Betting closes `grace_secs` before the deadline so no bet can race resolution
+/// Winners must claim within `claim_window_secs` of resolution (0 = no deadline)
+/// `resolution_policy` decides whether a target hit reported at the deadline counts
+/// Debug: Creation fee is held in escrow - refundable if the market never gets a bet
+#[allow(clippy::too_many_arguments)]
+pub fn initialize_market(
+    ctx: Context<InitializeMarket>,
+    _market_seed: String,
+    oracle_authority: Pubkey,
+    token_mint: Pubkey,
+    target_market_cap: u64,
+    deadline: i64,
+    max_oracle_skew_secs: i64,
+    grace_secs: i64,
+    claim_window_secs: i64,
+    title: String,
+    metadata_uri: String,
+    resolution_policy: ResolutionPolicy,
+) -> Result<()> {
+    let market = &mut ctx.accounts.market;
+    let current_time = Clock::get()?.unix_timestamp;
+    
+    require!(deadline > current_time, ParimutuelError::InvalidDeadline);
+    
+    let config = &ctx.accounts.config;
+    require!(!config.paused, ParimutuelError::ProtocolPaused);
+    let duration = deadline - current_time;
+    require!(
+        duration >= config.min_market_duration_secs,
+        ParimutuelError::DeadlineTooSoon
+    );
+    require!(
+        config.max_market_duration_secs == 0 || duration <= config.max_market_duration_secs,
+        ParimutuelError::DeadlineTooFar
+    );
+    require!(grace_secs >= 0, ParimutuelError::InvalidGracePeriod);
+    let betting_closes_at = deadline
+        .checked_sub(grace_secs)
+        .ok_or(ParimutuelError::Overflow)?;
+    require!(betting_closes_at > current_time, ParimutuelError::InvalidGracePeriod);
+    require!(target_market_cap > 0, ParimutuelError::InvalidAmount);
+    require!(claim_window_secs >= 0, ParimutuelError::InvalidClaimWindow);
+    require!(title.len() <= MAX_TITLE_LEN, ParimutuelError::TitleTooLong);
+    require!(
+        metadata_uri.len() <= MAX_METADATA_URI_LEN,
+        ParimutuelError::MetadataUriTooLong
+    );
+    
+    let creation_fee = ctx.accounts.config.creation_fee_lamports;
+    let creator_balance = ctx.accounts.creator.lamports();
+    let rent = Rent::get()?;
+    let rent_exempt_balance = rent.minimum_balance(Market::LEN);
+    let escrow_rent_exempt_balance = rent.minimum_balance(0);
+    let total_required = creation_fee
+        .checked_add(rent_exempt_balance)
+        .ok_or(ParimutuelError::Overflow)?
+        .checked_add(escrow_rent_exempt_balance)
+        .ok_or(ParimutuelError::Overflow)?;
+    
+    require!(
+        creator_balance >= total_required,
+        ParimutuelError::InsufficientFunds
+    );
+    
+    msg!("DEBUG: Transferring {} lamports creation fee to fee escrow", creation_fee);
+    
+    let cpi_context = CpiContext::new(
+        ctx.accounts.system_program.to_account_info(),
+        Transfer {
+            from: ctx.accounts.creator.to_account_info(),
+            to: ctx.accounts.fee_escrow.to_account_info(),
+        },
+    );
+    transfer(cpi_context, creation_fee)?;
+    
+    // Debug: Seed escrow with its rent-exempt reserve so claims can never purge it
+    let cpi_context = CpiContext::new(
+        ctx.accounts.system_program.to_account_info(),
+        Transfer {
+            from: ctx.accounts.creator.to_account_info(),
+            to: ctx.accounts.escrow.to_account_info(),
+        },
+    );
+    transfer(cpi_context, escrow_rent_exempt_balance)?;
+    
+    market.creator = ctx.accounts.creator.key();
+    market.oracle_authority = oracle_authority;
+    market.token_mint = token_mint;
+    market.total_yes_pool = 0;
+    market.total_no_pool = 0;
+    market.target_market_cap = target_market_cap;
+    market.deadline = deadline;
+    market.is_resolved = false;
+    market.winner = None;
+    market.target_reached = false;
+    market.resolved_at = 0;
+    market.last_market_cap = 0;
+    market.last_oracle_update = 0;
+    market.creation_fee_held = creation_fee;
+    market.total_claimed_lamports = 0;
+    market.total_claimed_stake = 0;
+    market.max_oracle_skew_secs = if max_oracle_skew_secs > 0 {
+        max_oracle_skew_secs
+    } else {
+        DEFAULT_MAX_ORACLE_SKEW_SECS
+    };
+    market.betting_closes_at = betting_closes_at;
+    market.unclaimed_swept_at = 0;
+    market.emergency_proposed_at = 0;
+    market.emergency_recovery = Pubkey::default();
+    market.switchboard_feed = Pubkey::default();
+    market.resolution_source = ResolutionSource::Unresolved;
+    market.claim_window_secs = claim_window_secs;
+    market.claim_deadline = 0;
+    market.is_void = false;
+    market.title = title;
+    market.metadata_uri = metadata_uri;
+    market.escrow_bump = ctx.bumps.escrow;
+    market.created_at = current_time;
+    market.last_bet_at = 0;
+    market.resolution_policy = resolution_policy;
+    market.market_index = ctx.accounts.config.market_count;
+    market.yes_bet_count = 0;
+    market.no_bet_count = 0;
+    market.bump = ctx.bumps.market;
+    
+    let registry_entry = &mut ctx.accounts.registry_entry;
+    registry_entry.market = market.key();
+    registry_entry.index = market.market_index;
+    registry_entry.bump = ctx.bumps.registry_entry;
+    
+    ctx.accounts.config.market_count = ctx.accounts.config.market_count
+        .checked_add(1)
+        .ok_or(ParimutuelError::Overflow)?;
+    
+    msg!("DEBUG: Parimutuel market initialized (permissionless)");
+    msg!("DEBUG: Title: {}", market.title);
+    msg!("DEBUG: Metadata URI: {}", market.metadata_uri);
+    msg!("DEBUG: Creator: {}", market.creator);
+    msg!("DEBUG: Creation fee paid: {} lamports", creation_fee);
+    msg!("DEBUG: Fee escrow: {}", ctx.accounts.fee_escrow.key());
+    msg!("DEBUG: Oracle: {}", oracle_authority);
+    msg!("DEBUG: Token: {}", token_mint);
+    msg!("DEBUG: Target Market Cap: ${}", target_market_cap as f64 / 1_000_000.0);
+    msg!("DEBUG: Deadline: {}", deadline);
+    msg!("DEBUG: Betting closes at: {}", betting_closes_at);
+    msg!("DEBUG: Max oracle skew: {}s", market.max_oracle_skew_secs);
+    msg!("DEBUG: Claim window: {}s (0 = no deadline)", claim_window_secs);
+    msg!("DEBUG: Resolution policy: {:?}", resolution_policy);
+    msg!("DEBUG: Registry index: {}", market.market_index);
+    
+    emit!(MarketRegistered {
+        market: market.key(),
+        index: market.market_index,
+        creator: market.creator,
+        token_mint,
+        deadline,
+    });
+    
+    emit!(MarketLifecycle {
+        market: market.key(),
+        phase: MarketPhase::Created,
+        actor: market.creator,
+        amount: creation_fee,
+        total_yes_pool: market.total_yes_pool,
+        total_no_pool: market.total_no_pool,
+        timestamp: current_time,
+    });
+    
+    Ok(())
+}
+
+/// Place a bet on YES or NO side
+/// `bet_index` picks a fresh UserBet slot; reusing a live index fails at account init
+/// Debug: No fixed limit - pools grow indefinitely as users bet
+pub fn place_bet(
+    ctx: Context<PlaceBet>,
+    _market_seed: String,
+    bet_index: u64,
+    amount: u64,
+    side: bool,
+) -> Result<()> {
+    let current_time = Clock::get()?.unix_timestamp;
+    
+    validate_betting_open(&ctx.accounts.config, &ctx.accounts.market, current_time)?;
+    
+    record_bet(
+        &mut ctx.accounts.market,
+        &mut ctx.accounts.user_bet,
+        &ctx.accounts.escrow,
+        &ctx.accounts.user,
+        &ctx.accounts.system_program,
+        bet_index,
+        amount,
+        side,
+        current_time,
+    )
+}
+
+/// Claim a resolved market's payout and re-bet it into another market atomically
+/// `amount` = None reinvests the full payout; Some(x) reinvests x and keeps the rest
+/// The claimed bet is `source_bet_index`; the new bet lands at `target_bet_index`
+/// Debug: The target market is validated before anything is claimed, so a closed
+/// target fails cleanly and leaves the source bet untouched
+#[allow(clippy::too_many_arguments)]
+pub fn claim_and_bet(
+    ctx: Context<ClaimAndBet>,
+    _source_market_seed: String,
+    _target_market_seed: String,
+    _source_bet_index: u64,
+    target_bet_index: u64,
+    amount: Option<u64>,
+    side: bool,
+) -> Result<()> {
+    let current_time = Clock::get()?.unix_timestamp;
+    
+    require!(
+        ctx.accounts.source_market.key() != ctx.accounts.target_market.key(),
+        ParimutuelError::InvalidMarket
+    );
+    validate_betting_open(&ctx.accounts.config, &ctx.accounts.target_market, current_time)?;
+    
+    let reward_lamports = settle_claim(
+        &mut ctx.accounts.source_market,
+        &mut ctx.accounts.source_user_bet,
+        &ctx.accounts.source_escrow,
+        &ctx.accounts.user,
+        &ctx.accounts.system_program,
+    )?;
+    
+    let stake = amount.unwrap_or(reward_lamports);
+    require!(stake <= reward_lamports, ParimutuelError::ReinvestExceedsReward);
+    
+    msg!("DEBUG: Reinvesting {} of {} lamports claimed", stake, reward_lamports);
+    
+    record_bet(
+        &mut ctx.accounts.target_market,
+        &mut ctx.accounts.target_user_bet,
+        &ctx.accounts.target_escrow,
+        &ctx.accounts.user,
+        &ctx.accounts.system_program,
+        target_bet_index,
+        stake,
+        side,
+        current_time,
+    )
+}
+
+/// Check that a market is still accepting bets
+/// Anti-sniping: with `block_bets_after_target` on, a recorded cap at or above the
+/// target closes betting before resolve_market is cranked
+/// Debug: Shared by place_bet and claim_and_bet so both reject the same states
+fn validate_betting_open(config: &Config, market: &Market, current_time: i64) -> Result<()> {
+    require!(!config.paused, ParimutuelError::ProtocolPaused);
+    require!(!market.is_resolved, ParimutuelError::MarketResolved);
+    
+    require!(current_time < market.betting_closes_at, ParimutuelError::DeadlinePassed);
+    
+    require!(
+        !config.block_bets_after_target || market.last_market_cap < market.target_market_cap,
+        ParimutuelError::TargetAlreadyReached
+    );
+    
+    Ok(())
+}
+
+/// Move a stake into the market escrow and record the bettor's position
+/// Debug: Callers must have run validate_betting_open first
+#[allow(clippy::too_many_arguments)]
+fn record_bet<'info>(
+    market: &mut Account<'info, Market>,
+    user_bet: &mut Account<'info, UserBet>,
+    escrow: &AccountInfo<'info>,
+    user: &Signer<'info>,
+    system_program: &Program<'info, System>,
+    bet_index: u64,
+    amount: u64,
+    side: bool,
+    current_time: i64,
+) -> Result<()> {
+    require!(amount > 0, ParimutuelError::InvalidAmount);
+    
+    // Debug: Transfer SOL from user to escrow PDA
+    msg!("DEBUG: Transferring {} lamports from user to escrow", amount);
+    
+    let cpi_context = CpiContext::new(
+        system_program.to_account_info(),
+        Transfer {
+            from: user.to_account_info(),
+            to: escrow.to_account_info(),
+        },
+    );
+    transfer(cpi_context, amount)?;
+    
+    // Update pool totals based on side
+    if side {
+        market.total_yes_pool = market.total_yes_pool
+            .checked_add(amount)
+            .ok_or(ParimutuelError::Overflow)?;
+        market.yes_bet_count = market.yes_bet_count
+            .checked_add(1)
+            .ok_or(ParimutuelError::Overflow)?;
+        msg!("DEBUG: YES pool updated to {} lamports", market.total_yes_pool);
+    } else {
+        market.total_no_pool = market.total_no_pool
+            .checked_add(amount)
+            .ok_or(ParimutuelError::Overflow)?;
+        market.no_bet_count = market.no_bet_count
+            .checked_add(1)
+            .ok_or(ParimutuelError::Overflow)?;
+        msg!("DEBUG: NO pool updated to {} lamports", market.total_no_pool);
+    }
+    market.last_bet_at = current_time;
+    
+    // Initialize user bet record
+    user_bet.user = user.key();
+    user_bet.market = market.key();
+    user_bet.amount = amount;
+    user_bet.side = side;
+    user_bet.claimed = false;
+    user_bet.bet_index = bet_index;
+    user_bet.market_index = market.market_index;
+    
+    msg!("DEBUG: User {} placed {} lamports on {}", 
+        user.key(), 
+        amount, 
+        if side { "YES" } else { "NO" }
+    );
+    
+    emit!(MarketLifecycle {
+        market: market.key(),
+        phase: MarketPhase::BetPlaced,
+        actor: user.key(),
+        amount,
+        total_yes_pool: market.total_yes_pool,
+        total_no_pool: market.total_no_pool,
+        timestamp: current_time,
+    });
+    
+    Ok(())
+}
+
+/// Resolve the market with oracle-provided market cap data
+/// `resolution` = None derives YES/NO from target/deadline; Some(Push) refunds every
+/// bettor's principal, Some(Yes/No) must agree with the derived outcome
+/// Debug: Oracle (crank) provides current market cap and verifies against target/deadline
+pub fn resolve_market(
+    ctx: Context<ResolveMarket>,
+    _market_seed: String,
+    current_market_cap: u64,
+    timestamp: i64,
+    resolution: Option<Resolution>,
+) -> Result<()> {
+    let market = &mut ctx.accounts.market;
+    let current_time = Clock::get()?.unix_timestamp;
+    
+    require!(
+        ctx.accounts.oracle.key() == market.oracle_authority,
+        ParimutuelError::Unauthorized
+    );
+    
+    require!(!market.is_resolved, ParimutuelError::MarketAlreadyResolved);
+    
+    validate_oracle_timestamp(market, timestamp, current_time)?;
+    
+    apply_market_cap_resolution(
+        market,
+        current_market_cap,
+        timestamp,
+        current_time,
+        ResolutionSource::Oracle,
+        ctx.accounts.oracle.key(),
+        resolution,
+    )
+}
+
+/// Point a market at the Switchboard aggregator it may be resolved from (oracle only)
+/// Debug: Pass Pubkey::default() to disable Switchboard resolution again
+pub fn set_switchboard_feed(
+    ctx: Context<SetSwitchboardFeed>,
+    _market_seed: String,
+    feed: Pubkey,
+) -> Result<()> {
+    let market = &mut ctx.accounts.market;
+    
+    require!(
+        ctx.accounts.oracle.key() == market.oracle_authority,
+        ParimutuelError::Unauthorized
+    );
+    require!(!market.is_resolved, ParimutuelError::MarketAlreadyResolved);
+    
+    market.switchboard_feed = feed;
+    
+    msg!("DEBUG: Switchboard feed set to {}", feed);
+    
+    Ok(())
+}
+
+/// Replace the oracle allowed to resolve the market (creator or config authority)
+/// Debug: Lets a compromised or migrated oracle key be swapped out before resolution
+pub fn set_oracle_authority(
+    ctx: Context<SetOracleAuthority>,
+    _market_seed: String,
+    new_oracle: Pubkey,
+) -> Result<()> {
+    let market = &mut ctx.accounts.market;
+    let authority = ctx.accounts.authority.key();
+    
+    require!(
+        authority == market.creator || authority == ctx.accounts.config.authority,
+        ParimutuelError::Unauthorized
+    );
+    require!(!market.is_resolved, ParimutuelError::MarketAlreadyResolved);
+    require!(new_oracle != Pubkey::default(), ParimutuelError::InvalidOracle);
+    
+    let old_oracle = market.oracle_authority;
+    market.oracle_authority = new_oracle;
+    
+    emit!(OracleAuthorityChanged {
+        market: market.key(),
+        old_oracle,
+        new_oracle,
+        changed_by: authority,
+        changed_at: Clock::get()?.unix_timestamp,
+    });
+    
+    msg!("DEBUG: Oracle authority changed from {} to {}", old_oracle, new_oracle);
+    
+    Ok(())
+}
+
+/// Resolve the market from its Switchboard aggregator (anyone)
+/// The latest confirmed round must have at least `min_oracle_results` responses and
+/// be opened within the market's oracle skew; its result (USD) is scaled to 6 decimals
+/// Debug: Same target/deadline rule as `resolve_market`
+pub fn resolve_market_from_switchboard(
+    ctx: Context<ResolveFromSwitchboard>,
+    _market_seed: String,
+) -> Result<()> {
+    let market = &mut ctx.accounts.market;
+    let current_time = Clock::get()?.unix_timestamp;
+    
+    require!(!market.is_resolved, ParimutuelError::MarketAlreadyResolved);
+    require!(
+        market.switchboard_feed != Pubkey::default(),
+        ParimutuelError::InvalidSwitchboardFeed
+    );
+    
+    let round = read_switchboard_round(&ctx.accounts.feed)?;
+    require!(
+        round.num_success > 0 && round.num_success >= round.min_oracle_results,
+        ParimutuelError::InsufficientOracleResults
+    );
+    validate_oracle_timestamp(market, round.round_open_timestamp, current_time)?;
+    
+    let current_market_cap = switchboard_to_market_cap(round.mantissa, round.scale)?;
+    
+    msg!("DEBUG: Switchboard round: {} results (min {}), opened at {}",
+        round.num_success, round.min_oracle_results, round.round_open_timestamp);
+    
+    apply_market_cap_resolution(
+        market,
+        current_market_cap,
+        round.round_open_timestamp,
+        current_time,
+        ResolutionSource::Switchboard,
+        ctx.accounts.caller.key(),
+        None,
+    )
+}
+
+/// Resolve a market NO after the deadline + grace period without the oracle (anyone)
+/// Refused once a heartbeat has recorded a cap at or above the target - that market
+/// needs a real resolution, not a default NO
+/// Debug: The target was not reported reached by the deadline, so NO wins objectively
+pub fn force_resolve_deadline(
+    ctx: Context<ForceResolveDeadline>,
+    _market_seed: String,
+) -> Result<()> {
+    let market = &mut ctx.accounts.market;
+    let current_time = Clock::get()?.unix_timestamp;
+    
+    require!(!market.is_resolved, ParimutuelError::MarketAlreadyResolved);
+    
+    let force_resolve_at = market.deadline.saturating_add(FORCE_RESOLVE_GRACE_SECS);
+    require!(
+        current_time >= force_resolve_at,
+        ParimutuelError::ForceResolveTooEarly
+    );
+    
+    require!(
+        market.last_market_cap < market.target_market_cap,
+        ParimutuelError::ResolutionMismatch
+    );
+    
+    market.is_resolved = true;
+    market.winner = Some(false);
+    market.target_reached = false;
+    market.resolved_at = current_time;
+    market.resolution_source = ResolutionSource::ForcedDeadline;
+    open_claim_window(market, current_time);
+    
+    msg!("DEBUG: Market force-resolved NO after oracle missed the deadline");
+    msg!("DEBUG: Deadline: {}, Grace ends: {}", market.deadline, force_resolve_at);
+    msg!("DEBUG: Resolved by: {:?}", ctx.accounts.caller.key());
+    msg!("DEBUG: Total YES pool: {} lamports", market.total_yes_pool);
+    msg!("DEBUG: Total NO pool: {} lamports", market.total_no_pool);
+    
+    emit!(MarketResolved {
+        market: market.key(),
+        winner: false,
+        target_reached: false,
+        last_market_cap: market.last_market_cap,
+        target_market_cap: market.target_market_cap,
+        total_yes_pool: market.total_yes_pool,
+        total_no_pool: market.total_no_pool,
+        resolved_at: current_time,
+        claim_deadline: market.claim_deadline,
+        is_void: false,
+    });
+    
+    emit!(MarketLifecycle {
+        market: market.key(),
+        phase: MarketPhase::Resolved,
+        actor: ctx.accounts.caller.key(),
+        amount: market.total_no_pool,
+        total_yes_pool: market.total_yes_pool,
+        total_no_pool: market.total_no_pool,
+        timestamp: current_time,
+    });
+    
+    Ok(())
+}
+
+/// Record the latest observed market cap without resolving (oracle only)
+/// Debug: Lets the UI show live progress toward target and oracle liveness
+pub fn update_oracle_heartbeat(
+    ctx: Context<UpdateOracleHeartbeat>,
+    _market_seed: String,
+    current_market_cap: u64,
+    timestamp: i64,
+) -> Result<()> {
+    let market = &mut ctx.accounts.market;
+    let current_time = Clock::get()?.unix_timestamp;
+    
+    require!(
+        ctx.accounts.oracle.key() == market.oracle_authority,
+        ParimutuelError::Unauthorized
+    );
+    
+    require!(!market.is_resolved, ParimutuelError::MarketAlreadyResolved);
+    
+    validate_oracle_timestamp(market, timestamp, current_time)?;
+    
+    require!(
+        timestamp >= market.last_oracle_update,
+        ParimutuelError::StaleData
+    );
+    
+    market.last_market_cap = current_market_cap;
+    market.last_oracle_update = timestamp;
+    
+    msg!("DEBUG: Oracle heartbeat recorded");
+    msg!("DEBUG: Current Market Cap: ${}", current_market_cap as f64 / 1_000_000.0);
+    msg!("DEBUG: Target Market Cap: ${}", market.target_market_cap as f64 / 1_000_000.0);
+    msg!("DEBUG: Oracle timestamp: {}", timestamp);
+    
+    Ok(())
+}
+
+/// Refund the held creation fee to the creator if no bets were ever placed
+/// Debug: Requires the deadline to have passed with both pools empty
+pub fn refund_creation_fee(
+    ctx: Context<RefundCreationFee>,
+    _market_seed: String,
+) -> Result<()> {
+    let market = &mut ctx.accounts.market;
+    let current_time = Clock::get()?.unix_timestamp;
+    
+    require!(current_time >= market.deadline, ParimutuelError::CannotResolveYet);
+    
+    let total_pool = market.total_yes_pool
+        .checked_add(market.total_no_pool)
+        .ok_or(ParimutuelError::Overflow)?;
+    require!(total_pool == 0, ParimutuelError::MarketHasBets);
+    
+    let fee = market.creation_fee_held;
+    require!(fee > 0, ParimutuelError::NoCreationFeeHeld);
+    
+    msg!("DEBUG: Refunding {} lamports creation fee to creator {}", fee, market.creator);
+    
+    let market_key = market.key();
+    let fee_escrow_seeds = &[
+        b"fee_escrow",
+        market_key.as_ref(),
+        &[ctx.bumps.fee_escrow],
+    ];
+    let signer_seeds = &[&fee_escrow_seeds[..]];
+    
+    let cpi_context = CpiContext::new_with_signer(
+        ctx.accounts.system_program.to_account_info(),
+        Transfer {
+            from: ctx.accounts.fee_escrow.to_account_info(),
+            to: ctx.accounts.creator.to_account_info(),
+        },
+        signer_seeds,
+    );
+    transfer(cpi_context, fee)?;
+    
+    market.creation_fee_held = 0;
+    
+    emit!(MarketLifecycle {
+        market: market.key(),
+        phase: MarketPhase::Voided,
+        actor: market.creator,
+        amount: fee,
+        total_yes_pool: market.total_yes_pool,
+        total_no_pool: market.total_no_pool,
+        timestamp: current_time,
+    });
+    
+    Ok(())
+}
+
+/// Release the held creation fee to the treasury once the market has a bet
+/// Debug: Permissionless - anyone can crank it once activity exists
+pub fn collect_creation_fee(
+    ctx: Context<CollectCreationFee>,
+    _market_seed: String,
+) -> Result<()> {
+    let market = &mut ctx.accounts.market;
+    
+    let total_pool = market.total_yes_pool
+        .checked_add(market.total_no_pool)
+        .ok_or(ParimutuelError::Overflow)?;
+    require!(total_pool > 0, ParimutuelError::NoBetsPlaced);
+    
+    let fee = market.creation_fee_held;
+    require!(fee > 0, ParimutuelError::NoCreationFeeHeld);
+    
+    msg!("DEBUG: Collecting {} lamports creation fee to treasury {}", fee, ctx.accounts.treasury.key());
+    
+    let market_key = market.key();
+    let fee_escrow_seeds = &[
+        b"fee_escrow",
+        market_key.as_ref(),
+        &[ctx.bumps.fee_escrow],
+    ];
+    let signer_seeds = &[&fee_escrow_seeds[..]];
+    
+    let cpi_context = CpiContext::new_with_signer(
+        ctx.accounts.system_program.to_account_info(),
+        Transfer {
+            from: ctx.accounts.fee_escrow.to_account_info(),
+            to: ctx.accounts.treasury.to_account_info(),
+        },
+        signer_seeds,
+    );
+    transfer(cpi_context, fee)?;
+    
+    market.creation_fee_held = 0;
+    
+    Ok(())
+}
+
+/// Claim proportional reward after market resolution
+/// Debug: Uses u128 for calculations to prevent overflow with large amounts
+/// The UserBet account is closed on success and its rent returned to the bettor
+pub fn claim_reward(
+    ctx: Context<ClaimReward>,
+    _market_seed: String,
+    _bet_index: u64,
+) -> Result<()> {
+    settle_claim(
+        &mut ctx.accounts.market,
+        &mut ctx.accounts.user_bet,
+        &ctx.accounts.escrow,
+        &ctx.accounts.user,
+        &ctx.accounts.system_program,
+    )?;
+    
+    Ok(())
+}
+
+/// Pay out a resolved bet from the market escrow and mark it claimed
+/// The payout is clamped to what the escrow holds above rent when the shortfall is
+/// rounding dust (see clamp_to_escrow), so the final claimer is never locked out
+/// Debug: Returns the lamports paid so claim_and_bet can reinvest them
+fn settle_claim<'info>(
+    market: &mut Account<'info, Market>,
+    user_bet: &mut Account<'info, UserBet>,
+    escrow: &AccountInfo<'info>,
+    user: &Signer<'info>,
+    system_program: &Program<'info, System>,
+) -> Result<u64> {
+    // Validation: Market must be resolved
+    require!(market.is_resolved, ParimutuelError::MarketNotResolved);
+    
+    // Validation: User must not have already claimed
+    require!(!user_bet.claimed, ParimutuelError::AlreadyClaimed);
+    
+    // Validation: Rewards are forfeited once the creator has swept the escrow
+    require!(market.unclaimed_swept_at == 0, ParimutuelError::RewardsForfeited);
+    
+    // Validation: Claim window must still be open
+    require!(
+        market.claim_deadline == 0 || Clock::get()?.unix_timestamp < market.claim_deadline,
+        ParimutuelError::ClaimWindowClosed
+    );
+    
+    // Push: every bettor gets their principal back, regardless of side
+    let computed_reward = if market.is_void {
+        msg!("DEBUG: Market pushed - refunding principal of {} lamports", user_bet.amount);
+        user_bet.amount
+    } else {
+        winning_reward_lamports(market, user_bet)?
+    };
+    
+    // Pay at most what the escrow holds above rent exemption
+    let rent_exempt_min = Rent::get()?.minimum_balance(0);
+    let escrow_available = escrow.lamports().saturating_sub(rent_exempt_min);
+    let reward_lamports = clamp_to_escrow(market, computed_reward, escrow_available)?;
+    
+    // Mark as claimed before the transfer (checks-effects-interactions)
+    // Debug: The only CPI is to the system program, which cannot call back, and Solana
+    // rejects indirect reentrancy into this program - this ordering is defensive hardening
+    // so any future reentrant path would already see the bet as claimed
+    user_bet.claimed = true;
+    market.total_claimed_lamports = market.total_claimed_lamports
+        .checked_add(reward_lamports)
+        .ok_or(ParimutuelError::Overflow)?;
+    market.total_claimed_stake = market.total_claimed_stake
+        .checked_add(user_bet.amount)
+        .ok_or(ParimutuelError::Overflow)?;
+    
+    // Transfer reward from escrow to user
+    let market_key = market.key();
+    let escrow_seeds = &[
+        b"escrow",
+        market_key.as_ref(),
+        &[market.escrow_bump],
+    ];
+    let signer_seeds = &[&escrow_seeds[..]];
+    
+    let cpi_context = CpiContext::new_with_signer(
+        system_program.to_account_info(),
+        Transfer {
+            from: escrow.to_account_info(),
+            to: user.to_account_info(),
+        },
+        signer_seeds,
+    );
+    transfer(cpi_context, reward_lamports)?;
+    
+    msg!("DEBUG: Reward of {} lamports claimed by user {}", 
+        reward_lamports, 
+        user.key()
+    );
+    
+    emit!(MarketLifecycle {
+        market: market.key(),
+        phase: MarketPhase::Claimed,
+        actor: user.key(),
+        amount: reward_lamports,
+        total_yes_pool: market.total_yes_pool,
+        total_no_pool: market.total_no_pool,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+    
+    Ok(reward_lamports)
+}
+
+/// Close a losing UserBet once the market is resolved, returning its rent to the bettor
+/// Debug: Pushed markets are excluded - every bet there is refundable via claim_reward
+pub fn close_losing_bet(
+    ctx: Context<CloseLosingBet>,
+    _market_seed: String,
+    _bet_index: u64,
+) -> Result<()> {
+    let market = &ctx.accounts.market;
+    let user_bet = &ctx.accounts.user_bet;
+    
+    require!(market.is_resolved, ParimutuelError::MarketNotResolved);
+    require!(!market.is_void, ParimutuelError::BetNotLost);
+    
+    let winner = market.winner.ok_or(ParimutuelError::NoWinner)?;
+    require!(user_bet.side != winner, ParimutuelError::BetNotLost);
+    
+    msg!("DEBUG: Closing losing bet of {} lamports for user {}",
+        user_bet.amount,
+        ctx.accounts.user.key()
+    );
+    
+    Ok(())
+}
+
+/// Report whether a market is ready to resolve, for keepers polling many markets
+/// `seconds_to_deadline` is deadline - now, negative once the deadline has passed
+/// Debug: Returns (deadline_passed, seconds_to_deadline, is_resolved) via return data
+pub fn is_resolvable(
+    ctx: Context<IsResolvable>,
+    _market_seed: String,
+) -> Result<()> {
+    let market = &ctx.accounts.market;
+    let current_time = Clock::get()?.unix_timestamp;
+    
+    let deadline_passed = current_time >= market.deadline;
+    let seconds_to_deadline = market.deadline.saturating_sub(current_time);
+    
+    msg!("DEBUG: Deadline passed: {}, seconds to deadline: {}, resolved: {}",
+        deadline_passed, seconds_to_deadline, market.is_resolved);
+    
+    set_return_data(&(deadline_passed, seconds_to_deadline, market.is_resolved).try_to_vec()?);
+    
+    Ok(())
+}
+
+/// Propose moving a market's escrow to a recovery address (config authority only)
+/// Two-step recovery path for stranded funds: the proposal is public on-chain and
+/// `emergency_withdraw` only succeeds EMERGENCY_TIMELOCK_SECS later, giving users time
+/// to react if the authority key is compromised
+/// Debug: Re-proposing replaces the recovery address and restarts the timelock
+pub fn propose_emergency_withdraw(
+    ctx: Context<ProposeEmergencyWithdraw>,
+    _market_seed: String,
+    recovery: Pubkey,
+) -> Result<()> {
+    let market = &mut ctx.accounts.market;
+    let current_time = Clock::get()?.unix_timestamp;
+    
+    require!(recovery != Pubkey::default(), ParimutuelError::InvalidRecoveryAddress);
+    
+    market.emergency_proposed_at = current_time;
+    market.emergency_recovery = recovery;
+    
+    let executable_at = current_time.saturating_add(EMERGENCY_TIMELOCK_SECS);
+    
+    msg!("DEBUG: Emergency withdrawal proposed for market {}", market.key());
+    msg!("DEBUG: Recovery: {}, Executable at: {}", recovery, executable_at);
+    
+    emit!(EmergencyWithdrawProposed {
+        market: market.key(),
+        recovery,
+        proposed_at: current_time,
+        executable_at,
+    });
+    
+    Ok(())
+}
+
+/// Move everything above the escrow's rent reserve to the proposed recovery address
+/// Debug: Config authority only, and only once the proposal's timelock has elapsed
+pub fn emergency_withdraw(
+    ctx: Context<EmergencyWithdraw>,
+    _market_seed: String,
+) -> Result<()> {
+    let market = &mut ctx.accounts.market;
+    let current_time = Clock::get()?.unix_timestamp;
+    
+    require!(market.emergency_proposed_at != 0, ParimutuelError::NoEmergencyProposal);
+    require!(
+        current_time >= market.emergency_proposed_at.saturating_add(EMERGENCY_TIMELOCK_SECS),
+        ParimutuelError::TimelockNotElapsed
+    );
+    
+    let rent_exempt_min = Rent::get()?.minimum_balance(0);
+    let amount = ctx.accounts.escrow.lamports().saturating_sub(rent_exempt_min);
+    
+    msg!("DEBUG: Emergency withdrawal of {} lamports to {}", amount, market.emergency_recovery);
+    
+    if amount > 0 {
+        let market_key = market.key();
+        let escrow_seeds = &[
+            b"escrow",
+            market_key.as_ref(),
+            &[market.escrow_bump],
+        ];
+        let signer_seeds = &[&escrow_seeds[..]];
+        
+        let cpi_context = CpiContext::new_with_signer(
+            ctx.accounts.system_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.escrow.to_account_info(),
+                to: ctx.accounts.recovery.to_account_info(),
+            },
+            signer_seeds,
+        );
+        transfer(cpi_context, amount)?;
+    }
+    
+    // Debug: One proposal, one withdrawal
+    market.emergency_proposed_at = 0;
+    
+    emit!(EmergencyWithdrawn {
+        market: market.key(),
+        recovery: ctx.accounts.recovery.key(),
+        amount,
+        withdrawn_at: current_time,
+    });
+    
+    Ok(())
+}
+
+/// Forfeit rewards still unclaimed UNCLAIMED_SWEEP_GRACE_SECS after resolution (creator only)
+/// Forfeiture rule: winners have 180 days from `resolved_at` to claim; after that the
+/// creator may move everything left in escrow above its rent reserve to themselves,
+/// and any later `claim_reward` fails with RewardsForfeited
+/// Debug: Already-claimed winners are unaffected - only the residue moves
+pub fn sweep_unclaimed(
+    ctx: Context<SweepUnclaimed>,
+    _market_seed: String,
+) -> Result<()> {
+    let market = &mut ctx.accounts.market;
+    let current_time = Clock::get()?.unix_timestamp;
+    
+    require!(market.is_resolved, ParimutuelError::MarketNotResolved);
+    require!(market.unclaimed_swept_at == 0, ParimutuelError::RewardsForfeited);
+    
+    let sweep_opens_at = market.resolved_at.saturating_add(UNCLAIMED_SWEEP_GRACE_SECS);
+    require!(current_time >= sweep_opens_at, ParimutuelError::SweepTooEarly);
+    
+    let rent_exempt_min = Rent::get()?.minimum_balance(0);
+    let unclaimed = ctx.accounts.escrow.lamports().saturating_sub(rent_exempt_min);
+    
+    msg!("DEBUG: Sweeping {} unclaimed lamports to creator {}", unclaimed, market.creator);
+    msg!("DEBUG: Resolved at: {}, Sweep opened at: {}", market.resolved_at, sweep_opens_at);
+    
+    if unclaimed > 0 {
+        let market_key = market.key();
+        let escrow_seeds = &[
+            b"escrow",
+            market_key.as_ref(),
+            &[market.escrow_bump],
+        ];
+        let signer_seeds = &[&escrow_seeds[..]];
+        
+        let cpi_context = CpiContext::new_with_signer(
+            ctx.accounts.system_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.escrow.to_account_info(),
+                to: ctx.accounts.creator.to_account_info(),
+            },
+            signer_seeds,
+        );
+        transfer(cpi_context, unclaimed)?;
+    }
+    
+    market.unclaimed_swept_at = current_time;
+    
+    emit!(MarketLifecycle {
+        market: market.key(),
+        phase: MarketPhase::Swept,
+        actor: market.creator,
+        amount: unclaimed,
+        total_yes_pool: market.total_yes_pool,
+        total_no_pool: market.total_no_pool,
+        timestamp: current_time,
+    });
+    
+    Ok(())
+}
+
+/// Sweep rewards unclaimed after the claim deadline to the treasury (anyone)
+/// Debug: Only for markets with a claim window; claims are rejected from here on
+pub fn sweep_expired_claims(
+    ctx: Context<SweepExpiredClaims>,
+    _market_seed: String,
+) -> Result<()> {
+    let market = &mut ctx.accounts.market;
+    let current_time = Clock::get()?.unix_timestamp;
+    
+    require!(market.is_resolved, ParimutuelError::MarketNotResolved);
+    require!(market.unclaimed_swept_at == 0, ParimutuelError::RewardsForfeited);
+    require!(
+        market.claim_deadline > 0 && current_time >= market.claim_deadline,
+        ParimutuelError::SweepTooEarly
+    );
+    
+    let rent_exempt_min = Rent::get()?.minimum_balance(0);
+    let unclaimed = ctx.accounts.escrow.lamports().saturating_sub(rent_exempt_min);
+    
+    msg!("DEBUG: Sweeping {} expired lamports to treasury {}", unclaimed, ctx.accounts.treasury.key());
+    msg!("DEBUG: Claim deadline: {}", market.claim_deadline);
+    
+    if unclaimed > 0 {
+        let market_key = market.key();
+        let escrow_seeds = &[
+            b"escrow",
+            market_key.as_ref(),
+            &[market.escrow_bump],
+        ];
+        let signer_seeds = &[&escrow_seeds[..]];
+        
+        let cpi_context = CpiContext::new_with_signer(
+            ctx.accounts.system_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.escrow.to_account_info(),
+                to: ctx.accounts.treasury.to_account_info(),
+            },
+            signer_seeds,
+        );
+        transfer(cpi_context, unclaimed)?;
+    }
+    
+    market.unclaimed_swept_at = current_time;
+    
+    emit!(MarketLifecycle {
+        market: market.key(),
+        phase: MarketPhase::Swept,
+        actor: ctx.accounts.caller.key(),
+        amount: unclaimed,
+        total_yes_pool: market.total_yes_pool,
+        total_no_pool: market.total_no_pool,
+        timestamp: current_time,
+    });
+    
+    Ok(())
+}
+
+/// Close a resolved market once every winner has claimed
+/// Rewards floor `(user_amount * total_pool) / winning_pool`, so the sum paid out is
+/// always <= total_pool and short of it by under a lamport per winning bet; only that
+/// rounding remainder is swept to treasury here. Anything larger (e.g. a losing pool
+/// nobody can claim because the winning side was empty) must first leave through
+/// sweep_expired_claims or sweep_unclaimed once the claim window has closed
+/// Debug: Escrow rent reserve and market rent are returned to the creator
+pub fn close_market(
+    ctx: Context<CloseMarket>,
+    _market_seed: String,
+) -> Result<()> {
+    let market = &ctx.accounts.market;
+    
+    require!(market.is_resolved, ParimutuelError::MarketNotResolved);
+    
+    // A push refunds both sides, so every stake must be reclaimed
+    let winning_pool = if market.is_void {
+        market.total_yes_pool
+            .checked_add(market.total_no_pool)
+            .ok_or(ParimutuelError::Overflow)?
+    } else if market.winner.ok_or(ParimutuelError::NoWinner)? {
+        market.total_yes_pool
+    } else {
+        market.total_no_pool
+    };
+    require!(
+        market.total_claimed_stake == winning_pool || market.unclaimed_swept_at != 0,
+        ParimutuelError::UnclaimedRewards
+    );
+    require!(market.creation_fee_held == 0, ParimutuelError::CreationFeeStillHeld);
+    
+    // Once swept, the escrow holds nothing owed to anyone
+    let rounding_remainder = if market.unclaimed_swept_at != 0 {
+        0
+    } else {
+        let winning_bets = winning_bet_count(market)?;
+        let total_pool = market.total_yes_pool
+            .checked_add(market.total_no_pool)
+            .ok_or(ParimutuelError::Overflow)?;
+        let remainder = total_pool.saturating_sub(market.total_claimed_lamports);
+        require!(
+            remainder == 0 || remainder < winning_bets,
+            ParimutuelError::ResidueNotSwept
+        );
+        remainder
+    };
+    
+    let escrow_balance = ctx.accounts.escrow.lamports();
+    let rent_exempt_min = Rent::get()?.minimum_balance(0);
+    let dust = std::cmp::min(rounding_remainder, escrow_balance.saturating_sub(rent_exempt_min));
+    
+    msg!("DEBUG: Closing market - paid out {} lamports, sweeping {} lamports dust",
+        market.total_claimed_lamports, dust);
+    
+    let market_key = market.key();
+    let escrow_seeds = &[
+        b"escrow",
+        market_key.as_ref(),
+        &[market.escrow_bump],
+    ];
+    let signer_seeds = &[&escrow_seeds[..]];
+    
+    if dust > 0 {
+        let cpi_context = CpiContext::new_with_signer(
+            ctx.accounts.system_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.escrow.to_account_info(),
+                to: ctx.accounts.treasury.to_account_info(),
+            },
+            signer_seeds,
+        );
+        transfer(cpi_context, dust)?;
+    }
+    
+    let remaining = ctx.accounts.escrow.lamports();
+    if remaining > 0 {
+        let cpi_context = CpiContext::new_with_signer(
+            ctx.accounts.system_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.escrow.to_account_info(),
+                to: ctx.accounts.creator.to_account_info(),
+            },
+            signer_seeds,
+        );
+        transfer(cpi_context, remaining)?;
+    }
+    
+    msg!("DEBUG: Market {} closed", market_key);
+    
+    emit!(MarketLifecycle {
+        market: market_key,
+        phase: MarketPhase::Closed,
+        actor: market.creator,
+        amount: dust,
+        total_yes_pool: market.total_yes_pool,
+        total_no_pool: market.total_no_pool,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+    
+    Ok(())
+}
+
+/// Initialize a categorical market with `outcome_count` outcomes (permissionless)
+/// Betting closes `grace_secs` before the deadline, same as binary markets
+/// Debug: Honors the config's creation fee and duration bounds
+pub fn initialize_categorical_market(
+    ctx: Context<InitializeCategoricalMarket>,
+    _market_seed: String,
+    oracle_authority: Pubkey,
+    outcome_count: u8,
+    deadline: i64,
+    grace_secs: i64,
+    title: String,
+) -> Result<()> {
+    let current_time = Clock::get()?.unix_timestamp;
+    let config = &ctx.accounts.config;
+    
+    require!(!config.paused, ParimutuelError::ProtocolPaused);
+    require!(
+        (2..=MAX_OUTCOMES).contains(&(outcome_count as usize)),
+        ParimutuelError::InvalidOutcomeCount
+    );
+    require!(oracle_authority != Pubkey::default(), ParimutuelError::InvalidOracle);
+    require!(deadline > current_time, ParimutuelError::InvalidDeadline);
+    let duration = deadline - current_time;
+    require!(
+        duration >= config.min_market_duration_secs,
+        ParimutuelError::DeadlineTooSoon
+    );
+    require!(
+        config.max_market_duration_secs == 0 || duration <= config.max_market_duration_secs,
+        ParimutuelError::DeadlineTooFar
+    );
+    require!(grace_secs >= 0, ParimutuelError::InvalidGracePeriod);
+    let betting_closes_at = deadline
+        .checked_sub(grace_secs)
+        .ok_or(ParimutuelError::Overflow)?;
+    require!(betting_closes_at > current_time, ParimutuelError::InvalidGracePeriod);
+    require!(title.len() <= MAX_TITLE_LEN, ParimutuelError::TitleTooLong);
+    
+    let creation_fee = config.creation_fee_lamports;
+    if creation_fee > 0 {
+        let cpi_context = CpiContext::new(
+            ctx.accounts.system_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.creator.to_account_info(),
+                to: ctx.accounts.treasury.to_account_info(),
+            },
+        );
+        transfer(cpi_context, creation_fee)?;
+    }
+    
+    // Debug: Seed escrow with its rent-exempt reserve so claims can never purge it
+    let cpi_context = CpiContext::new(
+        ctx.accounts.system_program.to_account_info(),
+        Transfer {
+            from: ctx.accounts.creator.to_account_info(),
+            to: ctx.accounts.escrow.to_account_info(),
+        },
+    );
+    transfer(cpi_context, Rent::get()?.minimum_balance(0))?;
+    
+    let market = &mut ctx.accounts.market;
+    market.creator = ctx.accounts.creator.key();
+    market.oracle_authority = oracle_authority;
+    market.outcome_pools = vec![0; outcome_count as usize];
+    market.deadline = deadline;
+    market.betting_closes_at = betting_closes_at;
     market.is_resolved = false;
-    market.winner = None;
-    market.target_reached = false;
+    market.winning_outcome = 0;
+    market.is_void = false;
     market.resolved_at = 0;
+    market.total_claimed_lamports = 0;
+    market.open_bet_count = 0;
+    market.unclaimed_swept_at = 0;
+    market.title = title;
+    market.escrow_bump = ctx.bumps.escrow;
+    market.created_at = current_time;
     market.bump = ctx.bumps.market;
     
-    msg!("DEBUG: Parimutuel market initialized (permissionless)");
+    msg!("DEBUG: Categorical market initialized with {} outcomes", outcome_count);
+    msg!("DEBUG: Title: {}", market.title);
     msg!("DEBUG: Creator: {}", market.creator);
-    msg!("DEBUG: Creation fee paid: {} lamports (0.015 SOL)", MARKET_CREATION_FEE);
-    msg!("DEBUG: Treasury: {}", ctx.accounts.treasury.key());
+    msg!("DEBUG: Creation fee paid: {} lamports", creation_fee);
     msg!("DEBUG: Oracle: {}", oracle_authority);
-    msg!("DEBUG: Token: {}", token_mint);
-    msg!("DEBUG: Target Market Cap: ${}", target_market_cap as f64 / 1_000_000.0);
     msg!("DEBUG: Deadline: {}", deadline);
+    msg!("DEBUG: Betting closes at: {}", betting_closes_at);
     
     Ok(())
 }
 
-/// Place a bet on YES or NO side
-/// Debug: No fixed limit - pools grow indefinitely as users bet
-pub fn place_bet(
-    ctx: Context<PlaceBet>,
+/// Place a bet on outcome `outcome_index` of a categorical market
+/// Debug: One bet per user per market, like binary markets
+pub fn place_categorical_bet(
+    ctx: Context<PlaceCategoricalBet>,
     _market_seed: String,
     amount: u64,
-    side: bool,
+    outcome_index: u8,
 ) -> Result<()> {
     let market = &mut ctx.accounts.market;
-    let user_bet = &mut ctx.accounts.user_bet;
     let current_time = Clock::get()?.unix_timestamp;
     
+    require!(!ctx.accounts.config.paused, ParimutuelError::ProtocolPaused);
     require!(!market.is_resolved, ParimutuelError::MarketResolved);
-    
-    require!(current_time < market.deadline, ParimutuelError::DeadlinePassed);
-    
+    require!(current_time < market.betting_closes_at, ParimutuelError::DeadlinePassed);
     require!(amount > 0, ParimutuelError::InvalidAmount);
+    require!(
+        (outcome_index as usize) < market.outcome_pools.len(),
+        ParimutuelError::InvalidOutcome
+    );
     
-    // Debug: Transfer SOL from user to escrow PDA
     msg!("DEBUG: Transferring {} lamports from user to escrow", amount);
     
     let cpi_context = CpiContext::new(
@@ -251,42 +2692,29 @@ pub fn place_bet(
     );
     transfer(cpi_context, amount)?;
     
-    // Update pool totals based on side
-    if side {
-        market.total_yes_pool = market.total_yes_pool
-            .checked_add(amount)
-            .ok_or(ParimutuelError::Overflow)?;
-        msg!("DEBUG: YES pool updated to {} lamports", market.total_yes_pool);
-    } else {
-        market.total_no_pool = market.total_no_pool
-            .checked_add(amount)
-            .ok_or(ParimutuelError::Overflow)?;
-        msg!("DEBUG: NO pool updated to {} lamports", market.total_no_pool);
-    }
+    let pool = &mut market.outcome_pools[outcome_index as usize];
+    *pool = pool.checked_add(amount).ok_or(ParimutuelError::Overflow)?;
+    msg!("DEBUG: Outcome {} pool updated to {} lamports", outcome_index, *pool);
+    market.open_bet_count = market.open_bet_count
+        .checked_add(1)
+        .ok_or(ParimutuelError::Overflow)?;
     
-    // Initialize user bet record
+    let user_bet = &mut ctx.accounts.user_bet;
     user_bet.user = ctx.accounts.user.key();
     user_bet.market = market.key();
     user_bet.amount = amount;
-    user_bet.side = side;
-    user_bet.claimed = false;
-    
-    msg!("DEBUG: User {} placed {} lamports on {}", 
-        ctx.accounts.user.key(), 
-        amount, 
-        if side { "YES" } else { "NO" }
-    );
+    user_bet.outcome_index = outcome_index;
     
     Ok(())
 }
 
-/// Resolve the market with oracle-provided market cap data
-/// Debug: Oracle (crank) provides current market cap and verifies against target/deadline
-pub fn resolve_market(
-    ctx: Context<ResolveMarket>,
+/// Resolve a categorical market to `winning_outcome` (oracle only)
+/// Only once betting has closed, so the oracle cannot settle while bets are still landing
+/// Debug: If nobody backed the winner the market is voided so stakes are not stranded
+pub fn resolve_categorical_market(
+    ctx: Context<ResolveCategoricalMarket>,
     _market_seed: String,
-    current_market_cap: u64,
-    timestamp: i64,
+    winning_outcome: u8,
 ) -> Result<()> {
     let market = &mut ctx.accounts.market;
     let current_time = Clock::get()?.unix_timestamp;
@@ -295,120 +2723,237 @@ pub fn resolve_market(
         ctx.accounts.oracle.key() == market.oracle_authority,
         ParimutuelError::Unauthorized
     );
-    
     require!(!market.is_resolved, ParimutuelError::MarketAlreadyResolved);
-    
+    require!(current_time >= market.betting_closes_at, ParimutuelError::BettingStillOpen);
     require!(
-        timestamp <= current_time + 300,
-        ParimutuelError::StaleData
+        (winning_outcome as usize) < market.outcome_pools.len(),
+        ParimutuelError::InvalidOutcome
     );
     
-    let target_reached = current_market_cap >= market.target_market_cap;
-    let deadline_passed = current_time >= market.deadline;
+    market.is_resolved = true;
+    market.winning_outcome = winning_outcome;
+    market.is_void = market.outcome_pools[winning_outcome as usize] == 0;
+    market.resolved_at = current_time;
     
-    require!(
-        target_reached || deadline_passed,
-        ParimutuelError::CannotResolveYet
-    );
+    msg!("DEBUG: Categorical market resolved to outcome {}", winning_outcome);
+    msg!("DEBUG: Outcome pools: {:?}", market.outcome_pools);
+    msg!("DEBUG: Voided: {}", market.is_void);
     
-    let winner = if target_reached {
-        true
+    emit!(CategoricalMarketResolved {
+        market: market.key(),
+        winning_outcome,
+        outcome_pools: market.outcome_pools.clone(),
+        is_void: market.is_void,
+        resolved_at: current_time,
+    });
+    
+    Ok(())
+}
+
+/// Claim a categorical bet: winners get (amount * total_pool) / winning_pool
+/// Once the creator has swept the escrow, claims only close the bet and return its rent
+/// Debug: A voided market refunds every principal; losing bets are closed with no payout
+pub fn claim_categorical_reward(
+    ctx: Context<ClaimCategoricalReward>,
+    _market_seed: String,
+) -> Result<()> {
+    let market = &mut ctx.accounts.market;
+    let user_bet = &ctx.accounts.user_bet;
+    
+    require!(market.is_resolved, ParimutuelError::MarketNotResolved);
+    
+    let reward_lamports = if market.unclaimed_swept_at != 0 {
+        0
+    } else if market.is_void {
+        user_bet.amount
+    } else if user_bet.outcome_index == market.winning_outcome {
+        let winning_pool = market.outcome_pools[market.winning_outcome as usize];
+        require!(winning_pool > 0, ParimutuelError::EmptyPool);
+        calculate_reward(user_bet.amount, winning_pool, market.total_pool()?)?
     } else {
-        false
+        0
     };
     
-    market.is_resolved = true;
-    market.winner = Some(winner);
-    market.target_reached = target_reached;
-    market.resolved_at = current_time;
+    if reward_lamports > 0 {
+        let rent_exempt_min = Rent::get()?.minimum_balance(0);
+        require!(
+            ctx.accounts.escrow.lamports().saturating_sub(rent_exempt_min) >= reward_lamports,
+            ParimutuelError::InsufficientEscrow
+        );
+        
+        market.total_claimed_lamports = market.total_claimed_lamports
+            .checked_add(reward_lamports)
+            .ok_or(ParimutuelError::Overflow)?;
+        
+        let market_key = market.key();
+        let escrow_seeds = &[
+            b"escrow",
+            market_key.as_ref(),
+            &[market.escrow_bump],
+        ];
+        let signer_seeds = &[&escrow_seeds[..]];
+        
+        let cpi_context = CpiContext::new_with_signer(
+            ctx.accounts.system_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.escrow.to_account_info(),
+                to: ctx.accounts.user.to_account_info(),
+            },
+            signer_seeds,
+        );
+        transfer(cpi_context, reward_lamports)?;
+    }
     
-    msg!("DEBUG: Market resolved by oracle");
-    msg!("DEBUG: Current Market Cap: ${}", current_market_cap as f64 / 1_000_000.0);
-    msg!("DEBUG: Target Market Cap: ${}", market.target_market_cap as f64 / 1_000_000.0);
-    msg!("DEBUG: Target Reached: {}", target_reached);
-    msg!("DEBUG: Deadline Passed: {}", deadline_passed);
-    msg!("DEBUG: Winner: {}", if winner { "YES" } else { "NO" });
-    msg!("DEBUG: Total YES pool: {} lamports", market.total_yes_pool);
-    msg!("DEBUG: Total NO pool: {} lamports", market.total_no_pool);
+    market.open_bet_count = market.open_bet_count
+        .checked_sub(1)
+        .ok_or(ParimutuelError::Overflow)?;
+    
+    msg!("DEBUG: Categorical bet on outcome {} claimed {} lamports",
+        user_bet.outcome_index, reward_lamports);
     
     Ok(())
 }
 
-/// Claim proportional reward after market resolution
-/// Debug: Uses u128 for calculations to prevent overflow with large amounts
-pub fn claim_reward(
-    ctx: Context<ClaimReward>,
+/// Void a categorical market the oracle never resolved (anyone)
+/// Opens FORCE_RESOLVE_GRACE_SECS after the deadline, as force_resolve_deadline does;
+/// with no reported outcome to settle on, every bettor reclaims their principal
+pub fn force_void_categorical_market(
+    ctx: Context<ForceVoidCategoricalMarket>,
     _market_seed: String,
 ) -> Result<()> {
-    let market = &ctx.accounts.market;
-    let user_bet = &mut ctx.accounts.user_bet;
+    let market = &mut ctx.accounts.market;
+    let current_time = Clock::get()?.unix_timestamp;
     
-    // Validation: Market must be resolved
-    require!(market.is_resolved, ParimutuelError::MarketNotResolved);
+    require!(!market.is_resolved, ParimutuelError::MarketAlreadyResolved);
     
-    // Validation: User must not have already claimed
-    require!(!user_bet.claimed, ParimutuelError::AlreadyClaimed);
+    let force_resolve_at = market.deadline.saturating_add(FORCE_RESOLVE_GRACE_SECS);
+    require!(
+        current_time >= force_resolve_at,
+        ParimutuelError::ForceResolveTooEarly
+    );
     
-    // Validation: User must be on winning side
-    let winner = market.winner.ok_or(ParimutuelError::NoWinner)?;
-    require!(user_bet.side == winner, ParimutuelError::NotWinner);
+    market.is_resolved = true;
+    market.is_void = true;
+    market.resolved_at = current_time;
     
-    // Calculate proportional reward using u128 to prevent overflow
-    // Formula: Reward = (User's Bet / Winning Pool) × Total Pool
-    let winning_pool = if winner {
-        market.total_yes_pool
-    } else {
-        market.total_no_pool
-    };
+    msg!("DEBUG: Categorical market voided after oracle missed the deadline");
+    msg!("DEBUG: Deadline: {}, Grace ends: {}", market.deadline, force_resolve_at);
+    msg!("DEBUG: Voided by: {:?}", ctx.accounts.caller.key());
+    msg!("DEBUG: Outcome pools: {:?}", market.outcome_pools);
+    
+    emit!(CategoricalMarketResolved {
+        market: market.key(),
+        winning_outcome: market.winning_outcome,
+        outcome_pools: market.outcome_pools.clone(),
+        is_void: true,
+        resolved_at: current_time,
+    });
+    
+    Ok(())
+}
+
+/// Forfeit categorical rewards still unclaimed UNCLAIMED_SWEEP_GRACE_SECS after resolution,
+/// sending the escrow residue to the creator (creator only)
+/// Debug: Later claims still close their bets, so the market can be closed afterwards
+pub fn sweep_unclaimed_categorical(
+    ctx: Context<SweepUnclaimedCategorical>,
+    _market_seed: String,
+) -> Result<()> {
+    let market = &mut ctx.accounts.market;
+    let current_time = Clock::get()?.unix_timestamp;
+    
+    require!(market.is_resolved, ParimutuelError::MarketNotResolved);
+    require!(market.unclaimed_swept_at == 0, ParimutuelError::RewardsForfeited);
     
-    let total_pool = market.total_yes_pool
-        .checked_add(market.total_no_pool)
-        .ok_or(ParimutuelError::Overflow)?;
+    let sweep_opens_at = market.resolved_at.saturating_add(UNCLAIMED_SWEEP_GRACE_SECS);
+    require!(current_time >= sweep_opens_at, ParimutuelError::SweepTooEarly);
     
-    // Debug: Use u128 for precise calculation with large numbers
-    msg!("DEBUG: Calculating reward - User bet: {}, Winning pool: {}, Total pool: {}", 
-        user_bet.amount, winning_pool, total_pool);
+    let rent_exempt_min = Rent::get()?.minimum_balance(0);
+    let unclaimed = ctx.accounts.escrow.lamports().saturating_sub(rent_exempt_min);
     
-    require!(winning_pool > 0, ParimutuelError::EmptyPool);
+    msg!("DEBUG: Sweeping {} unclaimed lamports to creator {}", unclaimed, market.creator);
+    msg!("DEBUG: Resolved at: {}, Sweep opened at: {}", market.resolved_at, sweep_opens_at);
     
-    // Reward = (user_amount * total_pool) / winning_pool
-    let reward = (user_bet.amount as u128)
-        .checked_mul(total_pool as u128)
-        .ok_or(ParimutuelError::Overflow)?
-        .checked_div(winning_pool as u128)
-        .ok_or(ParimutuelError::DivisionByZero)?;
+    if unclaimed > 0 {
+        let market_key = market.key();
+        let escrow_seeds = &[
+            b"escrow",
+            market_key.as_ref(),
+            &[market.escrow_bump],
+        ];
+        let signer_seeds = &[&escrow_seeds[..]];
+        
+        let cpi_context = CpiContext::new_with_signer(
+            ctx.accounts.system_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.escrow.to_account_info(),
+                to: ctx.accounts.creator.to_account_info(),
+            },
+            signer_seeds,
+        );
+        transfer(cpi_context, unclaimed)?;
+    }
     
-    let reward_lamports = u64::try_from(reward)
-        .map_err(|_| ParimutuelError::Overflow)?;
+    market.unclaimed_swept_at = current_time;
     
-    msg!("DEBUG: Calculated reward: {} lamports", reward_lamports);
+    Ok(())
+}
+
+/// Close a resolved categorical market once every bet has been claimed
+/// With all bets settled the escrow holds only its rent reserve plus the rounding
+/// remainder of the floored rewards, which is swept to treasury here
+/// Bets left unclaimed keep the market open, so a market re-created at the same seed
+/// can never meet a stale bet
+/// Debug: Escrow rent reserve and market rent are returned to the creator
+pub fn close_categorical_market(
+    ctx: Context<CloseCategoricalMarket>,
+    _market_seed: String,
+) -> Result<()> {
+    let market = &ctx.accounts.market;
+    
+    require!(market.is_resolved, ParimutuelError::MarketNotResolved);
+    require!(market.open_bet_count == 0, ParimutuelError::BetsStillOpen);
+    
+    let rent_exempt_min = Rent::get()?.minimum_balance(0);
+    let dust = ctx.accounts.escrow.lamports().saturating_sub(rent_exempt_min);
+    
+    msg!("DEBUG: Closing categorical market - paid out {} lamports, sweeping {} lamports dust",
+        market.total_claimed_lamports, dust);
     
-    // Transfer reward from escrow to user
     let market_key = market.key();
     let escrow_seeds = &[
         b"escrow",
         market_key.as_ref(),
-        &[ctx.bumps.escrow],
+        &[market.escrow_bump],
     ];
     let signer_seeds = &[&escrow_seeds[..]];
     
-    let cpi_context = CpiContext::new_with_signer(
-        ctx.accounts.system_program.to_account_info(),
-        Transfer {
-            from: ctx.accounts.escrow.to_account_info(),
-            to: ctx.accounts.user.to_account_info(),
-        },
-        signer_seeds,
-    );
-    transfer(cpi_context, reward_lamports)?;
+    if dust > 0 {
+        let cpi_context = CpiContext::new_with_signer(
+            ctx.accounts.system_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.escrow.to_account_info(),
+                to: ctx.accounts.treasury.to_account_info(),
+            },
+            signer_seeds,
+        );
+        transfer(cpi_context, dust)?;
+    }
     
-    // Mark as claimed
-    user_bet.claimed = true;
+    let remaining = ctx.accounts.escrow.lamports();
+    if remaining > 0 {
+        let cpi_context = CpiContext::new_with_signer(
+            ctx.accounts.system_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.escrow.to_account_info(),
+                to: ctx.accounts.creator.to_account_info(),
+            },
+            signer_seeds,
+        );
+        transfer(cpi_context, remaining)?;
+    }
     
-    msg!("DEBUG: Reward of {} lamports claimed by user {}", 
-        reward_lamports, 
-        ctx.accounts.user.key()
-    );
+    msg!("DEBUG: Categorical market {} closed", market_key);
     
     Ok(())
 }
@@ -438,6 +2983,9 @@ pub enum ParimutuelError {
     #[msg("User is not on the winning side")]
     NotWinner,
     
+    #[msg("Bet is not on the losing side of a resolved market")]
+    BetNotLost,
+    
     #[msg("No winner set for this market")]
     NoWinner,
     
@@ -456,15 +3004,2235 @@ pub enum ParimutuelError {
     #[msg("Invalid deadline: Must be in the future")]
     InvalidDeadline,
     
-    #[msg("Deadline has passed: Cannot place bets")]
+    #[msg("Betting has closed: Cannot place bets")]
     DeadlinePassed,
     
-    #[msg("Oracle data is stale: Timestamp too old")]
+    #[msg("Oracle data is stale: Timestamp outside the allowed skew window")]
     StaleData,
     
     #[msg("Cannot resolve yet: Target not reached and deadline not passed")]
     CannotResolveYet,
     
-    #[msg("Insufficient funds: Need 0.015 SOL + rent for market creation")]
+    #[msg("Insufficient funds: Need creation fee + rent for market creation")]
     InsufficientFunds,
+    
+    #[msg("Insufficient escrow: Reward would leave escrow below rent exemption")]
+    InsufficientEscrow,
+    
+    #[msg("Invalid treasury: Does not match the configured treasury")]
+    InvalidTreasury,
+    
+    #[msg("Market has bets: Creation fee is not refundable")]
+    MarketHasBets,
+    
+    #[msg("No bets placed: Creation fee is still refundable")]
+    NoBetsPlaced,
+    
+    #[msg("No creation fee held for this market")]
+    NoCreationFeeHeld,
+    
+    #[msg("Cannot close market: Some winners have not claimed")]
+    UnclaimedRewards,
+    
+    #[msg("Cannot close market: Creation fee must be collected or refunded first")]
+    CreationFeeStillHeld,
+    
+    #[msg("Invalid grace period: Betting must stay open for some time before the deadline")]
+    InvalidGracePeriod,
+    
+    #[msg("Cannot force-resolve yet: Oracle grace period after the deadline has not elapsed")]
+    ForceResolveTooEarly,
+    
+    #[msg("Rewards forfeited: Unclaimed rewards were swept after the claim window")]
+    RewardsForfeited,
+    
+    #[msg("Cannot sweep yet: Claim window after resolution has not elapsed")]
+    SweepTooEarly,
+    
+    #[msg("Deadline too soon: Below the minimum market duration")]
+    DeadlineTooSoon,
+    
+    #[msg("Deadline too far: Above the maximum market duration")]
+    DeadlineTooFar,
+    
+    #[msg("Invalid duration bounds: Max must be 0 or at least min")]
+    InvalidDurationBounds,
+    
+    #[msg("No emergency withdrawal has been proposed for this market")]
+    NoEmergencyProposal,
+    
+    #[msg("Emergency timelock has not elapsed")]
+    TimelockNotElapsed,
+    
+    #[msg("Invalid recovery address")]
+    InvalidRecoveryAddress,
+    
+    #[msg("Invalid Switchboard feed for this market")]
+    InvalidSwitchboardFeed,
+    
+    #[msg("Switchboard round has too few oracle results")]
+    InsufficientOracleResults,
+    
+    #[msg("Oracle reported an invalid value")]
+    InvalidOracleValue,
+    
+    #[msg("Invalid claim window: Must be 0 or positive")]
+    InvalidClaimWindow,
+    
+    #[msg("Claim window closed: Rewards can no longer be claimed")]
+    ClaimWindowClosed,
+    
+    #[msg("Forced resolution does not match the reported market cap")]
+    ResolutionMismatch,
+    
+    #[msg("Market title exceeds 64 bytes")]
+    TitleTooLong,
+    
+    #[msg("Metadata URI exceeds 128 bytes")]
+    MetadataUriTooLong,
+    
+    #[msg("Invalid oracle: Must not be the default pubkey")]
+    InvalidOracle,
+    
+    #[msg("Categorical markets need between 2 and 8 outcomes")]
+    InvalidOutcomeCount,
+    
+    #[msg("Outcome index out of range")]
+    InvalidOutcome,
+    
+    #[msg("Protocol is paused: New markets and bets are disabled")]
+    ProtocolPaused,
+    
+    #[msg("Reinvested amount exceeds the claimed reward")]
+    ReinvestExceedsReward,
+    
+    #[msg("Target already reached: Betting closed pending resolution")]
+    TargetAlreadyReached,
+    
+    #[msg("Invalid creation fee: Must be 0 or at least the rent-exempt minimum")]
+    InvalidCreationFee,
+    
+    #[msg("Cannot close market: Escrow holds more than rounding dust, sweep it after the claim window")]
+    ResidueNotSwept,
+    
+    #[msg("Cannot resolve yet: Betting is still open")]
+    BettingStillOpen,
+    
+    #[msg("Cannot close market: Some bets have not been claimed")]
+    BetsStillOpen,
+}
+
+/// Emitted when a market is resolved
+/// Debug: Carries the observed cap so the UI can show how close it got
+#[event]
+pub struct MarketResolved {
+    pub market: Pubkey,
+    pub winner: bool,
+    pub target_reached: bool,
+    pub last_market_cap: u64,
+    pub target_market_cap: u64,
+    pub total_yes_pool: u64,
+    pub total_no_pool: u64,
+    pub resolved_at: i64,
+    pub claim_deadline: i64,
+    pub is_void: bool,              // Push: `winner` is meaningless, everyone is refunded
+}
+
+/// Emitted when a market takes its slot in the registry
+/// Debug: Lets indexers build the full market list without replaying transactions
+#[event]
+pub struct MarketRegistered {
+    pub market: Pubkey,
+    pub index: u64,
+    pub creator: Pubkey,
+    pub token_mint: Pubkey,
+    pub deadline: i64,
+}
+
+/// Emitted when a market's oracle authority is rotated
+#[event]
+pub struct OracleAuthorityChanged {
+    pub market: Pubkey,
+    pub old_oracle: Pubkey,
+    pub new_oracle: Pubkey,
+    pub changed_by: Pubkey,
+    pub changed_at: i64,
+}
+
+/// Emitted when a categorical market is resolved
+#[event]
+pub struct CategoricalMarketResolved {
+    pub market: Pubkey,
+    pub winning_outcome: u8,
+    pub outcome_pools: Vec<u64>,
+    pub is_void: bool,              // Nobody backed the winner, everyone is refunded
+    pub resolved_at: i64,
+}
+
+#[event]
+pub struct EmergencyWithdrawProposed {
+    pub market: Pubkey,
+    pub recovery: Pubkey,
+    pub proposed_at: i64,
+    pub executable_at: i64,
+}
+
+#[event]
+pub struct EmergencyWithdrawn {
+    pub market: Pubkey,
+    pub recovery: Pubkey,
+    pub amount: u64,
+    pub withdrawn_at: i64,
+}
+
+/// Outcome an oracle may force on `resolve_market`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Resolution {
+    Yes,
+    No,
+    Push,
+}
+
+/// How a target hit reported at or after the deadline is treated, fixed at creation
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ResolutionPolicy {
+    /// cap >= target resolves YES whenever the report is accepted, even at/after the deadline
+    TargetWinsOnTie,
+    /// cap >= target resolves YES only if the report timestamp is strictly before the deadline
+    DeadlineWinsOnTie,
+}
+
+/// How a market was resolved, stored on `Market`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ResolutionSource {
+    Unresolved,
+    Oracle,
+    Switchboard,
+    ForcedDeadline,
+}
+
+/// Phase of a market's lifecycle, carried by `MarketLifecycle`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MarketPhase {
+    Created,
+    BetPlaced,
+    Resolved,
+    Claimed,
+    Voided,
+    Closed,
+    Swept,
+}
+
+/// Single event emitted at every market transition so indexers can subscribe to one type
+/// Refund outcomes are always Voided: a push resolution and a creation-fee refund for a
+/// market that never saw a bet
+/// Debug: `amount` depends on phase - Created: creation fee, BetPlaced: stake,
+/// Resolved: winning pool, Claimed: reward paid, Voided: pool refundable after a push or
+/// fee refunded, Closed: dust swept, Swept: unclaimed rewards forfeited to the creator
+#[event]
+pub struct MarketLifecycle {
+    pub market: Pubkey,
+    pub phase: MarketPhase,
+    pub actor: Pubkey,
+    pub amount: u64,
+    pub total_yes_pool: u64,
+    pub total_no_pool: u64,
+    pub timestamp: i64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anchor_lang::solana_program::entrypoint::ProgramResult;
+    use anchor_lang::solana_program::instruction::AccountMeta;
+    use anchor_lang::solana_program::program_error::ProgramError;
+    use anchor_lang::system_program;
+    use anchor_lang::{Event, InstructionData, ToAccountMetas};
+    use crate::ID;
+    use solana_sysvar::program_stubs::{set_syscall_stubs, SyscallStubs};
+    use std::cell::{Cell, RefCell};
+    use std::collections::HashMap;
+    use std::sync::Once;
+    
+    const SEED: &str = "pepe-1m";
+    const START: i64 = 1_700_000_000;
+    const DAY: i64 = 24 * 60 * 60;
+    const SOL: u64 = 1_000_000_000;
+    const TARGET: u64 = 1_000_000_000_000; // $1M with 6 decimals
+    
+    thread_local! {
+        static NOW: Cell<i64> = const { Cell::new(0) };
+        static EVENTS: RefCell<Vec<Vec<u8>>> = const { RefCell::new(Vec::new()) };
+        static RETURN_DATA: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+    }
+    
+    /// Sysvars for off-chain runs, with the clock kept per test thread
+    struct Stubs;
+    
+    impl SyscallStubs for Stubs {
+        fn sol_get_clock_sysvar(&self, var_addr: *mut u8) -> u64 {
+            let clock = Clock {
+                unix_timestamp: NOW.with(Cell::get),
+                ..Clock::default()
+            };
+            unsafe { std::ptr::write(var_addr as *mut Clock, clock) };
+            0
+        }
+        
+        fn sol_get_rent_sysvar(&self, var_addr: *mut u8) -> u64 {
+            unsafe { std::ptr::write(var_addr as *mut Rent, Rent::default()) };
+            0
+        }
+    }
+    
+    fn set_now(unix_timestamp: i64) {
+        NOW.with(|now| now.set(unix_timestamp));
+    }
+    
+    pub(super) fn record_event<T: Event>(event: &T) {
+        EVENTS.with(|events| events.borrow_mut().push(event.data()));
+    }
+    
+    /// Test double for `set_return_data`, which is a no-op off-chain
+    pub(super) fn set_return_data(data: &[u8]) {
+        RETURN_DATA.with(|return_data| *return_data.borrow_mut() = data.to_vec());
+    }
+    
+    /// Phases of the MarketLifecycle events emitted since the last call, in order
+    fn take_phases() -> Vec<MarketPhase> {
+        EVENTS.with(|events| {
+            events
+                .borrow_mut()
+                .drain(..)
+                .filter_map(|data| {
+                    let mut event = data.strip_prefix(MarketLifecycle::DISCRIMINATOR)?;
+                    Some(MarketLifecycle::deserialize(&mut event).unwrap().phase)
+                })
+                .collect()
+        })
+    }
+    
+    /// Test double for the system program transfer
+    /// Debug: CPIs cannot run off-chain, so this applies the checks the runtime and system
+    /// program would - `from` signed directly or via PDA seeds, is a system account and
+    /// can cover the amount - then moves the lamports itself
+    pub(super) fn transfer<'info>(
+        ctx: CpiContext<'_, '_, '_, 'info, Transfer<'info>>,
+        lamports: u64,
+    ) -> Result<()> {
+        let Transfer { from, to } = ctx.accounts;
+        let signed_by_pda = ctx.signer_seeds.iter().any(|seeds| {
+            Pubkey::create_program_address(seeds, &ID).is_ok_and(|pda| pda == *from.key)
+        });
+        if !from.is_signer && !signed_by_pda {
+            return Err(ProgramError::MissingRequiredSignature.into());
+        }
+        if *from.owner != system_program::ID {
+            return Err(ProgramError::InvalidAccountOwner.into());
+        }
+        if from.lamports() < lamports {
+            return Err(ProgramError::InsufficientFunds.into());
+        }
+        
+        **from.try_borrow_mut_lamports()? -= lamports;
+        **to.try_borrow_mut_lamports()? += lamports;
+        Ok(())
+    }
+    
+    fn leak<T>(value: T) -> &'static mut T {
+        Box::leak(Box::new(value))
+    }
+    
+    fn err(error: ParimutuelError) -> ProgramResult {
+        Err(Error::from(error).into())
+    }
+    
+    fn rent(space: usize) -> u64 {
+        Rent::default().minimum_balance(space)
+    }
+    
+    fn pda(seeds: &[&[u8]]) -> (Pubkey, u8) {
+        Pubkey::find_program_address(seeds, &ID)
+    }
+    
+    fn config_key() -> Pubkey {
+        pda(&[b"config"]).0
+    }
+    
+    fn market_key(seed: &str) -> Pubkey {
+        pda(&[b"market", seed.as_bytes()]).0
+    }
+    
+    fn categorical_market_key(seed: &str) -> Pubkey {
+        pda(&[b"categorical_market", seed.as_bytes()]).0
+    }
+    
+    fn categorical_bet_key(market: Pubkey, user: Pubkey) -> Pubkey {
+        pda(&[b"categorical_bet", market.as_ref(), user.as_ref()]).0
+    }
+    
+    fn escrow_key(market: Pubkey) -> Pubkey {
+        pda(&[b"escrow", market.as_ref()]).0
+    }
+    
+    fn fee_escrow_key(market: Pubkey) -> Pubkey {
+        pda(&[b"fee_escrow", market.as_ref()]).0
+    }
+    
+    fn registry_key(index: u64) -> Pubkey {
+        pda(&[b"registry", index.to_le_bytes().as_ref()]).0
+    }
+    
+    fn user_bet_key(market: Pubkey, user: Pubkey, bet_index: u64) -> Pubkey {
+        pda(&[b"user_bet", market.as_ref(), user.as_ref(), bet_index.to_le_bytes().as_ref()]).0
+    }
+    
+    /// An account as the runtime stores it between instructions
+    #[derive(Clone, Default)]
+    struct TestAccount {
+        owner: Pubkey,
+        lamports: u64,
+        data: Vec<u8>,
+    }
+    
+    /// Leak `account` laid out like the runtime's input buffer: `realloc` (and so `close`)
+    /// writes the new data length into the 8 bytes before the data and reads the original
+    /// length from the 4 bytes before the key
+    fn account_info(meta: &AccountMeta, account: TestAccount) -> AccountInfo<'static> {
+        #[repr(C)]
+        struct KeySlot {
+            original_data_len: u32,
+            key: Pubkey,
+        }
+        
+        let slot = leak(KeySlot {
+            original_data_len: account.data.len() as u32,
+            key: meta.pubkey,
+        });
+        let words = leak(vec![0u64; 1 + account.data.len().div_ceil(8)]);
+        let data = unsafe {
+            std::slice::from_raw_parts_mut(words.as_mut_ptr().add(1) as *mut u8, account.data.len())
+        };
+        data.copy_from_slice(&account.data);
+        
+        AccountInfo::new(
+            &slot.key,
+            meta.is_signer,
+            meta.is_writable,
+            leak(account.lamports),
+            data,
+            leak(account.owner),
+            meta.pubkey == system_program::ID,
+            0,
+        )
+    }
+    
+    /// Minimal in-memory runtime: accounts persist across instructions and an
+    /// instruction's writes are kept only if it succeeds
+    #[derive(Default)]
+    struct Bank {
+        accounts: HashMap<Pubkey, TestAccount>,
+    }
+    
+    impl Bank {
+        fn fund(&mut self, key: Pubkey, lamports: u64) {
+            self.accounts.entry(key).or_default().lamports += lamports;
+        }
+        
+        fn lamports(&self, key: &Pubkey) -> u64 {
+            self.accounts.get(key).map_or(0, |account| account.lamports)
+        }
+        
+        fn set_lamports(&mut self, key: &Pubkey, lamports: u64) {
+            self.accounts.entry(*key).or_default().lamports = lamports;
+        }
+        
+        fn get<T: AccountDeserialize>(&self, key: &Pubkey) -> T {
+            T::try_deserialize(&mut self.accounts[key].data.as_slice()).unwrap()
+        }
+        
+        /// Run `f` over the accounts of `metas`, first creating each `init` key as a zeroed
+        /// program-owned account of the given size paid for by `payer` - the state Anchor's
+        /// `init` leaves behind, which cannot run here since it needs a CPI
+        fn transact(
+            &mut self,
+            metas: &[AccountMeta],
+            init: &[(Pubkey, usize)],
+            payer: Pubkey,
+            f: impl FnOnce(&'static [AccountInfo<'static>]) -> ProgramResult,
+        ) -> ProgramResult {
+            let mut staged = self.accounts.clone();
+            for (key, space) in init {
+                if staged.get(key).is_some_and(|account| account.lamports > 0) {
+                    return Err(ProgramError::AccountAlreadyInitialized);
+                }
+                let payer = staged.entry(payer).or_default();
+                payer.lamports = payer.lamports
+                    .checked_sub(rent(*space))
+                    .ok_or(ProgramError::InsufficientFunds)?;
+                staged.insert(*key, TestAccount {
+                    owner: ID,
+                    lamports: rent(*space),
+                    data: vec![0; *space],
+                });
+            }
+            
+            let infos: &'static [AccountInfo<'static>] = leak(
+                metas
+                    .iter()
+                    .map(|meta| account_info(meta, staged.get(&meta.pubkey).cloned().unwrap_or_default()))
+                    .collect::<Vec<_>>(),
+            );
+            f(infos)?;
+            
+            for info in infos {
+                staged.insert(*info.key, TestAccount {
+                    owner: *info.owner,
+                    lamports: info.lamports(),
+                    data: info.data.borrow().to_vec(),
+                });
+            }
+            self.accounts = staged;
+            Ok(())
+        }
+        
+        /// Run an instruction through the program entrypoint
+        fn process(&mut self, metas: Vec<AccountMeta>, data: Vec<u8>) -> ProgramResult {
+            self.transact(&metas, &[], Pubkey::default(), |infos| crate::entry(&ID, infos, &data))
+        }
+    }
+    
+    /// A config, its treasury and authority, and a funded market creator and oracle
+    struct Env {
+        bank: Bank,
+        authority: Pubkey,
+        treasury: Pubkey,
+        creator: Pubkey,
+        oracle: Pubkey,
+    }
+    
+    fn market_args() -> crate::instruction::ParimutuelInitializeMarket {
+        crate::instruction::ParimutuelInitializeMarket {
+            market_seed: SEED.to_string(),
+            oracle_authority: Pubkey::default(),
+            token_mint: Pubkey::new_unique(),
+            target_market_cap: TARGET,
+            deadline: START + 30 * DAY,
+            max_oracle_skew_secs: 0,
+            grace_secs: 60 * 60,
+            claim_window_secs: 0,
+            title: "PEPE hits $1M".to_string(),
+            metadata_uri: String::new(),
+            resolution_policy: ResolutionPolicy::TargetWinsOnTie,
+        }
+    }
+    
+    /// Funded actors and no config yet, clock at START
+    fn unconfigured() -> Env {
+        static INSTALL_STUBS: Once = Once::new();
+        INSTALL_STUBS.call_once(|| {
+            set_syscall_stubs(Box::new(Stubs));
+        });
+        set_now(START);
+        EVENTS.with(|events| events.borrow_mut().clear());
+        
+        let mut env = Env {
+            bank: Bank::default(),
+            authority: Pubkey::new_unique(),
+            treasury: Pubkey::new_unique(),
+            creator: Pubkey::new_unique(),
+            oracle: Pubkey::new_unique(),
+        };
+        env.bank.fund(env.authority, 10 * SOL);
+        env.bank.fund(env.treasury, SOL);
+        env.bank.fund(env.creator, 10 * SOL);
+        env.bank.fund(env.oracle, SOL);
+        env
+    }
+    
+    /// `unconfigured` plus a config at the default creation fee
+    fn setup() -> Env {
+        let mut env = unconfigured();
+        env.initialize_config(DEFAULT_MARKET_CREATION_FEE).unwrap();
+        env
+    }
+    
+    /// `setup` plus an open market at SEED with the default arguments
+    fn setup_market() -> Env {
+        let mut env = setup();
+        env.create_market(market_args()).unwrap();
+        env
+    }
+    
+    impl Env {
+        fn bettor(&mut self) -> Pubkey {
+            let user = Pubkey::new_unique();
+            self.bank.fund(user, 100 * SOL);
+            user
+        }
+        
+        fn market(&self) -> Market {
+            self.bank.get(&market_key(SEED))
+        }
+        
+        fn escrow(&self) -> Pubkey {
+            escrow_key(market_key(SEED))
+        }
+        
+        fn initialize_config(&mut self, creation_fee_lamports: u64) -> ProgramResult {
+            let metas = crate::accounts::InitializeConfig {
+                config: config_key(),
+                authority: self.authority,
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None);
+            let treasury = self.treasury;
+            
+            self.bank.transact(&metas, &[(config_key(), Config::LEN)], self.authority, |infos| {
+                let mut accounts = InitializeConfig {
+                    config: Account::try_from_unchecked(&infos[0])?,
+                    authority: Signer::try_from(&infos[1])?,
+                    system_program: Program::try_from(&infos[2])?,
+                };
+                let bumps = InitializeConfigBumps { config: pda(&[b"config"]).1 };
+                initialize_config(
+                    Context::new(&ID, &mut accounts, &[], bumps),
+                    treasury,
+                    creation_fee_lamports,
+                )?;
+                accounts.exit(&ID)?;
+                Ok(())
+            })
+        }
+        
+        /// Create a market from `args`; a default oracle_authority is replaced by `self.oracle`
+        fn create_market(&mut self, args: crate::instruction::ParimutuelInitializeMarket) -> ProgramResult {
+            let config: Config = self.bank.get(&config_key());
+            let market = market_key(&args.market_seed);
+            let registry_entry = registry_key(config.market_count);
+            let metas = crate::accounts::InitializeMarket {
+                config: config_key(),
+                market,
+                escrow: escrow_key(market),
+                fee_escrow: fee_escrow_key(market),
+                registry_entry,
+                creator: self.creator,
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None);
+            let oracle_authority = if args.oracle_authority == Pubkey::default() {
+                self.oracle
+            } else {
+                args.oracle_authority
+            };
+            let init = [(market, Market::LEN), (registry_entry, MarketRegistryEntry::LEN)];
+            
+            self.bank.transact(&metas, &init, self.creator, |infos| {
+                let mut accounts = InitializeMarket {
+                    config: Account::try_from(&infos[0])?,
+                    market: Account::try_from_unchecked(&infos[1])?,
+                    escrow: infos[2].clone(),
+                    fee_escrow: infos[3].clone(),
+                    registry_entry: Account::try_from_unchecked(&infos[4])?,
+                    creator: Signer::try_from(&infos[5])?,
+                    system_program: Program::try_from(&infos[6])?,
+                };
+                let bumps = InitializeMarketBumps {
+                    market: pda(&[b"market", args.market_seed.as_bytes()]).1,
+                    escrow: pda(&[b"escrow", market.as_ref()]).1,
+                    fee_escrow: pda(&[b"fee_escrow", market.as_ref()]).1,
+                    registry_entry: pda(&[b"registry", config.market_count.to_le_bytes().as_ref()]).1,
+                };
+                initialize_market(
+                    Context::new(&ID, &mut accounts, &[], bumps),
+                    args.market_seed,
+                    oracle_authority,
+                    args.token_mint,
+                    args.target_market_cap,
+                    args.deadline,
+                    args.max_oracle_skew_secs,
+                    args.grace_secs,
+                    args.claim_window_secs,
+                    args.title,
+                    args.metadata_uri,
+                    args.resolution_policy,
+                )?;
+                accounts.exit(&ID)?;
+                Ok(())
+            })
+        }
+        
+        fn bet(&mut self, user: Pubkey, bet_index: u64, amount: u64, side: bool) -> ProgramResult {
+            let market = market_key(SEED);
+            let user_bet = user_bet_key(market, user, bet_index);
+            let metas = crate::accounts::PlaceBet {
+                config: config_key(),
+                market,
+                user_bet,
+                escrow: escrow_key(market),
+                user,
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None);
+            
+            self.bank.transact(&metas, &[(user_bet, UserBet::LEN)], user, |infos| {
+                let mut accounts = PlaceBet {
+                    config: Account::try_from(&infos[0])?,
+                    market: Account::try_from(&infos[1])?,
+                    user_bet: Account::try_from_unchecked(&infos[2])?,
+                    escrow: infos[3].clone(),
+                    user: Signer::try_from(&infos[4])?,
+                    system_program: Program::try_from(&infos[5])?,
+                };
+                place_bet(
+                    Context::new(&ID, &mut accounts, &[], PlaceBetBumps::default()),
+                    SEED.to_string(),
+                    bet_index,
+                    amount,
+                    side,
+                )?;
+                accounts.exit(&ID)?;
+                Ok(())
+            })
+        }
+        
+        fn resolve(&mut self, current_market_cap: u64, timestamp: i64, resolution: Option<Resolution>) -> ProgramResult {
+            let metas = crate::accounts::ResolveMarket {
+                market: market_key(SEED),
+                oracle: self.oracle,
+            }
+            .to_account_metas(None);
+            let data = crate::instruction::ParimutuelResolveMarket {
+                market_seed: SEED.to_string(),
+                current_market_cap,
+                timestamp,
+                resolution,
+            }
+            .data();
+            self.bank.process(metas, data)
+        }
+        
+        fn claim_with_escrow(&mut self, user: Pubkey, bet_index: u64, escrow: Pubkey) -> ProgramResult {
+            let market = market_key(SEED);
+            let metas = crate::accounts::ClaimReward {
+                market,
+                user_bet: user_bet_key(market, user, bet_index),
+                escrow,
+                user,
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None);
+            let data = crate::instruction::ParimutuelClaimReward {
+                market_seed: SEED.to_string(),
+                bet_index,
+            }
+            .data();
+            self.bank.process(metas, data)
+        }
+        
+        fn claim(&mut self, user: Pubkey, bet_index: u64) -> ProgramResult {
+            let escrow = self.escrow();
+            self.claim_with_escrow(user, bet_index, escrow)
+        }
+        
+        fn heartbeat(&mut self, current_market_cap: u64, timestamp: i64) -> ProgramResult {
+            let metas = crate::accounts::UpdateOracleHeartbeat {
+                market: market_key(SEED),
+                oracle: self.oracle,
+            }
+            .to_account_metas(None);
+            let data = crate::instruction::ParimutuelUpdateOracleHeartbeat {
+                market_seed: SEED.to_string(),
+                current_market_cap,
+                timestamp,
+            }
+            .data();
+            self.bank.process(metas, data)
+        }
+        
+        fn force_resolve(&mut self) -> ProgramResult {
+            let caller = self.bettor();
+            let metas = crate::accounts::ForceResolveDeadline {
+                market: market_key(SEED),
+                caller,
+            }
+            .to_account_metas(None);
+            let data = crate::instruction::ParimutuelForceResolveDeadline {
+                market_seed: SEED.to_string(),
+            }
+            .data();
+            self.bank.process(metas, data)
+        }
+        
+        fn collect_creation_fee(&mut self) -> ProgramResult {
+            self.collect_creation_fee_to(self.treasury)
+        }
+        
+        fn collect_creation_fee_to(&mut self, treasury: Pubkey) -> ProgramResult {
+            let market = market_key(SEED);
+            let metas = crate::accounts::CollectCreationFee {
+                config: config_key(),
+                market,
+                fee_escrow: fee_escrow_key(market),
+                treasury,
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None);
+            let data = crate::instruction::ParimutuelCollectCreationFee {
+                market_seed: SEED.to_string(),
+            }
+            .data();
+            self.bank.process(metas, data)
+        }
+        
+        fn refund_creation_fee(&mut self) -> ProgramResult {
+            let market = market_key(SEED);
+            let metas = crate::accounts::RefundCreationFee {
+                market,
+                fee_escrow: fee_escrow_key(market),
+                creator: self.creator,
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None);
+            let data = crate::instruction::ParimutuelRefundCreationFee {
+                market_seed: SEED.to_string(),
+            }
+            .data();
+            self.bank.process(metas, data)
+        }
+        
+        fn close_market(&mut self) -> ProgramResult {
+            let market = market_key(SEED);
+            let metas = crate::accounts::CloseMarket {
+                config: config_key(),
+                market,
+                escrow: escrow_key(market),
+                treasury: self.treasury,
+                creator: self.creator,
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None);
+            let data = crate::instruction::ParimutuelCloseMarket {
+                market_seed: SEED.to_string(),
+            }
+            .data();
+            self.bank.process(metas, data)
+        }
+        
+        fn sweep_expired_claims(&mut self) -> ProgramResult {
+            let caller = self.bettor();
+            let market = market_key(SEED);
+            let metas = crate::accounts::SweepExpiredClaims {
+                config: config_key(),
+                market,
+                escrow: escrow_key(market),
+                treasury: self.treasury,
+                caller,
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None);
+            let data = crate::instruction::ParimutuelSweepExpiredClaims {
+                market_seed: SEED.to_string(),
+            }
+            .data();
+            self.bank.process(metas, data)
+        }
+        
+        fn sweep_unclaimed(&mut self) -> ProgramResult {
+            let market = market_key(SEED);
+            let metas = crate::accounts::SweepUnclaimed {
+                market,
+                escrow: escrow_key(market),
+                creator: self.creator,
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None);
+            let data = crate::instruction::ParimutuelSweepUnclaimed {
+                market_seed: SEED.to_string(),
+            }
+            .data();
+            self.bank.process(metas, data)
+        }
+        
+        
+        fn set_market_duration_bounds(&mut self, min_market_duration_secs: i64, max_market_duration_secs: i64) -> ProgramResult {
+            let metas = crate::accounts::UpdateConfig {
+                config: config_key(),
+                authority: self.authority,
+            }
+            .to_account_metas(None);
+            let data = crate::instruction::ParimutuelSetMarketDurationBounds {
+                min_market_duration_secs,
+                max_market_duration_secs,
+            }
+            .data();
+            self.bank.process(metas, data)
+        }
+        
+        
+        fn propose_emergency_withdraw(&mut self, recovery: Pubkey) -> ProgramResult {
+            let metas = crate::accounts::ProposeEmergencyWithdraw {
+                config: config_key(),
+                market: market_key(SEED),
+                authority: self.authority,
+            }
+            .to_account_metas(None);
+            let data = crate::instruction::ParimutuelProposeEmergencyWithdraw {
+                market_seed: SEED.to_string(),
+                recovery,
+            }
+            .data();
+            self.bank.process(metas, data)
+        }
+        
+        fn emergency_withdraw(&mut self, recovery: Pubkey) -> ProgramResult {
+            let market = market_key(SEED);
+            let metas = crate::accounts::EmergencyWithdraw {
+                config: config_key(),
+                market,
+                escrow: escrow_key(market),
+                recovery,
+                authority: self.authority,
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None);
+            let data = crate::instruction::ParimutuelEmergencyWithdraw {
+                market_seed: SEED.to_string(),
+            }
+            .data();
+            self.bank.process(metas, data)
+        }
+        
+        
+        fn set_switchboard_feed(&mut self, feed: Pubkey) -> ProgramResult {
+            let metas = crate::accounts::SetSwitchboardFeed {
+                market: market_key(SEED),
+                oracle: self.oracle,
+            }
+            .to_account_metas(None);
+            let data = crate::instruction::ParimutuelSetSwitchboardFeed {
+                market_seed: SEED.to_string(),
+                feed,
+            }
+            .data();
+            self.bank.process(metas, data)
+        }
+        
+        fn resolve_from_switchboard(&mut self, feed: Pubkey) -> ProgramResult {
+            let caller = self.bettor();
+            let metas = crate::accounts::ResolveFromSwitchboard {
+                market: market_key(SEED),
+                feed,
+                caller,
+            }
+            .to_account_metas(None);
+            let data = crate::instruction::ParimutuelResolveMarketFromSwitchboard {
+                market_seed: SEED.to_string(),
+            }
+            .data();
+            self.bank.process(metas, data)
+        }
+        
+        /// Store a Switchboard aggregator at `feed` whose latest round reports
+        /// `mantissa * 10^-scale` dollars from `num_success` of `min_oracle_results` oracles
+        fn put_switchboard_round(&mut self, feed: Pubkey, num_success: u32, min_oracle_results: u32, round_open_timestamp: i64, mantissa: i128, scale: u32) {
+            let mut data = vec![0; SWITCHBOARD_RESULT_SCALE_OFFSET + 4];
+            let mut put = |offset: usize, bytes: &[u8]| data[offset..offset + bytes.len()].copy_from_slice(bytes);
+            put(0, &SWITCHBOARD_AGGREGATOR_DISCRIMINATOR);
+            put(SWITCHBOARD_MIN_ORACLE_RESULTS_OFFSET, &min_oracle_results.to_le_bytes());
+            put(SWITCHBOARD_NUM_SUCCESS_OFFSET, &num_success.to_le_bytes());
+            put(SWITCHBOARD_ROUND_OPEN_TIMESTAMP_OFFSET, &round_open_timestamp.to_le_bytes());
+            put(SWITCHBOARD_RESULT_MANTISSA_OFFSET, &mantissa.to_le_bytes());
+            put(SWITCHBOARD_RESULT_SCALE_OFFSET, &scale.to_le_bytes());
+            self.bank.accounts.insert(feed, TestAccount {
+                owner: SWITCHBOARD_PROGRAM_ID,
+                lamports: rent(data.len()),
+                data,
+            });
+        }
+        
+        
+        fn close_losing_bet(&mut self, user: Pubkey, bet_index: u64) -> ProgramResult {
+            let market = market_key(SEED);
+            let metas = crate::accounts::CloseLosingBet {
+                market,
+                user_bet: user_bet_key(market, user, bet_index),
+                user,
+            }
+            .to_account_metas(None);
+            let data = crate::instruction::ParimutuelCloseLosingBet {
+                market_seed: SEED.to_string(),
+                bet_index,
+            }
+            .data();
+            self.bank.process(metas, data)
+        }
+        
+        
+        fn set_oracle_authority(&mut self, authority: Pubkey, new_oracle: Pubkey) -> ProgramResult {
+            let metas = crate::accounts::SetOracleAuthority {
+                config: config_key(),
+                market: market_key(SEED),
+                authority,
+            }
+            .to_account_metas(None);
+            let data = crate::instruction::ParimutuelSetOracleAuthority {
+                market_seed: SEED.to_string(),
+                new_oracle,
+            }
+            .data();
+            self.bank.process(metas, data)
+        }
+        
+        
+        fn create_categorical_market(&mut self, outcome_count: u8) -> ProgramResult {
+            let market = categorical_market_key(SEED);
+            let metas = crate::accounts::InitializeCategoricalMarket {
+                config: config_key(),
+                market,
+                escrow: escrow_key(market),
+                treasury: self.treasury,
+                creator: self.creator,
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None);
+            let oracle_authority = self.oracle;
+            
+            self.bank.transact(&metas, &[(market, CategoricalMarket::LEN)], self.creator, |infos| {
+                let mut accounts = InitializeCategoricalMarket {
+                    config: Account::try_from(&infos[0])?,
+                    market: Account::try_from_unchecked(&infos[1])?,
+                    escrow: infos[2].clone(),
+                    treasury: infos[3].clone(),
+                    creator: Signer::try_from(&infos[4])?,
+                    system_program: Program::try_from(&infos[5])?,
+                };
+                let bumps = InitializeCategoricalMarketBumps {
+                    market: pda(&[b"categorical_market", SEED.as_bytes()]).1,
+                    escrow: pda(&[b"escrow", market.as_ref()]).1,
+                };
+                initialize_categorical_market(
+                    Context::new(&ID, &mut accounts, &[], bumps),
+                    SEED.to_string(),
+                    oracle_authority,
+                    outcome_count,
+                    START + 30 * DAY,
+                    60 * 60,
+                    "Which meme flips first".to_string(),
+                )?;
+                accounts.exit(&ID)?;
+                Ok(())
+            })
+        }
+        
+        fn categorical_bet(&mut self, user: Pubkey, amount: u64, outcome_index: u8) -> ProgramResult {
+            let market = categorical_market_key(SEED);
+            let user_bet = categorical_bet_key(market, user);
+            let metas = crate::accounts::PlaceCategoricalBet {
+                config: config_key(),
+                market,
+                user_bet,
+                escrow: escrow_key(market),
+                user,
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None);
+            
+            self.bank.transact(&metas, &[(user_bet, CategoricalBet::LEN)], user, |infos| {
+                let mut accounts = PlaceCategoricalBet {
+                    config: Account::try_from(&infos[0])?,
+                    market: Account::try_from(&infos[1])?,
+                    user_bet: Account::try_from_unchecked(&infos[2])?,
+                    escrow: infos[3].clone(),
+                    user: Signer::try_from(&infos[4])?,
+                    system_program: Program::try_from(&infos[5])?,
+                };
+                place_categorical_bet(
+                    Context::new(&ID, &mut accounts, &[], PlaceCategoricalBetBumps::default()),
+                    SEED.to_string(),
+                    amount,
+                    outcome_index,
+                )?;
+                accounts.exit(&ID)?;
+                Ok(())
+            })
+        }
+        
+        fn resolve_categorical(&mut self, winning_outcome: u8) -> ProgramResult {
+            let metas = crate::accounts::ResolveCategoricalMarket {
+                market: categorical_market_key(SEED),
+                oracle: self.oracle,
+            }
+            .to_account_metas(None);
+            let data = crate::instruction::ParimutuelResolveCategoricalMarket {
+                market_seed: SEED.to_string(),
+                winning_outcome,
+            }
+            .data();
+            self.bank.process(metas, data)
+        }
+        
+        fn claim_categorical(&mut self, user: Pubkey) -> ProgramResult {
+            let market = categorical_market_key(SEED);
+            let metas = crate::accounts::ClaimCategoricalReward {
+                market,
+                user_bet: categorical_bet_key(market, user),
+                escrow: escrow_key(market),
+                user,
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None);
+            let data = crate::instruction::ParimutuelClaimCategoricalReward {
+                market_seed: SEED.to_string(),
+            }
+            .data();
+            self.bank.process(metas, data)
+        }
+        
+        fn force_void_categorical(&mut self) -> ProgramResult {
+            let caller = self.bettor();
+            let metas = crate::accounts::ForceVoidCategoricalMarket {
+                market: categorical_market_key(SEED),
+                caller,
+            }
+            .to_account_metas(None);
+            let data = crate::instruction::ParimutuelForceVoidCategoricalMarket {
+                market_seed: SEED.to_string(),
+            }
+            .data();
+            self.bank.process(metas, data)
+        }
+        
+        fn sweep_unclaimed_categorical(&mut self) -> ProgramResult {
+            let market = categorical_market_key(SEED);
+            let metas = crate::accounts::SweepUnclaimedCategorical {
+                market,
+                escrow: escrow_key(market),
+                creator: self.creator,
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None);
+            let data = crate::instruction::ParimutuelSweepUnclaimedCategorical {
+                market_seed: SEED.to_string(),
+            }
+            .data();
+            self.bank.process(metas, data)
+        }
+        
+        fn close_categorical(&mut self) -> ProgramResult {
+            let market = categorical_market_key(SEED);
+            let metas = crate::accounts::CloseCategoricalMarket {
+                config: config_key(),
+                market,
+                escrow: escrow_key(market),
+                treasury: self.treasury,
+                creator: self.creator,
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None);
+            let data = crate::instruction::ParimutuelCloseCategoricalMarket {
+                market_seed: SEED.to_string(),
+            }
+            .data();
+            self.bank.process(metas, data)
+        }
+        
+        
+        fn set_paused(&mut self, paused: bool) -> ProgramResult {
+            let metas = crate::accounts::UpdateConfig {
+                config: config_key(),
+                authority: self.authority,
+            }
+            .to_account_metas(None);
+            let data = crate::instruction::ParimutuelSetPaused { paused }.data();
+            self.bank.process(metas, data)
+        }
+        
+        
+        fn claim_and_bet(&mut self, user: Pubkey, target_seed: &str, target_bet_index: u64, amount: Option<u64>, side: bool) -> ProgramResult {
+            let source_market = market_key(SEED);
+            let target_market = market_key(target_seed);
+            let target_user_bet = user_bet_key(target_market, user, target_bet_index);
+            let metas = crate::accounts::ClaimAndBet {
+                config: config_key(),
+                source_market,
+                source_user_bet: user_bet_key(source_market, user, 0),
+                source_escrow: escrow_key(source_market),
+                target_market,
+                target_user_bet,
+                target_escrow: escrow_key(target_market),
+                user,
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None);
+            
+            self.bank.transact(&metas, &[(target_user_bet, UserBet::LEN)], user, |infos| {
+                let mut accounts = ClaimAndBet {
+                    config: Account::try_from(&infos[0])?,
+                    source_market: Account::try_from(&infos[1])?,
+                    source_user_bet: Account::try_from(&infos[2])?,
+                    source_escrow: infos[3].clone(),
+                    target_market: Account::try_from(&infos[4])?,
+                    target_user_bet: Account::try_from_unchecked(&infos[5])?,
+                    target_escrow: infos[6].clone(),
+                    user: Signer::try_from(&infos[7])?,
+                    system_program: Program::try_from(&infos[8])?,
+                };
+                claim_and_bet(
+                    Context::new(&ID, &mut accounts, &[], ClaimAndBetBumps::default()),
+                    SEED.to_string(),
+                    target_seed.to_string(),
+                    0,
+                    target_bet_index,
+                    amount,
+                    side,
+                )?;
+                accounts.exit(&ID)?;
+                Ok(())
+            })
+        }
+        
+        
+        /// (deadline_passed, seconds_to_deadline, is_resolved) as reported by is_resolvable
+        fn is_resolvable(&mut self) -> (bool, i64, bool) {
+            let metas = crate::accounts::IsResolvable {
+                market: market_key(SEED),
+            }
+            .to_account_metas(None);
+            let data = crate::instruction::ParimutuelIsResolvable {
+                market_seed: SEED.to_string(),
+            }
+            .data();
+            self.bank.process(metas, data).unwrap();
+            RETURN_DATA.with(|return_data| {
+                AnchorDeserialize::deserialize(&mut return_data.borrow().as_slice()).unwrap()
+            })
+        }
+        
+        
+        fn set_block_bets_after_target(&mut self, enabled: bool) -> ProgramResult {
+            let metas = crate::accounts::UpdateConfig {
+                config: config_key(),
+                authority: self.authority,
+            }
+            .to_account_metas(None);
+            let data = crate::instruction::ParimutuelSetBlockBetsAfterTarget { enabled }.data();
+            self.bank.process(metas, data)
+        }
+        
+        
+        fn set_creation_fee(&mut self, authority: Pubkey, creation_fee_lamports: u64) -> ProgramResult {
+            let metas = crate::accounts::UpdateConfig {
+                config: config_key(),
+                authority,
+            }
+            .to_account_metas(None);
+            let data = crate::instruction::ParimutuelSetCreationFee { creation_fee_lamports }.data();
+            self.bank.process(metas, data)
+        }
+    }
+    
+    #[test]
+    fn last_claim_absorbs_a_rounding_shortfall() {
+        let mut env = setup_market();
+        let (alice, bob, carol) = (env.bettor(), env.bettor(), env.bettor());
+        env.bet(alice, 0, SOL, true).unwrap();
+        env.bet(bob, 0, 2 * SOL, true).unwrap();
+        env.bet(carol, 0, SOL, false).unwrap();
+        env.resolve(TARGET, START, None).unwrap();
+        
+        // Rewards floor to 1_333_333_333 + 2_666_666_666; leave the escrow 1 lamport
+        // short of that sum
+        let escrow = env.escrow();
+        env.bank.set_lamports(&escrow, rent(0) + 3_999_999_998);
+        
+        let before = env.bank.lamports(&alice);
+        env.claim(alice, 0).unwrap();
+        assert_eq!(env.bank.lamports(&alice) - before, 1_333_333_333 + rent(UserBet::LEN));
+        
+        let before = env.bank.lamports(&bob);
+        env.claim(bob, 0).unwrap();
+        assert_eq!(env.bank.lamports(&bob) - before, 2_666_666_665 + rent(UserBet::LEN));
+        assert_eq!(env.bank.lamports(&escrow), rent(0));
+        assert_eq!(env.market().total_claimed_lamports, 3_999_999_998);
+    }
+    
+    #[test]
+    fn claim_against_a_near_empty_escrow_fails_cleanly() {
+        let mut env = setup_market();
+        let (alice, bob) = (env.bettor(), env.bettor());
+        env.bet(alice, 0, SOL, true).unwrap();
+        env.bet(bob, 0, SOL, false).unwrap();
+        env.resolve(TARGET, START, None).unwrap();
+        
+        let escrow = env.escrow();
+        env.bank.set_lamports(&escrow, rent(0) + 1);
+        
+        assert_eq!(env.claim(alice, 0), err(ParimutuelError::InsufficientEscrow));
+        assert_eq!(env.bank.lamports(&escrow), rent(0) + 1);
+        assert_eq!(env.market().total_claimed_lamports, 0);
+    }
+    
+    #[test]
+    fn claim_rejects_a_shortfall_beyond_rounding_dust() {
+        let mut env = setup_market();
+        let (alice, bob, carol) = (env.bettor(), env.bettor(), env.bettor());
+        env.bet(alice, 0, SOL, true).unwrap();
+        env.bet(bob, 0, 2 * SOL, true).unwrap();
+        env.bet(carol, 0, SOL, false).unwrap();
+        env.resolve(TARGET, START, None).unwrap();
+        
+        // Two winning bets can lose at most one lamport of rounding each, so a shortfall
+        // of two means the escrow was drained
+        let escrow = env.escrow();
+        env.bank.set_lamports(&escrow, rent(0) + 3_999_999_997);
+        env.claim(alice, 0).unwrap();
+        
+        assert_eq!(env.claim(bob, 0), err(ParimutuelError::InsufficientEscrow));
+        assert!(!env.bank.get::<UserBet>(&user_bet_key(market_key(SEED), bob, 0)).claimed);
+    }
+    
+    #[test]
+    fn set_creation_fee_updates_the_config() {
+        let mut env = setup();
+        
+        env.set_creation_fee(env.authority, 2 * DEFAULT_MARKET_CREATION_FEE).unwrap();
+        assert_eq!(
+            env.bank.get::<Config>(&config_key()).creation_fee_lamports,
+            2 * DEFAULT_MARKET_CREATION_FEE
+        );
+        
+        env.set_creation_fee(env.authority, 0).unwrap();
+        assert_eq!(env.bank.get::<Config>(&config_key()).creation_fee_lamports, 0);
+        
+        let stranger = env.bettor();
+        assert_eq!(env.set_creation_fee(stranger, 1), err(ParimutuelError::Unauthorized));
+    }
+    
+    #[test]
+    fn creation_fee_below_escrow_rent_is_rejected() {
+        let mut env = setup();
+        assert_eq!(
+            env.set_creation_fee(env.authority, rent(0) - 1),
+            err(ParimutuelError::InvalidCreationFee)
+        );
+        env.set_creation_fee(env.authority, rent(0)).unwrap();
+        
+        let mut env = unconfigured();
+        assert_eq!(env.initialize_config(1), err(ParimutuelError::InvalidCreationFee));
+        env.initialize_config(0).unwrap();
+    }
+    
+    #[test]
+    fn market_is_created_at_the_updated_fee() {
+        let mut env = setup();
+        let fee = 3 * DEFAULT_MARKET_CREATION_FEE;
+        env.set_creation_fee(env.authority, fee).unwrap();
+        
+        env.create_market(market_args()).unwrap();
+        
+        assert_eq!(env.market().creation_fee_held, fee);
+        assert_eq!(env.bank.lamports(&fee_escrow_key(market_key(SEED))), fee);
+    }
+    
+    #[test]
+    fn lapsed_oracle_is_force_resolved_no_and_claimable() {
+        let mut env = setup_market();
+        let (alice, bob) = (env.bettor(), env.bettor());
+        env.bet(alice, 0, SOL, true).unwrap();
+        env.bet(bob, 0, SOL, false).unwrap();
+        
+        let force_resolve_at = env.market().deadline + FORCE_RESOLVE_GRACE_SECS;
+        set_now(force_resolve_at - 1);
+        assert_eq!(env.force_resolve(), err(ParimutuelError::ForceResolveTooEarly));
+        
+        set_now(force_resolve_at);
+        env.force_resolve().unwrap();
+        let market = env.market();
+        assert_eq!(market.winner, Some(false));
+        assert_eq!(market.resolution_source, ResolutionSource::ForcedDeadline);
+        
+        assert_eq!(env.claim(alice, 0), err(ParimutuelError::NotWinner));
+        let before = env.bank.lamports(&bob);
+        env.claim(bob, 0).unwrap();
+        assert_eq!(env.bank.lamports(&bob) - before, 2 * SOL + rent(UserBet::LEN));
+    }
+    
+    #[test]
+    fn force_resolve_is_refused_once_the_target_was_reported() {
+        let mut env = setup_market();
+        env.heartbeat(TARGET, START).unwrap();
+        
+        set_now(env.market().deadline + FORCE_RESOLVE_GRACE_SECS);
+        assert_eq!(env.force_resolve(), err(ParimutuelError::ResolutionMismatch));
+        assert!(!env.market().is_resolved);
+    }
+    
+    #[test]
+    fn lifecycle_events_follow_the_market_through_each_phase() {
+        let mut env = setup_market();
+        let (alice, bob) = (env.bettor(), env.bettor());
+        env.bet(alice, 0, SOL, true).unwrap();
+        env.bet(bob, 0, SOL, false).unwrap();
+        env.resolve(TARGET, START, None).unwrap();
+        env.claim(alice, 0).unwrap();
+        env.collect_creation_fee().unwrap();
+        env.close_market().unwrap();
+        
+        assert_eq!(
+            take_phases(),
+            [
+                MarketPhase::Created,
+                MarketPhase::BetPlaced,
+                MarketPhase::BetPlaced,
+                MarketPhase::Resolved,
+                MarketPhase::Claimed,
+                MarketPhase::Closed,
+            ]
+        );
+    }
+    
+    #[test]
+    fn refund_outcomes_are_reported_as_voided() {
+        let mut env = setup_market();
+        let alice = env.bettor();
+        env.bet(alice, 0, SOL, true).unwrap();
+        env.resolve(TARGET, START, Some(Resolution::Push)).unwrap();
+        env.claim(alice, 0).unwrap();
+        assert_eq!(
+            take_phases(),
+            [MarketPhase::Created, MarketPhase::BetPlaced, MarketPhase::Voided, MarketPhase::Claimed]
+        );
+        
+        let mut env = setup_market();
+        set_now(env.market().deadline);
+        env.refund_creation_fee().unwrap();
+        assert_eq!(take_phases(), [MarketPhase::Created, MarketPhase::Voided]);
+    }
+    
+    #[test]
+    fn close_market_sweeps_only_the_rounding_remainder() {
+        let mut env = setup_market();
+        let (alice, bob, carol) = (env.bettor(), env.bettor(), env.bettor());
+        env.bet(alice, 0, SOL, true).unwrap();
+        env.bet(bob, 0, 2 * SOL, true).unwrap();
+        env.bet(carol, 0, SOL, false).unwrap();
+        env.resolve(TARGET, START, None).unwrap();
+        env.claim(alice, 0).unwrap();
+        env.claim(bob, 0).unwrap();
+        env.collect_creation_fee().unwrap();
+        
+        // 4 SOL floored across stakes of 1 and 2 SOL pays out 3_999_999_999
+        assert_eq!(env.bank.lamports(&env.escrow()), rent(0) + 1);
+        let treasury_before = env.bank.lamports(&env.treasury);
+        let creator_before = env.bank.lamports(&env.creator);
+        env.close_market().unwrap();
+        
+        assert_eq!(env.bank.lamports(&env.treasury) - treasury_before, 1);
+        assert_eq!(
+            env.bank.lamports(&env.creator) - creator_before,
+            rent(0) + rent(Market::LEN)
+        );
+    }
+    
+    #[test]
+    fn unbacked_losing_pool_waits_for_the_claim_window() {
+        let mut env = setup();
+        env.create_market(crate::instruction::ParimutuelInitializeMarket {
+            claim_window_secs: DAY,
+            ..market_args()
+        })
+        .unwrap();
+        let bob = env.bettor();
+        env.bet(bob, 0, SOL, false).unwrap();
+        env.resolve(TARGET, START, None).unwrap();
+        env.collect_creation_fee().unwrap();
+        
+        // Nobody backed YES, so the whole NO pool is unclaimable - not rounding dust
+        assert_eq!(env.close_market(), err(ParimutuelError::ResidueNotSwept));
+        assert_eq!(env.sweep_expired_claims(), err(ParimutuelError::SweepTooEarly));
+        
+        set_now(START + DAY);
+        let treasury_before = env.bank.lamports(&env.treasury);
+        env.sweep_expired_claims().unwrap();
+        env.close_market().unwrap();
+        assert_eq!(env.bank.lamports(&env.treasury) - treasury_before, SOL);
+    }
+    
+    #[test]
+    fn stale_bet_cannot_claim_against_a_recreated_market() {
+        let mut env = setup();
+        env.create_market(crate::instruction::ParimutuelInitializeMarket {
+            claim_window_secs: DAY,
+            ..market_args()
+        })
+        .unwrap();
+        let (alice, bob) = (env.bettor(), env.bettor());
+        env.bet(alice, 0, SOL, true).unwrap();
+        env.bet(bob, 0, SOL, false).unwrap();
+        env.resolve(TARGET, START, None).unwrap();
+        
+        // Alice never claims; her reward is swept and the market closed
+        set_now(START + DAY);
+        env.sweep_expired_claims().unwrap();
+        env.collect_creation_fee().unwrap();
+        env.close_market().unwrap();
+        
+        // Same seed, same addresses, fresh escrow
+        env.create_market(market_args()).unwrap();
+        let (carol, dave) = (env.bettor(), env.bettor());
+        env.bet(carol, 0, SOL, true).unwrap();
+        env.bet(dave, 0, SOL, false).unwrap();
+        env.resolve(TARGET, START + DAY, None).unwrap();
+        
+        assert_eq!(env.claim(alice, 0), err(ParimutuelError::InvalidMarket));
+        env.claim(carol, 0).unwrap();
+    }
+    
+    #[test]
+    fn calculate_reward_rounds_down() {
+        // 1 of 3 lamports backing the winner, 10 in the pool: 10/3 floors to 3
+        assert_eq!(calculate_reward(1, 3, 10).unwrap(), 3);
+        assert_eq!(calculate_reward(2, 3, 10).unwrap(), 6);
+        // Exact division leaves no remainder
+        assert_eq!(calculate_reward(SOL, 2 * SOL, 4 * SOL).unwrap(), 2 * SOL);
+    }
+    
+    #[test]
+    fn calculate_reward_pays_a_single_winner_the_whole_pool() {
+        assert_eq!(calculate_reward(SOL, SOL, 7 * SOL).unwrap(), 7 * SOL);
+        assert_eq!(calculate_reward(1, 1, 1).unwrap(), 1);
+    }
+    
+    #[test]
+    fn calculate_reward_rejects_an_empty_winning_pool() {
+        assert_eq!(
+            calculate_reward(SOL, 0, SOL).unwrap_err(),
+            ParimutuelError::DivisionByZero.into()
+        );
+    }
+    
+    #[test]
+    fn calculate_reward_handles_overflow_sized_pools() {
+        // The u64 product overflows but the u128 intermediate does not
+        assert_eq!(calculate_reward(u64::MAX, u64::MAX, u64::MAX).unwrap(), u64::MAX);
+        assert_eq!(calculate_reward(u64::MAX / 2, u64::MAX - 1, u64::MAX - 1).unwrap(), u64::MAX / 2);
+        // A payout above u64::MAX is an error, not a wrap
+        assert_eq!(
+            calculate_reward(u64::MAX, 1, 2).unwrap_err(),
+            ParimutuelError::Overflow.into()
+        );
+    }
+    
+    /// xorshift64 - deterministic, so a failing case can be replayed from its iteration
+    fn next_random(state: &mut u64) -> u64 {
+        *state ^= *state << 13;
+        *state ^= *state >> 7;
+        *state ^= *state << 17;
+        *state
+    }
+    
+    #[test]
+    fn random_pools_pay_out_at_most_the_pool_and_less_than_a_lamport_per_winner() {
+        let mut state = 0x9E37_79B9_7F4A_7C15;
+        for iteration in 0..2_000 {
+            let bet_count = 1 + next_random(&mut state) % 32;
+            let bets: Vec<(u64, bool)> = (0..bet_count)
+                .map(|_| {
+                    // Mix dust-sized and whale-sized stakes
+                    let amount = match next_random(&mut state) % 3 {
+                        0 => 1 + next_random(&mut state) % 1_000,
+                        1 => 1 + next_random(&mut state) % (10 * SOL),
+                        _ => 1 + next_random(&mut state) % (1_000_000 * SOL),
+                    };
+                    (amount, next_random(&mut state) & 1 == 0)
+                })
+                .collect();
+            let winner = next_random(&mut state) & 1 == 0;
+            
+            let total_pool: u64 = bets.iter().map(|(amount, _)| amount).sum();
+            let winning_bets: Vec<u64> = bets
+                .iter()
+                .filter(|(_, side)| *side == winner)
+                .map(|(amount, _)| *amount)
+                .collect();
+            let winning_pool: u64 = winning_bets.iter().sum();
+            if winning_pool == 0 {
+                continue;
+            }
+            
+            let paid: u64 = winning_bets
+                .iter()
+                .map(|amount| calculate_reward(*amount, winning_pool, total_pool).unwrap())
+                .sum();
+            assert!(paid <= total_pool, "iteration {iteration}: paid {paid} of {total_pool}");
+            assert!(
+                total_pool - paid < winning_bets.len() as u64,
+                "iteration {iteration}: dust {} with {} winners",
+                total_pool - paid,
+                winning_bets.len()
+            );
+        }
+    }
+    
+    #[test]
+    fn creation_fee_only_reaches_the_configured_treasury() {
+        let mut env = setup_market();
+        let alice = env.bettor();
+        env.bet(alice, 0, SOL, true).unwrap();
+        
+        let impostor = env.bettor();
+        assert_eq!(
+            env.collect_creation_fee_to(impostor),
+            err(ParimutuelError::InvalidTreasury)
+        );
+        assert_eq!(env.bank.lamports(&impostor), 100 * SOL);
+        
+        let before = env.bank.lamports(&env.treasury);
+        env.collect_creation_fee().unwrap();
+        assert_eq!(env.bank.lamports(&env.treasury) - before, DEFAULT_MARKET_CREATION_FEE);
+    }
+    
+    #[test]
+    fn heartbeat_is_recorded_without_resolving() {
+        let mut env = setup_market();
+        set_now(START + 600);
+        env.heartbeat(TARGET / 2, START + 590).unwrap();
+        
+        let market = env.market();
+        assert_eq!(market.last_market_cap, TARGET / 2);
+        assert_eq!(market.last_oracle_update, START + 590);
+        assert!(!market.is_resolved);
+        
+        // Heartbeats only move forward in time
+        assert_eq!(env.heartbeat(TARGET / 4, START + 580), err(ParimutuelError::StaleData));
+        assert_eq!(env.market().last_market_cap, TARGET / 2);
+    }
+    
+    #[test]
+    fn resolution_stores_the_reported_cap() {
+        let mut env = setup_market();
+        set_now(START + 60);
+        env.resolve(TARGET + 123_456, START + 55, None).unwrap();
+        
+        let market = env.market();
+        assert_eq!(market.last_market_cap, TARGET + 123_456);
+        assert_eq!(market.last_oracle_update, START + 55);
+        assert_eq!(market.winner, Some(true));
+    }
+    
+    #[test]
+    fn creation_fee_is_refunded_when_nobody_bets() {
+        let mut env = setup_market();
+        assert_eq!(env.refund_creation_fee(), err(ParimutuelError::CannotResolveYet));
+        
+        set_now(env.market().deadline);
+        let before = env.bank.lamports(&env.creator);
+        env.refund_creation_fee().unwrap();
+        
+        assert_eq!(env.bank.lamports(&env.creator) - before, DEFAULT_MARKET_CREATION_FEE);
+        assert_eq!(env.market().creation_fee_held, 0);
+        assert_eq!(env.collect_creation_fee(), err(ParimutuelError::NoBetsPlaced));
+    }
+    
+    #[test]
+    fn creation_fee_is_not_refunded_once_a_bet_lands() {
+        let mut env = setup_market();
+        let alice = env.bettor();
+        env.bet(alice, 0, SOL, true).unwrap();
+        
+        set_now(env.market().deadline);
+        assert_eq!(env.refund_creation_fee(), err(ParimutuelError::MarketHasBets));
+        
+        let before = env.bank.lamports(&env.treasury);
+        env.collect_creation_fee().unwrap();
+        assert_eq!(env.bank.lamports(&env.treasury) - before, DEFAULT_MARKET_CREATION_FEE);
+    }
+    
+    #[test]
+    fn oracle_reports_outside_the_skew_are_stale() {
+        let mut env = setup_market();
+        let now = START + DAY;
+        set_now(now);
+        let skew = DEFAULT_MAX_ORACLE_SKEW_SECS;
+        
+        assert_eq!(env.resolve(TARGET, now - skew - 1, None), err(ParimutuelError::StaleData));
+        assert_eq!(env.resolve(TARGET, now + skew + 1, None), err(ParimutuelError::StaleData));
+        assert!(!env.market().is_resolved);
+        
+        env.resolve(TARGET, now - skew, None).unwrap();
+        assert!(env.market().is_resolved);
+    }
+    
+    #[test]
+    fn bets_close_a_grace_period_before_the_deadline() {
+        let mut env = setup_market();
+        let alice = env.bettor();
+        let market = env.market();
+        assert_eq!(market.betting_closes_at, market.deadline - 60 * 60);
+        
+        set_now(market.betting_closes_at - 1);
+        env.bet(alice, 0, SOL, true).unwrap();
+        
+        set_now(market.betting_closes_at);
+        assert_eq!(env.bet(alice, 1, SOL, true), err(ParimutuelError::DeadlinePassed));
+        assert_eq!(env.market().total_yes_pool, SOL);
+    }
+    
+    #[test]
+    fn sweep_unclaimed_takes_only_the_residue_and_forfeits_later_claims() {
+        let mut env = setup_market();
+        let (alice, bob, carol) = (env.bettor(), env.bettor(), env.bettor());
+        env.bet(alice, 0, SOL, true).unwrap();
+        env.bet(bob, 0, SOL, true).unwrap();
+        env.bet(carol, 0, SOL, false).unwrap();
+        env.resolve(TARGET, START, None).unwrap();
+        
+        let before = env.bank.lamports(&alice);
+        env.claim(alice, 0).unwrap();
+        let alice_paid = env.bank.lamports(&alice) - before;
+        
+        let sweep_opens_at = env.market().resolved_at + UNCLAIMED_SWEEP_GRACE_SECS;
+        set_now(sweep_opens_at - 1);
+        assert_eq!(env.sweep_unclaimed(), err(ParimutuelError::SweepTooEarly));
+        
+        set_now(sweep_opens_at);
+        let before = env.bank.lamports(&env.creator);
+        env.sweep_unclaimed().unwrap();
+        assert_eq!(env.bank.lamports(&env.creator) - before, 3 * SOL / 2);
+        assert_eq!(env.bank.lamports(&env.escrow()), rent(0));
+        assert_eq!(alice_paid, 3 * SOL / 2 + rent(UserBet::LEN));
+        
+        assert_eq!(env.claim(bob, 0), err(ParimutuelError::RewardsForfeited));
+        assert_eq!(env.sweep_unclaimed(), err(ParimutuelError::RewardsForfeited));
+    }
+    
+    #[test]
+    fn deadline_must_fall_within_the_duration_bounds() {
+        let mut env = setup();
+        let too_soon = crate::instruction::ParimutuelInitializeMarket {
+            deadline: START + DEFAULT_MIN_MARKET_DURATION_SECS - 1,
+            grace_secs: 0,
+            ..market_args()
+        };
+        assert_eq!(env.create_market(too_soon), err(ParimutuelError::DeadlineTooSoon));
+        
+        env.set_market_duration_bounds(DAY, 7 * DAY).unwrap();
+        let too_far = crate::instruction::ParimutuelInitializeMarket {
+            deadline: START + 7 * DAY + 1,
+            ..market_args()
+        };
+        assert_eq!(env.create_market(too_far), err(ParimutuelError::DeadlineTooFar));
+        
+        let at_max = crate::instruction::ParimutuelInitializeMarket {
+            deadline: START + 7 * DAY,
+            ..market_args()
+        };
+        env.create_market(at_max).unwrap();
+        assert_eq!(env.market().deadline, START + 7 * DAY);
+    }
+    
+    #[test]
+    fn second_claim_finds_the_bet_closed_and_pays_nothing() {
+        let mut env = setup_market();
+        let (alice, bob) = (env.bettor(), env.bettor());
+        env.bet(alice, 0, SOL, true).unwrap();
+        env.bet(bob, 0, SOL, false).unwrap();
+        env.resolve(TARGET, START, None).unwrap();
+        env.claim(alice, 0).unwrap();
+        
+        let alice_before = env.bank.lamports(&alice);
+        let escrow_before = env.bank.lamports(&env.escrow());
+        assert_eq!(
+            env.claim(alice, 0),
+            Err(ProgramError::Custom(anchor_lang::error::ErrorCode::AccountNotInitialized.into()))
+        );
+        assert_eq!(env.bank.lamports(&alice), alice_before);
+        assert_eq!(env.bank.lamports(&env.escrow()), escrow_before);
+        assert_eq!(env.market().total_claimed_lamports, 2 * SOL);
+    }
+    
+    #[test]
+    fn emergency_withdraw_waits_for_the_timelock() {
+        let mut env = setup_market();
+        let alice = env.bettor();
+        env.bet(alice, 0, 2 * SOL, true).unwrap();
+        
+        let recovery = Pubkey::new_unique();
+        assert_eq!(env.emergency_withdraw(Pubkey::default()), err(ParimutuelError::NoEmergencyProposal));
+        env.propose_emergency_withdraw(recovery).unwrap();
+        
+        set_now(START + EMERGENCY_TIMELOCK_SECS - 1);
+        assert_eq!(env.emergency_withdraw(recovery), err(ParimutuelError::TimelockNotElapsed));
+        
+        set_now(START + EMERGENCY_TIMELOCK_SECS);
+        env.emergency_withdraw(recovery).unwrap();
+        assert_eq!(env.bank.lamports(&recovery), 2 * SOL);
+        assert_eq!(env.bank.lamports(&env.escrow()), rent(0));
+        assert_eq!(env.market().emergency_proposed_at, 0);
+    }
+    
+    #[test]
+    fn switchboard_round_at_the_target_resolves_yes() {
+        let mut env = setup_market();
+        let feed = Pubkey::new_unique();
+        env.set_switchboard_feed(feed).unwrap();
+        set_now(START + DAY);
+        
+        // $1.25M with three decimals, reported by two of three required oracles
+        env.put_switchboard_round(feed, 2, 3, START + DAY - 10, 1_250_000_000, 3);
+        assert_eq!(env.resolve_from_switchboard(feed), err(ParimutuelError::InsufficientOracleResults));
+        
+        env.put_switchboard_round(feed, 3, 3, START + DAY - 10, 1_250_000_000, 3);
+        env.resolve_from_switchboard(feed).unwrap();
+        
+        let market = env.market();
+        assert!(market.is_resolved);
+        assert_eq!(market.winner, Some(true));
+        assert_eq!(market.last_market_cap, 1_250_000_000_000);
+        assert_eq!(market.last_oracle_update, START + DAY - 10);
+    }
+    
+    #[test]
+    fn claims_close_with_the_claim_window() {
+        let mut env = setup();
+        env.create_market(crate::instruction::ParimutuelInitializeMarket {
+            claim_window_secs: 7 * DAY,
+            ..market_args()
+        })
+        .unwrap();
+        let (alice, bob, carol) = (env.bettor(), env.bettor(), env.bettor());
+        env.bet(alice, 0, SOL, true).unwrap();
+        env.bet(bob, 0, SOL, true).unwrap();
+        env.bet(carol, 0, SOL, false).unwrap();
+        env.resolve(TARGET, START, None).unwrap();
+        assert_eq!(env.market().claim_deadline, START + 7 * DAY);
+        
+        set_now(START + 7 * DAY - 1);
+        env.claim(alice, 0).unwrap();
+        
+        set_now(START + 7 * DAY);
+        assert_eq!(env.claim(bob, 0), err(ParimutuelError::ClaimWindowClosed));
+        assert!(!env.bank.get::<UserBet>(&user_bet_key(market_key(SEED), bob, 0)).claimed);
+    }
+    
+    #[test]
+    fn push_refunds_both_sides_their_principal() {
+        let mut env = setup_market();
+        let (alice, bob) = (env.bettor(), env.bettor());
+        env.bet(alice, 0, SOL, true).unwrap();
+        env.bet(bob, 0, 3 * SOL, false).unwrap();
+        env.resolve(TARGET, START, Some(Resolution::Push)).unwrap();
+        
+        let market = env.market();
+        assert!(market.is_void);
+        assert_eq!(market.winner, None);
+        
+        let before = env.bank.lamports(&alice);
+        env.claim(alice, 0).unwrap();
+        assert_eq!(env.bank.lamports(&alice) - before, SOL + rent(UserBet::LEN));
+        
+        let before = env.bank.lamports(&bob);
+        env.claim(bob, 0).unwrap();
+        assert_eq!(env.bank.lamports(&bob) - before, 3 * SOL + rent(UserBet::LEN));
+        assert_eq!(env.bank.lamports(&env.escrow()), rent(0));
+    }
+    
+    #[test]
+    fn title_and_metadata_uri_are_stored_within_their_limits() {
+        let mut env = setup();
+        let too_long_title = crate::instruction::ParimutuelInitializeMarket {
+            title: "x".repeat(MAX_TITLE_LEN + 1),
+            ..market_args()
+        };
+        assert_eq!(env.create_market(too_long_title), err(ParimutuelError::TitleTooLong));
+        
+        let too_long_uri = crate::instruction::ParimutuelInitializeMarket {
+            metadata_uri: "x".repeat(MAX_METADATA_URI_LEN + 1),
+            ..market_args()
+        };
+        assert_eq!(env.create_market(too_long_uri), err(ParimutuelError::MetadataUriTooLong));
+        
+        let title = "x".repeat(MAX_TITLE_LEN);
+        let metadata_uri = format!("https://{}", "x".repeat(MAX_METADATA_URI_LEN - 8));
+        env.create_market(crate::instruction::ParimutuelInitializeMarket {
+            title: title.clone(),
+            metadata_uri: metadata_uri.clone(),
+            ..market_args()
+        })
+        .unwrap();
+        
+        let market = env.market();
+        assert_eq!(market.title, title);
+        assert_eq!(market.metadata_uri, metadata_uri);
+    }
+    
+    #[test]
+    fn bet_account_rent_returns_to_winners_and_losers() {
+        let mut env = setup_market();
+        let (alice, bob) = (env.bettor(), env.bettor());
+        env.bet(alice, 0, SOL, true).unwrap();
+        env.bet(bob, 0, SOL, false).unwrap();
+        env.resolve(TARGET, START, None).unwrap();
+        
+        assert_eq!(env.close_losing_bet(alice, 0), err(ParimutuelError::BetNotLost));
+        
+        let before = env.bank.lamports(&alice);
+        env.claim(alice, 0).unwrap();
+        assert_eq!(env.bank.lamports(&alice) - before, 2 * SOL + rent(UserBet::LEN));
+        
+        let before = env.bank.lamports(&bob);
+        env.close_losing_bet(bob, 0).unwrap();
+        assert_eq!(env.bank.lamports(&bob) - before, rent(UserBet::LEN));
+        
+        let market = market_key(SEED);
+        for user in [alice, bob] {
+            assert_eq!(env.bank.lamports(&user_bet_key(market, user, 0)), 0);
+        }
+    }
+    
+    #[test]
+    fn claim_rejects_an_escrow_at_a_non_canonical_bump() {
+        let mut env = setup_market();
+        let (alice, bob) = (env.bettor(), env.bettor());
+        env.bet(alice, 0, SOL, true).unwrap();
+        env.bet(bob, 0, SOL, false).unwrap();
+        env.resolve(TARGET, START, None).unwrap();
+        
+        let market = market_key(SEED);
+        let canonical_bump = env.market().escrow_bump;
+        let decoy = (0..canonical_bump)
+            .rev()
+            .find_map(|bump| {
+                Pubkey::create_program_address(&[b"escrow", market.as_ref(), &[bump]], &ID).ok()
+            })
+            .unwrap();
+        env.bank.fund(decoy, 10 * SOL);
+        
+        assert_eq!(
+            env.claim_with_escrow(alice, 0, decoy),
+            Err(ProgramError::Custom(anchor_lang::error::ErrorCode::ConstraintSeeds.into()))
+        );
+        assert_eq!(env.bank.lamports(&decoy), 10 * SOL);
+        env.claim(alice, 0).unwrap();
+    }
+    
+    #[test]
+    fn oracle_report_from_before_creation_is_stale() {
+        let mut env = setup();
+        let created_at = START + DAY;
+        set_now(created_at);
+        env.create_market(market_args()).unwrap();
+        
+        // Within the skew of the clock, but older than the market itself
+        set_now(created_at + 60);
+        assert_eq!(env.resolve(TARGET, created_at - 1, None), err(ParimutuelError::StaleData));
+        assert_eq!(env.heartbeat(TARGET / 2, created_at - 1), err(ParimutuelError::StaleData));
+        
+        env.resolve(TARGET, created_at, None).unwrap();
+    }
+    
+    #[test]
+    fn market_records_creation_and_last_bet_times() {
+        let mut env = setup();
+        set_now(START + 90);
+        env.create_market(market_args()).unwrap();
+        assert_eq!(env.market().created_at, START + 90);
+        assert_eq!(env.market().last_bet_at, 0);
+        
+        let alice = env.bettor();
+        set_now(START + DAY);
+        env.bet(alice, 0, SOL, true).unwrap();
+        assert_eq!(env.market().last_bet_at, START + DAY);
+        
+        set_now(START + 2 * DAY);
+        env.bet(alice, 1, SOL, false).unwrap();
+        let market = env.market();
+        assert_eq!(market.last_bet_at, START + 2 * DAY);
+        assert_eq!(market.created_at, START + 90);
+    }
+    
+    #[test]
+    fn oracle_authority_rotates_only_before_resolution_and_by_the_creator_or_authority() {
+        let mut env = setup_market();
+        let old_oracle = env.oracle;
+        
+        let stranger = env.bettor();
+        assert_eq!(
+            env.set_oracle_authority(stranger, stranger),
+            err(ParimutuelError::Unauthorized)
+        );
+        
+        let new_oracle = Pubkey::new_unique();
+        env.set_oracle_authority(env.creator, new_oracle).unwrap();
+        assert_eq!(env.market().oracle_authority, new_oracle);
+        assert_eq!(env.resolve(TARGET, START, None), err(ParimutuelError::Unauthorized));
+        
+        env.set_oracle_authority(env.authority, old_oracle).unwrap();
+        env.resolve(TARGET, START, None).unwrap();
+        assert_eq!(
+            env.set_oracle_authority(env.creator, new_oracle),
+            err(ParimutuelError::MarketAlreadyResolved)
+        );
+    }
+    
+    #[test]
+    fn categorical_market_pays_the_winning_outcome() {
+        let mut env = setup();
+        env.create_categorical_market(3).unwrap();
+        let market = categorical_market_key(SEED);
+        let escrow = escrow_key(market);
+        assert_eq!(env.bank.lamports(&escrow), rent(0));
+        
+        let (alice, bob, carol) = (env.bettor(), env.bettor(), env.bettor());
+        env.categorical_bet(alice, SOL, 0).unwrap();
+        env.categorical_bet(bob, 2 * SOL, 1).unwrap();
+        env.categorical_bet(carol, SOL, 2).unwrap();
+        assert_eq!(env.categorical_bet(alice, SOL, 3), Err(ProgramError::AccountAlreadyInitialized));
+        assert_eq!(env.bank.get::<CategoricalMarket>(&market).outcome_pools, [SOL, 2 * SOL, SOL]);
+        
+        // Not while bets can still land
+        assert_eq!(env.resolve_categorical(1), err(ParimutuelError::BettingStillOpen));
+        set_now(env.bank.get::<CategoricalMarket>(&market).betting_closes_at);
+        assert_eq!(env.resolve_categorical(3), err(ParimutuelError::InvalidOutcome));
+        env.resolve_categorical(1).unwrap();
+        let resolved = env.bank.get::<CategoricalMarket>(&market);
+        assert!(resolved.is_resolved && !resolved.is_void);
+        assert_eq!(resolved.winning_outcome, 1);
+        
+        let before = env.bank.lamports(&bob);
+        env.claim_categorical(bob).unwrap();
+        assert_eq!(env.bank.lamports(&bob) - before, 4 * SOL + rent(CategoricalBet::LEN));
+        
+        let before = env.bank.lamports(&carol);
+        env.claim_categorical(carol).unwrap();
+        assert_eq!(env.bank.lamports(&carol) - before, rent(CategoricalBet::LEN));
+        
+        assert_eq!(env.bank.lamports(&escrow), rent(0));
+        assert_eq!(env.bank.get::<CategoricalMarket>(&market).total_claimed_lamports, 4 * SOL);
+    }
+    
+    #[test]
+    fn lapsed_categorical_oracle_voids_the_market_for_refunds() {
+        let mut env = setup();
+        env.create_categorical_market(3).unwrap();
+        let market = categorical_market_key(SEED);
+        let (alice, bob) = (env.bettor(), env.bettor());
+        env.categorical_bet(alice, SOL, 0).unwrap();
+        env.categorical_bet(bob, 2 * SOL, 1).unwrap();
+        
+        let force_void_at = env.bank.get::<CategoricalMarket>(&market).deadline + FORCE_RESOLVE_GRACE_SECS;
+        set_now(force_void_at - 1);
+        assert_eq!(env.force_void_categorical(), err(ParimutuelError::ForceResolveTooEarly));
+        
+        set_now(force_void_at);
+        env.force_void_categorical().unwrap();
+        assert!(env.bank.get::<CategoricalMarket>(&market).is_void);
+        assert_eq!(env.resolve_categorical(0), err(ParimutuelError::MarketAlreadyResolved));
+        
+        let before = env.bank.lamports(&bob);
+        env.claim_categorical(bob).unwrap();
+        assert_eq!(env.bank.lamports(&bob) - before, 2 * SOL + rent(CategoricalBet::LEN));
+        
+        // Alice's open bet holds the market open until she reclaims it
+        assert_eq!(env.close_categorical(), err(ParimutuelError::BetsStillOpen));
+        env.claim_categorical(alice).unwrap();
+        let creator_before = env.bank.lamports(&env.creator);
+        env.close_categorical().unwrap();
+        assert_eq!(
+            env.bank.lamports(&env.creator) - creator_before,
+            rent(0) + rent(CategoricalMarket::LEN)
+        );
+    }
+    
+    #[test]
+    fn categorical_sweep_forfeits_unclaimed_rewards_and_lets_the_market_close() {
+        let mut env = setup();
+        env.create_categorical_market(2).unwrap();
+        let market = categorical_market_key(SEED);
+        let (alice, bob) = (env.bettor(), env.bettor());
+        env.categorical_bet(alice, SOL, 0).unwrap();
+        env.categorical_bet(bob, SOL, 1).unwrap();
+        set_now(env.bank.get::<CategoricalMarket>(&market).betting_closes_at);
+        env.resolve_categorical(0).unwrap();
+        assert_eq!(env.close_categorical(), err(ParimutuelError::BetsStillOpen));
+        
+        let sweep_opens_at = env.bank.get::<CategoricalMarket>(&market).resolved_at + UNCLAIMED_SWEEP_GRACE_SECS;
+        set_now(sweep_opens_at - 1);
+        assert_eq!(env.sweep_unclaimed_categorical(), err(ParimutuelError::SweepTooEarly));
+        
+        set_now(sweep_opens_at);
+        let before = env.bank.lamports(&env.creator);
+        env.sweep_unclaimed_categorical().unwrap();
+        assert_eq!(env.bank.lamports(&env.creator) - before, 2 * SOL);
+        assert_eq!(env.bank.lamports(&escrow_key(market)), rent(0));
+        assert_eq!(env.sweep_unclaimed_categorical(), err(ParimutuelError::RewardsForfeited));
+        
+        // Alice's forfeited win only returns her bet's rent
+        let before = env.bank.lamports(&alice);
+        env.claim_categorical(alice).unwrap();
+        assert_eq!(env.bank.lamports(&alice) - before, rent(CategoricalBet::LEN));
+        env.claim_categorical(bob).unwrap();
+        
+        let treasury_before = env.bank.lamports(&env.treasury);
+        env.close_categorical().unwrap();
+        assert_eq!(env.bank.lamports(&env.treasury), treasury_before);
+        assert_eq!(env.bank.lamports(&market), 0);
+    }
+    
+    #[test]
+    fn target_hit_at_the_deadline_follows_the_resolution_policy() {
+        for (policy, winner) in [
+            (ResolutionPolicy::TargetWinsOnTie, Some(true)),
+            (ResolutionPolicy::DeadlineWinsOnTie, Some(false)),
+        ] {
+            let mut env = setup();
+            env.create_market(crate::instruction::ParimutuelInitializeMarket {
+                resolution_policy: policy,
+                ..market_args()
+            })
+            .unwrap();
+            let deadline = env.market().deadline;
+            set_now(deadline);
+            
+            env.resolve(TARGET, deadline, None).unwrap();
+            assert_eq!(env.market().winner, winner, "{:?}", policy);
+        }
+    }
+    
+    #[test]
+    fn pause_blocks_bets_but_not_claims() {
+        let mut env = setup_market();
+        let (alice, bob) = (env.bettor(), env.bettor());
+        env.bet(alice, 0, SOL, true).unwrap();
+        env.bet(bob, 0, SOL, false).unwrap();
+        
+        env.set_paused(true).unwrap();
+        assert_eq!(env.bet(alice, 1, SOL, true), err(ParimutuelError::ProtocolPaused));
+        
+        env.resolve(TARGET, START, None).unwrap();
+        let before = env.bank.lamports(&alice);
+        env.claim(alice, 0).unwrap();
+        assert_eq!(env.bank.lamports(&alice) - before, 2 * SOL + rent(UserBet::LEN));
+        
+        env.set_paused(false).unwrap();
+        assert_eq!(env.bet(alice, 1, SOL, true), err(ParimutuelError::MarketResolved));
+    }
+    
+    #[test]
+    fn claim_and_bet_reinvests_into_an_open_market_only() {
+        let mut env = setup_market();
+        let target_seed = "doge-1m";
+        env.create_market(crate::instruction::ParimutuelInitializeMarket {
+            market_seed: target_seed.to_string(),
+            ..market_args()
+        })
+        .unwrap();
+        let (alice, bob, carol) = (env.bettor(), env.bettor(), env.bettor());
+        env.bet(alice, 0, SOL, true).unwrap();
+        env.bet(bob, 0, SOL, true).unwrap();
+        env.bet(carol, 0, 2 * SOL, false).unwrap();
+        env.resolve(TARGET, START, None).unwrap();
+        
+        assert_eq!(
+            env.claim_and_bet(alice, target_seed, 0, Some(2 * SOL + 1), true),
+            err(ParimutuelError::ReinvestExceedsReward)
+        );
+        
+        let before = env.bank.lamports(&alice);
+        env.claim_and_bet(alice, target_seed, 0, Some(SOL), false).unwrap();
+        assert_eq!(env.bank.lamports(&alice) - before, SOL);
+        let target: Market = env.bank.get(&market_key(target_seed));
+        assert_eq!(target.total_no_pool, SOL);
+        let rebet: UserBet = env.bank.get(&user_bet_key(market_key(target_seed), alice, 0));
+        assert_eq!((rebet.amount, rebet.side), (SOL, false));
+        
+        // Once the target stops taking bets the whole chain is refused and the claim stays open
+        set_now(target.betting_closes_at);
+        assert_eq!(
+            env.claim_and_bet(bob, target_seed, 0, None, true),
+            err(ParimutuelError::DeadlinePassed)
+        );
+        let before = env.bank.lamports(&bob);
+        env.claim(bob, 0).unwrap();
+        assert_eq!(env.bank.lamports(&bob) - before, 2 * SOL + rent(UserBet::LEN));
+    }
+    
+    #[test]
+    fn registry_lists_every_market_in_creation_order() {
+        let mut env = setup();
+        let seeds = ["pepe-1m", "doge-1m", "wif-1m"];
+        for seed in seeds {
+            env.create_market(crate::instruction::ParimutuelInitializeMarket {
+                market_seed: seed.to_string(),
+                ..market_args()
+            })
+            .unwrap();
+        }
+        
+        let config: Config = env.bank.get(&config_key());
+        assert_eq!(config.market_count, seeds.len() as u64);
+        for (index, seed) in (0..config.market_count).zip(seeds) {
+            let entry: MarketRegistryEntry = env.bank.get(&registry_key(index));
+            assert_eq!(entry.market, market_key(seed));
+            assert_eq!(entry.index, index);
+            assert_eq!(env.bank.get::<Market>(&entry.market).market_index, index);
+        }
+    }
+    
+    #[test]
+    fn each_bet_index_is_claimed_on_its_own() {
+        let mut env = setup_market();
+        let (alice, bob) = (env.bettor(), env.bettor());
+        env.bet(alice, 0, SOL, true).unwrap();
+        env.bet(alice, 1, 3 * SOL, true).unwrap();
+        env.bet(bob, 0, 4 * SOL, false).unwrap();
+        assert_eq!(env.bet(alice, 1, SOL, true), Err(ProgramError::AccountAlreadyInitialized));
+        env.resolve(TARGET, START, None).unwrap();
+        
+        let market = market_key(SEED);
+        let before = env.bank.lamports(&alice);
+        env.claim(alice, 1).unwrap();
+        assert_eq!(env.bank.lamports(&alice) - before, 6 * SOL + rent(UserBet::LEN));
+        assert!(!env.bank.get::<UserBet>(&user_bet_key(market, alice, 0)).claimed);
+        
+        let before = env.bank.lamports(&alice);
+        env.claim(alice, 0).unwrap();
+        assert_eq!(env.bank.lamports(&alice) - before, 2 * SOL + rent(UserBet::LEN));
+        assert_eq!(env.bank.lamports(&env.escrow()), rent(0));
+    }
+    
+    #[test]
+    fn is_resolvable_tracks_the_deadline_and_resolution() {
+        let mut env = setup_market();
+        let deadline = env.market().deadline;
+        
+        assert_eq!(env.is_resolvable(), (false, deadline - START, false));
+        
+        set_now(deadline - 1);
+        assert_eq!(env.is_resolvable(), (false, 1, false));
+        
+        set_now(deadline);
+        assert_eq!(env.is_resolvable(), (true, 0, false));
+        
+        set_now(deadline + 60);
+        assert_eq!(env.is_resolvable(), (true, -60, false));
+        
+        env.resolve(TARGET / 2, deadline + 60, None).unwrap();
+        assert_eq!(env.is_resolvable(), (true, -60, true));
+    }
+    
+    #[test]
+    fn bets_after_a_target_heartbeat_follow_the_config_flag() {
+        let mut env = setup_market();
+        let alice = env.bettor();
+        env.heartbeat(TARGET, START).unwrap();
+        
+        env.set_block_bets_after_target(true).unwrap();
+        assert_eq!(env.bet(alice, 0, SOL, true), err(ParimutuelError::TargetAlreadyReached));
+        
+        env.set_block_bets_after_target(false).unwrap();
+        env.bet(alice, 0, SOL, true).unwrap();
+        assert_eq!(env.market().total_yes_pool, SOL);
+    }
 }
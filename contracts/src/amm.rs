@@ -1,257 +1,478 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token::{self, Token, TokenAccount, Transfer};
+use anchor_lang::solana_program::instruction::{AccountMeta, Instruction};
+#[cfg(not(test))]
+use anchor_lang::solana_program::program::{invoke, invoke_signed, set_return_data};
+#[cfg(not(test))]
+use anchor_spl::{token, token_interface};
+use anchor_spl::token::{Token, TokenAccount, TransferChecked as LegacyTransferChecked};
+use anchor_spl::token_interface::{
+    Mint as InterfaceMint, TokenAccount as InterfaceTokenAccount, TokenInterface, TransferChecked,
+};
+#[cfg(test)]
+use tests::{emit, invoke, invoke_signed, set_return_data, token, token_interface};
 
 declare_id!("MemeMarket1111111111111111111111111111111111");
 
+/// Price precision: 1_000_000 = 1.0 (matches the orderbook)
+pub const PRICE_PRECISION: u64 = 1_000_000;
+
+/// Anchor discriminator of the orderbook program's `Orderbook` account
+/// sha256("account:Orderbook")[..8]
+pub const ORDERBOOK_DISCRIMINATOR: [u8; 8] = [43, 34, 25, 113, 195, 69, 72, 7];
+
+/// Anchor discriminator of the orderbook program's `redeem_share_tokens` instruction
+/// sha256("global:redeem_share_tokens")[..8]
+pub const REDEEM_SHARE_TOKENS_DISCRIMINATOR: [u8; 8] = [153, 93, 100, 103, 89, 236, 114, 30];
+
+/// Anchor discriminator of the orderbook program's `mint_complete_set_tokens` instruction
+/// sha256("global:mint_complete_set_tokens")[..8]
+pub const MINT_COMPLETE_SET_TOKENS_DISCRIMINATOR: [u8; 8] = [179, 207, 52, 9, 200, 190, 76, 5];
+
+/// Basis points denominator: 10_000 = 100%
+pub const BPS_DENOMINATOR: u64 = 10_000;
+
+/// Default price impact cap for `swap_at_market`: 5%
+pub const DEFAULT_MAX_PRICE_IMPACT_BPS: u16 = 500;
+
+/// Scale of `AmmPool.acc_reward_per_lp`: reward base units per LP token * 10^12
+pub const REWARD_PRECISION: u128 = 1_000_000_000_000;
+
 #[program]
 pub mod amm {
     use super::*;
 
     /// Initialize AMM pool for YES/NO shares
+    /// `min_price`/`max_price` bound the implied YES probability (PRICE_PRECISION units);
+    /// pass 0 for both to allow the full open interval (0, 1)
+    /// `CurveType::ConcentratedRange` concentrates all liquidity inside that band
+    /// The pool starts empty - reserves are only ever credited by `add_liquidity`, which
+    /// moves the tokens in, so recorded reserves always match the pool token accounts
+    #[allow(clippy::too_many_arguments)]
     pub fn initialize_pool(
         ctx: Context<InitializePool>,
         pool_id: Pubkey,
         market_id: Pubkey,
         yes_mint: Pubkey,
         no_mint: Pubkey,
-        initial_yes_amount: u64,
-        initial_no_amount: u64,
+        min_price: u64,
+        max_price: u64,
+        curve_type: CurveType,
     ) -> Result<()> {
         let pool = &mut ctx.accounts.pool;
         
+        let (min_price, max_price) = if min_price == 0 && max_price == 0 {
+            (1, PRICE_PRECISION - 1)
+        } else {
+            (min_price, max_price)
+        };
+        require!(
+            min_price > 0 && min_price < max_price && max_price < PRICE_PRECISION,
+            ErrorCode::InvalidPriceBand
+        );
+        // Swapping a mint for itself would corrupt every reserve update
+        require!(yes_mint != no_mint, ErrorCode::InvalidMint);
+        
         pool.authority = ctx.accounts.authority.key();
         pool.pool_id = pool_id;
         pool.market_id = market_id;
         pool.yes_mint = yes_mint;
         pool.no_mint = no_mint;
-        pool.yes_reserves = initial_yes_amount;
-        pool.no_reserves = initial_no_amount;
+        pool.yes_reserves = 0;
+        pool.no_reserves = 0;
         pool.total_supply = 0;
         pool.fee_numerator = 30; // 0.3% fee
         pool.fee_denominator = 10000;
+        pool.min_price = min_price;
+        pool.max_price = max_price;
+        pool.protocol_fee_recipient = ctx.accounts.authority.key();
+        pool.protocol_fee_bps = 0;
+        pool.linked_orderbook = Pubkey::default();
+        pool.is_active = true;
+        pool.max_price_impact_bps = DEFAULT_MAX_PRICE_IMPACT_BPS;
         pool.created_at = Clock::get()?.unix_timestamp;
+        pool.curve_type = curve_type;
+        pool.in_progress = false;
+        pool.reward_mint = Pubkey::default(); // Liquidity mining is opt-in via initialize_rewards
+        pool.reward_rate = 0;
+        pool.acc_reward_per_lp = 0;
+        pool.last_reward_update = pool.created_at;
         
-        // Calculate initial k (constant product)
-        pool.k = initial_yes_amount
-            .checked_mul(initial_no_amount)
-            .unwrap();
+        // Empty pool: zero offsets and k until the first add_liquidity seeds it
+        refresh_liquidity(pool)?;
         
         emit!(PoolInitialized {
             pool_id,
             market_id,
-            yes_reserves: initial_yes_amount,
-            no_reserves: initial_no_amount,
-            k: pool.k,
+            curve_type,
         });
         
         Ok(())
     }
 
     /// Swap YES shares for NO shares
+    /// Returns (amount_out, execution_price_scaled) via return data - the first
+    /// 8 bytes are amount_out as a little-endian u64, so a router can size its next hop
     pub fn swap_yes_for_no(
         ctx: Context<SwapYesForNo>,
-        pool_id: Pubkey,
+        _pool_id: Pubkey,
         yes_amount_in: u64,
         minimum_no_out: u64,
     ) -> Result<()> {
-        let pool = &mut ctx.accounts.pool;
-        
-        require!(yes_amount_in > 0, ErrorCode::InvalidAmount);
-        require!(pool.yes_reserves > 0 && pool.no_reserves > 0, ErrorCode::EmptyPool);
+        execute_swap(
+            &mut ctx.accounts.pool,
+            ctx.bumps.pool,
+            &ctx.accounts.pool_yes_shares,
+            &ctx.accounts.pool_no_shares,
+            &ctx.accounts.user_yes_shares,
+            &ctx.accounts.user_no_shares,
+            &ctx.accounts.yes_mint,
+            &ctx.accounts.no_mint,
+            &ctx.accounts.protocol_fee_account,
+            &ctx.accounts.user,
+            &ctx.accounts.token_program,
+            true,
+            yes_amount_in,
+            minimum_no_out,
+            false,
+        )
+    }
+
+    /// Swap NO shares for YES shares
+    /// Returns (amount_out, execution_price_scaled) via return data - the first
+    /// 8 bytes are amount_out as a little-endian u64, so a router can size its next hop
+    pub fn swap_no_for_yes(
+        ctx: Context<SwapNoForYes>,
+        _pool_id: Pubkey,
+        no_amount_in: u64,
+        minimum_yes_out: u64,
+    ) -> Result<()> {
+        execute_swap(
+            &mut ctx.accounts.pool,
+            ctx.bumps.pool,
+            &ctx.accounts.pool_no_shares,
+            &ctx.accounts.pool_yes_shares,
+            &ctx.accounts.user_no_shares,
+            &ctx.accounts.user_yes_shares,
+            &ctx.accounts.no_mint,
+            &ctx.accounts.yes_mint,
+            &ctx.accounts.protocol_fee_account,
+            &ctx.accounts.user,
+            &ctx.accounts.token_program,
+            false,
+            no_amount_in,
+            minimum_yes_out,
+            false,
+        )
+    }
+
+    /// Buy `side` with `amount_in` of the opposite share and take whatever the curve gives
+    /// No output floor - the trade is instead bounded by the pool's `max_price_impact_bps`
+    /// Returns (amount_out, execution_price_scaled) via return data - the first
+    /// 8 bytes are amount_out as a little-endian u64, so a router can size its next hop
+    pub fn swap_at_market(
+        ctx: Context<SwapAtMarket>,
+        _pool_id: Pubkey,
+        side: Outcome,
+        amount_in: u64,
+    ) -> Result<()> {
+        let accounts = ctx.accounts;
         
-        // Calculate fee
-        let fee = yes_amount_in
-            .checked_mul(pool.fee_numerator)
-            .unwrap()
-            .checked_div(pool.fee_denominator)
-            .unwrap();
+        // Buying YES spends NO, buying NO spends YES
+        let (pool_in, pool_out, user_in, user_out, mint_in, mint_out) = match side {
+            Outcome::Yes => (
+                &accounts.pool_no_shares,
+                &accounts.pool_yes_shares,
+                &accounts.user_no_shares,
+                &accounts.user_yes_shares,
+                &accounts.no_mint,
+                &accounts.yes_mint,
+            ),
+            Outcome::No => (
+                &accounts.pool_yes_shares,
+                &accounts.pool_no_shares,
+                &accounts.user_yes_shares,
+                &accounts.user_no_shares,
+                &accounts.yes_mint,
+                &accounts.no_mint,
+            ),
+        };
         
-        let yes_amount_after_fee = yes_amount_in.checked_sub(fee).unwrap();
+        // Protocol fee is skimmed from the input leg
+        require!(
+            accounts.protocol_fee_account.mint == pool_in.mint,
+            ErrorCode::InvalidFeeRecipient
+        );
         
-        // Calculate output using constant product formula
-        let new_yes_reserves = pool.yes_reserves.checked_add(yes_amount_after_fee).unwrap();
-        let new_no_reserves = pool.k
-            .checked_div(new_yes_reserves)
-            .unwrap();
+        execute_swap(
+            &mut accounts.pool,
+            ctx.bumps.pool,
+            pool_in,
+            pool_out,
+            user_in,
+            user_out,
+            mint_in,
+            mint_out,
+            &accounts.protocol_fee_account,
+            &accounts.user,
+            &accounts.token_program,
+            side == Outcome::No,
+            amount_in,
+            0,
+            true,
+        )
+    }
+
+    /// Buy YES with SOL in one call: mint complete sets through the linked orderbook,
+    /// then swap their NO leg into YES on this pool, leaving the user holding only YES
+    /// `sol_amount` buys as many sets as it fully covers at the orderbook's SOL rate;
+    /// `min_yes_out` bounds the minted YES plus the swap output together
+    /// Returns (yes_out, sets_minted) via return data
+    pub fn buy_yes_with_sol(
+        ctx: Context<BuyYesWithSol>,
+        pool_id: Pubkey,
+        sol_amount: u64,
+        min_yes_out: u64,
+    ) -> Result<()> {
+        let pool = &ctx.accounts.pool;
         
-        let no_amount_out = pool.no_reserves.checked_sub(new_no_reserves).unwrap();
+        require!(
+            pool.linked_orderbook != Pubkey::default()
+                && ctx.accounts.orderbook.key() == pool.linked_orderbook
+                && ctx.accounts.orderbook.owner == ctx.accounts.orderbook_program.key,
+            ErrorCode::InvalidOrderbook
+        );
+        let orderbook = read_orderbook_snapshot(&ctx.accounts.orderbook)?;
+        require!(orderbook.market_id == pool.market_id, ErrorCode::MarketMismatch);
+        require!(
+            orderbook.yes_mint == pool.yes_mint && orderbook.no_mint == pool.no_mint,
+            ErrorCode::InvalidMint
+        );
+        require!(orderbook.one_dollar_lamports > 0, ErrorCode::InvalidOrderbook);
         
-        require!(no_amount_out >= minimum_no_out, ErrorCode::SlippageExceeded);
+        // Sets cost $1 each, rounded up by the orderbook, so flooring here keeps the
+        // deposit within sol_amount
+        let sets_minted = (sol_amount as u128)
+            .checked_mul(10u128.pow(orderbook.share_decimals as u32))
+            .ok_or(ErrorCode::MathOverflow)?
+            / orderbook.one_dollar_lamports as u128;
+        let sets_minted = u64::try_from(sets_minted).map_err(|_| ErrorCode::MathOverflow)?;
+        require!(sets_minted > 0, ErrorCode::InvalidAmount);
         
-        // Transfer YES shares from user to pool
-        let cpi_accounts = Transfer {
-            from: ctx.accounts.user_yes_shares.to_account_info(),
-            to: ctx.accounts.pool_yes_shares.to_account_info(),
-            authority: ctx.accounts.user.to_account_info(),
-        };
-        let cpi_program = ctx.accounts.token_program.to_account_info();
-        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
-        token::transfer(cpi_ctx, yes_amount_in)?;
+        let yes_before = ctx.accounts.user_yes_shares.amount;
         
-        // Transfer NO shares from pool to user
-        let seeds = &[
-            b"pool",
-            pool_id.as_ref(),
-            &[ctx.bumps.pool],
-        ];
-        let signer = &[&seeds[..]];
+        // Mint the sets straight into the user's share accounts; the user signs the deposit
+        let mut data = MINT_COMPLETE_SET_TOKENS_DISCRIMINATOR.to_vec();
+        sets_minted.serialize(&mut data)?;
         
-        let cpi_accounts = Transfer {
-            from: ctx.accounts.pool_no_shares.to_account_info(),
-            to: ctx.accounts.user_no_shares.to_account_info(),
-            authority: pool.to_account_info(),
+        let ix = Instruction {
+            program_id: ctx.accounts.orderbook_program.key(),
+            accounts: vec![
+                AccountMeta::new(ctx.accounts.user.key(), true),
+                AccountMeta::new(ctx.accounts.orderbook.key(), false),
+                AccountMeta::new(ctx.accounts.yes_mint.key(), false),
+                AccountMeta::new(ctx.accounts.no_mint.key(), false),
+                AccountMeta::new(ctx.accounts.user_yes_shares.key(), false),
+                AccountMeta::new(ctx.accounts.user_no_shares.key(), false),
+                AccountMeta::new(ctx.accounts.orderbook_vault.key(), false),
+                AccountMeta::new_readonly(ctx.accounts.token_program.key(), false),
+                AccountMeta::new_readonly(ctx.accounts.system_program.key(), false),
+            ],
+            data,
         };
-        let cpi_program = ctx.accounts.token_program.to_account_info();
-        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
-        token::transfer(cpi_ctx, no_amount_out)?;
+        invoke(
+            &ix,
+            &[
+                ctx.accounts.user.to_account_info(),
+                ctx.accounts.orderbook.to_account_info(),
+                ctx.accounts.yes_mint.to_account_info(),
+                ctx.accounts.no_mint.to_account_info(),
+                ctx.accounts.user_yes_shares.to_account_info(),
+                ctx.accounts.user_no_shares.to_account_info(),
+                ctx.accounts.orderbook_vault.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
+                ctx.accounts.system_program.to_account_info(),
+                ctx.accounts.orderbook_program.to_account_info(),
+            ],
+        )?;
         
-        // Update pool state
-        pool.yes_reserves = new_yes_reserves;
-        pool.no_reserves = new_no_reserves;
+        // Swap the freshly minted NO leg into YES; the overall floor is checked below
+        execute_swap(
+            &mut ctx.accounts.pool,
+            ctx.bumps.pool,
+            &ctx.accounts.pool_no_shares,
+            &ctx.accounts.pool_yes_shares,
+            &ctx.accounts.user_no_shares,
+            &ctx.accounts.user_yes_shares,
+            &ctx.accounts.no_mint,
+            &ctx.accounts.yes_mint,
+            &ctx.accounts.protocol_fee_account,
+            &ctx.accounts.user,
+            &ctx.accounts.token_program,
+            false,
+            sets_minted,
+            min_yes_out.saturating_sub(sets_minted),
+            false,
+        )?;
+        
+        let user_yes_shares = &mut ctx.accounts.user_yes_shares;
+        user_yes_shares.reload()?;
+        let yes_out = user_yes_shares.amount
+            .checked_sub(yes_before)
+            .ok_or(ErrorCode::MathOverflow)?;
+        require!(yes_out >= min_yes_out, ErrorCode::SlippageExceeded);
         
-        emit!(SwapExecuted {
+        emit!(YesBoughtWithSol {
             pool_id,
             user: ctx.accounts.user.key(),
-            yes_amount_in,
-            no_amount_out,
-            fee,
+            sol_amount,
+            sets_minted,
+            yes_out,
         });
         
+        set_return_data(&(yes_out, sets_minted).try_to_vec()?);
+        
         Ok(())
     }
 
-    /// Swap NO shares for YES shares
-    pub fn swap_no_for_yes(
-        ctx: Context<SwapNoForYes>,
-        pool_id: Pubkey,
-        no_amount_in: u64,
-        minimum_yes_out: u64,
+    /// Cap the price impact `swap_at_market` will accept, in bps (authority only)
+    pub fn set_max_price_impact(
+        ctx: Context<UpdatePool>,
+        max_price_impact_bps: u16,
     ) -> Result<()> {
-        let pool = &mut ctx.accounts.pool;
+        require!(
+            max_price_impact_bps > 0 && max_price_impact_bps as u64 <= BPS_DENOMINATOR,
+            ErrorCode::InvalidPriceImpact
+        );
         
-        require!(no_amount_in > 0, ErrorCode::InvalidAmount);
-        require!(pool.yes_reserves > 0 && pool.no_reserves > 0, ErrorCode::EmptyPool);
+        let pool = &mut ctx.accounts.pool;
+        pool.max_price_impact_bps = max_price_impact_bps;
         
-        // Calculate fee
-        let fee = no_amount_in
-            .checked_mul(pool.fee_numerator)
-            .unwrap()
-            .checked_div(pool.fee_denominator)
-            .unwrap();
+        Ok(())
+    }
+
+    /// Set the protocol fee recipient and its cut of each swap (authority only)
+    /// `protocol_fee_bps` is charged on the swap input and carved out of the pool fee,
+    /// so it can never exceed fee_numerator / fee_denominator
+    pub fn set_protocol_fee(
+        ctx: Context<UpdatePool>,
+        protocol_fee_recipient: Pubkey,
+        protocol_fee_bps: u16,
+    ) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
         
-        let no_amount_after_fee = no_amount_in.checked_sub(fee).unwrap();
+        require!(
+            (protocol_fee_bps as u128) * (pool.fee_denominator as u128)
+                <= (pool.fee_numerator as u128) * (BPS_DENOMINATOR as u128),
+            ErrorCode::ProtocolFeeTooHigh
+        );
         
-        // Calculate output using constant product formula
-        let new_no_reserves = pool.no_reserves.checked_add(no_amount_after_fee).unwrap();
-        let new_yes_reserves = pool.k
-            .checked_div(new_no_reserves)
-            .unwrap();
+        pool.protocol_fee_recipient = protocol_fee_recipient;
+        pool.protocol_fee_bps = protocol_fee_bps;
         
-        let yes_amount_out = pool.yes_reserves.checked_sub(new_yes_reserves).unwrap();
+        emit!(ProtocolFeeUpdated {
+            pool_id: pool.pool_id,
+            protocol_fee_recipient,
+            protocol_fee_bps,
+        });
         
-        require!(yes_amount_out >= minimum_yes_out, ErrorCode::SlippageExceeded);
+        Ok(())
+    }
+
+    /// Link the orderbook that trades the same market, for `get_blended_odds` (authority only)
+    /// The orderbook must be for the pool's market, and once it has SPL share mints
+    /// they must be the pool's yes_mint/no_mint
+    pub fn set_linked_orderbook(ctx: Context<LinkOrderbook>) -> Result<()> {
+        let orderbook = read_orderbook_snapshot(&ctx.accounts.orderbook)?;
+        let pool = &mut ctx.accounts.pool;
         
-        // Transfer NO shares from user to pool
-        let cpi_accounts = Transfer {
-            from: ctx.accounts.user_no_shares.to_account_info(),
-            to: ctx.accounts.pool_no_shares.to_account_info(),
-            authority: ctx.accounts.user.to_account_info(),
-        };
-        let cpi_program = ctx.accounts.token_program.to_account_info();
-        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
-        token::transfer(cpi_ctx, no_amount_in)?;
+        require!(orderbook.market_id == pool.market_id, ErrorCode::MarketMismatch);
+        if orderbook.yes_mint != Pubkey::default() || orderbook.no_mint != Pubkey::default() {
+            require!(
+                orderbook.yes_mint == pool.yes_mint && orderbook.no_mint == pool.no_mint,
+                ErrorCode::InvalidMint
+            );
+        }
         
-        // Transfer YES shares from pool to user
-        let seeds = &[
-            b"pool",
-            pool_id.as_ref(),
-            &[ctx.bumps.pool],
-        ];
-        let signer = &[&seeds[..]];
+        let linked_orderbook = ctx.accounts.orderbook.key();
+        pool.linked_orderbook = linked_orderbook;
         
-        let cpi_accounts = Transfer {
-            from: ctx.accounts.pool_yes_shares.to_account_info(),
-            to: ctx.accounts.user_yes_shares.to_account_info(),
-            authority: pool.to_account_info(),
-        };
-        let cpi_program = ctx.accounts.token_program.to_account_info();
-        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
-        token::transfer(cpi_ctx, yes_amount_out)?;
+        Ok(())
+    }
+
+    /// Stop trading on the pool once its market resolves (authority only)
+    /// Swaps are rejected afterwards; liquidity removal stays open so LPs can exit
+    pub fn deactivate_pool(ctx: Context<UpdatePool>) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
         
-        // Update pool state
-        pool.yes_reserves = new_yes_reserves;
-        pool.no_reserves = new_no_reserves;
+        require!(pool.is_active, ErrorCode::PoolInactive);
+        pool.is_active = false;
         
-        emit!(SwapExecuted {
-            pool_id,
-            user: ctx.accounts.user.key(),
-            yes_amount_out,
-            no_amount_in,
-            fee,
+        emit!(PoolDeactivated {
+            pool_id: pool.pool_id,
+            market_id: pool.market_id,
+            timestamp: Clock::get()?.unix_timestamp,
         });
         
         Ok(())
     }
 
     /// Add liquidity to the pool
+    /// Uniswap v2 `addLiquidity`: deposits the `*_desired` amounts at the current reserve
+    /// ratio, never more than desired on either side, and reverts if the matched amount
+    /// falls below `*_min` because a swap moved the ratio before this landed
     pub fn add_liquidity(
         ctx: Context<AddLiquidity>,
         pool_id: Pubkey,
-        yes_amount: u64,
-        no_amount: u64,
+        yes_amount_desired: u64,
+        no_amount_desired: u64,
+        yes_amount_min: u64,
+        no_amount_min: u64,
         minimum_lp_tokens: u64,
     ) -> Result<()> {
         let pool = &mut ctx.accounts.pool;
         
+        require!(yes_amount_desired > 0 && no_amount_desired > 0, ErrorCode::InvalidAmount);
+        lock_pool(pool)?;
+        
+        let (yes_amount, no_amount) = optimal_deposit(
+            pool,
+            yes_amount_desired,
+            no_amount_desired,
+            yes_amount_min,
+            no_amount_min,
+        )?;
         require!(yes_amount > 0 && no_amount > 0, ErrorCode::InvalidAmount);
         
         // Calculate LP tokens to mint based on current pool size
-        let lp_tokens_to_mint = if pool.total_supply == 0 {
-            // First liquidity provider gets proportional to initial deposits
-            yes_amount.checked_mul(no_amount).unwrap()
-        } else {
-            // Calculate based on existing reserves
-            let yes_ratio = yes_amount
-                .checked_mul(pool.total_supply)
-                .unwrap()
-                .checked_div(pool.yes_reserves)
-                .unwrap();
-            let no_ratio = no_amount
-                .checked_mul(pool.total_supply)
-                .unwrap()
-                .checked_div(pool.no_reserves)
-                .unwrap();
-            
-            // Use the minimum to maintain ratio
-            std::cmp::min(yes_ratio, no_ratio)
-        };
+        let lp_tokens_to_mint = calculate_lp_to_mint(pool, yes_amount, no_amount)?;
         
         require!(lp_tokens_to_mint >= minimum_lp_tokens, ErrorCode::SlippageExceeded);
         
         // Transfer shares from user to pool
-        let cpi_accounts = Transfer {
+        let cpi_accounts = LegacyTransferChecked {
             from: ctx.accounts.user_yes_shares.to_account_info(),
+            mint: ctx.accounts.yes_mint.to_account_info(),
             to: ctx.accounts.pool_yes_shares.to_account_info(),
             authority: ctx.accounts.user.to_account_info(),
         };
         let cpi_program = ctx.accounts.token_program.to_account_info();
-        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
-        token::transfer(cpi_ctx, yes_amount)?;
+        let cpi_ctx = CpiContext::new(cpi_program.clone(), cpi_accounts);
+        token::transfer_checked(cpi_ctx, yes_amount, ctx.accounts.yes_mint.decimals)?;
         
-        let cpi_accounts = Transfer {
+        let cpi_accounts = LegacyTransferChecked {
             from: ctx.accounts.user_no_shares.to_account_info(),
+            mint: ctx.accounts.no_mint.to_account_info(),
             to: ctx.accounts.pool_no_shares.to_account_info(),
             authority: ctx.accounts.user.to_account_info(),
         };
         let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
-        token::transfer(cpi_ctx, no_amount)?;
+        token::transfer_checked(cpi_ctx, no_amount, ctx.accounts.no_mint.decimals)?;
         
-        // Mint LP tokens
+        // Mint LP tokens, signed by the pool PDA that is the mint authority
         let seeds = &[
             b"pool",
             pool_id.as_ref(),
-            b"lp_mint",
-            &[ctx.bumps.lp_mint],
+            &[ctx.bumps.pool],
         ];
         let signer = &[&seeds[..]];
         
@@ -264,11 +485,40 @@ pub mod amm {
         let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
         token::mint_to(cpi_ctx, lp_tokens_to_mint)?;
         
+        // Accrue rewards at the old supply before the new LP starts earning
+        let now = Clock::get()?.unix_timestamp;
+        update_reward_index(pool, now)?;
+        accrue_position_rewards(pool, &mut ctx.accounts.position, ctx.accounts.user_lp_tokens.amount, now)?;
+        
         // Update pool state
-        pool.yes_reserves += yes_amount;
-        pool.no_reserves += no_amount;
-        pool.k = pool.yes_reserves.checked_mul(pool.no_reserves).unwrap();
-        pool.total_supply += lp_tokens_to_mint;
+        pool.yes_reserves = pool.yes_reserves
+            .checked_add(yes_amount)
+            .ok_or(ErrorCode::MathOverflow)?;
+        pool.no_reserves = pool.no_reserves
+            .checked_add(no_amount)
+            .ok_or(ErrorCode::MathOverflow)?;
+        refresh_liquidity(pool)?;
+        pool.total_supply = pool.total_supply
+            .checked_add(lp_tokens_to_mint)
+            .ok_or(ErrorCode::MathOverflow)?;
+        
+        // Snapshot the deposit so the LP can measure impermanent loss later
+        let position = &mut ctx.accounts.position;
+        position.owner = ctx.accounts.user.key();
+        position.pool_id = pool_id;
+        position.yes_at_deposit = position.yes_at_deposit
+            .checked_add(yes_amount)
+            .ok_or(ErrorCode::MathOverflow)?;
+        position.no_at_deposit = position.no_at_deposit
+            .checked_add(no_amount)
+            .ok_or(ErrorCode::MathOverflow)?;
+        position.lp_minted = position.lp_minted
+            .checked_add(lp_tokens_to_mint)
+            .ok_or(ErrorCode::MathOverflow)?;
+        position.deposited_at = now;
+        position.bump = ctx.bumps.position;
+        sync_reward_debt(pool, position)?;
+        unlock_pool(pool);
         
         emit!(LiquidityAdded {
             pool_id,
@@ -295,38 +545,273 @@ pub mod amm {
         require!(pool.total_supply > 0, ErrorCode::EmptyPool);
         
         // Calculate proportional amounts
-        let yes_amount_out = lp_amount
-            .checked_mul(pool.yes_reserves)
-            .unwrap()
-            .checked_div(pool.total_supply)
-            .unwrap();
-        
-        let no_amount_out = lp_amount
-            .checked_mul(pool.no_reserves)
-            .unwrap()
-            .checked_div(pool.total_supply)
-            .unwrap();
+        let yes_amount_out = lp_share_of_reserve(lp_amount, pool.yes_reserves, pool.total_supply)?;
+        let no_amount_out = lp_share_of_reserve(lp_amount, pool.no_reserves, pool.total_supply)?;
         
         require!(yes_amount_out >= minimum_yes_out, ErrorCode::SlippageExceeded);
         require!(no_amount_out >= minimum_no_out, ErrorCode::SlippageExceeded);
         
-        // Burn LP tokens
+        withdraw_liquidity(ctx, pool_id, lp_amount, yes_amount_out, no_amount_out)
+    }
+
+    /// Remove liquidity by targeting a YES amount out
+    /// Burns whatever LP is needed to withdraw `yes_amount_desired` YES plus the proportional NO
+    pub fn remove_liquidity_for_amount(
+        ctx: Context<RemoveLiquidity>,
+        pool_id: Pubkey,
+        yes_amount_desired: u64,
+        maximum_lp_in: u64,
+        minimum_no_out: u64,
+    ) -> Result<()> {
+        let pool = &ctx.accounts.pool;
+        
+        require!(yes_amount_desired > 0, ErrorCode::InvalidAmount);
+        require!(pool.total_supply > 0 && pool.yes_reserves > 0, ErrorCode::EmptyPool);
+        require!(yes_amount_desired <= pool.yes_reserves, ErrorCode::InsufficientLiquidity);
+        
+        // Inverse of the proportional math, rounded up so the pool never pays out more than the LP burned
+        let lp_amount = calculate_lp_for_amount(yes_amount_desired, pool.yes_reserves, pool.total_supply)?;
+        
+        require!(lp_amount <= maximum_lp_in, ErrorCode::SlippageExceeded);
+        require!(
+            ctx.accounts.user_lp_tokens.amount >= lp_amount,
+            ErrorCode::InsufficientLpTokens
+        );
+        
+        let yes_amount_out = lp_share_of_reserve(lp_amount, pool.yes_reserves, pool.total_supply)?;
+        let no_amount_out = lp_share_of_reserve(lp_amount, pool.no_reserves, pool.total_supply)?;
+        
+        require!(no_amount_out >= minimum_no_out, ErrorCode::SlippageExceeded);
+        
+        withdraw_liquidity(ctx, pool_id, lp_amount, yes_amount_out, no_amount_out)
+    }
+
+    /// Token-2022 variant of the swaps: buy `side` with `amount_in` of the opposite share
+    /// Works with legacy or Token-2022 share mints through `share_token_program`;
+    /// transfer fees are deducted before pricing and `minimum_out` applies to what arrives
+    /// Returns (amount_out, execution_price_scaled) via return data, like the legacy swaps
+    pub fn swap_interface(
+        ctx: Context<SwapInterface>,
+        _pool_id: Pubkey,
+        side: Outcome,
+        amount_in: u64,
+        minimum_out: u64,
+    ) -> Result<()> {
+        let accounts = ctx.accounts;
+        
+        // Buying YES spends NO, buying NO spends YES
+        let (pool_in, pool_out, user_in, user_out, mint_in, mint_out) = match side {
+            Outcome::Yes => (
+                &mut accounts.pool_no_shares,
+                &accounts.pool_yes_shares,
+                &accounts.user_no_shares,
+                &mut accounts.user_yes_shares,
+                &accounts.no_mint,
+                &accounts.yes_mint,
+            ),
+            Outcome::No => (
+                &mut accounts.pool_yes_shares,
+                &accounts.pool_no_shares,
+                &accounts.user_yes_shares,
+                &mut accounts.user_no_shares,
+                &accounts.yes_mint,
+                &accounts.no_mint,
+            ),
+        };
+        
+        // Protocol fee is skimmed from the input leg
+        require!(
+            accounts.protocol_fee_account.mint == pool_in.mint,
+            ErrorCode::InvalidFeeRecipient
+        );
+        
+        execute_swap_interface(
+            &mut accounts.pool,
+            ctx.bumps.pool,
+            pool_in,
+            pool_out,
+            user_in,
+            user_out,
+            mint_in,
+            mint_out,
+            &accounts.protocol_fee_account,
+            &accounts.user,
+            &accounts.share_token_program,
+            side == Outcome::No,
+            amount_in,
+            minimum_out,
+        )
+    }
+
+    /// Token-2022 variant of `add_liquidity`
+    /// LP is minted against the shares the pool actually received after transfer fees;
+    /// the LP mint itself stays on the legacy token program
+    /// Amounts are matched to the reserve ratio exactly as in `add_liquidity`
+    pub fn add_liquidity_interface(
+        ctx: Context<AddLiquidityInterface>,
+        pool_id: Pubkey,
+        yes_amount_desired: u64,
+        no_amount_desired: u64,
+        yes_amount_min: u64,
+        no_amount_min: u64,
+        minimum_lp_tokens: u64,
+    ) -> Result<()> {
+        require!(yes_amount_desired > 0 && no_amount_desired > 0, ErrorCode::InvalidAmount);
+        lock_pool(&mut ctx.accounts.pool)?;
+        
+        let accounts = &mut *ctx.accounts;
+        let (yes_amount, no_amount) = optimal_deposit(
+            &accounts.pool,
+            yes_amount_desired,
+            no_amount_desired,
+            yes_amount_min,
+            no_amount_min,
+        )?;
+        require!(yes_amount > 0 && no_amount > 0, ErrorCode::InvalidAmount);
+        let yes_before = accounts.pool_yes_shares.amount;
+        let no_before = accounts.pool_no_shares.amount;
+        
+        // Transfer shares from user to pool
+        token_interface::transfer_checked(
+            CpiContext::new(
+                accounts.share_token_program.to_account_info(),
+                TransferChecked {
+                    from: accounts.user_yes_shares.to_account_info(),
+                    mint: accounts.yes_mint.to_account_info(),
+                    to: accounts.pool_yes_shares.to_account_info(),
+                    authority: accounts.user.to_account_info(),
+                },
+            ),
+            yes_amount,
+            accounts.yes_mint.decimals,
+        )?;
+        token_interface::transfer_checked(
+            CpiContext::new(
+                accounts.share_token_program.to_account_info(),
+                TransferChecked {
+                    from: accounts.user_no_shares.to_account_info(),
+                    mint: accounts.no_mint.to_account_info(),
+                    to: accounts.pool_no_shares.to_account_info(),
+                    authority: accounts.user.to_account_info(),
+                },
+            ),
+            no_amount,
+            accounts.no_mint.decimals,
+        )?;
+        
+        accounts.pool_yes_shares.reload()?;
+        accounts.pool_no_shares.reload()?;
+        let yes_received = accounts.pool_yes_shares.amount
+            .checked_sub(yes_before)
+            .ok_or(ErrorCode::MathOverflow)?;
+        let no_received = accounts.pool_no_shares.amount
+            .checked_sub(no_before)
+            .ok_or(ErrorCode::MathOverflow)?;
+        require!(yes_received > 0 && no_received > 0, ErrorCode::InvalidAmount);
+        
+        let lp_tokens_to_mint = calculate_lp_to_mint(&accounts.pool, yes_received, no_received)?;
+        require!(lp_tokens_to_mint >= minimum_lp_tokens, ErrorCode::SlippageExceeded);
+        
+        // Mint LP tokens, signed by the pool PDA that is the mint authority
         let seeds = &[
             b"pool",
             pool_id.as_ref(),
-            b"lp_mint",
-            &[ctx.bumps.lp_mint],
+            &[ctx.bumps.pool],
         ];
         let signer = &[&seeds[..]];
         
-        let cpi_accounts = token::Burn {
-            mint: ctx.accounts.lp_mint.to_account_info(),
-            from: ctx.accounts.user_lp_tokens.to_account_info(),
-            authority: ctx.accounts.user.to_account_info(),
-        };
-        let cpi_program = ctx.accounts.token_program.to_account_info();
-        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
-        token::burn(cpi_ctx, lp_amount)?;
+        token::mint_to(
+            CpiContext::new_with_signer(
+                accounts.token_program.to_account_info(),
+                token::MintTo {
+                    mint: accounts.lp_mint.to_account_info(),
+                    to: accounts.user_lp_tokens.to_account_info(),
+                    authority: accounts.pool.to_account_info(),
+                },
+                signer,
+            ),
+            lp_tokens_to_mint,
+        )?;
+        
+        // Accrue rewards at the old supply before the new LP starts earning
+        let pool = &mut accounts.pool;
+        let now = Clock::get()?.unix_timestamp;
+        update_reward_index(pool, now)?;
+        accrue_position_rewards(pool, &mut accounts.position, accounts.user_lp_tokens.amount, now)?;
+        
+        // Update pool state
+        pool.yes_reserves = pool.yes_reserves
+            .checked_add(yes_received)
+            .ok_or(ErrorCode::MathOverflow)?;
+        pool.no_reserves = pool.no_reserves
+            .checked_add(no_received)
+            .ok_or(ErrorCode::MathOverflow)?;
+        refresh_liquidity(pool)?;
+        pool.total_supply = pool.total_supply
+            .checked_add(lp_tokens_to_mint)
+            .ok_or(ErrorCode::MathOverflow)?;
+        unlock_pool(pool);
+        
+        // Snapshot the deposit so the LP can measure impermanent loss later
+        let position = &mut accounts.position;
+        position.owner = accounts.user.key();
+        position.pool_id = pool_id;
+        position.yes_at_deposit = position.yes_at_deposit
+            .checked_add(yes_received)
+            .ok_or(ErrorCode::MathOverflow)?;
+        position.no_at_deposit = position.no_at_deposit
+            .checked_add(no_received)
+            .ok_or(ErrorCode::MathOverflow)?;
+        position.lp_minted = position.lp_minted
+            .checked_add(lp_tokens_to_mint)
+            .ok_or(ErrorCode::MathOverflow)?;
+        position.deposited_at = now;
+        position.bump = ctx.bumps.position;
+        sync_reward_debt(pool, position)?;
+        
+        emit!(LiquidityAdded {
+            pool_id,
+            user: accounts.user.key(),
+            yes_amount: yes_received,
+            no_amount: no_received,
+            lp_tokens_minted: lp_tokens_to_mint,
+        });
+        
+        Ok(())
+    }
+
+    /// Token-2022 variant of `remove_liquidity`
+    /// Minimums apply to the shares the user actually receives after transfer fees
+    pub fn remove_liquidity_interface(
+        ctx: Context<RemoveLiquidityInterface>,
+        pool_id: Pubkey,
+        lp_amount: u64,
+        minimum_yes_out: u64,
+        minimum_no_out: u64,
+    ) -> Result<()> {
+        require!(lp_amount > 0, ErrorCode::InvalidAmount);
+        require!(ctx.accounts.pool.total_supply > 0, ErrorCode::EmptyPool);
+        lock_pool(&mut ctx.accounts.pool)?;
+        
+        let accounts = &mut *ctx.accounts;
+        let pool = &accounts.pool;
+        
+        // Calculate proportional amounts
+        let yes_amount_out = lp_share_of_reserve(lp_amount, pool.yes_reserves, pool.total_supply)?;
+        let no_amount_out = lp_share_of_reserve(lp_amount, pool.no_reserves, pool.total_supply)?;
+        
+        // Burn LP tokens
+        token::burn(
+            CpiContext::new(
+                accounts.token_program.to_account_info(),
+                token::Burn {
+                    mint: accounts.lp_mint.to_account_info(),
+                    from: accounts.user_lp_tokens.to_account_info(),
+                    authority: accounts.user.to_account_info(),
+                },
+            ),
+            lp_amount,
+        )?;
         
         // Transfer shares from pool to user
         let seeds = &[
@@ -335,33 +820,72 @@ pub mod amm {
             &[ctx.bumps.pool],
         ];
         let signer = &[&seeds[..]];
+        let yes_before = accounts.user_yes_shares.amount;
+        let no_before = accounts.user_no_shares.amount;
         
-        let cpi_accounts = Transfer {
-            from: ctx.accounts.pool_yes_shares.to_account_info(),
-            to: ctx.accounts.user_yes_shares.to_account_info(),
-            authority: pool.to_account_info(),
-        };
-        let cpi_program = ctx.accounts.token_program.to_account_info();
-        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
-        token::transfer(cpi_ctx, yes_amount_out)?;
+        token_interface::transfer_checked(
+            CpiContext::new_with_signer(
+                accounts.share_token_program.to_account_info(),
+                TransferChecked {
+                    from: accounts.pool_yes_shares.to_account_info(),
+                    mint: accounts.yes_mint.to_account_info(),
+                    to: accounts.user_yes_shares.to_account_info(),
+                    authority: accounts.pool.to_account_info(),
+                },
+                signer,
+            ),
+            yes_amount_out,
+            accounts.yes_mint.decimals,
+        )?;
+        token_interface::transfer_checked(
+            CpiContext::new_with_signer(
+                accounts.share_token_program.to_account_info(),
+                TransferChecked {
+                    from: accounts.pool_no_shares.to_account_info(),
+                    mint: accounts.no_mint.to_account_info(),
+                    to: accounts.user_no_shares.to_account_info(),
+                    authority: accounts.pool.to_account_info(),
+                },
+                signer,
+            ),
+            no_amount_out,
+            accounts.no_mint.decimals,
+        )?;
         
-        let cpi_accounts = Transfer {
-            from: ctx.accounts.pool_no_shares.to_account_info(),
-            to: ctx.accounts.user_no_shares.to_account_info(),
-            authority: pool.to_account_info(),
-        };
-        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
-        token::transfer(cpi_ctx, no_amount_out)?;
+        accounts.user_yes_shares.reload()?;
+        accounts.user_no_shares.reload()?;
+        let yes_delivered = accounts.user_yes_shares.amount.saturating_sub(yes_before);
+        let no_delivered = accounts.user_no_shares.amount.saturating_sub(no_before);
+        require!(yes_delivered >= minimum_yes_out, ErrorCode::SlippageExceeded);
+        require!(no_delivered >= minimum_no_out, ErrorCode::SlippageExceeded);
         
         // Update pool state
-        pool.yes_reserves -= yes_amount_out;
-        pool.no_reserves -= no_amount_out;
-        pool.k = pool.yes_reserves.checked_mul(pool.no_reserves).unwrap();
-        pool.total_supply -= lp_amount;
+        let pool = &mut accounts.pool;
+        let now = Clock::get()?.unix_timestamp;
+        update_reward_index(pool, now)?;
+        pool.yes_reserves = pool.yes_reserves
+            .checked_sub(yes_amount_out)
+            .ok_or(ErrorCode::MathOverflow)?;
+        pool.no_reserves = pool.no_reserves
+            .checked_sub(no_amount_out)
+            .ok_or(ErrorCode::MathOverflow)?;
+        refresh_liquidity(pool)?;
+        pool.total_supply = pool.total_supply
+            .checked_sub(lp_amount)
+            .ok_or(ErrorCode::MathOverflow)?;
+        unlock_pool(pool);
+        
+        let position = &mut accounts.position;
+        position.owner = accounts.user.key();
+        position.pool_id = pool_id;
+        position.bump = ctx.bumps.position;
+        accrue_position_rewards(pool, position, accounts.user_lp_tokens.amount, now)?;
+        reduce_position(position, lp_amount)?;
+        sync_reward_debt(pool, position)?;
         
         emit!(LiquidityRemoved {
             pool_id,
-            user: ctx.accounts.user.key(),
+            user: accounts.user.key(),
             lp_tokens_burned: lp_amount,
             yes_amount_out,
             no_amount_out,
@@ -370,32 +894,1321 @@ pub mod amm {
         Ok(())
     }
 
-    /// Get current price for YES shares in terms of NO shares
-    pub fn get_yes_price(ctx: Context<GetPrice>) -> Result<u64> {
+    /// Exit a resolved market's pool at final value: burn LP, burn the pool's losing
+    /// shares and redeem its winning shares for SOL through the linked orderbook
+    /// Requires a deactivated pool and a resolved linked orderbook whose share token
+    /// mints are this pool's; the LP receives the SOL, less the orderbook's redemption fee,
+    /// instead of raw share tokens
+    pub fn settle_and_remove_liquidity(
+        ctx: Context<SettleLiquidity>,
+        pool_id: Pubkey,
+        lp_amount: u64,
+        minimum_lamports_out: u64,
+    ) -> Result<()> {
         let pool = &ctx.accounts.pool;
         
-        if pool.no_reserves == 0 {
-            return Err(ErrorCode::EmptyPool.into());
+        require!(!pool.is_active, ErrorCode::PoolStillActive);
+        require!(lp_amount > 0, ErrorCode::InvalidAmount);
+        require!(pool.total_supply > 0, ErrorCode::EmptyPool);
+        require!(
+            pool.linked_orderbook != Pubkey::default()
+                && ctx.accounts.orderbook.key() == pool.linked_orderbook
+                && ctx.accounts.orderbook.owner == ctx.accounts.orderbook_program.key,
+            ErrorCode::InvalidOrderbook
+        );
+        let orderbook = read_orderbook_snapshot(&ctx.accounts.orderbook)?;
+        require!(orderbook.market_id == pool.market_id, ErrorCode::MarketMismatch);
+        let winning_outcome = orderbook.winning_outcome
+            .filter(|_| !orderbook.is_active)
+            .ok_or(ErrorCode::MarketNotResolved)?;
+        
+        // LP's proportional slice of each side
+        let yes_amount_out = lp_share_of_reserve(lp_amount, pool.yes_reserves, pool.total_supply)?;
+        let no_amount_out = lp_share_of_reserve(lp_amount, pool.no_reserves, pool.total_supply)?;
+        
+        let (winning_amount, losing_amount, winning_vault, losing_vault, winning_mint, losing_mint) =
+            match winning_outcome {
+                Outcome::Yes => (
+                    yes_amount_out,
+                    no_amount_out,
+                    ctx.accounts.pool_yes_shares.to_account_info(),
+                    ctx.accounts.pool_no_shares.to_account_info(),
+                    ctx.accounts.yes_mint.to_account_info(),
+                    ctx.accounts.no_mint.to_account_info(),
+                ),
+                Outcome::No => (
+                    no_amount_out,
+                    yes_amount_out,
+                    ctx.accounts.pool_no_shares.to_account_info(),
+                    ctx.accounts.pool_yes_shares.to_account_info(),
+                    ctx.accounts.no_mint.to_account_info(),
+                    ctx.accounts.yes_mint.to_account_info(),
+                ),
+            };
+        
+        lock_pool(&mut ctx.accounts.pool)?;
+        
+        // Burn LP tokens
+        token::burn(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                token::Burn {
+                    mint: ctx.accounts.lp_mint.to_account_info(),
+                    from: ctx.accounts.user_lp_tokens.to_account_info(),
+                    authority: ctx.accounts.user.to_account_info(),
+                },
+            ),
+            lp_amount,
+        )?;
+        
+        let seeds = &[
+            b"pool",
+            pool_id.as_ref(),
+            &[ctx.bumps.pool],
+        ];
+        let signer = &[&seeds[..]];
+        let pool_info = ctx.accounts.pool.to_account_info();
+        
+        // Losing shares are worthless: burn them out of the pool
+        if losing_amount > 0 {
+            token::burn(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    token::Burn {
+                        mint: losing_mint,
+                        from: losing_vault,
+                        authority: pool_info.clone(),
+                    },
+                    signer,
+                ),
+                losing_amount,
+            )?;
+        }
+        
+        // Winning shares redeem at $1 from the orderbook vault, paid to the pool PDA
+        let lamports_before = pool_info.lamports();
+        if winning_amount > 0 {
+            let mut data = REDEEM_SHARE_TOKENS_DISCRIMINATOR.to_vec();
+            winning_outcome.serialize(&mut data)?;
+            winning_amount.serialize(&mut data)?;
+            
+            let ix = Instruction {
+                program_id: ctx.accounts.orderbook_program.key(),
+                accounts: vec![
+                    AccountMeta::new(pool_info.key(), true),
+                    AccountMeta::new(ctx.accounts.orderbook.key(), false),
+                    AccountMeta::new(winning_mint.key(), false),
+                    AccountMeta::new(winning_vault.key(), false),
+                    AccountMeta::new(ctx.accounts.orderbook_vault.key(), false),
+                    AccountMeta::new(ctx.accounts.orderbook_fee_vault.key(), false),
+                    AccountMeta::new_readonly(ctx.accounts.token_program.key(), false),
+                    AccountMeta::new_readonly(ctx.accounts.system_program.key(), false),
+                ],
+                data,
+            };
+            invoke_signed(
+                &ix,
+                &[
+                    pool_info.clone(),
+                    ctx.accounts.orderbook.to_account_info(),
+                    winning_mint,
+                    winning_vault,
+                    ctx.accounts.orderbook_vault.to_account_info(),
+                    ctx.accounts.orderbook_fee_vault.to_account_info(),
+                    ctx.accounts.token_program.to_account_info(),
+                    ctx.accounts.system_program.to_account_info(),
+                    ctx.accounts.orderbook_program.to_account_info(),
+                ],
+                signer,
+            )?;
         }
+        let payout = pool_info.lamports()
+            .checked_sub(lamports_before)
+            .ok_or(ErrorCode::MathOverflow)?;
+        require!(payout >= minimum_lamports_out, ErrorCode::SlippageExceeded);
+        
+        // Forward the redemption from the pool PDA to the LP
+        **pool_info.try_borrow_mut_lamports()? -= payout;
+        **ctx.accounts.user.to_account_info().try_borrow_mut_lamports()? += payout;
+        
+        // Update pool state
+        let pool = &mut ctx.accounts.pool;
+        let now = Clock::get()?.unix_timestamp;
+        update_reward_index(pool, now)?;
+        pool.yes_reserves = pool.yes_reserves
+            .checked_sub(yes_amount_out)
+            .ok_or(ErrorCode::MathOverflow)?;
+        pool.no_reserves = pool.no_reserves
+            .checked_sub(no_amount_out)
+            .ok_or(ErrorCode::MathOverflow)?;
+        refresh_liquidity(pool)?;
+        pool.total_supply = pool.total_supply
+            .checked_sub(lp_amount)
+            .ok_or(ErrorCode::MathOverflow)?;
         
-        let price = pool.yes_reserves.checked_div(pool.no_reserves).unwrap();
-        Ok(price)
+        let position = &mut ctx.accounts.position;
+        position.owner = ctx.accounts.user.key();
+        position.pool_id = pool_id;
+        position.bump = ctx.bumps.position;
+        accrue_position_rewards(pool, position, ctx.accounts.user_lp_tokens.amount, now)?;
+        reduce_position(position, lp_amount)?;
+        sync_reward_debt(pool, position)?;
+        unlock_pool(pool);
+        
+        emit!(LiquiditySettled {
+            pool_id,
+            user: ctx.accounts.user.key(),
+            lp_tokens_burned: lp_amount,
+            winning_shares_redeemed: winning_amount,
+            losing_shares_burned: losing_amount,
+            payout_lamports: payout,
+        });
+        
+        Ok(())
+    }
+
+    /// Get current price for YES shares in terms of NO shares, in PRICE_PRECISION units
+    /// Capped at PRICE_PRECISION; never errors on an empty side:
+    /// yes_reserves == 0 -> 0, no_reserves == 0 -> PRICE_PRECISION (YES ~ certain)
+    pub fn get_yes_price(ctx: Context<GetPrice>) -> Result<u64> {
+        let (yes_reserves, no_reserves) = effective_reserves(&ctx.accounts.pool)?;
+        capped_reserve_ratio(yes_reserves, no_reserves)
     }
 
-    /// Get current price for NO shares in terms of YES shares
+    /// Get current price for NO shares in terms of YES shares, in PRICE_PRECISION units
+    /// Capped at PRICE_PRECISION; never errors on an empty side:
+    /// no_reserves == 0 -> 0, yes_reserves == 0 -> PRICE_PRECISION (NO ~ certain)
     pub fn get_no_price(ctx: Context<GetPrice>) -> Result<u64> {
+        let (yes_reserves, no_reserves) = effective_reserves(&ctx.accounts.pool)?;
+        capped_reserve_ratio(no_reserves, yes_reserves)
+    }
+
+    /// Check the pool's recorded reserves against its share token account balances
+    /// Invariant: yes_reserves == pool_yes_shares.amount and no_reserves == pool_no_shares.amount
+    /// Read-only, for auditors and monitoring bots - fails with ReserveMismatch on drift
+    pub fn reconcile_reserves(ctx: Context<ReconcileReserves>, _pool_id: Pubkey) -> Result<()> {
         let pool = &ctx.accounts.pool;
+        let yes_balance = ctx.accounts.pool_yes_shares.amount;
+        let no_balance = ctx.accounts.pool_no_shares.amount;
+        
+        require!(
+            pool.yes_reserves == yes_balance && pool.no_reserves == no_balance,
+            ErrorCode::ReserveMismatch
+        );
+        
+        Ok(())
+    }
+
+    /// Sweep tokens donated directly to the pool accounts out to a recipient (authority only)
+    /// Sends balance - reserves on each side, restoring balance == reserves; reserves and
+    /// k are untouched, so donations never reach pricing or LP value
+    /// A balance below reserves is a real deficit and fails with ReserveMismatch
+    pub fn skim(ctx: Context<Skim>, pool_id: Pubkey) -> Result<()> {
+        let accounts = &ctx.accounts;
+        let pool = &accounts.pool;
+        
+        require!(
+            accounts.pool_yes_shares.amount >= pool.yes_reserves
+                && accounts.pool_no_shares.amount >= pool.no_reserves,
+            ErrorCode::ReserveMismatch
+        );
+        let yes_excess = accounts.pool_yes_shares.amount - pool.yes_reserves;
+        let no_excess = accounts.pool_no_shares.amount - pool.no_reserves;
+        
+        let seeds = &[
+            b"pool",
+            pool_id.as_ref(),
+            &[ctx.bumps.pool],
+        ];
+        let signer = &[&seeds[..]];
         
-        if pool.yes_reserves == 0 {
-            return Err(ErrorCode::EmptyPool.into());
+        if yes_excess > 0 {
+            token_interface::transfer_checked(
+                CpiContext::new_with_signer(
+                    accounts.share_token_program.to_account_info(),
+                    TransferChecked {
+                        from: accounts.pool_yes_shares.to_account_info(),
+                        mint: accounts.yes_mint.to_account_info(),
+                        to: accounts.recipient_yes_shares.to_account_info(),
+                        authority: accounts.pool.to_account_info(),
+                    },
+                    signer,
+                ),
+                yes_excess,
+                accounts.yes_mint.decimals,
+            )?;
+        }
+        if no_excess > 0 {
+            token_interface::transfer_checked(
+                CpiContext::new_with_signer(
+                    accounts.share_token_program.to_account_info(),
+                    TransferChecked {
+                        from: accounts.pool_no_shares.to_account_info(),
+                        mint: accounts.no_mint.to_account_info(),
+                        to: accounts.recipient_no_shares.to_account_info(),
+                        authority: accounts.pool.to_account_info(),
+                    },
+                    signer,
+                ),
+                no_excess,
+                accounts.no_mint.decimals,
+            )?;
         }
         
-        let price = pool.no_reserves.checked_div(pool.yes_reserves).unwrap();
-        Ok(price)
+        emit!(ReservesSkimmed {
+            pool_id,
+            yes_amount: yes_excess,
+            no_amount: no_excess,
+        });
+        
+        Ok(())
     }
-}
 
-// Account structures
+    /// Turn on liquidity mining with `reward_mint`, emitting `reward_rate` units per second
+    /// across all LP (authority only)
+    /// Rewards are paid out of the pool-owned reward vault created here; fund it with a
+    /// plain token transfer. Each position earns pro-rata to its lp_minted, counting only
+    /// LP still held in the depositor's wallet, and settles on every add, remove, settle
+    /// and claim; swap fees compound into reserves, so there is no fee collection to settle
+    pub fn initialize_rewards(
+        ctx: Context<InitializeRewards>,
+        _pool_id: Pubkey,
+        reward_rate: u64,
+    ) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        
+        require!(pool.reward_mint == Pubkey::default(), ErrorCode::RewardsAlreadyEnabled);
+        
+        // Nothing accrued before now: start the index clock here
+        pool.last_reward_update = Clock::get()?.unix_timestamp;
+        pool.reward_mint = ctx.accounts.reward_mint.key();
+        pool.reward_rate = reward_rate;
+        
+        Ok(())
+    }
+
+    /// Change the liquidity mining emission rate (authority only)
+    /// Rewards up to now accrue at the old rate first; 0 pauses emission
+    pub fn set_reward_rate(
+        ctx: Context<UpdatePool>,
+        reward_rate: u64,
+    ) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        
+        require!(pool.reward_mint != Pubkey::default(), ErrorCode::RewardsNotEnabled);
+        
+        update_reward_index(pool, Clock::get()?.unix_timestamp)?;
+        pool.reward_rate = reward_rate;
+        
+        Ok(())
+    }
+
+    /// Pay a position's accrued liquidity mining rewards out of the reward vault
+    /// Pays at most the vault balance; any shortfall stays pending for a later claim
+    /// Returns the amount paid via return data
+    pub fn claim_rewards(ctx: Context<ClaimRewards>, pool_id: Pubkey) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        let position = &mut ctx.accounts.position;
+        
+        require!(pool.reward_mint != Pubkey::default(), ErrorCode::RewardsNotEnabled);
+        
+        let now = Clock::get()?.unix_timestamp;
+        update_reward_index(pool, now)?;
+        accrue_position_rewards(pool, position, ctx.accounts.user_lp_tokens.amount, now)?;
+        
+        let amount = std::cmp::min(position.pending_rewards, ctx.accounts.reward_vault.amount);
+        require!(amount > 0, ErrorCode::NoRewardsToClaim);
+        
+        let seeds = &[
+            b"pool",
+            pool_id.as_ref(),
+            &[ctx.bumps.pool],
+        ];
+        let signer = &[&seeds[..]];
+        
+        token::transfer_checked(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                LegacyTransferChecked {
+                    from: ctx.accounts.reward_vault.to_account_info(),
+                    mint: ctx.accounts.reward_mint.to_account_info(),
+                    to: ctx.accounts.user_reward_tokens.to_account_info(),
+                    authority: pool.to_account_info(),
+                },
+                signer,
+            ),
+            amount,
+            ctx.accounts.reward_mint.decimals,
+        )?;
+        position.pending_rewards -= amount;
+        
+        emit!(RewardsClaimed {
+            pool_id,
+            user: ctx.accounts.user.key(),
+            amount,
+        });
+        
+        set_return_data(&amount.try_to_vec()?);
+        
+        Ok(())
+    }
+
+    /// Blend the orderbook's last YES price with the AMM's implied YES price
+    /// Each venue is weighted by its share count: AMM yes + no reserves vs
+    /// orderbook total_yes_shares + total_no_shares; a venue with no liquidity
+    /// drops out, and with neither the orderbook's (seeded) last price is used
+    /// Returns (blended, amm_price, orderbook_price) in PRICE_PRECISION units via return data
+    pub fn get_blended_odds(ctx: Context<GetBlendedOdds>) -> Result<()> {
+        let pool = &ctx.accounts.pool;
+        
+        require!(
+            pool.linked_orderbook != Pubkey::default()
+                && ctx.accounts.orderbook.key() == pool.linked_orderbook,
+            ErrorCode::InvalidOrderbook
+        );
+        let orderbook = read_orderbook_snapshot(&ctx.accounts.orderbook)?;
+        require!(orderbook.market_id == pool.market_id, ErrorCode::MarketMismatch);
+        
+        let amm_liquidity = (pool.yes_reserves as u128) + (pool.no_reserves as u128);
+        let orderbook_liquidity =
+            (orderbook.total_yes_shares as u128) + (orderbook.total_no_shares as u128);
+        
+        let amm_price = if amm_liquidity > 0 {
+            let (yes_reserves, no_reserves) = effective_reserves(pool)?;
+            implied_yes_price(yes_reserves, no_reserves)?
+        } else {
+            0
+        };
+        let orderbook_price = orderbook.last_yes_price;
+        
+        let blended = if amm_liquidity == 0 {
+            orderbook_price
+        } else if orderbook_liquidity == 0 {
+            amm_price
+        } else {
+            let weighted = (amm_price as u128)
+                .checked_mul(amm_liquidity)
+                .ok_or(ErrorCode::MathOverflow)?
+                .checked_add(
+                    (orderbook_price as u128)
+                        .checked_mul(orderbook_liquidity)
+                        .ok_or(ErrorCode::MathOverflow)?,
+                )
+                .ok_or(ErrorCode::MathOverflow)?;
+            (weighted / (amm_liquidity + orderbook_liquidity)) as u64
+        };
+        
+        set_return_data(&(blended, amm_price, orderbook_price).try_to_vec()?);
+        
+        Ok(())
+    }
+}
+
+// Helpers
+
+/// LP tokens minted for a deposit of `yes_amount` YES and `no_amount` NO
+/// The first deposit mints sqrt(yes * no), which always fits in u64 and keeps supply on
+/// the scale of the reserves; later ones mint against the scarcer side
+fn calculate_lp_to_mint(pool: &AmmPool, yes_amount: u64, no_amount: u64) -> Result<u64> {
+    if pool.total_supply == 0 {
+        // First liquidity provider: geometric mean of the opening deposits
+        return Ok(integer_sqrt(yes_amount as u128 * no_amount as u128) as u64);
+    }
+    
+    // Calculate based on existing reserves (u128 so the product cannot wrap)
+    let yes_ratio = (yes_amount as u128 * pool.total_supply as u128)
+        .checked_div(pool.yes_reserves as u128)
+        .ok_or(ErrorCode::EmptyPool)?;
+    let no_ratio = (no_amount as u128 * pool.total_supply as u128)
+        .checked_div(pool.no_reserves as u128)
+        .ok_or(ErrorCode::EmptyPool)?;
+    
+    // Use the minimum to maintain ratio
+    u64::try_from(std::cmp::min(yes_ratio, no_ratio)).map_err(|_| ErrorCode::MathOverflow.into())
+}
+
+/// Deposit amounts matching the current reserve ratio, capped at the desired amounts
+/// Uniswap v2 `_addLiquidity`: take all of one side and quote the other; an empty pool
+/// takes both desired amounts, which set the opening ratio
+fn optimal_deposit(
+    pool: &AmmPool,
+    yes_amount_desired: u64,
+    no_amount_desired: u64,
+    yes_amount_min: u64,
+    no_amount_min: u64,
+) -> Result<(u64, u64)> {
+    if pool.total_supply == 0 {
+        return Ok((yes_amount_desired, no_amount_desired));
+    }
+    
+    let no_amount_optimal = quote_amount(yes_amount_desired, pool.yes_reserves, pool.no_reserves)?;
+    if no_amount_optimal <= no_amount_desired {
+        require!(no_amount_optimal >= no_amount_min, ErrorCode::SlippageExceeded);
+        return Ok((yes_amount_desired, no_amount_optimal));
+    }
+    
+    let yes_amount_optimal = quote_amount(no_amount_desired, pool.no_reserves, pool.yes_reserves)?;
+    require!(
+        yes_amount_optimal <= yes_amount_desired && yes_amount_optimal >= yes_amount_min,
+        ErrorCode::SlippageExceeded
+    );
+    Ok((yes_amount_optimal, no_amount_desired))
+}
+
+/// `amount` of one side priced in the other at the reserve ratio, rounded down
+fn quote_amount(amount: u64, reserve_from: u64, reserve_to: u64) -> Result<u64> {
+    let quoted = (amount as u128)
+        .checked_mul(reserve_to as u128)
+        .ok_or(ErrorCode::MathOverflow)?
+        .checked_div(reserve_from as u128)
+        .ok_or(ErrorCode::EmptyPool)?;
+    u64::try_from(quoted).map_err(|_| ErrorCode::MathOverflow.into())
+}
+
+/// Reserve owed to `lp_amount` LP: lp_amount * reserve / total_supply, rounded down in u128
+fn lp_share_of_reserve(lp_amount: u64, reserve: u64, total_supply: u64) -> Result<u64> {
+    let share = (lp_amount as u128)
+        .checked_mul(reserve as u128)
+        .ok_or(ErrorCode::MathOverflow)?
+        .checked_div(total_supply as u128)
+        .ok_or(ErrorCode::EmptyPool)?;
+    u64::try_from(share).map_err(|_| ErrorCode::MathOverflow.into())
+}
+
+/// LP tokens needed to withdraw `yes_amount` YES: ceil(yes_amount * total_supply / yes_reserves)
+fn calculate_lp_for_amount(yes_amount: u64, yes_reserves: u64, total_supply: u64) -> Result<u64> {
+    let numerator = (yes_amount as u128)
+        .checked_mul(total_supply as u128)
+        .ok_or(ErrorCode::MathOverflow)?;
+    let lp_amount = numerator
+        .checked_add(yes_reserves as u128 - 1)
+        .ok_or(ErrorCode::MathOverflow)?
+        / yes_reserves as u128;
+    u64::try_from(lp_amount).map_err(|_| ErrorCode::MathOverflow.into())
+}
+
+/// Burn `lp_amount` LP and pay out the given reserves, shared by both removal paths
+fn withdraw_liquidity(
+    ctx: Context<RemoveLiquidity>,
+    pool_id: Pubkey,
+    lp_amount: u64,
+    yes_amount_out: u64,
+    no_amount_out: u64,
+) -> Result<()> {
+    let pool = &mut ctx.accounts.pool;
+    lock_pool(pool)?;
+    
+    // Burn LP tokens
+    let seeds = &[
+        b"pool",
+        pool_id.as_ref(),
+        b"lp_mint",
+        &[ctx.bumps.lp_mint],
+    ];
+    let signer = &[&seeds[..]];
+    
+    let cpi_accounts = token::Burn {
+        mint: ctx.accounts.lp_mint.to_account_info(),
+        from: ctx.accounts.user_lp_tokens.to_account_info(),
+        authority: ctx.accounts.user.to_account_info(),
+    };
+    let cpi_program = ctx.accounts.token_program.to_account_info();
+    let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+    token::burn(cpi_ctx, lp_amount)?;
+    
+    // Transfer shares from pool to user
+    let seeds = &[
+        b"pool",
+        pool_id.as_ref(),
+        &[ctx.bumps.pool],
+    ];
+    let signer = &[&seeds[..]];
+    
+    let cpi_accounts = LegacyTransferChecked {
+        from: ctx.accounts.pool_yes_shares.to_account_info(),
+        mint: ctx.accounts.yes_mint.to_account_info(),
+        to: ctx.accounts.user_yes_shares.to_account_info(),
+        authority: pool.to_account_info(),
+    };
+    let cpi_program = ctx.accounts.token_program.to_account_info();
+    let cpi_ctx = CpiContext::new_with_signer(cpi_program.clone(), cpi_accounts, signer);
+    token::transfer_checked(cpi_ctx, yes_amount_out, ctx.accounts.yes_mint.decimals)?;
+    
+    let cpi_accounts = LegacyTransferChecked {
+        from: ctx.accounts.pool_no_shares.to_account_info(),
+        mint: ctx.accounts.no_mint.to_account_info(),
+        to: ctx.accounts.user_no_shares.to_account_info(),
+        authority: pool.to_account_info(),
+    };
+    let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+    token::transfer_checked(cpi_ctx, no_amount_out, ctx.accounts.no_mint.decimals)?;
+    
+    // Update pool state
+    let now = Clock::get()?.unix_timestamp;
+    update_reward_index(pool, now)?;
+    pool.yes_reserves = pool.yes_reserves
+        .checked_sub(yes_amount_out)
+        .ok_or(ErrorCode::MathOverflow)?;
+    pool.no_reserves = pool.no_reserves
+        .checked_sub(no_amount_out)
+        .ok_or(ErrorCode::MathOverflow)?;
+    refresh_liquidity(pool)?;
+    pool.total_supply = pool.total_supply
+        .checked_sub(lp_amount)
+        .ok_or(ErrorCode::MathOverflow)?;
+    
+    // Settle rewards, then shrink the deposit snapshot pro-rata to the LP burned
+    let position = &mut ctx.accounts.position;
+    position.owner = ctx.accounts.user.key();
+    position.pool_id = pool_id;
+    position.bump = ctx.bumps.position;
+    accrue_position_rewards(pool, position, ctx.accounts.user_lp_tokens.amount, now)?;
+    reduce_position(position, lp_amount)?;
+    sync_reward_debt(pool, position)?;
+    unlock_pool(pool);
+    
+    emit!(LiquidityRemoved {
+        pool_id,
+        user: ctx.accounts.user.key(),
+        lp_tokens_burned: lp_amount,
+        yes_amount_out,
+        no_amount_out,
+    });
+    
+    Ok(())
+}
+
+/// Advance the pool's reward index to `now`, spreading the elapsed emission over total_supply
+/// Must run before total_supply changes so each interval is split over the LP
+/// that existed during it; with no LP or no rate the interval's rewards are simply not emitted
+fn update_reward_index(pool: &mut AmmPool, now: i64) -> Result<()> {
+    let elapsed = now.saturating_sub(pool.last_reward_update);
+    if elapsed <= 0 {
+        return Ok(());
+    }
+    if pool.total_supply > 0 && pool.reward_rate > 0 {
+        let increment = (pool.reward_rate as u128)
+            .checked_mul(elapsed as u128)
+            .and_then(|v| v.checked_mul(REWARD_PRECISION))
+            .ok_or(ErrorCode::MathOverflow)?
+            / pool.total_supply as u128;
+        pool.acc_reward_per_lp = pool.acc_reward_per_lp
+            .checked_add(increment)
+            .ok_or(ErrorCode::MathOverflow)?;
+    }
+    pool.last_reward_update = now;
+    Ok(())
+}
+
+/// Rewards a position's lp_minted has earned since its reward_debt was last synced
+/// LP tokens are transferable, so only the part of lp_minted still held in `lp_balance`
+/// earns; the share of LP moved out of the wallet is forfeited
+fn unaccrued_rewards(pool: &AmmPool, position: &LiquidityPosition, lp_balance: u64) -> Result<u64> {
+    let accumulated = (position.lp_minted as u128)
+        .checked_mul(pool.acc_reward_per_lp)
+        .ok_or(ErrorCode::MathOverflow)?;
+    let mut owed = accumulated.saturating_sub(position.reward_debt);
+    if lp_balance < position.lp_minted {
+        owed = owed
+            .checked_mul(lp_balance as u128)
+            .ok_or(ErrorCode::MathOverflow)?
+            / position.lp_minted as u128;
+    }
+    u64::try_from(owed / REWARD_PRECISION).map_err(|_| ErrorCode::MathOverflow.into())
+}
+
+/// Move a position's earned rewards into pending_rewards at its current lp_minted,
+/// capped by the LP the user holds in `lp_balance` (read before any mint or burn)
+/// Call after update_reward_index and before lp_minted changes, then sync_reward_debt
+fn accrue_position_rewards(
+    pool: &AmmPool,
+    position: &mut LiquidityPosition,
+    lp_balance: u64,
+    now: i64,
+) -> Result<()> {
+    let owed = unaccrued_rewards(pool, position, lp_balance)?;
+    position.pending_rewards = position.pending_rewards
+        .checked_add(owed)
+        .ok_or(ErrorCode::MathOverflow)?;
+    position.last_update = now;
+    sync_reward_debt(pool, position)
+}
+
+/// Mark everything the position's lp_minted has earned so far as accounted for
+fn sync_reward_debt(pool: &AmmPool, position: &mut LiquidityPosition) -> Result<()> {
+    position.reward_debt = (position.lp_minted as u128)
+        .checked_mul(pool.acc_reward_per_lp)
+        .ok_or(ErrorCode::MathOverflow)?;
+    Ok(())
+}
+
+/// Remove the burned share of a position's deposit snapshot
+/// Burning more LP than the position minted clears it entirely
+fn reduce_position(position: &mut LiquidityPosition, lp_burned: u64) -> Result<()> {
+    if lp_burned >= position.lp_minted {
+        position.yes_at_deposit = 0;
+        position.no_at_deposit = 0;
+        position.lp_minted = 0;
+        return Ok(());
+    }
+    
+    let yes_removed = (position.yes_at_deposit as u128)
+        .checked_mul(lp_burned as u128)
+        .ok_or(ErrorCode::MathOverflow)?
+        / position.lp_minted as u128;
+    let no_removed = (position.no_at_deposit as u128)
+        .checked_mul(lp_burned as u128)
+        .ok_or(ErrorCode::MathOverflow)?
+        / position.lp_minted as u128;
+    
+    position.yes_at_deposit -= yes_removed as u64;
+    position.no_at_deposit -= no_removed as u64;
+    position.lp_minted -= lp_burned;
+    Ok(())
+}
+
+/// Constant-product swap shared by both directions and `swap_at_market`
+/// The curve runs on effective reserves, so concentrated pools trade on their virtual
+/// reserves and can never pay out more than the real reserve
+/// `yes_to_no` names the input side; the `pool_in`/`user_in` accounts hold that share
+/// `enforce_impact_cap` bounds the trade by `max_price_impact_bps` on top of `minimum_out`
+#[allow(clippy::too_many_arguments)]
+fn execute_swap<'info>(
+    pool: &mut Account<'info, AmmPool>,
+    pool_bump: u8,
+    pool_in: &Account<'info, TokenAccount>,
+    pool_out: &Account<'info, TokenAccount>,
+    user_in: &Account<'info, TokenAccount>,
+    user_out: &Account<'info, TokenAccount>,
+    mint_in: &Account<'info, token::Mint>,
+    mint_out: &Account<'info, token::Mint>,
+    protocol_fee_account: &Account<'info, TokenAccount>,
+    user: &Signer<'info>,
+    token_program: &Program<'info, Token>,
+    yes_to_no: bool,
+    amount_in: u64,
+    minimum_out: u64,
+    enforce_impact_cap: bool,
+) -> Result<()> {
+    require!(pool.is_active, ErrorCode::PoolInactive);
+    require!(amount_in > 0, ErrorCode::InvalidAmount);
+    require!(pool.yes_reserves > 0 && pool.no_reserves > 0, ErrorCode::EmptyPool);
+    lock_pool(pool)?;
+    
+    let quote = quote_swap(pool, yes_to_no, amount_in, enforce_impact_cap)?;
+    let SwapQuote { fee, protocol_fee, amount_out } = quote;
+    require!(amount_out >= minimum_out, ErrorCode::SlippageExceeded);
+    
+    let execution_price_scaled = calculate_execution_price(amount_in, amount_out)?;
+    
+    // Transfer input shares from user to pool
+    let cpi_accounts = LegacyTransferChecked {
+        from: user_in.to_account_info(),
+        mint: mint_in.to_account_info(),
+        to: pool_in.to_account_info(),
+        authority: user.to_account_info(),
+    };
+    let cpi_program = token_program.to_account_info();
+    let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+    token::transfer_checked(cpi_ctx, amount_in, mint_in.decimals)?;
+    
+    // Transfer output shares from pool to user
+    let pool_id = pool.pool_id;
+    let seeds = &[
+        b"pool",
+        pool_id.as_ref(),
+        &[pool_bump],
+    ];
+    let signer = &[&seeds[..]];
+    
+    let cpi_accounts = LegacyTransferChecked {
+        from: pool_out.to_account_info(),
+        mint: mint_out.to_account_info(),
+        to: user_out.to_account_info(),
+        authority: pool.to_account_info(),
+    };
+    let cpi_program = token_program.to_account_info();
+    let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+    token::transfer_checked(cpi_ctx, amount_out, mint_out.decimals)?;
+    
+    // Transfer protocol fee from pool to the protocol fee recipient
+    if protocol_fee > 0 {
+        let cpi_accounts = LegacyTransferChecked {
+            from: pool_in.to_account_info(),
+            mint: mint_in.to_account_info(),
+            to: protocol_fee_account.to_account_info(),
+            authority: pool.to_account_info(),
+        };
+        let cpi_program = token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        token::transfer_checked(cpi_ctx, protocol_fee, mint_in.decimals)?;
+    }
+    
+    record_swap(pool, yes_to_no, amount_in, quote)?;
+    
+    emit!(SwapExecuted {
+        pool_id,
+        user: user.key(),
+        yes_to_no,
+        amount_in,
+        amount_out,
+        fee,
+        protocol_fee,
+        execution_price_scaled,
+    });
+    unlock_pool(pool);
+    
+    // Expose the fill to the caller: (amount_out, execution_price_scaled)
+    // Borsh encodes the tuple as two little-endian u64s, amount_out first
+    set_return_data(&(amount_out, execution_price_scaled).try_to_vec()?);
+    
+    Ok(())
+}
+
+/// Token-2022 counterpart of `execute_swap`
+/// Every leg uses `transfer_checked`, and transfer-fee mints are handled by measuring
+/// what actually arrives: the curve is priced on the amount the pool received and
+/// `minimum_out` is checked against the amount the user received
+#[allow(clippy::too_many_arguments)]
+fn execute_swap_interface<'info>(
+    pool: &mut Account<'info, AmmPool>,
+    pool_bump: u8,
+    pool_in: &mut InterfaceAccount<'info, InterfaceTokenAccount>,
+    pool_out: &InterfaceAccount<'info, InterfaceTokenAccount>,
+    user_in: &InterfaceAccount<'info, InterfaceTokenAccount>,
+    user_out: &mut InterfaceAccount<'info, InterfaceTokenAccount>,
+    mint_in: &InterfaceAccount<'info, InterfaceMint>,
+    mint_out: &InterfaceAccount<'info, InterfaceMint>,
+    protocol_fee_account: &InterfaceAccount<'info, InterfaceTokenAccount>,
+    user: &Signer<'info>,
+    token_program: &Interface<'info, TokenInterface>,
+    yes_to_no: bool,
+    amount_in: u64,
+    minimum_out: u64,
+) -> Result<()> {
+    require!(pool.is_active, ErrorCode::PoolInactive);
+    require!(amount_in > 0, ErrorCode::InvalidAmount);
+    require!(pool.yes_reserves > 0 && pool.no_reserves > 0, ErrorCode::EmptyPool);
+    lock_pool(pool)?;
+    
+    // Transfer input shares from user to pool, keeping only what survives the transfer fee
+    let pool_in_before = pool_in.amount;
+    token_interface::transfer_checked(
+        CpiContext::new(
+            token_program.to_account_info(),
+            TransferChecked {
+                from: user_in.to_account_info(),
+                mint: mint_in.to_account_info(),
+                to: pool_in.to_account_info(),
+                authority: user.to_account_info(),
+            },
+        ),
+        amount_in,
+        mint_in.decimals,
+    )?;
+    pool_in.reload()?;
+    let received_in = pool_in.amount
+        .checked_sub(pool_in_before)
+        .ok_or(ErrorCode::MathOverflow)?;
+    require!(received_in > 0, ErrorCode::InvalidAmount);
+    
+    let quote = quote_swap(pool, yes_to_no, received_in, false)?;
+    let SwapQuote { fee, protocol_fee, amount_out } = quote;
+    
+    let pool_id = pool.pool_id;
+    let seeds = &[
+        b"pool",
+        pool_id.as_ref(),
+        &[pool_bump],
+    ];
+    let signer = &[&seeds[..]];
+    
+    // Transfer output shares from pool to user
+    let user_out_before = user_out.amount;
+    token_interface::transfer_checked(
+        CpiContext::new_with_signer(
+            token_program.to_account_info(),
+            TransferChecked {
+                from: pool_out.to_account_info(),
+                mint: mint_out.to_account_info(),
+                to: user_out.to_account_info(),
+                authority: pool.to_account_info(),
+            },
+            signer,
+        ),
+        amount_out,
+        mint_out.decimals,
+    )?;
+    user_out.reload()?;
+    let delivered_out = user_out.amount
+        .checked_sub(user_out_before)
+        .ok_or(ErrorCode::MathOverflow)?;
+    require!(delivered_out >= minimum_out, ErrorCode::SlippageExceeded);
+    
+    let execution_price_scaled = calculate_execution_price(amount_in, delivered_out)?;
+    
+    // Transfer protocol fee from pool to the protocol fee recipient
+    if protocol_fee > 0 {
+        token_interface::transfer_checked(
+            CpiContext::new_with_signer(
+                token_program.to_account_info(),
+                TransferChecked {
+                    from: pool_in.to_account_info(),
+                    mint: mint_in.to_account_info(),
+                    to: protocol_fee_account.to_account_info(),
+                    authority: pool.to_account_info(),
+                },
+                signer,
+            ),
+            protocol_fee,
+            mint_in.decimals,
+        )?;
+    }
+    
+    record_swap(pool, yes_to_no, received_in, quote)?;
+    
+    emit!(SwapExecuted {
+        pool_id,
+        user: user.key(),
+        yes_to_no,
+        amount_in,
+        amount_out: delivered_out,
+        fee,
+        protocol_fee,
+        execution_price_scaled,
+    });
+    unlock_pool(pool);
+    
+    // Same return data layout as `execute_swap`, with the amount the user actually received
+    set_return_data(&(delivered_out, execution_price_scaled).try_to_vec()?);
+    
+    Ok(())
+}
+
+/// Curve result for a prospective swap
+#[derive(Clone, Copy)]
+struct SwapQuote {
+    fee: u64,          // Pool fee taken from the input
+    protocol_fee: u64, // Protocol's cut of the input, leaves the pool
+    amount_out: u64,   // Output paid by the pool
+}
+
+/// Price `amount_in` against the curve without touching state
+/// Checks the range edge, the optional impact cap and the price band
+fn quote_swap(
+    pool: &AmmPool,
+    yes_to_no: bool,
+    amount_in: u64,
+    enforce_impact_cap: bool,
+) -> Result<SwapQuote> {
+    let (effective_yes, effective_no) = effective_reserves(pool)?;
+    let (reserve_in, reserve_out, real_reserve_out) = if yes_to_no {
+        (effective_yes, effective_no, pool.no_reserves)
+    } else {
+        (effective_no, effective_yes, pool.yes_reserves)
+    };
+    
+    // Calculate fee
+    let fee = (amount_in as u128)
+        .checked_mul(pool.fee_numerator as u128)
+        .ok_or(ErrorCode::MathOverflow)?
+        .checked_div(pool.fee_denominator as u128)
+        .ok_or(ErrorCode::MathOverflow)?;
+    let fee = u64::try_from(fee).map_err(|_| ErrorCode::MathOverflow)?;
+    
+    let amount_after_fee = amount_in.checked_sub(fee).ok_or(ErrorCode::MathOverflow)?;
+    
+    // Protocol's cut of the fee leaves the pool; the rest stays in reserves for LPs
+    let protocol_fee = calculate_protocol_fee(amount_in, pool.protocol_fee_bps)?;
+    
+    // Calculate output using constant product formula
+    let amount_out = get_amount_out(
+        amount_in,
+        reserve_in,
+        reserve_out,
+        pool.fee_numerator,
+        pool.fee_denominator,
+    )?;
+    let new_reserve_in = reserve_in.checked_add(amount_after_fee).ok_or(ErrorCode::MathOverflow)?;
+    let new_reserve_out = reserve_out.checked_sub(amount_out).ok_or(ErrorCode::InsufficientLiquidity)?;
+    
+    // A concentrated pool runs out of the output share at its range edge
+    require!(amount_out <= real_reserve_out, ErrorCode::InsufficientLiquidity);
+    
+    if enforce_impact_cap {
+        let impact_bps = calculate_price_impact_bps(amount_after_fee, amount_out, reserve_in, reserve_out)?;
+        require!(
+            impact_bps <= pool.max_price_impact_bps as u64,
+            ErrorCode::PriceImpactTooHigh
+        );
+    }
+    
+    // Selling YES pushes its implied probability down, buying pushes it up - keep it inside the band
+    let (new_yes_reserves, new_no_reserves) = if yes_to_no {
+        (new_reserve_in, new_reserve_out)
+    } else {
+        (new_reserve_out, new_reserve_in)
+    };
+    check_price_band(pool, new_yes_reserves, new_no_reserves)?;
+    
+    Ok(SwapQuote { fee, protocol_fee, amount_out })
+}
+
+/// Apply a settled swap to the reserves
+/// LP share of the fee is credited to reserves, growing k; virtual offsets stay put
+/// during swaps - only liquidity changes re-derive them
+fn record_swap(pool: &mut AmmPool, yes_to_no: bool, amount_in: u64, quote: SwapQuote) -> Result<()> {
+    let (real_reserve_in, real_reserve_out) = if yes_to_no {
+        (pool.yes_reserves, pool.no_reserves)
+    } else {
+        (pool.no_reserves, pool.yes_reserves)
+    };
+    let credited_in = real_reserve_in
+        .checked_add(amount_in)
+        .ok_or(ErrorCode::MathOverflow)?
+        .checked_sub(quote.protocol_fee)
+        .ok_or(ErrorCode::MathOverflow)?;
+    let remaining_out = real_reserve_out
+        .checked_sub(quote.amount_out)
+        .ok_or(ErrorCode::MathOverflow)?;
+    if yes_to_no {
+        pool.yes_reserves = credited_in;
+        pool.no_reserves = remaining_out;
+    } else {
+        pool.no_reserves = credited_in;
+        pool.yes_reserves = remaining_out;
+    }
+    let (effective_yes, effective_no) = effective_reserves(pool)?;
+    pool.k = (effective_yes as u128)
+        .checked_mul(effective_no as u128)
+        .ok_or(ErrorCode::MathOverflow)?;
+    Ok(())
+}
+
+/// Constant-product output for `amount_in` after a `fee_numerator / fee_denominator` fee
+/// Uniswap v2 `getAmountOut`: in' * reserve_out / (reserve_in + in'), in' = in * (1 - fee),
+/// rounded down so the pool never pays out more than the curve allows
+pub fn get_amount_out(
+    amount_in: u64,
+    reserve_in: u64,
+    reserve_out: u64,
+    fee_numerator: u64,
+    fee_denominator: u64,
+) -> Result<u64> {
+    require!(amount_in > 0, ErrorCode::InvalidAmount);
+    require!(reserve_in > 0 && reserve_out > 0, ErrorCode::InsufficientLiquidity);
+    require!(fee_numerator < fee_denominator, ErrorCode::InvalidAmount);
+    
+    let amount_in_with_fee = (amount_in as u128)
+        .checked_mul((fee_denominator - fee_numerator) as u128)
+        .ok_or(ErrorCode::MathOverflow)?;
+    let numerator = amount_in_with_fee
+        .checked_mul(reserve_out as u128)
+        .ok_or(ErrorCode::MathOverflow)?;
+    let denominator = (reserve_in as u128)
+        .checked_mul(fee_denominator as u128)
+        .ok_or(ErrorCode::MathOverflow)?
+        .checked_add(amount_in_with_fee)
+        .ok_or(ErrorCode::MathOverflow)?;
+    Ok((numerator / denominator) as u64)
+}
+
+/// Input needed to receive exactly `amount_out` after a `fee_numerator / fee_denominator` fee
+/// Uniswap v2 `getAmountIn`: reserve_in * out / ((reserve_out - out) * (1 - fee)) + 1,
+/// rounded up so `get_amount_out(get_amount_in(x)) >= x`
+pub fn get_amount_in(
+    amount_out: u64,
+    reserve_in: u64,
+    reserve_out: u64,
+    fee_numerator: u64,
+    fee_denominator: u64,
+) -> Result<u64> {
+    require!(amount_out > 0, ErrorCode::InvalidAmount);
+    require!(reserve_in > 0 && amount_out < reserve_out, ErrorCode::InsufficientLiquidity);
+    require!(fee_numerator < fee_denominator, ErrorCode::InvalidAmount);
+    
+    let numerator = (reserve_in as u128)
+        .checked_mul(amount_out as u128)
+        .ok_or(ErrorCode::MathOverflow)?
+        .checked_mul(fee_denominator as u128)
+        .ok_or(ErrorCode::MathOverflow)?;
+    let denominator = ((reserve_out - amount_out) as u128)
+        .checked_mul((fee_denominator - fee_numerator) as u128)
+        .ok_or(ErrorCode::MathOverflow)?;
+    u64::try_from(numerator / denominator + 1).map_err(|_| ErrorCode::MathOverflow.into())
+}
+
+/// How far a fill's price sits above the pre-trade spot price, in bps
+/// Spot is reserve_in / reserve_out; the fill is measured after the pool fee so the
+/// cap only reflects curve movement
+fn calculate_price_impact_bps(
+    amount_in_after_fee: u64,
+    amount_out: u64,
+    reserve_in: u64,
+    reserve_out: u64,
+) -> Result<u64> {
+    // (in * reserve_out - out * reserve_in) / (out * reserve_in)
+    let paid = (amount_in_after_fee as u128)
+        .checked_mul(reserve_out as u128)
+        .ok_or(ErrorCode::MathOverflow)?;
+    let at_spot = (amount_out as u128)
+        .checked_mul(reserve_in as u128)
+        .ok_or(ErrorCode::MathOverflow)?;
+    require!(at_spot > 0, ErrorCode::InsufficientLiquidity);
+    let impact = paid
+        .saturating_sub(at_spot)
+        .checked_mul(BPS_DENOMINATOR as u128)
+        .ok_or(ErrorCode::MathOverflow)?
+        / at_spot;
+    Ok(u64::try_from(impact).unwrap_or(u64::MAX))
+}
+
+/// Effective price paid per unit of output, in PRICE_PRECISION units (fee included)
+fn calculate_execution_price(amount_in: u64, amount_out: u64) -> Result<u64> {
+    require!(amount_out > 0, ErrorCode::InsufficientLiquidity);
+    let price = (amount_in as u128)
+        .checked_mul(PRICE_PRECISION as u128)
+        .ok_or(ErrorCode::MathOverflow)?
+        .checked_div(amount_out as u128)
+        .ok_or(ErrorCode::MathOverflow)?;
+    Ok(u64::try_from(price).map_err(|_| ErrorCode::MathOverflow)?)
+}
+
+/// Protocol's share of a swap input: amount_in * protocol_fee_bps / 10_000
+fn calculate_protocol_fee(amount_in: u64, protocol_fee_bps: u16) -> Result<u64> {
+    let fee = (amount_in as u128)
+        .checked_mul(protocol_fee_bps as u128)
+        .ok_or(ErrorCode::MathOverflow)?
+        / BPS_DENOMINATOR as u128;
+    Ok(fee as u64)
+}
+
+/// Mirror of the orderbook program's `OrderSide` (same Borsh encoding)
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Yes,
+    No,
+}
+
+/// Leading fields of the orderbook program's `Orderbook` account
+/// Mirrors the on-chain layout up to `no_mint`; later fields are ignored
+#[derive(AnchorDeserialize)]
+struct OrderbookSnapshot {
+    _authority: Pubkey,
+    market_id: Pubkey,
+    one_dollar_lamports: u64,
+    _yes_order_count: u64,
+    _no_order_count: u64,
+    total_yes_shares: u64,
+    total_no_shares: u64,
+    _total_volume_lamports: u64,
+    last_yes_price: u64,
+    _last_no_price: u64,
+    _created_at: i64,
+    is_active: bool,
+    winning_outcome: Option<Outcome>,
+    _resolved_at: i64,
+    _last_match_slot: u64,
+    _max_open_orders: u64,
+    _open_yes_orders: u64,
+    _open_no_orders: u64,
+    _open_buy_collateral_lamports: u64,
+    share_decimals: u8,
+    yes_mint: Pubkey,
+    no_mint: Pubkey,
+}
+
+/// Decode an `Orderbook` account, checking its discriminator
+/// The caller pins the account to `pool.linked_orderbook`, which is what makes it trusted
+fn read_orderbook_snapshot(account: &AccountInfo) -> Result<OrderbookSnapshot> {
+    let data = account.try_borrow_data()?;
+    require!(
+        data.len() > 8 && data[..8] == ORDERBOOK_DISCRIMINATOR,
+        ErrorCode::InvalidOrderbook
+    );
+    
+    OrderbookSnapshot::deserialize(&mut &data[8..])
+        .map_err(|_| ErrorCode::InvalidOrderbook.into())
+}
+
+/// numerator / denominator in PRICE_PRECISION units, capped at PRICE_PRECISION
+/// An empty numerator prices at 0 (checked first, so an empty pool reads 0);
+/// an empty denominator prices at the cap instead of dividing by zero
+fn capped_reserve_ratio(numerator: u64, denominator: u64) -> Result<u64> {
+    if numerator == 0 {
+        return Ok(0);
+    }
+    if denominator == 0 {
+        return Ok(PRICE_PRECISION);
+    }
+    let ratio = (numerator as u128)
+        .checked_mul(PRICE_PRECISION as u128)
+        .ok_or(ErrorCode::MathOverflow)?
+        / denominator as u128;
+    Ok(std::cmp::min(ratio, PRICE_PRECISION as u128) as u64)
+}
+
+/// Implied YES probability of a YES/NO pool, in PRICE_PRECISION units
+/// YES is scarce when yes_reserves is low, so p(YES) = no / (yes + no)
+fn implied_yes_price(yes_reserves: u64, no_reserves: u64) -> Result<u64> {
+    let total = (yes_reserves as u128)
+        .checked_add(no_reserves as u128)
+        .ok_or(ErrorCode::MathOverflow)?;
+    require!(total > 0, ErrorCode::EmptyPool);
+    let price = (no_reserves as u128)
+        .checked_mul(PRICE_PRECISION as u128)
+        .ok_or(ErrorCode::MathOverflow)?
+        / total;
+    Ok(price as u64)
+}
+
+/// Mark the pool as mid-instruction before any CPI, rejecting a nested swap/liquidity call
+/// The flag is written through to account data immediately - a reentrant call
+/// deserializes the pool from there, not from this instruction's in-memory copy
+fn lock_pool(pool: &mut Account<AmmPool>) -> Result<()> {
+    require!(!pool.in_progress, ErrorCode::ReentrancyDetected);
+    pool.in_progress = true;
+    pool.exit(&crate::ID)
+}
+
+/// Clear the reentrancy flag once the pool's reserves are final
+/// Persisted by Anchor on exit; a failed instruction reverts the lock with it
+fn unlock_pool(pool: &mut AmmPool) {
+    pool.in_progress = false;
+}
+
+/// Reserves the curve trades on: real reserves plus the virtual offsets of a concentrated pool
+fn effective_reserves(pool: &AmmPool) -> Result<(u64, u64)> {
+    Ok((
+        pool.yes_reserves
+            .checked_add(pool.virtual_yes_offset)
+            .ok_or(ErrorCode::MathOverflow)?,
+        pool.no_reserves
+            .checked_add(pool.virtual_no_offset)
+            .ok_or(ErrorCode::MathOverflow)?,
+    ))
+}
+
+/// Re-derive the virtual offsets (concentrated pools) and k after the real reserves changed
+/// Full-range pools keep zero offsets, so k stays yes_reserves * no_reserves
+fn refresh_liquidity(pool: &mut AmmPool) -> Result<()> {
+    let (virtual_yes_offset, virtual_no_offset) = match pool.curve_type {
+        CurveType::ConstantProduct => (0, 0),
+        CurveType::ConcentratedRange => concentrated_offsets(
+            pool.yes_reserves,
+            pool.no_reserves,
+            pool.min_price,
+            pool.max_price,
+        )?,
+    };
+    pool.virtual_yes_offset = virtual_yes_offset;
+    pool.virtual_no_offset = virtual_no_offset;
+    
+    let (effective_yes, effective_no) = effective_reserves(pool)?;
+    pool.k = (effective_yes as u128)
+        .checked_mul(effective_no as u128)
+        .ok_or(ErrorCode::MathOverflow)?;
+    Ok(())
+}
+
+/// sqrt(NO/YES reserve ratio) at implied YES probability `price`, scaled by PRICE_PRECISION
+/// p = no / (yes + no), so no / yes = p / (1 - p)
+fn sqrt_reserve_ratio(price: u64) -> Result<u128> {
+    let precision = PRICE_PRECISION as u128;
+    let ratio = (price as u128)
+        .checked_mul(precision)
+        .ok_or(ErrorCode::MathOverflow)?
+        .checked_div(precision - price as u128)
+        .ok_or(ErrorCode::MathOverflow)?;
+    Ok(integer_sqrt(ratio.checked_mul(precision).ok_or(ErrorCode::MathOverflow)?))
+}
+
+/// Floor square root (Newton's method)
+fn integer_sqrt(value: u128) -> u128 {
+    if value < 2 {
+        return value;
+    }
+    let mut x = value;
+    let mut y = x.div_ceil(2);
+    while y < x {
+        x = y;
+        y = (x + value / x) / 2;
+    }
+    x
+}
+
+/// Virtual reserve offsets that concentrate `yes`/`no` into [min_price, max_price]
+/// Single-range Uniswap v3: (x + L / sqrt(Pb)) * (y + L * sqrt(Pa)) = L^2 with
+/// x = YES, y = NO and P = NO/YES; L is the positive root of
+/// (1 - sqrt(Pa/Pb)) L^2 - (x sqrt(Pa) + y / sqrt(Pb)) L - x y = 0
+fn concentrated_offsets(yes: u64, no: u64, min_price: u64, max_price: u64) -> Result<(u64, u64)> {
+    if yes == 0 && no == 0 {
+        return Ok((0, 0));
+    }
+    let precision = PRICE_PRECISION as u128;
+    let sqrt_a = sqrt_reserve_ratio(min_price)?;
+    let sqrt_b = sqrt_reserve_ratio(max_price)?;
+    require!(sqrt_a > 0 && sqrt_b > sqrt_a, ErrorCode::InvalidPriceBand);
+    
+    // Quadratic coefficients, a scaled by PRICE_PRECISION
+    let a = (sqrt_b - sqrt_a)
+        .checked_mul(precision)
+        .ok_or(ErrorCode::MathOverflow)?
+        / sqrt_b;
+    let b = (yes as u128)
+        .checked_mul(sqrt_a)
+        .ok_or(ErrorCode::MathOverflow)?
+        / precision
+        + (no as u128)
+            .checked_mul(precision)
+            .ok_or(ErrorCode::MathOverflow)?
+            / sqrt_b;
+    let c = (yes as u128)
+        .checked_mul(no as u128)
+        .ok_or(ErrorCode::MathOverflow)?;
+    require!(a > 0, ErrorCode::InvalidPriceBand);
+    
+    let discriminant = b
+        .checked_mul(b)
+        .ok_or(ErrorCode::MathOverflow)?
+        .checked_add(
+            c.checked_mul(4 * a)
+                .ok_or(ErrorCode::MathOverflow)?
+                / precision,
+        )
+        .ok_or(ErrorCode::MathOverflow)?;
+    let liquidity = (b + integer_sqrt(discriminant))
+        .checked_mul(precision)
+        .ok_or(ErrorCode::MathOverflow)?
+        / (2 * a);
+    
+    let yes_offset = liquidity
+        .checked_mul(precision)
+        .ok_or(ErrorCode::MathOverflow)?
+        / sqrt_b;
+    let no_offset = liquidity
+        .checked_mul(sqrt_a)
+        .ok_or(ErrorCode::MathOverflow)?
+        / precision;
+    Ok((
+        u64::try_from(yes_offset).map_err(|_| ErrorCode::MathOverflow)?,
+        u64::try_from(no_offset).map_err(|_| ErrorCode::MathOverflow)?,
+    ))
+}
+
+/// Reject swaps that would move the implied YES probability outside the pool's band
+/// Shares redeem at exactly 0 or 1, so the constant-product curve is only allowed to
+/// trade inside [min_price, max_price] rather than drifting to a degenerate edge
+fn check_price_band(pool: &AmmPool, new_yes_reserves: u64, new_no_reserves: u64) -> Result<()> {
+    let new_price = implied_yes_price(new_yes_reserves, new_no_reserves)?;
+    require!(
+        new_price >= pool.min_price && new_price <= pool.max_price,
+        ErrorCode::PriceOutOfBand
+    );
+    Ok(())
+}
+
+// Account structures
 #[account]
 pub struct AmmPool {
     pub authority: Pubkey,
@@ -403,13 +2216,64 @@ pub struct AmmPool {
     pub market_id: Pubkey,
     pub yes_mint: Pubkey,
     pub no_mint: Pubkey,
+    // State-tracked reserves: all pricing reads these, never the raw token balances,
+    // so tokens donated straight to the pool accounts cannot move the price (see `skim`)
     pub yes_reserves: u64,
     pub no_reserves: u64,
     pub k: u128, // Constant product
     pub total_supply: u64,
     pub fee_numerator: u64,
     pub fee_denominator: u64,
+    pub min_price: u64,  // Lowest allowed implied YES probability (PRICE_PRECISION units)
+    pub max_price: u64,  // Highest allowed implied YES probability (PRICE_PRECISION units)
+    pub protocol_fee_recipient: Pubkey, // Receives the protocol cut of swap fees
+    pub protocol_fee_bps: u16,          // Protocol cut, in bps of swap input (<= pool fee)
+    pub linked_orderbook: Pubkey,       // Orderbook for the same market (default = none)
+    pub is_active: bool,                // false once deactivated: swaps blocked, LPs can still exit
+    pub max_price_impact_bps: u16,      // Impact cap applied by swap_at_market
     pub created_at: i64,
+    pub curve_type: CurveType,          // Full-range or concentrated in [min_price, max_price]
+    pub virtual_yes_offset: u64,        // Virtual YES added to yes_reserves by a concentrated curve
+    pub virtual_no_offset: u64,         // Virtual NO added to no_reserves by a concentrated curve
+    pub in_progress: bool,              // Reentrancy guard, set while a swap/liquidity call runs
+    pub reward_mint: Pubkey,            // Liquidity mining reward token (default = no rewards)
+    pub reward_rate: u64,               // Reward base units emitted per second across all LP
+    pub acc_reward_per_lp: u128,        // Rewards per LP token since inception, REWARD_PRECISION scaled
+    pub last_reward_update: i64,        // Time acc_reward_per_lp was last advanced
+}
+
+/// Liquidity shape of an `AmmPool`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CurveType {
+    /// Constant product over the real reserves, liquidity spread over (0, 1)
+    ConstantProduct,
+    /// Constant product over virtual reserves that put all liquidity inside the price band;
+    /// the pool holds only the output side at either edge of the band
+    ConcentratedRange,
+}
+
+/// Per-LP deposit snapshot for impermanent-loss reporting
+/// Compare `yes_at_deposit`/`no_at_deposit` (held) against the current
+/// `lp_minted / total_supply` share of reserves (provided)
+#[account]
+pub struct LiquidityPosition {
+    pub owner: Pubkey,
+    pub pool_id: Pubkey,
+    pub yes_at_deposit: u64, // YES contributed for the LP still held
+    pub no_at_deposit: u64,  // NO contributed for the LP still held
+    pub lp_minted: u64,      // LP minted against this snapshot, net of removals
+    pub deposited_at: i64,   // Time of the most recent deposit
+    pub bump: u8,
+    pub reward_debt: u128,   // lp_minted * acc_reward_per_lp already accounted for, REWARD_PRECISION scaled
+    pub pending_rewards: u64, // Rewards accrued and not yet claimed
+    pub last_update: i64,    // Time rewards were last accrued into pending_rewards
+}
+
+impl LiquidityPosition {
+    /// 8 (discriminator) + 32 (owner) + 32 (pool_id) + 8 (yes_at_deposit) + 8 (no_at_deposit)
+    /// + 8 (lp_minted) + 8 (deposited_at) + 1 (bump) + 16 (reward_debt) + 8 (pending_rewards)
+    /// + 8 (last_update)
+    pub const LEN: usize = 8 + 32 + 32 + 8 + 8 + 8 + 8 + 1 + 16 + 8 + 8;
 }
 
 // Context structs
@@ -422,7 +2286,8 @@ pub struct InitializePool<'info> {
     #[account(
         init,
         payer = authority,
-        space = 8 + 32 + 32 + 32 + 32 + 8 + 8 + 16 + 8 + 8 + 8 + 8,
+        space = 8 + 32 + 32 + 32 + 32 + 32 + 8 + 8 + 16 + 8 + 8 + 8 + 8 + 8 + 32 + 2 + 32 + 1 + 2 + 8 + 1 + 8 + 8 + 1
+            + 32 + 8 + 16 + 8,
         seeds = [b"pool", pool_id.as_ref()],
         bump
     )]
@@ -476,7 +2341,16 @@ pub struct SwapYesForNo<'info> {
     )]
     pub user_no_shares: Box<Account<'info, TokenAccount>>,
     
+    #[account(
+        mut,
+        token::mint = yes_mint,
+        constraint = protocol_fee_account.owner == pool.protocol_fee_recipient @ ErrorCode::InvalidFeeRecipient,
+    )]
+    pub protocol_fee_account: Box<Account<'info, TokenAccount>>,
+    
+    #[account(address = pool.yes_mint @ ErrorCode::InvalidMint)]
     pub yes_mint: Box<Account<'info, token::Mint>>,
+    #[account(address = pool.no_mint @ ErrorCode::InvalidMint)]
     pub no_mint: Box<Account<'info, token::Mint>>,
     pub token_program: Program<'info, Token>,
 }
@@ -526,14 +2400,25 @@ pub struct SwapNoForYes<'info> {
     )]
     pub user_no_shares: Box<Account<'info, TokenAccount>>,
     
+    #[account(
+        mut,
+        token::mint = no_mint,
+        constraint = protocol_fee_account.owner == pool.protocol_fee_recipient @ ErrorCode::InvalidFeeRecipient,
+    )]
+    pub protocol_fee_account: Box<Account<'info, TokenAccount>>,
+    
+    #[account(address = pool.yes_mint @ ErrorCode::InvalidMint)]
     pub yes_mint: Box<Account<'info, token::Mint>>,
+    #[account(address = pool.no_mint @ ErrorCode::InvalidMint)]
     pub no_mint: Box<Account<'info, token::Mint>>,
     pub token_program: Program<'info, Token>,
 }
 
+/// Accounts for `buy_yes_with_sol`: the NO->YES swap accounts plus the linked orderbook
+/// accounts that `mint_complete_set_tokens` needs; the share mints are written by the mint
 #[derive(Accounts)]
 #[instruction(pool_id: Pubkey)]
-pub struct AddLiquidity<'info> {
+pub struct BuyYesWithSol<'info> {
     #[account(mut)]
     pub user: Signer<'info>,
     
@@ -545,19 +2430,7 @@ pub struct AddLiquidity<'info> {
     pub pool: Account<'info, AmmPool>,
     
     #[account(
-        init_if_needed,
-        payer = user,
-        seeds = [b"pool", pool_id.as_ref(), b"lp_mint"],
-        bump,
-        mint::decimals = 6,
-        mint::authority = pool,
-        mint::freeze_authority = pool,
-    )]
-    pub lp_mint: Box<Account<'info, token::Mint>>,
-    
-    #[account(
-        init_if_needed,
-        payer = user,
+        mut,
         seeds = [b"pool", pool_id.as_ref(), b"yes_shares"],
         bump,
         token::mint = yes_mint,
@@ -566,8 +2439,7 @@ pub struct AddLiquidity<'info> {
     pub pool_yes_shares: Box<Account<'info, TokenAccount>>,
     
     #[account(
-        init_if_needed,
-        payer = user,
+        mut,
         seeds = [b"pool", pool_id.as_ref(), b"no_shares"],
         bump,
         token::mint = no_mint,
@@ -575,16 +2447,6 @@ pub struct AddLiquidity<'info> {
     )]
     pub pool_no_shares: Box<Account<'info, TokenAccount>>,
     
-    #[account(
-        init_if_needed,
-        payer = user,
-        seeds = [b"user", user.key().as_ref(), pool_id.as_ref(), b"lp_tokens"],
-        bump,
-        token::mint = lp_mint,
-        token::authority = user,
-    )]
-    pub user_lp_tokens: Box<Account<'info, TokenAccount>>,
-    
     #[account(
         mut,
         token::mint = yes_mint,
@@ -599,16 +2461,38 @@ pub struct AddLiquidity<'info> {
     )]
     pub user_no_shares: Box<Account<'info, TokenAccount>>,
     
+    #[account(
+        mut,
+        token::mint = no_mint,
+        constraint = protocol_fee_account.owner == pool.protocol_fee_recipient @ ErrorCode::InvalidFeeRecipient,
+    )]
+    pub protocol_fee_account: Box<Account<'info, TokenAccount>>,
+    
+    #[account(mut, address = pool.yes_mint @ ErrorCode::InvalidMint)]
     pub yes_mint: Box<Account<'info, token::Mint>>,
+    #[account(mut, address = pool.no_mint @ ErrorCode::InvalidMint)]
     pub no_mint: Box<Account<'info, token::Mint>>,
+    
+    /// CHECK: Must be pool.linked_orderbook owned by orderbook_program, decoded in the handler
+    #[account(mut)]
+    pub orderbook: AccountInfo<'info>,
+    
+    /// CHECK: Orderbook vault PDA, validated by the orderbook program during the mint
+    #[account(mut)]
+    pub orderbook_vault: AccountInfo<'info>,
+    
+    /// CHECK: Program owning `orderbook`, invoked to mint the complete sets
+    #[account(executable)]
+    pub orderbook_program: AccountInfo<'info>,
+    
     pub token_program: Program<'info, Token>,
     pub system_program: Program<'info, System>,
-    pub rent: Sysvar<'info, Rent>,
 }
 
+/// Accounts for `swap_at_market`: both share sides, with the fee account on the input mint
 #[derive(Accounts)]
 #[instruction(pool_id: Pubkey)]
-pub struct RemoveLiquidity<'info> {
+pub struct SwapAtMarket<'info> {
     #[account(mut)]
     pub user: Signer<'info>,
     
@@ -621,8 +2505,165 @@ pub struct RemoveLiquidity<'info> {
     
     #[account(
         mut,
-        seeds = [b"pool", pool_id.as_ref(), b"lp_mint"],
-        bump
+        seeds = [b"pool", pool_id.as_ref(), b"yes_shares"],
+        bump,
+        token::mint = yes_mint,
+        token::authority = pool,
+    )]
+    pub pool_yes_shares: Box<Account<'info, TokenAccount>>,
+    
+    #[account(
+        mut,
+        seeds = [b"pool", pool_id.as_ref(), b"no_shares"],
+        bump,
+        token::mint = no_mint,
+        token::authority = pool,
+    )]
+    pub pool_no_shares: Box<Account<'info, TokenAccount>>,
+    
+    #[account(
+        mut,
+        token::mint = yes_mint,
+        token::authority = user,
+    )]
+    pub user_yes_shares: Box<Account<'info, TokenAccount>>,
+    
+    #[account(
+        mut,
+        token::mint = no_mint,
+        token::authority = user,
+    )]
+    pub user_no_shares: Box<Account<'info, TokenAccount>>,
+    
+    #[account(
+        mut,
+        constraint = protocol_fee_account.owner == pool.protocol_fee_recipient @ ErrorCode::InvalidFeeRecipient,
+    )]
+    pub protocol_fee_account: Box<Account<'info, TokenAccount>>,
+    
+    #[account(address = pool.yes_mint @ ErrorCode::InvalidMint)]
+    pub yes_mint: Box<Account<'info, token::Mint>>,
+    #[account(address = pool.no_mint @ ErrorCode::InvalidMint)]
+    pub no_mint: Box<Account<'info, token::Mint>>,
+    pub token_program: Program<'info, Token>,
+}
+
+/// Update pool settings (authority only)
+/// Shared by all admin setters on the pool
+#[derive(Accounts)]
+pub struct UpdatePool<'info> {
+    pub authority: Signer<'info>,
+    
+    #[account(
+        mut,
+        has_one = authority @ ErrorCode::Unauthorized
+    )]
+    pub pool: Account<'info, AmmPool>,
+}
+
+#[derive(Accounts)]
+#[instruction(pool_id: Pubkey)]
+pub struct AddLiquidity<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"pool", pool_id.as_ref()],
+        bump
+    )]
+    pub pool: Account<'info, AmmPool>,
+    
+    #[account(
+        init_if_needed,
+        payer = user,
+        seeds = [b"pool", pool_id.as_ref(), b"lp_mint"],
+        bump,
+        mint::decimals = 6,
+        mint::authority = pool,
+        mint::freeze_authority = pool,
+    )]
+    pub lp_mint: Box<Account<'info, token::Mint>>,
+    
+    #[account(
+        init_if_needed,
+        payer = user,
+        seeds = [b"pool", pool_id.as_ref(), b"yes_shares"],
+        bump,
+        token::mint = yes_mint,
+        token::authority = pool,
+    )]
+    pub pool_yes_shares: Box<Account<'info, TokenAccount>>,
+    
+    #[account(
+        init_if_needed,
+        payer = user,
+        seeds = [b"pool", pool_id.as_ref(), b"no_shares"],
+        bump,
+        token::mint = no_mint,
+        token::authority = pool,
+    )]
+    pub pool_no_shares: Box<Account<'info, TokenAccount>>,
+    
+    #[account(
+        init_if_needed,
+        payer = user,
+        seeds = [b"user", user.key().as_ref(), pool_id.as_ref(), b"lp_tokens"],
+        bump,
+        token::mint = lp_mint,
+        token::authority = user,
+    )]
+    pub user_lp_tokens: Box<Account<'info, TokenAccount>>,
+    
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = LiquidityPosition::LEN,
+        seeds = [b"position", pool_id.as_ref(), user.key().as_ref()],
+        bump
+    )]
+    pub position: Box<Account<'info, LiquidityPosition>>,
+    
+    #[account(
+        mut,
+        token::mint = yes_mint,
+        token::authority = user,
+    )]
+    pub user_yes_shares: Box<Account<'info, TokenAccount>>,
+    
+    #[account(
+        mut,
+        token::mint = no_mint,
+        token::authority = user,
+    )]
+    pub user_no_shares: Box<Account<'info, TokenAccount>>,
+    
+    #[account(address = pool.yes_mint @ ErrorCode::InvalidMint)]
+    pub yes_mint: Box<Account<'info, token::Mint>>,
+    #[account(address = pool.no_mint @ ErrorCode::InvalidMint)]
+    pub no_mint: Box<Account<'info, token::Mint>>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+#[instruction(pool_id: Pubkey)]
+pub struct RemoveLiquidity<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"pool", pool_id.as_ref()],
+        bump
+    )]
+    pub pool: Account<'info, AmmPool>,
+    
+    #[account(
+        mut,
+        seeds = [b"pool", pool_id.as_ref(), b"lp_mint"],
+        bump
     )]
     pub lp_mint: Box<Account<'info, token::Mint>>,
     
@@ -651,6 +2692,16 @@ pub struct RemoveLiquidity<'info> {
     )]
     pub user_lp_tokens: Box<Account<'info, TokenAccount>>,
     
+    /// Settled on every burn; created empty for LP received by transfer
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = LiquidityPosition::LEN,
+        seeds = [b"position", pool_id.as_ref(), user.key().as_ref()],
+        bump
+    )]
+    pub position: Box<Account<'info, LiquidityPosition>>,
+    
     #[account(
         mut,
         token::mint = yes_mint,
@@ -665,62 +2716,2554 @@ pub struct RemoveLiquidity<'info> {
     )]
     pub user_no_shares: Box<Account<'info, TokenAccount>>,
     
+    #[account(address = pool.yes_mint @ ErrorCode::InvalidMint)]
     pub yes_mint: Box<Account<'info, token::Mint>>,
+    #[account(address = pool.no_mint @ ErrorCode::InvalidMint)]
     pub no_mint: Box<Account<'info, token::Mint>>,
     pub token_program: Program<'info, Token>,
+    
+    pub system_program: Program<'info, System>,
 }
 
+/// Accounts for `swap_interface`
+/// Share accounts may belong to the legacy token program or Token-2022
 #[derive(Accounts)]
-pub struct GetPrice<'info> {
+#[instruction(pool_id: Pubkey)]
+pub struct SwapInterface<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"pool", pool_id.as_ref()],
+        bump
+    )]
     pub pool: Account<'info, AmmPool>,
+    
+    #[account(
+        mut,
+        seeds = [b"pool", pool_id.as_ref(), b"yes_shares"],
+        bump,
+        token::mint = yes_mint,
+        token::authority = pool,
+        token::token_program = share_token_program,
+    )]
+    pub pool_yes_shares: Box<InterfaceAccount<'info, InterfaceTokenAccount>>,
+    
+    #[account(
+        mut,
+        seeds = [b"pool", pool_id.as_ref(), b"no_shares"],
+        bump,
+        token::mint = no_mint,
+        token::authority = pool,
+        token::token_program = share_token_program,
+    )]
+    pub pool_no_shares: Box<InterfaceAccount<'info, InterfaceTokenAccount>>,
+    
+    #[account(
+        mut,
+        token::mint = yes_mint,
+        token::authority = user,
+        token::token_program = share_token_program,
+    )]
+    pub user_yes_shares: Box<InterfaceAccount<'info, InterfaceTokenAccount>>,
+    
+    #[account(
+        mut,
+        token::mint = no_mint,
+        token::authority = user,
+        token::token_program = share_token_program,
+    )]
+    pub user_no_shares: Box<InterfaceAccount<'info, InterfaceTokenAccount>>,
+    
+    #[account(
+        mut,
+        token::token_program = share_token_program,
+        constraint = protocol_fee_account.owner == pool.protocol_fee_recipient @ ErrorCode::InvalidFeeRecipient,
+    )]
+    pub protocol_fee_account: Box<InterfaceAccount<'info, InterfaceTokenAccount>>,
+    
+    #[account(address = pool.yes_mint @ ErrorCode::InvalidMint)]
+    pub yes_mint: Box<InterfaceAccount<'info, InterfaceMint>>,
+    #[account(address = pool.no_mint @ ErrorCode::InvalidMint)]
+    pub no_mint: Box<InterfaceAccount<'info, InterfaceMint>>,
+    pub share_token_program: Interface<'info, TokenInterface>,
 }
 
-// Error codes
-#[error_code]
-pub enum ErrorCode {
-    #[msg("Invalid amount")]
-    InvalidAmount,
-    #[msg("Pool is empty")]
-    EmptyPool,
-    #[msg("Slippage exceeded")]
-    SlippageExceeded,
-    #[msg("Insufficient liquidity")]
-    InsufficientLiquidity,
-}
-
-// Events
-#[event]
-pub struct PoolInitialized {
-    pub pool_id: Pubkey,
-    pub market_id: Pubkey,
-    pub yes_reserves: u64,
-    pub no_reserves: u64,
-    pub k: u128,
-}
-
-#[event]
-pub struct SwapExecuted {
-    pub pool_id: Pubkey,
-    pub user: Pubkey,
-    pub yes_amount_in: u64,
-    pub no_amount_out: u64,
-    pub fee: u64,
-}
-
-#[event]
-pub struct LiquidityAdded {
-    pub pool_id: Pubkey,
-    pub user: Pubkey,
-    pub yes_amount: u64,
-    pub no_amount: u64,
-    pub lp_tokens_minted: u64,
-}
-
-#[event]
-pub struct LiquidityRemoved {
-    pub pool_id: Pubkey,
-    pub user: Pubkey,
-    pub lp_tokens_burned: u64,
-    pub yes_amount_out: u64,
-    pub no_amount_out: u64,
+/// Accounts for `add_liquidity_interface`
+/// Shares go through `share_token_program`; the LP mint stays on the legacy token program
+#[derive(Accounts)]
+#[instruction(pool_id: Pubkey)]
+pub struct AddLiquidityInterface<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"pool", pool_id.as_ref()],
+        bump
+    )]
+    pub pool: Account<'info, AmmPool>,
+    
+    #[account(
+        init_if_needed,
+        payer = user,
+        seeds = [b"pool", pool_id.as_ref(), b"lp_mint"],
+        bump,
+        mint::decimals = 6,
+        mint::authority = pool,
+        mint::freeze_authority = pool,
+    )]
+    pub lp_mint: Box<Account<'info, token::Mint>>,
+    
+    #[account(
+        init_if_needed,
+        payer = user,
+        seeds = [b"pool", pool_id.as_ref(), b"yes_shares"],
+        bump,
+        token::mint = yes_mint,
+        token::authority = pool,
+        token::token_program = share_token_program,
+    )]
+    pub pool_yes_shares: Box<InterfaceAccount<'info, InterfaceTokenAccount>>,
+    
+    #[account(
+        init_if_needed,
+        payer = user,
+        seeds = [b"pool", pool_id.as_ref(), b"no_shares"],
+        bump,
+        token::mint = no_mint,
+        token::authority = pool,
+        token::token_program = share_token_program,
+    )]
+    pub pool_no_shares: Box<InterfaceAccount<'info, InterfaceTokenAccount>>,
+    
+    #[account(
+        init_if_needed,
+        payer = user,
+        seeds = [b"user", user.key().as_ref(), pool_id.as_ref(), b"lp_tokens"],
+        bump,
+        token::mint = lp_mint,
+        token::authority = user,
+    )]
+    pub user_lp_tokens: Box<Account<'info, TokenAccount>>,
+    
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = LiquidityPosition::LEN,
+        seeds = [b"position", pool_id.as_ref(), user.key().as_ref()],
+        bump
+    )]
+    pub position: Box<Account<'info, LiquidityPosition>>,
+    
+    #[account(
+        mut,
+        token::mint = yes_mint,
+        token::authority = user,
+        token::token_program = share_token_program,
+    )]
+    pub user_yes_shares: Box<InterfaceAccount<'info, InterfaceTokenAccount>>,
+    
+    #[account(
+        mut,
+        token::mint = no_mint,
+        token::authority = user,
+        token::token_program = share_token_program,
+    )]
+    pub user_no_shares: Box<InterfaceAccount<'info, InterfaceTokenAccount>>,
+    
+    #[account(address = pool.yes_mint @ ErrorCode::InvalidMint)]
+    pub yes_mint: Box<InterfaceAccount<'info, InterfaceMint>>,
+    #[account(address = pool.no_mint @ ErrorCode::InvalidMint)]
+    pub no_mint: Box<InterfaceAccount<'info, InterfaceMint>>,
+    pub token_program: Program<'info, Token>,
+    pub share_token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+/// Accounts for `remove_liquidity_interface`
+#[derive(Accounts)]
+#[instruction(pool_id: Pubkey)]
+pub struct RemoveLiquidityInterface<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"pool", pool_id.as_ref()],
+        bump
+    )]
+    pub pool: Account<'info, AmmPool>,
+    
+    #[account(
+        mut,
+        seeds = [b"pool", pool_id.as_ref(), b"lp_mint"],
+        bump
+    )]
+    pub lp_mint: Box<Account<'info, token::Mint>>,
+    
+    #[account(
+        mut,
+        seeds = [b"pool", pool_id.as_ref(), b"yes_shares"],
+        bump,
+        token::mint = yes_mint,
+        token::authority = pool,
+        token::token_program = share_token_program,
+    )]
+    pub pool_yes_shares: Box<InterfaceAccount<'info, InterfaceTokenAccount>>,
+    
+    #[account(
+        mut,
+        seeds = [b"pool", pool_id.as_ref(), b"no_shares"],
+        bump,
+        token::mint = no_mint,
+        token::authority = pool,
+        token::token_program = share_token_program,
+    )]
+    pub pool_no_shares: Box<InterfaceAccount<'info, InterfaceTokenAccount>>,
+    
+    #[account(
+        mut,
+        token::mint = lp_mint,
+        token::authority = user,
+    )]
+    pub user_lp_tokens: Box<Account<'info, TokenAccount>>,
+    
+    /// Settled on every burn; created empty for LP received by transfer
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = LiquidityPosition::LEN,
+        seeds = [b"position", pool_id.as_ref(), user.key().as_ref()],
+        bump
+    )]
+    pub position: Box<Account<'info, LiquidityPosition>>,
+    
+    #[account(
+        mut,
+        token::mint = yes_mint,
+        token::authority = user,
+        token::token_program = share_token_program,
+    )]
+    pub user_yes_shares: Box<InterfaceAccount<'info, InterfaceTokenAccount>>,
+    
+    #[account(
+        mut,
+        token::mint = no_mint,
+        token::authority = user,
+        token::token_program = share_token_program,
+    )]
+    pub user_no_shares: Box<InterfaceAccount<'info, InterfaceTokenAccount>>,
+    
+    #[account(address = pool.yes_mint @ ErrorCode::InvalidMint)]
+    pub yes_mint: Box<InterfaceAccount<'info, InterfaceMint>>,
+    #[account(address = pool.no_mint @ ErrorCode::InvalidMint)]
+    pub no_mint: Box<InterfaceAccount<'info, InterfaceMint>>,
+    pub token_program: Program<'info, Token>,
+    pub share_token_program: Interface<'info, TokenInterface>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(pool_id: Pubkey)]
+pub struct SettleLiquidity<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"pool", pool_id.as_ref()],
+        bump
+    )]
+    pub pool: Account<'info, AmmPool>,
+    
+    #[account(
+        mut,
+        seeds = [b"pool", pool_id.as_ref(), b"lp_mint"],
+        bump
+    )]
+    pub lp_mint: Box<Account<'info, token::Mint>>,
+    
+    #[account(
+        mut,
+        seeds = [b"pool", pool_id.as_ref(), b"yes_shares"],
+        bump,
+        token::mint = yes_mint,
+        token::authority = pool,
+    )]
+    pub pool_yes_shares: Box<Account<'info, TokenAccount>>,
+    
+    #[account(
+        mut,
+        seeds = [b"pool", pool_id.as_ref(), b"no_shares"],
+        bump,
+        token::mint = no_mint,
+        token::authority = pool,
+    )]
+    pub pool_no_shares: Box<Account<'info, TokenAccount>>,
+    
+    #[account(
+        mut,
+        token::mint = lp_mint,
+        token::authority = user,
+    )]
+    pub user_lp_tokens: Box<Account<'info, TokenAccount>>,
+    
+    /// Settled on every burn; created empty for LP received by transfer
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = LiquidityPosition::LEN,
+        seeds = [b"position", pool_id.as_ref(), user.key().as_ref()],
+        bump
+    )]
+    pub position: Box<Account<'info, LiquidityPosition>>,
+    
+    #[account(mut)]
+    pub yes_mint: Box<Account<'info, token::Mint>>,
+    #[account(mut)]
+    pub no_mint: Box<Account<'info, token::Mint>>,
+    
+    /// CHECK: Must be pool.linked_orderbook owned by orderbook_program, decoded in the handler
+    #[account(mut)]
+    pub orderbook: AccountInfo<'info>,
+    
+    /// CHECK: Orderbook vault PDA, validated by the orderbook program during redemption
+    #[account(mut)]
+    pub orderbook_vault: AccountInfo<'info>,
+    
+    /// CHECK: Orderbook FeeVault PDA receiving the redemption fee, validated by the orderbook program
+    #[account(mut)]
+    pub orderbook_fee_vault: AccountInfo<'info>,
+    
+    /// CHECK: Program owning `orderbook`, invoked to redeem the winning shares
+    #[account(executable)]
+    pub orderbook_program: AccountInfo<'info>,
+    
+    pub token_program: Program<'info, Token>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+/// Link an orderbook to the pool (authority only)
+#[derive(Accounts)]
+pub struct LinkOrderbook<'info> {
+    pub authority: Signer<'info>,
+    
+    #[account(
+        mut,
+        has_one = authority @ ErrorCode::Unauthorized
+    )]
+    pub pool: Account<'info, AmmPool>,
+    
+    /// CHECK: Decoded in the handler and checked against the pool's market and mints
+    pub orderbook: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct GetPrice<'info> {
+    pub pool: Account<'info, AmmPool>,
+}
+
+#[derive(Accounts)]
+#[instruction(pool_id: Pubkey)]
+pub struct ReconcileReserves<'info> {
+    #[account(
+        seeds = [b"pool", pool_id.as_ref()],
+        bump
+    )]
+    pub pool: Account<'info, AmmPool>,
+    
+    #[account(
+        seeds = [b"pool", pool_id.as_ref(), b"yes_shares"],
+        bump,
+        token::authority = pool,
+    )]
+    pub pool_yes_shares: Box<InterfaceAccount<'info, InterfaceTokenAccount>>,
+    
+    #[account(
+        seeds = [b"pool", pool_id.as_ref(), b"no_shares"],
+        bump,
+        token::authority = pool,
+    )]
+    pub pool_no_shares: Box<InterfaceAccount<'info, InterfaceTokenAccount>>,
+}
+
+#[derive(Accounts)]
+#[instruction(pool_id: Pubkey)]
+pub struct Skim<'info> {
+    pub authority: Signer<'info>,
+    
+    #[account(
+        seeds = [b"pool", pool_id.as_ref()],
+        bump,
+        has_one = authority @ ErrorCode::Unauthorized
+    )]
+    pub pool: Account<'info, AmmPool>,
+    
+    #[account(
+        mut,
+        seeds = [b"pool", pool_id.as_ref(), b"yes_shares"],
+        bump,
+        token::mint = yes_mint,
+        token::authority = pool,
+        token::token_program = share_token_program,
+    )]
+    pub pool_yes_shares: Box<InterfaceAccount<'info, InterfaceTokenAccount>>,
+    
+    #[account(
+        mut,
+        seeds = [b"pool", pool_id.as_ref(), b"no_shares"],
+        bump,
+        token::mint = no_mint,
+        token::authority = pool,
+        token::token_program = share_token_program,
+    )]
+    pub pool_no_shares: Box<InterfaceAccount<'info, InterfaceTokenAccount>>,
+    
+    #[account(
+        mut,
+        token::mint = yes_mint,
+        token::token_program = share_token_program,
+    )]
+    pub recipient_yes_shares: Box<InterfaceAccount<'info, InterfaceTokenAccount>>,
+    
+    #[account(
+        mut,
+        token::mint = no_mint,
+        token::token_program = share_token_program,
+    )]
+    pub recipient_no_shares: Box<InterfaceAccount<'info, InterfaceTokenAccount>>,
+    
+    #[account(address = pool.yes_mint @ ErrorCode::InvalidMint)]
+    pub yes_mint: Box<InterfaceAccount<'info, InterfaceMint>>,
+    #[account(address = pool.no_mint @ ErrorCode::InvalidMint)]
+    pub no_mint: Box<InterfaceAccount<'info, InterfaceMint>>,
+    pub share_token_program: Interface<'info, TokenInterface>,
+}
+
+/// Accounts for `initialize_rewards`: creates the pool-owned reward vault for `reward_mint`
+#[derive(Accounts)]
+#[instruction(pool_id: Pubkey)]
+pub struct InitializeRewards<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"pool", pool_id.as_ref()],
+        bump,
+        has_one = authority @ ErrorCode::Unauthorized
+    )]
+    pub pool: Account<'info, AmmPool>,
+    
+    pub reward_mint: Box<Account<'info, token::Mint>>,
+    
+    #[account(
+        init,
+        payer = authority,
+        seeds = [b"pool", pool_id.as_ref(), b"reward_vault"],
+        bump,
+        token::mint = reward_mint,
+        token::authority = pool,
+    )]
+    pub reward_vault: Box<Account<'info, TokenAccount>>,
+    
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Accounts for `claim_rewards`
+#[derive(Accounts)]
+#[instruction(pool_id: Pubkey)]
+pub struct ClaimRewards<'info> {
+    pub user: Signer<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"pool", pool_id.as_ref()],
+        bump
+    )]
+    pub pool: Account<'info, AmmPool>,
+    
+    #[account(
+        mut,
+        seeds = [b"position", pool_id.as_ref(), user.key().as_ref()],
+        bump = position.bump
+    )]
+    pub position: Box<Account<'info, LiquidityPosition>>,
+    
+    /// The user's LP account from `add_liquidity`; its balance caps what the position earns
+    #[account(
+        seeds = [b"user", user.key().as_ref(), pool_id.as_ref(), b"lp_tokens"],
+        bump,
+        token::authority = user,
+    )]
+    pub user_lp_tokens: Box<Account<'info, TokenAccount>>,
+    
+    #[account(
+        mut,
+        seeds = [b"pool", pool_id.as_ref(), b"reward_vault"],
+        bump,
+        token::mint = reward_mint,
+        token::authority = pool,
+    )]
+    pub reward_vault: Box<Account<'info, TokenAccount>>,
+    
+    #[account(
+        mut,
+        token::mint = reward_mint,
+        token::authority = user,
+    )]
+    pub user_reward_tokens: Box<Account<'info, TokenAccount>>,
+    
+    #[account(address = pool.reward_mint @ ErrorCode::InvalidMint)]
+    pub reward_mint: Box<Account<'info, token::Mint>>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct GetBlendedOdds<'info> {
+    pub pool: Account<'info, AmmPool>,
+    
+    /// CHECK: Must be pool.linked_orderbook, decoded in the handler
+    pub orderbook: AccountInfo<'info>,
+}
+
+// Error codes
+#[error_code]
+pub enum ErrorCode {
+    #[msg("Invalid amount")]
+    InvalidAmount,
+    #[msg("Pool is empty")]
+    EmptyPool,
+    #[msg("Slippage exceeded")]
+    SlippageExceeded,
+    #[msg("Insufficient liquidity")]
+    InsufficientLiquidity,
+    #[msg("Math overflow")]
+    MathOverflow,
+    #[msg("Invalid price band")]
+    InvalidPriceBand,
+    #[msg("Swap would move price outside the allowed band")]
+    PriceOutOfBand,
+    #[msg("Unauthorized")]
+    Unauthorized,
+    #[msg("Protocol fee cannot exceed the pool fee")]
+    ProtocolFeeTooHigh,
+    #[msg("Fee account is not owned by the protocol fee recipient")]
+    InvalidFeeRecipient,
+    #[msg("Insufficient LP tokens")]
+    InsufficientLpTokens,
+    #[msg("Account is not an orderbook")]
+    InvalidOrderbook,
+    #[msg("Orderbook and pool are for different markets")]
+    MarketMismatch,
+    #[msg("Pool is inactive")]
+    PoolInactive,
+    #[msg("Pool must be deactivated before settlement")]
+    PoolStillActive,
+    #[msg("Linked orderbook is not resolved")]
+    MarketNotResolved,
+    #[msg("Price impact cap must be between 1 and 10000 bps")]
+    InvalidPriceImpact,
+    #[msg("Swap exceeds the pool's price impact cap")]
+    PriceImpactTooHigh,
+    #[msg("Share mints are identical or do not match the market")]
+    InvalidMint,
+    #[msg("Nested swap or liquidity call on the same pool")]
+    ReentrancyDetected,
+    #[msg("Pool reserves do not match its token account balances")]
+    ReserveMismatch,
+    #[msg("Liquidity mining is already enabled for this pool")]
+    RewardsAlreadyEnabled,
+    #[msg("Liquidity mining is not enabled for this pool")]
+    RewardsNotEnabled,
+    #[msg("No rewards available to claim")]
+    NoRewardsToClaim,
+}
+
+// Events
+#[event]
+pub struct PoolInitialized {
+    pub pool_id: Pubkey,
+    pub market_id: Pubkey,
+    pub curve_type: CurveType,
+}
+
+#[event]
+pub struct SwapExecuted {
+    pub pool_id: Pubkey,
+    pub user: Pubkey,
+    pub yes_to_no: bool,             // true = YES in / NO out, false = NO in / YES out
+    pub amount_in: u64,              // Input leg, fee included
+    pub amount_out: u64,             // Output leg
+    pub fee: u64,                    // Fee taken from the input leg
+    pub protocol_fee: u64,           // Portion of fee sent to the protocol recipient
+    pub execution_price_scaled: u64, // amount_in per unit out, in PRICE_PRECISION units
+}
+
+#[event]
+pub struct ProtocolFeeUpdated {
+    pub pool_id: Pubkey,
+    pub protocol_fee_recipient: Pubkey,
+    pub protocol_fee_bps: u16,
+}
+
+#[event]
+pub struct RewardsClaimed {
+    pub pool_id: Pubkey,
+    pub user: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct ReservesSkimmed {
+    pub pool_id: Pubkey,
+    pub yes_amount: u64,
+    pub no_amount: u64,
+}
+
+#[event]
+pub struct PoolDeactivated {
+    pub pool_id: Pubkey,
+    pub market_id: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct LiquidityAdded {
+    pub pool_id: Pubkey,
+    pub user: Pubkey,
+    pub yes_amount: u64,
+    pub no_amount: u64,
+    pub lp_tokens_minted: u64,
+}
+
+#[event]
+pub struct YesBoughtWithSol {
+    pub pool_id: Pubkey,
+    pub user: Pubkey,
+    pub sol_amount: u64,
+    pub sets_minted: u64,
+    pub yes_out: u64,
+}
+
+#[event]
+pub struct LiquiditySettled {
+    pub pool_id: Pubkey,
+    pub user: Pubkey,
+    pub lp_tokens_burned: u64,
+    pub winning_shares_redeemed: u64,
+    pub losing_shares_burned: u64,
+    pub payout_lamports: u64,
+}
+
+#[event]
+pub struct LiquidityRemoved {
+    pub pool_id: Pubkey,
+    pub user: Pubkey,
+    pub lp_tokens_burned: u64,
+    pub yes_amount_out: u64,
+    pub no_amount_out: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anchor_lang::solana_program::entrypoint::{ProgramResult, MAX_PERMITTED_DATA_INCREASE};
+    use anchor_lang::solana_program::program_error::ProgramError;
+    use anchor_lang::solana_program::program_option::COption;
+    use anchor_lang::solana_program::program_pack::Pack;
+    use anchor_lang::solana_program::sysvar;
+    use anchor_lang::{system_program, Discriminator, Event, InstructionData, ToAccountMetas};
+    use anchor_spl::token_2022::spl_token_2022;
+    use solana_sysvar::program_stubs::{set_syscall_stubs, SyscallStubs};
+    use spl_token_2022::error::TokenError;
+    use spl_token_2022::extension::transfer_fee::{TransferFee, TransferFeeAmount, TransferFeeConfig};
+    use spl_token_2022::extension::{
+        BaseStateWithExtensions, BaseStateWithExtensionsMut, ExtensionType, StateWithExtensions,
+        StateWithExtensionsMut,
+    };
+    use spl_token_2022::state::{Account as TokenState, AccountState, Mint as MintState};
+    use std::cell::{Cell, RefCell};
+    use std::collections::HashMap;
+    use std::sync::Once;
+
+    /// (amount_in, reserve_in, reserve_out, fee_numerator, fee_denominator, expected_out)
+    /// Expected values are floor(in * (d - n) * r_out / (r_in * d + in * (d - n)))
+    const AMOUNT_OUT_CASES: &[(u64, u64, u64, u64, u64, u64)] = &[
+        (100, 1_000, 1_000, 0, 1_000, 90),
+        (1_000, 10_000, 10_000, 3, 1_000, 906),
+        (1_000_000, 1_000_000, 1_000_000, 3, 1_000, 499_248),
+        (1, 1_000_000, 1_000_000, 3, 1_000, 0),
+        (500, 1_000, 4_000, 0, 1_000, 1_333),
+    ];
+
+    /// (amount_out, reserve_in, reserve_out, fee_numerator, fee_denominator, expected_in)
+    /// Expected values are floor(r_in * out * d / ((r_out - out) * (d - n))) + 1
+    const AMOUNT_IN_CASES: &[(u64, u64, u64, u64, u64, u64)] = &[
+        (90, 1_000, 1_000, 0, 1_000, 99),
+        (906, 10_000, 10_000, 3, 1_000, 1_000),
+        (499_248, 1_000_000, 1_000_000, 3, 1_000, 999_997),
+        // An exact quotient still rounds up by one
+        (500, 1_000, 1_000, 0, 1_000, 1_001),
+        (1_333, 1_000, 4_000, 0, 1_000, 500),
+    ];
+
+    #[test]
+    fn get_amount_out_matches_the_constant_product() {
+        for &(amount_in, reserve_in, reserve_out, fee_numerator, fee_denominator, expected) in AMOUNT_OUT_CASES {
+            assert_eq!(
+                get_amount_out(amount_in, reserve_in, reserve_out, fee_numerator, fee_denominator).unwrap(),
+                expected,
+                "amount_in {amount_in} against {reserve_in}/{reserve_out} at {fee_numerator}/{fee_denominator}"
+            );
+        }
+    }
+
+    #[test]
+    fn get_amount_in_matches_the_constant_product() {
+        for &(amount_out, reserve_in, reserve_out, fee_numerator, fee_denominator, expected) in AMOUNT_IN_CASES {
+            assert_eq!(
+                get_amount_in(amount_out, reserve_in, reserve_out, fee_numerator, fee_denominator).unwrap(),
+                expected,
+                "amount_out {amount_out} against {reserve_in}/{reserve_out} at {fee_numerator}/{fee_denominator}"
+            );
+        }
+    }
+
+    #[test]
+    fn get_amount_in_is_enough_to_buy_the_amount_out() {
+        for &(amount_out, reserve_in, reserve_out, fee_numerator, fee_denominator, _) in AMOUNT_IN_CASES {
+            let amount_in = get_amount_in(amount_out, reserve_in, reserve_out, fee_numerator, fee_denominator).unwrap();
+            let bought = get_amount_out(amount_in, reserve_in, reserve_out, fee_numerator, fee_denominator).unwrap();
+            assert!(bought >= amount_out, "{amount_in} in buys {bought} < {amount_out}");
+        }
+    }
+
+    #[test]
+    fn the_fee_is_taken_from_the_input() {
+        let without_fee = get_amount_out(1_000, 10_000, 10_000, 0, 1_000).unwrap();
+        let with_fee = get_amount_out(1_000, 10_000, 10_000, 3, 1_000).unwrap();
+        assert_eq!(without_fee, 909);
+        assert_eq!(with_fee, 906);
+        
+        assert_eq!(get_amount_in(909, 10_000, 10_000, 0, 1_000).unwrap(), 1_000);
+        assert_eq!(get_amount_in(909, 10_000, 10_000, 3, 1_000).unwrap(), 1_003);
+    }
+
+    #[test]
+    fn invalid_inputs_are_rejected() {
+        assert_eq!(get_amount_out(0, 1_000, 1_000, 3, 1_000), Err(ErrorCode::InvalidAmount.into()));
+        assert_eq!(get_amount_out(1, 0, 1_000, 3, 1_000), Err(ErrorCode::InsufficientLiquidity.into()));
+        assert_eq!(get_amount_out(1, 1_000, 1_000, 1_000, 1_000), Err(ErrorCode::InvalidAmount.into()));
+        assert_eq!(get_amount_in(0, 1_000, 1_000, 3, 1_000), Err(ErrorCode::InvalidAmount.into()));
+        assert_eq!(get_amount_in(1_000, 1_000, 1_000, 3, 1_000), Err(ErrorCode::InsufficientLiquidity.into()));
+    }
+
+    #[test]
+    fn overflow_sized_inputs_error_instead_of_wrapping() {
+        assert_eq!(
+            get_amount_out(u64::MAX, u64::MAX, u64::MAX, 3, 1_000),
+            Err(ErrorCode::MathOverflow.into())
+        );
+        assert_eq!(
+            get_amount_in(u64::MAX - 1, u64::MAX, u64::MAX, 3, 1_000),
+            Err(ErrorCode::MathOverflow.into())
+        );
+        // Fits in u128 but the required input exceeds u64
+        assert_eq!(get_amount_in(1, u64::MAX, 2, 0, 1), Err(ErrorCode::MathOverflow.into()));
+    }
+
+    const SOL: u64 = 1_000_000_000;
+    const DOLLAR: u64 = SOL; // one_dollar_lamports of the linked orderbook: $1 = 1 SOL
+    const REDEMPTION_FEE_BPS: u16 = 100; // redemption_fee_bps of the linked orderbook
+    const SHARE_DECIMALS: u8 = 6;
+    const START: i64 = 1_700_000_000;
+
+    /// Stand-in for the orderbook program, whose CPIs the `invoke` doubles answer
+    const ORDERBOOK_PROGRAM: Pubkey = Pubkey::new_from_array([7; 32]);
+
+    thread_local! {
+        static NOW: Cell<i64> = const { Cell::new(0) };
+        static EVENTS: RefCell<Vec<Vec<u8>>> = const { RefCell::new(Vec::new()) };
+        static RETURN_DATA: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+    }
+
+    /// Sysvars for off-chain runs, with the clock kept per test thread
+    struct Stubs;
+
+    impl SyscallStubs for Stubs {
+        fn sol_get_clock_sysvar(&self, var_addr: *mut u8) -> u64 {
+            let clock = Clock {
+                unix_timestamp: NOW.with(Cell::get),
+                ..Clock::default()
+            };
+            unsafe { std::ptr::write(var_addr as *mut Clock, clock) };
+            0
+        }
+
+        fn sol_get_rent_sysvar(&self, var_addr: *mut u8) -> u64 {
+            unsafe { std::ptr::write(var_addr as *mut Rent, Rent::default()) };
+            0
+        }
+    }
+
+    fn set_now(unix_timestamp: i64) {
+        NOW.with(|now| now.set(unix_timestamp));
+    }
+
+    /// Event logs go nowhere off-chain, so under test `emit!` records each event instead
+    macro_rules! emit {
+        ($event:expr) => {
+            tests::record_event(&$event)
+        };
+    }
+    pub(super) use emit;
+
+    pub(super) fn record_event<T: Event>(event: &T) {
+        EVENTS.with(|events| events.borrow_mut().push(event.data()));
+    }
+
+    /// Drain the recorded events, decoding those of type `T`
+    fn take_events<T: Event + AnchorDeserialize>() -> Vec<T> {
+        EVENTS.with(|events| {
+            events
+                .borrow_mut()
+                .drain(..)
+                .filter_map(|data| data.strip_prefix(T::DISCRIMINATOR).map(|event| T::try_from_slice(event).unwrap()))
+                .collect()
+        })
+    }
+
+    /// Test double for `set_return_data`, which is a no-op off-chain
+    pub(super) fn set_return_data(data: &[u8]) {
+        RETURN_DATA.with(|return_data| *return_data.borrow_mut() = data.to_vec());
+    }
+
+    /// Decode what the last instruction passed to `set_return_data`
+    fn take_return_data<T: AnchorDeserialize>() -> T {
+        RETURN_DATA.with(|return_data| T::try_from_slice(&return_data.borrow_mut().split_off(0)).unwrap())
+    }
+
+    /// Whether `account` signed directly or through one of this program's PDA seeds
+    fn is_signed(account: &AccountInfo, signer_seeds: &[&[&[u8]]]) -> bool {
+        account.is_signer
+            || signer_seeds.iter().any(|seeds| {
+                Pubkey::create_program_address(seeds, &ID).is_ok_and(|pda| pda == *account.key)
+            })
+    }
+
+    /// `transfer_checked` as either token program runs it: balance, mint, decimals and
+    /// authority checks, then the move; a Token-2022 transfer fee is withheld in `to`
+    #[allow(clippy::too_many_arguments)]
+    fn checked_transfer<'info>(
+        program: &AccountInfo<'info>,
+        from: &AccountInfo<'info>,
+        mint: &AccountInfo<'info>,
+        to: &AccountInfo<'info>,
+        authority: &AccountInfo<'info>,
+        signer_seeds: &[&[&[u8]]],
+        amount: u64,
+        decimals: u8,
+    ) -> ProgramResult {
+        if [from, mint, to].iter().any(|account| account.owner != program.key) {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        let mut from_data = from.try_borrow_mut_data()?;
+        let mut from_state = StateWithExtensionsMut::<TokenState>::unpack(&mut from_data)?;
+        if from_state.base.amount < amount {
+            return Err(TokenError::InsufficientFunds.into());
+        }
+        if from_state.base.mint != *mint.key {
+            return Err(TokenError::MintMismatch.into());
+        }
+        let fee = {
+            let mint_data = mint.try_borrow_data()?;
+            let mint_state = StateWithExtensions::<MintState>::unpack(&mint_data)?;
+            if mint_state.base.decimals != decimals {
+                return Err(TokenError::MintDecimalsMismatch.into());
+            }
+            match mint_state.get_extension::<TransferFeeConfig>() {
+                Ok(config) => config.calculate_epoch_fee(0, amount).ok_or(ProgramError::ArithmeticOverflow)?,
+                Err(_) => 0,
+            }
+        };
+        if from_state.base.owner != *authority.key {
+            return Err(TokenError::OwnerMismatch.into());
+        }
+        if !is_signed(authority, signer_seeds) {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+        if from.key == to.key {
+            return Ok(());
+        }
+        
+        let mut to_data = to.try_borrow_mut_data()?;
+        let mut to_state = StateWithExtensionsMut::<TokenState>::unpack(&mut to_data)?;
+        if to_state.base.mint != *mint.key {
+            return Err(TokenError::MintMismatch.into());
+        }
+        from_state.base.amount -= amount;
+        to_state.base.amount = to_state.base.amount
+            .checked_add(amount - fee)
+            .ok_or(ProgramError::ArithmeticOverflow)?;
+        if fee > 0 {
+            let withheld = &mut to_state.get_extension_mut::<TransferFeeAmount>()?.withheld_amount;
+            *withheld = (u64::from(*withheld) + fee).into();
+        }
+        from_state.pack_base();
+        to_state.pack_base();
+        Ok(())
+    }
+
+    /// Credit `amount` to a token account of `mint` and grow the supply, as a mint_to does
+    fn mint_tokens(mint: &AccountInfo, to: &AccountInfo, amount: u64) -> ProgramResult {
+        let mut mint_data = mint.try_borrow_mut_data()?;
+        let mut mint_state = StateWithExtensionsMut::<MintState>::unpack(&mut mint_data)?;
+        let mut to_data = to.try_borrow_mut_data()?;
+        let mut to_state = StateWithExtensionsMut::<TokenState>::unpack(&mut to_data)?;
+        if to_state.base.mint != *mint.key {
+            return Err(TokenError::MintMismatch.into());
+        }
+        
+        mint_state.base.supply = mint_state.base.supply.checked_add(amount).ok_or(ProgramError::ArithmeticOverflow)?;
+        to_state.base.amount = to_state.base.amount.checked_add(amount).ok_or(ProgramError::ArithmeticOverflow)?;
+        mint_state.pack_base();
+        to_state.pack_base();
+        Ok(())
+    }
+
+    /// Debit `amount` from a token account of `mint` and shrink the supply, as a burn does
+    fn burn_tokens(mint: &AccountInfo, from: &AccountInfo, amount: u64) -> ProgramResult {
+        let mut mint_data = mint.try_borrow_mut_data()?;
+        let mut mint_state = StateWithExtensionsMut::<MintState>::unpack(&mut mint_data)?;
+        let mut from_data = from.try_borrow_mut_data()?;
+        let mut from_state = StateWithExtensionsMut::<TokenState>::unpack(&mut from_data)?;
+        if from_state.base.mint != *mint.key {
+            return Err(TokenError::MintMismatch.into());
+        }
+        
+        from_state.base.amount = from_state.base.amount.checked_sub(amount).ok_or(TokenError::InsufficientFunds)?;
+        mint_state.base.supply = mint_state.base.supply.checked_sub(amount).ok_or(TokenError::InsufficientFunds)?;
+        mint_state.pack_base();
+        from_state.pack_base();
+        Ok(())
+    }
+
+    /// `anchor_spl::token` with test doubles for the legacy token CPIs the pool makes
+    pub(super) mod token {
+        pub use anchor_spl::token::*;
+        use super::{burn_tokens, checked_transfer, is_signed, mint_tokens, MintState, TokenError};
+        use super::{StateWithExtensions, TokenState};
+        use anchor_lang::prelude::*;
+        use anchor_lang::solana_program::program_error::ProgramError;
+        use anchor_lang::solana_program::program_option::COption;
+
+        pub fn transfer_checked<'info>(
+            ctx: CpiContext<'_, '_, '_, 'info, TransferChecked<'info>>,
+            amount: u64,
+            decimals: u8,
+        ) -> Result<()> {
+            let TransferChecked { from, mint, to, authority } = ctx.accounts;
+            checked_transfer(&ctx.program, &from, &mint, &to, &authority, ctx.signer_seeds, amount, decimals)?;
+            Ok(())
+        }
+
+        pub fn mint_to<'info>(
+            ctx: CpiContext<'_, '_, '_, 'info, MintTo<'info>>,
+            amount: u64,
+        ) -> Result<()> {
+            let MintTo { mint, to, authority } = ctx.accounts;
+            let mint_authority = StateWithExtensions::<MintState>::unpack(&mint.try_borrow_data()?)?.base.mint_authority;
+            if mint_authority != COption::Some(*authority.key) || !is_signed(&authority, ctx.signer_seeds) {
+                return Err(ProgramError::MissingRequiredSignature.into());
+            }
+            
+            mint_tokens(&mint, &to, amount)?;
+            Ok(())
+        }
+
+        pub fn burn<'info>(
+            ctx: CpiContext<'_, '_, '_, 'info, Burn<'info>>,
+            amount: u64,
+        ) -> Result<()> {
+            let Burn { mint, from, authority } = ctx.accounts;
+            let owner = StateWithExtensions::<TokenState>::unpack(&from.try_borrow_data()?)?.base.owner;
+            if owner != *authority.key {
+                return Err(ProgramError::from(TokenError::OwnerMismatch).into());
+            }
+            if !is_signed(&authority, ctx.signer_seeds) {
+                return Err(ProgramError::MissingRequiredSignature.into());
+            }
+            
+            burn_tokens(&mint, &from, amount)?;
+            Ok(())
+        }
+    }
+
+    /// `anchor_spl::token_interface` with a test double for the share transfers, which
+    /// run under whichever token program owns the share accounts
+    pub(super) mod token_interface {
+        pub use anchor_spl::token_interface::*;
+        use super::checked_transfer;
+        use anchor_lang::prelude::*;
+
+        pub fn transfer_checked<'info>(
+            ctx: CpiContext<'_, '_, '_, 'info, TransferChecked<'info>>,
+            amount: u64,
+            decimals: u8,
+        ) -> Result<()> {
+            let TransferChecked { from, mint, to, authority } = ctx.accounts;
+            checked_transfer(&ctx.program, &from, &mint, &to, &authority, ctx.signer_seeds, amount, decimals)?;
+            Ok(())
+        }
+    }
+
+    /// Test double for `invoke`
+    pub(super) fn invoke(instruction: &Instruction, account_infos: &[AccountInfo]) -> ProgramResult {
+        invoke_signed(instruction, account_infos, &[])
+    }
+
+    /// `(is_signer, is_writable)` of each account the orderbook's `MintCompleteSetTokens`
+    /// expects, ending with the token and system programs
+    const MINT_COMPLETE_SET_TOKENS_ACCOUNTS: [(bool, bool); 9] = [
+        (true, true),
+        (false, true),
+        (false, true),
+        (false, true),
+        (false, true),
+        (false, true),
+        (false, true),
+        (false, false),
+        (false, false),
+    ];
+
+    /// `(is_signer, is_writable)` of each account the orderbook's `RedeemShareTokens`
+    /// expects, ending with the token and system programs
+    const REDEEM_SHARE_TOKENS_ACCOUNTS: [(bool, bool); 8] = [
+        (true, true),
+        (false, true),
+        (false, true),
+        (false, true),
+        (false, true),
+        (false, true),
+        (false, false),
+        (false, false),
+    ];
+
+    /// The `Orderbook` fields after those in `OrderbookSnapshot`, up to `redemption_fee_bps`
+    #[derive(AnchorDeserialize)]
+    struct OrderbookFees {
+        _resolution_dollar_lamports: u64,
+        _taker_and_maker_bps: [u16; 2],
+        _fee_pot_lamports: u64,
+        _creator: Pubkey,
+        _creator_fee_bps: u16,
+        _counters: [u64; 6],
+        redemption_fee_bps: u16,
+    }
+
+    /// Test double for `invoke_signed`, answering the two orderbook instructions the pool
+    /// calls the way the orderbook program settles them: Anchor's account list check, the
+    /// runtime's signer check, then the share token and lamport movements, priced at the
+    /// orderbook's SOL rate
+    pub(super) fn invoke_signed(
+        instruction: &Instruction,
+        account_infos: &[AccountInfo],
+        signers_seeds: &[&[&[u8]]],
+    ) -> ProgramResult {
+        let (discriminator, mut args) = instruction.data.split_at(8);
+        let layout: &[(bool, bool)] = if discriminator == MINT_COMPLETE_SET_TOKENS_DISCRIMINATOR {
+            &MINT_COMPLETE_SET_TOKENS_ACCOUNTS
+        } else if discriminator == REDEEM_SHARE_TOKENS_DISCRIMINATOR {
+            &REDEEM_SHARE_TOKENS_ACCOUNTS
+        } else {
+            return Err(ProgramError::InvalidInstructionData);
+        };
+        let metas = &instruction.accounts;
+        if metas.len() != layout.len() {
+            return Err(ProgramError::NotEnoughAccountKeys);
+        }
+        let flags = metas.iter().map(|meta| (meta.is_signer, meta.is_writable));
+        let programs = [metas[layout.len() - 2].pubkey, metas[layout.len() - 1].pubkey];
+        if !flags.eq(layout.iter().copied()) || programs != [token::ID, system_program::ID] {
+            return Err(ProgramError::InvalidArgument);
+        }
+        
+        let accounts = instruction
+            .accounts
+            .iter()
+            .map(|meta| {
+                let info = account_infos
+                    .iter()
+                    .find(|info| *info.key == meta.pubkey)
+                    .ok_or(ProgramError::NotEnoughAccountKeys)?;
+                if meta.is_signer && !is_signed(info, signers_seeds) {
+                    return Err(ProgramError::MissingRequiredSignature);
+                }
+                Ok(info)
+            })
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        if instruction.program_id != ORDERBOOK_PROGRAM || *accounts[1].owner != ORDERBOOK_PROGRAM {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        let orderbook = read_orderbook_snapshot(accounts[1])?;
+        let unit = 10u128.pow(orderbook.share_decimals as u32);
+        
+        if discriminator == MINT_COMPLETE_SET_TOKENS_DISCRIMINATOR {
+            // [user, orderbook, yes_mint, no_mint, user_yes, user_no, vault, ..]
+            let quantity = u64::deserialize(&mut args)?;
+            let cost = (quantity as u128 * orderbook.one_dollar_lamports as u128).div_ceil(unit) as u64;
+            if !orderbook.is_active || accounts[0].lamports() < cost {
+                return Err(ProgramError::InsufficientFunds);
+            }
+            **accounts[0].try_borrow_mut_lamports()? -= cost;
+            **accounts[6].try_borrow_mut_lamports()? += cost;
+            mint_tokens(accounts[2], accounts[4], quantity)?;
+            mint_tokens(accounts[3], accounts[5], quantity)
+        } else {
+            // [holder, orderbook, winning_mint, holder_tokens, vault, fee_vault, ..]
+            let winning_outcome = Outcome::deserialize(&mut args)?;
+            let quantity = u64::deserialize(&mut args)?;
+            if orderbook.is_active || orderbook.winning_outcome != Some(winning_outcome) {
+                return Err(ProgramError::InvalidArgument);
+            }
+            let fee_bps = {
+                let data = accounts[1].try_borrow_data()?;
+                let mut rest = &data[8..];
+                OrderbookSnapshot::deserialize(&mut rest)?;
+                OrderbookFees::deserialize(&mut rest)?.redemption_fee_bps
+            };
+            let payout = (quantity as u128 * orderbook.one_dollar_lamports as u128 / unit) as u64;
+            let fee = (payout as u128 * fee_bps as u128 / 10_000) as u64;
+            burn_tokens(accounts[2], accounts[3], quantity)?;
+            **accounts[4].try_borrow_mut_lamports()? -= payout;
+            **accounts[5].try_borrow_mut_lamports()? += fee;
+            **accounts[0].try_borrow_mut_lamports()? += payout - fee;
+            Ok(())
+        }
+    }
+
+    fn err(error: ErrorCode) -> ProgramResult {
+        Err(Error::from(error).into())
+    }
+
+    fn leak<T>(value: T) -> &'static mut T {
+        Box::leak(Box::new(value))
+    }
+
+    fn rent(space: usize) -> u64 {
+        Rent::default().minimum_balance(space)
+    }
+
+    fn pda(seeds: &[&[u8]]) -> Pubkey {
+        Pubkey::find_program_address(seeds, &ID).0
+    }
+
+    fn serialized<T: AccountSerialize>(value: &T) -> Vec<u8> {
+        let mut data = Vec::new();
+        value.try_serialize(&mut data).unwrap();
+        data
+    }
+
+    /// An account as the runtime stores it between instructions
+    #[derive(Clone, Default)]
+    struct TestAccount {
+        owner: Pubkey,
+        lamports: u64,
+        data: Vec<u8>,
+    }
+
+    /// Leak `account` laid out like the runtime's input buffer: `realloc` writes the new
+    /// data length into the 8 bytes before the data and reads the original length from
+    /// the 4 bytes before the key, and the data may grow by MAX_PERMITTED_DATA_INCREASE
+    /// The system, token and orderbook program keys are marked executable
+    fn account_info(meta: &AccountMeta, account: TestAccount) -> AccountInfo<'static> {
+        #[repr(C)]
+        struct KeySlot {
+            original_data_len: u32,
+            key: Pubkey,
+        }
+        
+        let slot = leak(KeySlot {
+            original_data_len: account.data.len() as u32,
+            key: meta.pubkey,
+        });
+        let capacity = account.data.len() + MAX_PERMITTED_DATA_INCREASE;
+        let words = leak(vec![0u64; 1 + capacity.div_ceil(8)]);
+        let data = unsafe {
+            std::slice::from_raw_parts_mut(words.as_mut_ptr().add(1) as *mut u8, account.data.len())
+        };
+        data.copy_from_slice(&account.data);
+        let programs = [system_program::ID, token::ID, spl_token_2022::ID, ORDERBOOK_PROGRAM];
+        
+        AccountInfo::new(
+            &slot.key,
+            meta.is_signer,
+            meta.is_writable,
+            leak(account.lamports),
+            data,
+            leak(account.owner),
+            programs.contains(&meta.pubkey),
+            0,
+        )
+    }
+
+    /// Minimal in-memory runtime: accounts persist across instructions and an
+    /// instruction's writes are kept only if it succeeds
+    #[derive(Default)]
+    struct Bank {
+        accounts: HashMap<Pubkey, TestAccount>,
+    }
+
+    impl Bank {
+        fn fund(&mut self, key: Pubkey, lamports: u64) {
+            self.accounts.entry(key).or_default().lamports += lamports;
+        }
+
+        fn exists(&self, key: &Pubkey) -> bool {
+            self.accounts.get(key).is_some_and(|account| account.lamports > 0)
+        }
+
+        /// Create a rent-exempt account outside any instruction
+        fn put(&mut self, key: Pubkey, owner: Pubkey, data: Vec<u8>) {
+            self.accounts.insert(key, TestAccount { owner, lamports: rent(data.len()), data });
+        }
+
+        fn get<T: AccountDeserialize>(&self, key: &Pubkey) -> T {
+            T::try_deserialize(&mut self.accounts[key].data.as_slice()).unwrap()
+        }
+
+        /// Run `f` over the accounts of `metas`, first creating each `init` key with the
+        /// given owner and data, rent paid by `payer` - the state Anchor's `init` leaves
+        /// behind, which cannot run here since it needs a CPI
+        fn transact(
+            &mut self,
+            metas: &[AccountMeta],
+            init: &[(Pubkey, Pubkey, Vec<u8>)],
+            payer: Pubkey,
+            f: impl FnOnce(&'static [AccountInfo<'static>]) -> ProgramResult,
+        ) -> ProgramResult {
+            let mut staged = self.accounts.clone();
+            for (key, owner, data) in init {
+                if staged.get(key).is_some_and(|account| account.lamports > 0) {
+                    return Err(ProgramError::AccountAlreadyInitialized);
+                }
+                let payer = staged.entry(payer).or_default();
+                payer.lamports = payer.lamports
+                    .checked_sub(rent(data.len()))
+                    .ok_or(ProgramError::InsufficientFunds)?;
+                staged.insert(*key, TestAccount {
+                    owner: *owner,
+                    lamports: rent(data.len()),
+                    data: data.clone(),
+                });
+            }
+            
+            let infos: &'static [AccountInfo<'static>] = leak(
+                metas
+                    .iter()
+                    .map(|meta| account_info(meta, staged.get(&meta.pubkey).cloned().unwrap_or_default()))
+                    .collect::<Vec<_>>(),
+            );
+            f(infos)?;
+            
+            for info in infos {
+                staged.insert(*info.key, TestAccount {
+                    owner: *info.owner,
+                    lamports: info.lamports(),
+                    data: info.data.borrow().to_vec(),
+                });
+            }
+            self.accounts = staged;
+            Ok(())
+        }
+
+        /// Run an instruction through the program entrypoint, staging `init` accounts first
+        fn process(
+            &mut self,
+            metas: Vec<AccountMeta>,
+            data: Vec<u8>,
+            init: &[(Pubkey, Pubkey, Vec<u8>)],
+            payer: Pubkey,
+        ) -> ProgramResult {
+            self.transact(&metas, init, payer, |infos| crate::entry(&ID, infos, &data))
+        }
+    }
+
+    /// A zeroed program account of `space` bytes under `T`'s discriminator: what `init`
+    /// leaves behind, and what lets an `init_if_needed` account pass Anchor's checks
+    fn init<T: Discriminator>(key: Pubkey, space: usize) -> (Pubkey, Pubkey, Vec<u8>) {
+        let mut data = vec![0; space];
+        data[..8].copy_from_slice(T::DISCRIMINATOR);
+        (key, ID, data)
+    }
+
+    /// An initialized token account of `mint` held by `owner`, with the transfer fee
+    /// slot Token-2022 requires when the mint charges one
+    fn token_account_data(mint_data: &[u8], mint: Pubkey, owner: Pubkey) -> Vec<u8> {
+        let charges_fee = StateWithExtensions::<MintState>::unpack(mint_data)
+            .unwrap()
+            .get_extension::<TransferFeeConfig>()
+            .is_ok();
+        let extensions = if charges_fee { vec![ExtensionType::TransferFeeAmount] } else { Vec::new() };
+        let mut data = vec![0; ExtensionType::try_calculate_account_len::<TokenState>(&extensions).unwrap()];
+        let mut state = StateWithExtensionsMut::<TokenState>::unpack_uninitialized(&mut data).unwrap();
+        if charges_fee {
+            state.init_extension::<TransferFeeAmount>(true).unwrap();
+            state.init_account_type().unwrap();
+        }
+        state.base = TokenState {
+            mint,
+            owner,
+            state: AccountState::Initialized,
+            ..Default::default()
+        };
+        state.pack_base();
+        data
+    }
+
+    /// The rent sysvar account `add_liquidity` reads
+    fn rent_sysvar() -> Vec<u8> {
+        let rent = Rent::default();
+        let mut data = rent.lamports_per_byte_year.to_le_bytes().to_vec();
+        data.extend(rent.exemption_threshold.to_le_bytes());
+        data.push(rent.burn_percent);
+        data
+    }
+
+    /// A pool over two fresh share mints, its authority (also the protocol fee
+    /// recipient) and the clock at START
+    struct Env {
+        bank: Bank,
+        pool_id: Pubkey,
+        market_id: Pubkey,
+        authority: Pubkey,
+        yes_mint: Pubkey,
+        no_mint: Pubkey,
+    }
+
+    /// Funded authority and share mints under `share_program`, no pool yet
+    fn uninitialized(share_program: Pubkey) -> Env {
+        static INSTALL_STUBS: Once = Once::new();
+        INSTALL_STUBS.call_once(|| {
+            set_syscall_stubs(Box::new(Stubs));
+        });
+        set_now(START);
+        
+        let mut env = Env {
+            bank: Bank::default(),
+            pool_id: Pubkey::new_unique(),
+            market_id: Pubkey::new_unique(),
+            authority: Pubkey::new_unique(),
+            yes_mint: Pubkey::new_unique(),
+            no_mint: Pubkey::new_unique(),
+        };
+        env.bank.fund(env.authority, 100 * SOL);
+        env.bank.put(sysvar::rent::ID, sysvar::ID, rent_sysvar());
+        env.create_mint(env.yes_mint, share_program, SHARE_DECIMALS);
+        env.create_mint(env.no_mint, share_program, SHARE_DECIMALS);
+        env
+    }
+
+    /// `uninitialized` plus a full-range constant-product pool over legacy share mints
+    fn setup() -> Env {
+        let mut env = uninitialized(token::ID);
+        env.initialize(0, 0, CurveType::ConstantProduct).unwrap();
+        env
+    }
+
+    impl Env {
+        fn pool_key(&self) -> Pubkey {
+            pda(&[b"pool", self.pool_id.as_ref()])
+        }
+
+        /// A pool-owned account: `yes_shares`, `no_shares`, `lp_mint` or `reward_vault`
+        fn pool_account(&self, seed: &[u8]) -> Pubkey {
+            pda(&[b"pool", self.pool_id.as_ref(), seed])
+        }
+
+        fn lp_tokens(&self, user: Pubkey) -> Pubkey {
+            pda(&[b"user", user.as_ref(), self.pool_id.as_ref(), b"lp_tokens"])
+        }
+
+        fn position_key(&self, user: Pubkey) -> Pubkey {
+            pda(&[b"position", self.pool_id.as_ref(), user.as_ref()])
+        }
+
+        fn pool(&self) -> AmmPool {
+            self.bank.get(&self.pool_key())
+        }
+
+        fn position(&self, user: Pubkey) -> LiquidityPosition {
+            self.bank.get(&self.position_key(user))
+        }
+
+        /// `get_yes_price` or `get_no_price`, called directly: Anchor's return data
+        /// is a no-op off-chain
+        fn price(&mut self, yes: bool) -> u64 {
+            let metas = crate::accounts::GetPrice { pool: self.pool_key() }.to_account_metas(None);
+            let mut price = 0;
+            
+            self.bank
+                .transact(&metas, &[], self.authority, |infos| {
+                    let mut accounts = GetPrice { pool: Account::try_from(&infos[0])? };
+                    let ctx = Context::new(&ID, &mut accounts, &[], GetPriceBumps::default());
+                    price = if yes { amm::get_yes_price(ctx)? } else { amm::get_no_price(ctx)? };
+                    Ok(())
+                })
+                .unwrap();
+            price
+        }
+
+        /// Rewrite the pool account between instructions
+        fn edit_pool(&mut self, f: impl FnOnce(&mut AmmPool)) {
+            let mut pool = self.pool();
+            f(&mut pool);
+            self.bank.accounts.get_mut(&self.pool_key()).unwrap().data = serialized(&pool);
+        }
+
+        fn create_mint(&mut self, key: Pubkey, program: Pubkey, decimals: u8) {
+            let mut data = vec![0; MintState::LEN];
+            let mut state = StateWithExtensionsMut::<MintState>::unpack_uninitialized(&mut data).unwrap();
+            state.base = MintState {
+                mint_authority: COption::Some(self.authority),
+                decimals,
+                is_initialized: true,
+                ..Default::default()
+            };
+            state.pack_base();
+            self.bank.put(key, program, data);
+        }
+
+        /// Give `mint` a Token-2022 transfer fee of `basis_points`, withheld on every transfer
+        fn charge_transfer_fee(&mut self, mint: Pubkey, basis_points: u16) {
+            let account = &self.bank.accounts[&mint];
+            let base = StateWithExtensions::<MintState>::unpack(&account.data).unwrap().base;
+            let program = account.owner;
+            let len = ExtensionType::try_calculate_account_len::<MintState>(&[ExtensionType::TransferFeeConfig]);
+            let mut data = vec![0; len.unwrap()];
+            let mut state = StateWithExtensionsMut::<MintState>::unpack_uninitialized(&mut data).unwrap();
+            let fee = TransferFee {
+                epoch: 0.into(),
+                maximum_fee: u64::MAX.into(),
+                transfer_fee_basis_points: basis_points.into(),
+            };
+            let config = state.init_extension::<TransferFeeConfig>(true).unwrap();
+            (config.older_transfer_fee, config.newer_transfer_fee) = (fee, fee);
+            state.init_account_type().unwrap();
+            state.base = base;
+            state.pack_base();
+            self.bank.put(mint, program, data);
+        }
+
+        /// `owner`'s token account of `mint`, at a fixed address per owner and mint
+        fn token_account(&self, owner: Pubkey, mint: Pubkey) -> Pubkey {
+            Pubkey::find_program_address(&[owner.as_ref(), mint.as_ref()], &token::ID).0
+        }
+
+        /// Mint `amount` of `mint` to `owner`, creating their token account if needed
+        fn give(&mut self, owner: Pubkey, mint: Pubkey, amount: u64) -> Pubkey {
+            let key = self.token_account(owner, mint);
+            if !self.bank.exists(&key) {
+                let mint_account = &self.bank.accounts[&mint];
+                let data = token_account_data(&mint_account.data, mint, owner);
+                let program = mint_account.owner;
+                self.bank.put(key, program, data);
+            }
+            let metas = [AccountMeta::new(mint, false), AccountMeta::new(key, false)];
+            self.bank.transact(&metas, &[], self.authority, |infos| mint_tokens(&infos[0], &infos[1], amount)).unwrap();
+            key
+        }
+
+        fn token_balance(&self, key: &Pubkey) -> u64 {
+            StateWithExtensions::<TokenState>::unpack(&self.bank.accounts[key].data).unwrap().base.amount
+        }
+
+        /// Move tokens between two accounts outside the pool, as a wallet transfer would
+        fn move_tokens(&mut self, from: Pubkey, to: Pubkey, amount: u64) {
+            for (key, delta) in [(from, amount.wrapping_neg()), (to, amount)] {
+                let data = &mut self.bank.accounts.get_mut(&key).unwrap().data;
+                let mut state = StateWithExtensionsMut::<TokenState>::unpack(data).unwrap();
+                state.base.amount = state.base.amount.wrapping_add(delta);
+                state.pack_base();
+            }
+        }
+
+        /// A funded user holding `yes` YES and `no` NO shares
+        fn trader(&mut self, yes: u64, no: u64) -> Pubkey {
+            let user = Pubkey::new_unique();
+            self.bank.fund(user, 100 * SOL);
+            self.give(user, self.yes_mint, yes);
+            self.give(user, self.no_mint, no);
+            user
+        }
+
+        fn initialize(&mut self, min_price: u64, max_price: u64, curve_type: CurveType) -> ProgramResult {
+            let metas = crate::accounts::InitializePool {
+                authority: self.authority,
+                pool: self.pool_key(),
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None);
+            let space = 8 + 32 + 32 + 32 + 32 + 32 + 8 + 8 + 16 + 8 + 8 + 8 + 8 + 8 + 32 + 2 + 32 + 1 + 2 + 8 + 1 + 8 + 8 + 1
+                + 32 + 8 + 16 + 8;
+            let init = [init::<AmmPool>(self.pool_key(), space)];
+            let (pool_id, market_id, yes_mint, no_mint) = (self.pool_id, self.market_id, self.yes_mint, self.no_mint);
+            
+            self.bank.transact(&metas, &init, self.authority, |infos| {
+                let mut accounts = InitializePool {
+                    authority: Signer::try_from(&infos[0])?,
+                    pool: Account::try_from_unchecked(&infos[1])?,
+                    system_program: Program::try_from(&infos[2])?,
+                };
+                let bumps = InitializePoolBumps {
+                    pool: Pubkey::find_program_address(&[b"pool", pool_id.as_ref()], &ID).1,
+                };
+                amm::initialize_pool(
+                    Context::new(&ID, &mut accounts, &[], bumps),
+                    pool_id,
+                    market_id,
+                    yes_mint,
+                    no_mint,
+                    min_price,
+                    max_price,
+                    curve_type,
+                )?;
+                accounts.exit(&ID)?;
+                Ok(())
+            })
+        }
+
+        /// `init_if_needed` staging for the LP mint, the pool's share accounts and the
+        /// user's LP account and position, as the first deposit would create them
+        fn init_liquidity_accounts(&self, user: Pubkey) -> Vec<(Pubkey, Pubkey, Vec<u8>)> {
+            let pool = self.pool_key();
+            let lp_mint = self.pool_account(b"lp_mint");
+            let mut data = vec![0; MintState::LEN];
+            let mut state = StateWithExtensionsMut::<MintState>::unpack_uninitialized(&mut data).unwrap();
+            state.base = MintState {
+                mint_authority: COption::Some(pool),
+                decimals: 6,
+                is_initialized: true,
+                freeze_authority: COption::Some(pool),
+                ..Default::default()
+            };
+            state.pack_base();
+            let lp_tokens = token_account_data(&data, lp_mint, user);
+            
+            let mut staged = Vec::new();
+            if !self.bank.exists(&lp_mint) {
+                staged.push((lp_mint, token::ID, data));
+            }
+            for (seed, mint) in [(&b"yes_shares"[..], self.yes_mint), (b"no_shares", self.no_mint)] {
+                let key = self.pool_account(seed);
+                if !self.bank.exists(&key) {
+                    let mint_account = &self.bank.accounts[&mint];
+                    staged.push((key, mint_account.owner, token_account_data(&mint_account.data, mint, pool)));
+                }
+            }
+            if !self.bank.exists(&self.lp_tokens(user)) {
+                staged.push((self.lp_tokens(user), token::ID, lp_tokens));
+            }
+            staged.extend(self.init_position(user));
+            staged
+        }
+
+        /// `init_if_needed` staging for a user's position
+        fn init_position(&self, user: Pubkey) -> Option<(Pubkey, Pubkey, Vec<u8>)> {
+            let key = self.position_key(user);
+            (!self.bank.exists(&key)).then(|| init::<LiquidityPosition>(key, LiquidityPosition::LEN))
+        }
+
+        /// Deposit at the pool's ratio with no minimums
+        fn add_liquidity(&mut self, user: Pubkey, yes: u64, no: u64) -> ProgramResult {
+            self.add_liquidity_with(user, yes, no, 0, 0, 0)
+        }
+
+        fn add_liquidity_with(
+            &mut self,
+            user: Pubkey,
+            yes_amount_desired: u64,
+            no_amount_desired: u64,
+            yes_amount_min: u64,
+            no_amount_min: u64,
+            minimum_lp_tokens: u64,
+        ) -> ProgramResult {
+            let metas = crate::accounts::AddLiquidity {
+                user,
+                pool: self.pool_key(),
+                lp_mint: self.pool_account(b"lp_mint"),
+                pool_yes_shares: self.pool_account(b"yes_shares"),
+                pool_no_shares: self.pool_account(b"no_shares"),
+                user_lp_tokens: self.lp_tokens(user),
+                position: self.position_key(user),
+                user_yes_shares: self.token_account(user, self.yes_mint),
+                user_no_shares: self.token_account(user, self.no_mint),
+                yes_mint: self.yes_mint,
+                no_mint: self.no_mint,
+                token_program: token::ID,
+                system_program: system_program::ID,
+                rent: sysvar::rent::ID,
+            }
+            .to_account_metas(None);
+            let data = crate::instruction::AddLiquidity {
+                pool_id: self.pool_id,
+                yes_amount_desired,
+                no_amount_desired,
+                yes_amount_min,
+                no_amount_min,
+                minimum_lp_tokens,
+            }
+            .data();
+            let init = self.init_liquidity_accounts(user);
+            self.bank.process(metas, data, &init, user)
+        }
+
+        fn add_liquidity_interface(
+            &mut self,
+            user: Pubkey,
+            yes_amount_desired: u64,
+            no_amount_desired: u64,
+        ) -> ProgramResult {
+            let metas = crate::accounts::AddLiquidityInterface {
+                user,
+                pool: self.pool_key(),
+                lp_mint: self.pool_account(b"lp_mint"),
+                pool_yes_shares: self.pool_account(b"yes_shares"),
+                pool_no_shares: self.pool_account(b"no_shares"),
+                user_lp_tokens: self.lp_tokens(user),
+                position: self.position_key(user),
+                user_yes_shares: self.token_account(user, self.yes_mint),
+                user_no_shares: self.token_account(user, self.no_mint),
+                yes_mint: self.yes_mint,
+                no_mint: self.no_mint,
+                token_program: token::ID,
+                share_token_program: self.bank.accounts[&self.yes_mint].owner,
+                system_program: system_program::ID,
+                rent: sysvar::rent::ID,
+            }
+            .to_account_metas(None);
+            let data = crate::instruction::AddLiquidityInterface {
+                pool_id: self.pool_id,
+                yes_amount_desired,
+                no_amount_desired,
+                yes_amount_min: 0,
+                no_amount_min: 0,
+                minimum_lp_tokens: 0,
+            }
+            .data();
+            let init = self.init_liquidity_accounts(user);
+            self.bank.process(metas, data, &init, user)
+        }
+
+        fn deactivate(&mut self) -> ProgramResult {
+            let metas = crate::accounts::UpdatePool { authority: self.authority, pool: self.pool_key() }
+                .to_account_metas(None);
+            self.bank.process(metas, crate::instruction::DeactivatePool {}.data(), &[], self.authority)
+        }
+
+        /// Stage an orderbook for the pool's market, resolved when `winning_outcome` is set,
+        /// and link it to the pool
+        fn link_orderbook(&mut self, winning_outcome: Option<Outcome>) {
+            let orderbook = Pubkey::new_unique();
+            let mut data = ORDERBOOK_DISCRIMINATOR.to_vec();
+            (
+                Pubkey::new_unique(),
+                self.market_id,
+                DOLLAR,
+                [0u64; 8],
+                winning_outcome.is_none(),
+                winning_outcome,
+                [0u64; 6],
+                SHARE_DECIMALS,
+                self.yes_mint,
+                self.no_mint,
+            )
+                .serialize(&mut data)
+                .unwrap();
+            (DOLLAR, [0u16; 2], 0u64, Pubkey::new_unique(), 0u16, [0u64; 6], REDEMPTION_FEE_BPS)
+                .serialize(&mut data)
+                .unwrap();
+            self.bank.put(orderbook, ORDERBOOK_PROGRAM, data);
+            self.edit_pool(|pool| pool.linked_orderbook = orderbook);
+            self.bank.fund(self.orderbook_vault(), 100 * SOL);
+        }
+
+        fn orderbook_vault(&self) -> Pubkey {
+            let orderbook = self.pool().linked_orderbook;
+            Pubkey::find_program_address(&[b"vault", orderbook.as_ref()], &ORDERBOOK_PROGRAM).0
+        }
+
+        fn orderbook_fee_vault(&self) -> Pubkey {
+            let orderbook = self.pool().linked_orderbook;
+            Pubkey::find_program_address(&[b"fee_vault", orderbook.as_ref()], &ORDERBOOK_PROGRAM).0
+        }
+
+        fn settle_and_remove_liquidity(
+            &mut self,
+            user: Pubkey,
+            lp_amount: u64,
+            minimum_lamports_out: u64,
+        ) -> ProgramResult {
+            let metas = crate::accounts::SettleLiquidity {
+                user,
+                pool: self.pool_key(),
+                lp_mint: self.pool_account(b"lp_mint"),
+                pool_yes_shares: self.pool_account(b"yes_shares"),
+                pool_no_shares: self.pool_account(b"no_shares"),
+                user_lp_tokens: self.lp_tokens(user),
+                position: self.position_key(user),
+                yes_mint: self.yes_mint,
+                no_mint: self.no_mint,
+                orderbook: self.pool().linked_orderbook,
+                orderbook_vault: self.orderbook_vault(),
+                orderbook_fee_vault: self.orderbook_fee_vault(),
+                orderbook_program: ORDERBOOK_PROGRAM,
+                token_program: token::ID,
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None);
+            let data = crate::instruction::SettleAndRemoveLiquidity {
+                pool_id: self.pool_id,
+                lp_amount,
+                minimum_lamports_out,
+            }
+            .data();
+            let init: Vec<_> = self.init_position(user).into_iter().collect();
+            self.bank.process(metas, data, &init, user)
+        }
+
+        fn buy_yes_with_sol(&mut self, user: Pubkey, sol_amount: u64, min_yes_out: u64) -> ProgramResult {
+            let metas = crate::accounts::BuyYesWithSol {
+                user,
+                pool: self.pool_key(),
+                pool_yes_shares: self.pool_account(b"yes_shares"),
+                pool_no_shares: self.pool_account(b"no_shares"),
+                user_yes_shares: self.give(user, self.yes_mint, 0),
+                user_no_shares: self.give(user, self.no_mint, 0),
+                protocol_fee_account: self.fee_account(self.no_mint),
+                yes_mint: self.yes_mint,
+                no_mint: self.no_mint,
+                orderbook: self.pool().linked_orderbook,
+                orderbook_vault: self.orderbook_vault(),
+                orderbook_program: ORDERBOOK_PROGRAM,
+                token_program: token::ID,
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None);
+            let data = crate::instruction::BuyYesWithSol {
+                pool_id: self.pool_id,
+                sol_amount,
+                min_yes_out,
+            }
+            .data();
+            self.bank.process(metas, data, &[], user)
+        }
+
+        /// The protocol fee recipient's account of `mint`, created empty if needed
+        fn fee_account(&mut self, mint: Pubkey) -> Pubkey {
+            let recipient = self.pool().protocol_fee_recipient;
+            self.give(recipient, mint, 0)
+        }
+
+        /// `swap_yes_for_no` when `yes_to_no`, else `swap_no_for_yes`
+        fn swap(&mut self, user: Pubkey, yes_to_no: bool, amount_in: u64, minimum_out: u64) -> ProgramResult {
+            let protocol_fee_account = self.fee_account(if yes_to_no { self.yes_mint } else { self.no_mint });
+            let (pool, pool_yes_shares, pool_no_shares) =
+                (self.pool_key(), self.pool_account(b"yes_shares"), self.pool_account(b"no_shares"));
+            let user_yes_shares = self.token_account(user, self.yes_mint);
+            let user_no_shares = self.token_account(user, self.no_mint);
+            let (yes_mint, no_mint) = (self.yes_mint, self.no_mint);
+            let (metas, data) = if yes_to_no {
+                let metas = crate::accounts::SwapYesForNo {
+                    user,
+                    pool,
+                    pool_yes_shares,
+                    pool_no_shares,
+                    user_yes_shares,
+                    user_no_shares,
+                    protocol_fee_account,
+                    yes_mint,
+                    no_mint,
+                    token_program: token::ID,
+                }
+                .to_account_metas(None);
+                let data = crate::instruction::SwapYesForNo {
+                    _pool_id: self.pool_id,
+                    yes_amount_in: amount_in,
+                    minimum_no_out: minimum_out,
+                }
+                .data();
+                (metas, data)
+            } else {
+                let metas = crate::accounts::SwapNoForYes {
+                    user,
+                    pool,
+                    pool_yes_shares,
+                    pool_no_shares,
+                    user_yes_shares,
+                    user_no_shares,
+                    protocol_fee_account,
+                    yes_mint,
+                    no_mint,
+                    token_program: token::ID,
+                }
+                .to_account_metas(None);
+                let data = crate::instruction::SwapNoForYes {
+                    _pool_id: self.pool_id,
+                    no_amount_in: amount_in,
+                    minimum_yes_out: minimum_out,
+                }
+                .data();
+                (metas, data)
+            };
+            self.bank.process(metas, data, &[], user)
+        }
+
+        fn swap_at_market(&mut self, user: Pubkey, side: Outcome, amount_in: u64) -> ProgramResult {
+            let mint_in = if side == Outcome::Yes { self.no_mint } else { self.yes_mint };
+            let metas = crate::accounts::SwapAtMarket {
+                user,
+                pool: self.pool_key(),
+                pool_yes_shares: self.pool_account(b"yes_shares"),
+                pool_no_shares: self.pool_account(b"no_shares"),
+                user_yes_shares: self.token_account(user, self.yes_mint),
+                user_no_shares: self.token_account(user, self.no_mint),
+                protocol_fee_account: self.fee_account(mint_in),
+                yes_mint: self.yes_mint,
+                no_mint: self.no_mint,
+                token_program: token::ID,
+            }
+            .to_account_metas(None);
+            let data = crate::instruction::SwapAtMarket { _pool_id: self.pool_id, side, amount_in }.data();
+            self.bank.process(metas, data, &[], user)
+        }
+
+        fn swap_interface(
+            &mut self,
+            user: Pubkey,
+            side: Outcome,
+            amount_in: u64,
+            minimum_out: u64,
+        ) -> ProgramResult {
+            let mint_in = if side == Outcome::Yes { self.no_mint } else { self.yes_mint };
+            let metas = crate::accounts::SwapInterface {
+                user,
+                pool: self.pool_key(),
+                pool_yes_shares: self.pool_account(b"yes_shares"),
+                pool_no_shares: self.pool_account(b"no_shares"),
+                user_yes_shares: self.token_account(user, self.yes_mint),
+                user_no_shares: self.token_account(user, self.no_mint),
+                protocol_fee_account: self.fee_account(mint_in),
+                yes_mint: self.yes_mint,
+                no_mint: self.no_mint,
+                share_token_program: self.bank.accounts[&mint_in].owner,
+            }
+            .to_account_metas(None);
+            let data = crate::instruction::SwapInterface {
+                _pool_id: self.pool_id,
+                side,
+                amount_in,
+                minimum_out,
+            }
+            .data();
+            self.bank.process(metas, data, &[], user)
+        }
+
+        fn remove_liquidity(
+            &mut self,
+            user: Pubkey,
+            lp_amount: u64,
+            minimum_yes_out: u64,
+            minimum_no_out: u64,
+        ) -> ProgramResult {
+            let data = crate::instruction::RemoveLiquidity {
+                pool_id: self.pool_id,
+                lp_amount,
+                minimum_yes_out,
+                minimum_no_out,
+            }
+            .data();
+            self.remove_liquidity_instruction(user, data)
+        }
+
+        /// Run a `RemoveLiquidity` instruction for `user`
+        fn remove_liquidity_instruction(&mut self, user: Pubkey, data: Vec<u8>) -> ProgramResult {
+            let metas = crate::accounts::RemoveLiquidity {
+                user,
+                pool: self.pool_key(),
+                lp_mint: self.pool_account(b"lp_mint"),
+                pool_yes_shares: self.pool_account(b"yes_shares"),
+                pool_no_shares: self.pool_account(b"no_shares"),
+                user_lp_tokens: self.lp_tokens(user),
+                position: self.position_key(user),
+                user_yes_shares: self.token_account(user, self.yes_mint),
+                user_no_shares: self.token_account(user, self.no_mint),
+                yes_mint: self.yes_mint,
+                no_mint: self.no_mint,
+                token_program: token::ID,
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None);
+            let init: Vec<_> = self.init_position(user).into_iter().collect();
+            self.bank.process(metas, data, &init, user)
+        }
+
+        fn reconcile_reserves(&mut self) -> ProgramResult {
+            let metas = crate::accounts::ReconcileReserves {
+                pool: self.pool_key(),
+                pool_yes_shares: self.pool_account(b"yes_shares"),
+                pool_no_shares: self.pool_account(b"no_shares"),
+            }
+            .to_account_metas(None);
+            let data = crate::instruction::ReconcileReserves { _pool_id: self.pool_id }.data();
+            self.bank.process(metas, data, &[], self.authority)
+        }
+
+        /// Sweep donations to `recipient`'s share accounts, signed by `authority`
+        fn skim(&mut self, authority: Pubkey, recipient: Pubkey) -> ProgramResult {
+            let metas = crate::accounts::Skim {
+                authority,
+                pool: self.pool_key(),
+                pool_yes_shares: self.pool_account(b"yes_shares"),
+                pool_no_shares: self.pool_account(b"no_shares"),
+                recipient_yes_shares: self.give(recipient, self.yes_mint, 0),
+                recipient_no_shares: self.give(recipient, self.no_mint, 0),
+                yes_mint: self.yes_mint,
+                no_mint: self.no_mint,
+                share_token_program: token::ID,
+            }
+            .to_account_metas(None);
+            let data = crate::instruction::Skim { pool_id: self.pool_id }.data();
+            self.bank.process(metas, data, &[], authority)
+        }
+
+        /// Turn on liquidity mining at `reward_rate` and fund the vault with `funding`
+        fn enable_rewards(&mut self, reward_rate: u64, funding: u64) -> Pubkey {
+            let reward_mint = Pubkey::new_unique();
+            self.create_mint(reward_mint, token::ID, 6);
+            let reward_vault = self.pool_account(b"reward_vault");
+            let metas = crate::accounts::InitializeRewards {
+                authority: self.authority,
+                pool: self.pool_key(),
+                reward_mint,
+                reward_vault,
+                token_program: token::ID,
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None);
+            let vault_data = token_account_data(&self.bank.accounts[&reward_mint].data, reward_mint, self.pool_key());
+            let init = [(reward_vault, token::ID, vault_data)];
+            let pool_id = self.pool_id;
+            
+            self.bank.transact(&metas, &init, self.authority, |infos| {
+                let mut accounts = InitializeRewards {
+                    authority: Signer::try_from(&infos[0])?,
+                    pool: Account::try_from(&infos[1])?,
+                    reward_mint: Box::new(Account::try_from(&infos[2])?),
+                    reward_vault: Box::new(Account::try_from(&infos[3])?),
+                    token_program: Program::try_from(&infos[4])?,
+                    system_program: Program::try_from(&infos[5])?,
+                };
+                amm::initialize_rewards(
+                    Context::new(&ID, &mut accounts, &[], InitializeRewardsBumps::default()),
+                    pool_id,
+                    reward_rate,
+                )?;
+                accounts.exit(&ID)?;
+                Ok(())
+            })
+            .unwrap();
+            
+            let funder = self.give(self.authority, reward_mint, funding);
+            self.move_tokens(funder, reward_vault, funding);
+            reward_mint
+        }
+
+        /// Claim `user`'s rewards into their reward token account and return the amount paid
+        fn claim_rewards(&mut self, user: Pubkey) -> std::result::Result<u64, ProgramError> {
+            let reward_mint = self.pool().reward_mint;
+            let user_reward_tokens = self.give(user, reward_mint, 0);
+            let metas = crate::accounts::ClaimRewards {
+                user,
+                pool: self.pool_key(),
+                position: self.position_key(user),
+                user_lp_tokens: self.lp_tokens(user),
+                reward_vault: self.pool_account(b"reward_vault"),
+                user_reward_tokens,
+                reward_mint,
+                token_program: token::ID,
+            }
+            .to_account_metas(None);
+            let data = crate::instruction::ClaimRewards { pool_id: self.pool_id }.data();
+            self.bank.process(metas, data, &[], user)?;
+            Ok(take_return_data())
+        }
+    }
+
+    #[test]
+    fn rewards_split_by_lp_held_over_time() {
+        let mut env = setup();
+        let reward_mint = env.enable_rewards(100, 1_000_000);
+        let alice = env.trader(1_000_000, 1_000_000);
+        let bob = env.trader(1_000_000, 1_000_000);
+        
+        // Alice earns alone for 100s, then shares the next 100s equally with Bob
+        env.add_liquidity(alice, 1_000_000, 1_000_000).unwrap();
+        set_now(START + 100);
+        env.add_liquidity(bob, 1_000_000, 1_000_000).unwrap();
+        set_now(START + 200);
+        assert_eq!(env.claim_rewards(alice), Ok(10_000 + 5_000));
+        assert_eq!(env.claim_rewards(bob), Ok(5_000));
+        
+        // Half of Alice's LP leaves her wallet: she earns on the half she still holds
+        let outsider = env.give(Pubkey::new_unique(), env.pool_account(b"lp_mint"), 0);
+        env.move_tokens(env.lp_tokens(alice), outsider, 500_000);
+        set_now(START + 300);
+        assert_eq!(env.claim_rewards(alice), Ok(2_500));
+        assert_eq!(env.claim_rewards(bob), Ok(5_000));
+        
+        assert_eq!(env.token_balance(&env.token_account(alice, reward_mint)), 17_500);
+        assert_eq!(env.token_balance(&env.pool_account(b"reward_vault")), 1_000_000 - 27_500);
+    }
+
+    #[test]
+    fn a_pool_marked_in_progress_rejects_swaps_and_liquidity_calls() {
+        let mut env = setup();
+        let lp = env.trader(1_000_000, 1_000_000);
+        env.add_liquidity(lp, 1_000_000, 1_000_000).unwrap();
+        let trader = env.trader(10_000, 10_000);
+        
+        // The flag lock_pool writes through before any CPI, as a reentrant call would find it
+        env.edit_pool(|pool| pool.in_progress = true);
+        assert_eq!(env.swap(trader, true, 1_000, 0), err(ErrorCode::ReentrancyDetected));
+        assert_eq!(env.swap(trader, false, 1_000, 0), err(ErrorCode::ReentrancyDetected));
+        assert_eq!(env.add_liquidity(trader, 1_000, 1_000), err(ErrorCode::ReentrancyDetected));
+        assert_eq!(env.remove_liquidity(lp, 1_000, 0, 0), err(ErrorCode::ReentrancyDetected));
+        
+        // Every call that locks the pool also leaves it unlocked
+        env.edit_pool(|pool| pool.in_progress = false);
+        env.swap(trader, true, 1_000, 0).unwrap();
+        env.add_liquidity(trader, 1_000, 1_000).unwrap();
+        env.remove_liquidity(lp, 1_000, 0, 0).unwrap();
+        assert!(!env.pool().in_progress);
+    }
+
+    #[test]
+    fn a_swap_reports_its_execution_price_in_the_event_and_return_data() {
+        let mut env = setup();
+        let lp = env.trader(1_000_000, 1_000_000);
+        env.add_liquidity(lp, 1_000_000, 1_000_000).unwrap();
+        let trader = env.trader(10_000, 0);
+        take_events::<SwapExecuted>();
+        
+        env.swap(trader, true, 10_000, 0).unwrap();
+        let amount_out = env.token_balance(&env.token_account(trader, env.no_mint));
+        let [event] = take_events::<SwapExecuted>().try_into().ok().unwrap();
+        assert_eq!(event.amount_out, amount_out);
+        // YES paid per NO received: above $1 once fee and slippage are in
+        assert_eq!(event.execution_price_scaled, 10_000 * PRICE_PRECISION / amount_out);
+        assert!(event.execution_price_scaled > PRICE_PRECISION);
+        assert_eq!(take_return_data::<(u64, u64)>(), (amount_out, event.execution_price_scaled));
+    }
+
+    #[test]
+    fn swap_events_name_the_input_and_output_legs_in_both_directions() {
+        let mut env = setup();
+        let lp = env.trader(1_000_000, 1_000_000);
+        env.add_liquidity(lp, 1_000_000, 1_000_000).unwrap();
+        let trader = env.trader(10_000, 0);
+        let (trader_yes, trader_no) = (env.token_account(trader, env.yes_mint), env.token_account(trader, env.no_mint));
+        take_events::<SwapExecuted>();
+        
+        env.swap(trader, true, 10_000, 0).unwrap();
+        let no_out = env.token_balance(&trader_no);
+        env.swap(trader, false, no_out, 0).unwrap();
+        let yes_out = env.token_balance(&trader_yes);
+        
+        let [yes_in, no_in] = take_events::<SwapExecuted>().try_into().ok().unwrap();
+        assert!(yes_in.yes_to_no && !no_in.yes_to_no);
+        assert_eq!((yes_in.user, yes_in.pool_id), (trader, env.pool_id));
+        assert_eq!((yes_in.amount_in, yes_in.amount_out), (10_000, no_out));
+        assert_eq!((no_in.amount_in, no_in.amount_out), (no_out, yes_out));
+        // Fees come off the input leg, so the round trip loses value
+        assert!(yes_in.fee > 0 && no_in.fee > 0);
+        assert!(yes_out < 10_000);
+    }
+
+    #[test]
+    fn a_swap_that_would_leave_the_price_band_is_rejected() {
+        let mut env = uninitialized(token::ID);
+        env.initialize(400_000, 600_000, CurveType::ConstantProduct).unwrap();
+        let lp = env.trader(1_000_000, 1_000_000);
+        env.add_liquidity(lp, 1_000_000, 1_000_000).unwrap();
+        let trader = env.trader(300_000, 300_000);
+        
+        // Selling 300k YES would take its price to ~0.37, buying with 300k NO to ~0.63
+        assert_eq!(env.swap(trader, true, 300_000, 0), err(ErrorCode::PriceOutOfBand));
+        assert_eq!(env.swap(trader, false, 300_000, 0), err(ErrorCode::PriceOutOfBand));
+        assert_eq!((env.pool().yes_reserves, env.pool().no_reserves), (1_000_000, 1_000_000));
+        
+        // 150k moves it to ~0.43, still inside
+        env.swap(trader, true, 150_000, 0).unwrap();
+        let pool = env.pool();
+        let price = implied_yes_price(pool.yes_reserves, pool.no_reserves).unwrap();
+        assert!((400_000..500_000).contains(&price));
+    }
+
+    #[test]
+    fn the_protocol_cut_leaves_the_pool_and_the_rest_of_the_fee_stays_with_lps() {
+        let mut env = setup();
+        let lp = env.trader(1_000_000, 1_000_000);
+        env.add_liquidity(lp, 1_000_000, 1_000_000).unwrap();
+        let recipient = Pubkey::new_unique();
+        env.edit_pool(|pool| {
+            pool.protocol_fee_recipient = recipient;
+            pool.protocol_fee_bps = 10;
+        });
+        let trader = env.trader(100_000, 0);
+        take_events::<SwapExecuted>();
+        
+        env.swap(trader, true, 100_000, 0).unwrap();
+        
+        // 0.3% of the input is the fee; 0.1% of the input goes to the recipient
+        let [event] = take_events::<SwapExecuted>().try_into().ok().unwrap();
+        assert_eq!((event.fee, event.protocol_fee), (300, 100));
+        assert_eq!(env.token_balance(&env.token_account(recipient, env.yes_mint)), 100);
+        // The LP share stays in the pool, credited to the reserves it holds
+        let pool_yes = env.token_balance(&env.pool_account(b"yes_shares"));
+        assert_eq!(pool_yes, 1_000_000 + 100_000 - 100);
+        assert_eq!(env.pool().yes_reserves, pool_yes);
+        assert_eq!(env.pool().no_reserves, 1_000_000 - event.amount_out);
+    }
+
+    #[test]
+    fn removing_for_a_yes_amount_pays_the_proportional_no() {
+        let mut env = setup();
+        let lp = env.trader(1_000_000, 1_000_000);
+        env.add_liquidity(lp, 1_000_000, 1_000_000).unwrap();
+        let trader = env.trader(100_000, 0);
+        env.swap(trader, true, 100_000, 0).unwrap();
+        let before = env.pool();
+        let lp_before = env.token_balance(&env.lp_tokens(lp));
+        
+        let data = crate::instruction::RemoveLiquidityForAmount {
+            pool_id: env.pool_id,
+            yes_amount_desired: 110_000,
+            maximum_lp_in: u64::MAX,
+            minimum_no_out: 0,
+        }
+        .data();
+        env.remove_liquidity_instruction(lp, data).unwrap();
+        
+        let lp_burned = lp_before - env.token_balance(&env.lp_tokens(lp));
+        let yes_out = env.token_balance(&env.token_account(lp, env.yes_mint));
+        let no_out = env.token_balance(&env.token_account(lp, env.no_mint));
+        // Rounding the LP up never shorts the requested YES, and NO follows the same LP share
+        assert!(yes_out >= 110_000);
+        assert_eq!(yes_out, (lp_burned as u128 * before.yes_reserves as u128 / before.total_supply as u128) as u64);
+        assert_eq!(no_out, (lp_burned as u128 * before.no_reserves as u128 / before.total_supply as u128) as u64);
+        // The pool is YES-heavy after the trade, so less NO than YES comes out
+        assert!(no_out < yes_out);
+        assert_eq!(env.pool().no_reserves, before.no_reserves - no_out);
+    }
+
+    #[test]
+    fn a_position_keeps_its_deposit_snapshot_while_swaps_move_the_reserves() {
+        let mut env = setup();
+        let lp = env.trader(1_000_000, 500_000);
+        env.add_liquidity(lp, 1_000_000, 500_000).unwrap();
+        let minted = env.token_balance(&env.lp_tokens(lp));
+        let trader = env.trader(0, 100_000);
+        
+        set_now(START + 60);
+        env.swap(trader, false, 100_000, 0).unwrap();
+        let pool = env.pool();
+        assert!(pool.yes_reserves < 1_000_000 && pool.no_reserves > 500_000);
+        
+        let position = env.position(lp);
+        assert_eq!((position.owner, position.pool_id), (lp, env.pool_id));
+        assert_eq!((position.yes_at_deposit, position.no_at_deposit), (1_000_000, 500_000));
+        assert_eq!(position.lp_minted, minted);
+        assert_eq!(position.deposited_at, START);
+    }
+
+    #[test]
+    fn price_views_cap_at_the_edges_instead_of_failing_on_an_empty_side() {
+        let mut env = setup();
+        assert_eq!((env.price(true), env.price(false)), (0, 0));
+        
+        env.edit_pool(|pool| (pool.yes_reserves, pool.no_reserves) = (0, 100));
+        assert_eq!((env.price(true), env.price(false)), (0, PRICE_PRECISION));
+        
+        env.edit_pool(|pool| (pool.yes_reserves, pool.no_reserves) = (100, 0));
+        assert_eq!((env.price(true), env.price(false)), (PRICE_PRECISION, 0));
+        
+        // A lopsided pool caps at PRICE_PRECISION rather than quoting above $1
+        env.edit_pool(|pool| (pool.yes_reserves, pool.no_reserves) = (300, 100));
+        assert_eq!((env.price(true), env.price(false)), (PRICE_PRECISION, PRICE_PRECISION / 3));
+    }
+
+    #[test]
+    fn a_deactivated_pool_blocks_swaps_but_lets_lps_exit() {
+        let mut env = setup();
+        let lp = env.trader(1_000_000, 1_000_000);
+        env.add_liquidity(lp, 1_000_000, 1_000_000).unwrap();
+        let trader = env.trader(10_000, 10_000);
+        take_events::<PoolDeactivated>();
+        
+        set_now(START + 60);
+        env.deactivate().unwrap();
+        let [event] = take_events::<PoolDeactivated>().try_into().ok().unwrap();
+        assert_eq!((event.pool_id, event.timestamp), (env.pool_id, START + 60));
+        assert!(!env.pool().is_active);
+        assert_eq!(env.deactivate(), err(ErrorCode::PoolInactive));
+        
+        assert_eq!(env.swap(trader, true, 1_000, 0), err(ErrorCode::PoolInactive));
+        assert_eq!(env.swap(trader, false, 1_000, 0), err(ErrorCode::PoolInactive));
+        
+        let lp_tokens = env.token_balance(&env.lp_tokens(lp));
+        env.remove_liquidity(lp, lp_tokens, 0, 0).unwrap();
+        assert_eq!(env.token_balance(&env.lp_tokens(lp)), 0);
+        assert_eq!(env.token_balance(&env.token_account(lp, env.yes_mint)), 1_000_000);
+        assert_eq!(env.token_balance(&env.token_account(lp, env.no_mint)), 1_000_000);
+    }
+
+    #[test]
+    fn settling_after_resolution_pays_the_winning_side_in_sol() {
+        let mut env = setup();
+        let lp = env.trader(1_000_000, 1_000_000);
+        env.add_liquidity(lp, 1_000_000, 1_000_000).unwrap();
+        let trader = env.trader(0, 100_000);
+        env.swap(trader, false, 100_000, 0).unwrap();
+        env.deactivate().unwrap();
+        env.link_orderbook(Some(Outcome::Yes));
+        let before = env.pool();
+        let lp_tokens = env.token_balance(&env.lp_tokens(lp));
+        let lamports = env.bank.accounts[&lp].lamports;
+        take_events::<LiquiditySettled>();
+        
+        // The sole LP's YES redeems at $1 per share less the redemption fee, the NO is burned
+        let gross = before.yes_reserves * DOLLAR / 10u64.pow(SHARE_DECIMALS as u32);
+        let fee = gross * REDEMPTION_FEE_BPS as u64 / 10_000;
+        let payout = gross - fee;
+        assert_eq!(env.settle_and_remove_liquidity(lp, lp_tokens, payout + 1), err(ErrorCode::SlippageExceeded));
+        env.settle_and_remove_liquidity(lp, lp_tokens, payout).unwrap();
+        
+        assert_eq!(env.bank.accounts[&lp].lamports, lamports + payout);
+        assert_eq!(env.bank.accounts[&env.orderbook_vault()].lamports, 100 * SOL - gross);
+        assert_eq!(env.bank.accounts[&env.orderbook_fee_vault()].lamports, fee);
+        let [event] = take_events::<LiquiditySettled>().try_into().ok().unwrap();
+        assert_eq!(event.winning_shares_redeemed, before.yes_reserves);
+        assert_eq!(event.losing_shares_burned, before.no_reserves);
+        assert_eq!((event.lp_tokens_burned, event.payout_lamports), (lp_tokens, payout));
+        assert_eq!(env.token_balance(&env.pool_account(b"yes_shares")), 0);
+        assert_eq!(env.token_balance(&env.pool_account(b"no_shares")), 0);
+        assert_eq!((env.pool().yes_reserves, env.pool().no_reserves, env.pool().total_supply), (0, 0, 0));
+    }
+
+    #[test]
+    fn swap_at_market_fills_small_trades_and_blocks_ones_past_the_impact_cap() {
+        let mut env = setup();
+        let lp = env.trader(1_000_000, 1_000_000);
+        env.add_liquidity(lp, 1_000_000, 1_000_000).unwrap();
+        let trader = env.trader(200_000, 0);
+        assert_eq!(env.pool().max_price_impact_bps, DEFAULT_MAX_PRICE_IMPACT_BPS);
+        
+        // ~1% of the reserve moves the price ~1%, inside the 5% cap
+        env.swap_at_market(trader, Outcome::No, 10_000).unwrap();
+        let (amount_out, _) = take_return_data::<(u64, u64)>();
+        assert_eq!(env.token_balance(&env.token_account(trader, env.no_mint)), amount_out);
+        
+        // ~10% is blocked, though the same trade clears as a plain swap
+        assert_eq!(env.swap_at_market(trader, Outcome::No, 100_000), err(ErrorCode::PriceImpactTooHigh));
+        env.swap(trader, true, 100_000, 0).unwrap();
+    }
+
+    #[test]
+    fn a_pool_cannot_be_created_with_the_same_mint_on_both_sides() {
+        let mut env = uninitialized(token::ID);
+        env.no_mint = env.yes_mint;
+        
+        assert_eq!(env.initialize(0, 0, CurveType::ConstantProduct), err(ErrorCode::InvalidMint));
+        assert!(!env.bank.exists(&env.pool_key()));
+    }
+
+    #[test]
+    fn a_router_can_size_its_next_hop_from_the_swap_return_data() {
+        let mut env = setup();
+        let lp = env.trader(1_000_000, 1_000_000);
+        env.add_liquidity(lp, 1_000_000, 1_000_000).unwrap();
+        let router = env.trader(10_000, 0);
+        let router_no = env.token_account(router, env.no_mint);
+        
+        // A caller only relies on the first 8 bytes: amount_out, little-endian
+        env.swap(router, true, 10_000, 0).unwrap();
+        let data = take_return_data::<[u8; 16]>();
+        let no_out = u64::from_le_bytes(data[..8].try_into().unwrap());
+        assert_eq!(no_out, env.token_balance(&router_no));
+        
+        // Spending exactly what came back empties the account
+        env.swap(router, false, no_out, 0).unwrap();
+        let data = take_return_data::<[u8; 16]>();
+        let yes_out = u64::from_le_bytes(data[..8].try_into().unwrap());
+        assert_eq!(env.token_balance(&router_no), 0);
+        assert_eq!(env.token_balance(&env.token_account(router, env.yes_mint)), yes_out);
+    }
+
+    #[test]
+    fn a_concentrated_pool_fills_deeper_and_stops_at_its_range_edge() {
+        let pool_with = |curve_type| {
+            let mut env = uninitialized(token::ID);
+            env.initialize(400_000, 600_000, curve_type).unwrap();
+            let lp = env.trader(1_000_000, 1_000_000);
+            env.add_liquidity(lp, 1_000_000, 1_000_000).unwrap();
+            let trader = env.trader(10_000_000, 0);
+            (env, trader)
+        };
+        let no_out = |env: &mut Env, trader, yes_in| {
+            env.swap(trader, true, yes_in, 0).map(|()| take_return_data::<(u64, u64)>().0)
+        };
+        let (mut full_range, full_range_trader) = pool_with(CurveType::ConstantProduct);
+        let (mut concentrated, trader) = pool_with(CurveType::ConcentratedRange);
+        
+        // Same capital, same trade: the concentrated pool slips far less
+        let full_range_out = no_out(&mut full_range, full_range_trader, 50_000).unwrap();
+        let concentrated_out = no_out(&mut concentrated, trader, 50_000).unwrap();
+        assert!(full_range_out < 50_000 * 96 / 100);
+        assert!(concentrated_out > 50_000 * 98 / 100);
+        
+        // The real NO runs out at the band's lower edge, however much YES is offered
+        assert_eq!(concentrated.swap(trader, true, 3_000_000, 0), err(ErrorCode::InsufficientLiquidity));
+        // Short of it the trade fills and the price stays in the band
+        let pool = concentrated.pool();
+        no_out(&mut concentrated, trader, 900_000).unwrap();
+        let after = concentrated.pool();
+        assert!(after.no_reserves > 0 && after.no_reserves < pool.no_reserves / 4);
+        let (yes, no) = effective_reserves(&after).unwrap();
+        assert!(implied_yes_price(yes, no).unwrap() >= after.min_price);
+    }
+
+    #[test]
+    fn a_token_2022_pool_prices_and_books_what_survives_the_transfer_fee() {
+        let mut env = uninitialized(spl_token_2022::ID);
+        env.charge_transfer_fee(env.yes_mint, 100);
+        env.initialize(0, 0, CurveType::ConstantProduct).unwrap();
+        let lp = env.trader(1_000_000, 1_000_000);
+        let trader = env.trader(10_000, 10_000);
+        let trader_yes = env.token_account(trader, env.yes_mint);
+        let trader_no = env.token_account(trader, env.no_mint);
+        let balances = |env: &Env| {
+            let pool = env.pool();
+            assert_eq!(env.token_balance(&env.pool_account(b"yes_shares")), pool.yes_reserves);
+            assert_eq!(env.token_balance(&env.pool_account(b"no_shares")), pool.no_reserves);
+            (pool.yes_reserves, pool.no_reserves)
+        };
+        
+        // 1% of the YES is withheld on the way in
+        env.add_liquidity_interface(lp, 1_000_000, 1_000_000).unwrap();
+        assert_eq!(balances(&env), (990_000, 1_000_000));
+        
+        // Selling YES prices only the 9,900 that arrive
+        env.swap_interface(trader, Outcome::No, 10_000, 0).unwrap();
+        let no_out = get_amount_out(9_900, 990_000, 1_000_000, 30, 10_000).unwrap();
+        assert_eq!(take_return_data::<(u64, u64)>().0, no_out);
+        assert_eq!(env.token_balance(&trader_no), 10_000 + no_out);
+        assert_eq!(balances(&env), (990_000 + 9_900, 1_000_000 - no_out));
+        
+        // Buying YES delivers the curve output less the fee, and minimum_out is held to that
+        let yes_out = get_amount_out(10_000, 1_000_000 - no_out, 999_900, 30, 10_000).unwrap();
+        let delivered = yes_out - yes_out.div_ceil(100);
+        let slipped = env.swap_interface(trader, Outcome::Yes, 10_000, delivered + 1);
+        assert_eq!(slipped, err(ErrorCode::SlippageExceeded));
+        env.swap_interface(trader, Outcome::Yes, 10_000, delivered).unwrap();
+        assert_eq!(take_return_data::<(u64, u64)>().0, delivered);
+        assert_eq!(env.token_balance(&trader_yes), delivered);
+        assert_eq!(balances(&env), (999_900 - yes_out, 1_000_000 - no_out + 10_000));
+    }
+
+    #[test]
+    fn a_mint_with_other_decimals_fails_the_checked_transfer() {
+        let mut env = setup();
+        let lp = env.trader(1_000_000, 1_000_000);
+        env.add_liquidity(lp, 1_000_000, 1_000_000).unwrap();
+        let trader = env.trader(10_000, 0);
+        let impostor = Pubkey::new_unique();
+        env.create_mint(impostor, token::ID, 9);
+        let metas = crate::accounts::SwapYesForNo {
+            user: trader,
+            pool: env.pool_key(),
+            pool_yes_shares: env.pool_account(b"yes_shares"),
+            pool_no_shares: env.pool_account(b"no_shares"),
+            user_yes_shares: env.token_account(trader, env.yes_mint),
+            user_no_shares: env.token_account(trader, env.no_mint),
+            protocol_fee_account: env.fee_account(env.yes_mint),
+            yes_mint: impostor,
+            no_mint: env.no_mint,
+            token_program: token::ID,
+        }
+        .to_account_metas(None);
+        let data = crate::instruction::SwapYesForNo {
+            _pool_id: env.pool_id,
+            yes_amount_in: 10_000,
+            minimum_no_out: 0,
+        }
+        .data();
+        
+        // The account constraints catch the substitute first
+        let rejected = env.bank.process(metas.clone(), data, &[], trader);
+        assert_eq!(rejected, Err(Error::from(anchor_lang::error::ErrorCode::ConstraintTokenMint).into()));
+        
+        // Past the account constraints, transfer_checked itself refuses the substitute mint
+        let pool_id = env.pool_id;
+        let swapped = env.bank.transact(&metas, &[], trader, |infos| {
+            let mut accounts = SwapYesForNo {
+                user: Signer::try_from(&infos[0])?,
+                pool: Account::try_from(&infos[1])?,
+                pool_yes_shares: Box::new(Account::try_from(&infos[2])?),
+                pool_no_shares: Box::new(Account::try_from(&infos[3])?),
+                user_yes_shares: Box::new(Account::try_from(&infos[4])?),
+                user_no_shares: Box::new(Account::try_from(&infos[5])?),
+                protocol_fee_account: Box::new(Account::try_from(&infos[6])?),
+                yes_mint: Box::new(Account::try_from(&infos[7])?),
+                no_mint: Box::new(Account::try_from(&infos[8])?),
+                token_program: Program::try_from(&infos[9])?,
+            };
+            let bumps = SwapYesForNoBumps {
+                pool: Pubkey::find_program_address(&[b"pool", pool_id.as_ref()], &ID).1,
+                ..Default::default()
+            };
+            amm::swap_yes_for_no(Context::new(&ID, &mut accounts, &[], bumps), pool_id, 10_000, 0)?;
+            Ok(())
+        });
+        assert_eq!(swapped, Err(TokenError::MintMismatch.into()));
+        assert_eq!(env.token_balance(&env.token_account(trader, env.yes_mint)), 10_000);
+        assert_eq!(env.pool().yes_reserves, 1_000_000);
+    }
+
+    #[test]
+    fn reserves_match_the_pool_balances_from_init_through_the_first_deposit() {
+        let mut env = setup();
+        let pool = env.pool();
+        assert_eq!((pool.yes_reserves, pool.no_reserves, pool.k, pool.total_supply), (0, 0, 0, 0));
+        // No share accounts, so nothing a swap could price against
+        assert!(!env.bank.exists(&env.pool_account(b"yes_shares")));
+        assert!(!env.bank.exists(&env.pool_account(b"no_shares")));
+        
+        let lp = env.trader(1_000_000, 400_000);
+        env.add_liquidity(lp, 1_000_000, 400_000).unwrap();
+        let pool = env.pool();
+        assert_eq!(env.token_balance(&env.pool_account(b"yes_shares")), pool.yes_reserves);
+        assert_eq!(env.token_balance(&env.pool_account(b"no_shares")), pool.no_reserves);
+        assert_eq!((pool.yes_reserves, pool.no_reserves), (1_000_000, 400_000));
+        assert_eq!(pool.k, 1_000_000 * 400_000);
+    }
+
+    #[test]
+    fn reconcile_reserves_detects_tokens_sent_straight_to_the_pool() {
+        let mut env = setup();
+        let lp = env.trader(1_000_000, 1_000_000);
+        env.add_liquidity(lp, 1_000_000, 1_000_000).unwrap();
+        let trader = env.trader(10_000, 10_000);
+        env.swap(trader, true, 10_000, 0).unwrap();
+        env.reconcile_reserves().unwrap();
+        
+        // A plain transfer into the pool's account bypasses the reserve bookkeeping
+        let donor = env.trader(0, 500);
+        env.move_tokens(env.token_account(donor, env.no_mint), env.pool_account(b"no_shares"), 500);
+        assert_eq!(env.reconcile_reserves(), err(ErrorCode::ReserveMismatch));
+        assert_eq!(env.token_balance(&env.pool_account(b"no_shares")), env.pool().no_reserves + 500);
+    }
+
+    #[test]
+    fn skim_sweeps_donations_without_touching_reserves() {
+        let mut env = setup();
+        let lp = env.trader(1_000_000, 1_000_000);
+        env.add_liquidity(lp, 1_000_000, 1_000_000).unwrap();
+        let donor = env.trader(700, 300);
+        env.move_tokens(env.token_account(donor, env.yes_mint), env.pool_account(b"yes_shares"), 700);
+        env.move_tokens(env.token_account(donor, env.no_mint), env.pool_account(b"no_shares"), 300);
+        let before = env.pool();
+        let recipient = Pubkey::new_unique();
+        take_events::<ReservesSkimmed>();
+        
+        assert_eq!(env.skim(donor, recipient), err(ErrorCode::Unauthorized));
+        env.skim(env.authority, recipient).unwrap();
+        
+        assert_eq!(env.token_balance(&env.token_account(recipient, env.yes_mint)), 700);
+        assert_eq!(env.token_balance(&env.token_account(recipient, env.no_mint)), 300);
+        let [event] = take_events::<ReservesSkimmed>().try_into().ok().unwrap();
+        assert_eq!((event.yes_amount, event.no_amount), (700, 300));
+        env.reconcile_reserves().unwrap();
+        // Pricing never saw the donation
+        let after = env.pool();
+        assert_eq!((after.yes_reserves, after.no_reserves), (before.yes_reserves, before.no_reserves));
+        assert_eq!(after.k, before.k);
+    }
+
+    #[test]
+    fn deposit_minimums_protect_an_lp_when_a_swap_moves_the_ratio_first() {
+        let mut env = setup();
+        let lp = env.trader(1_000_000, 1_000_000);
+        env.add_liquidity(lp, 1_000_000, 1_000_000).unwrap();
+        let late_lp = env.trader(100_000, 100_000);
+        
+        // Signed at 1:1, but a YES sale lands first
+        let trader = env.trader(100_000, 0);
+        env.swap(trader, true, 100_000, 0).unwrap();
+        let pool = env.pool();
+        
+        // Matching 100k YES now only takes ~83k NO, below the 99k minimum
+        let guarded = env.add_liquidity_with(late_lp, 100_000, 100_000, 99_000, 99_000, 0);
+        assert_eq!(guarded, err(ErrorCode::SlippageExceeded));
+        assert_eq!(env.token_balance(&env.token_account(late_lp, env.no_mint)), 100_000);
+        
+        // Without the guard the deposit follows the new ratio and leaves the excess NO unspent
+        env.add_liquidity_with(late_lp, 100_000, 100_000, 0, 0, 0).unwrap();
+        let no_spent = 100_000 - env.token_balance(&env.token_account(late_lp, env.no_mint));
+        assert_eq!(no_spent, 100_000 * pool.no_reserves / pool.yes_reserves);
+        assert!(no_spent < 99_000);
+        assert_eq!(env.token_balance(&env.token_account(late_lp, env.yes_mint)), 0);
+    }
+
+    #[test]
+    fn buying_yes_with_sol_swaps_away_the_whole_no_leg() {
+        let mut env = setup();
+        let lp = env.trader(1_000_000, 1_000_000);
+        env.add_liquidity(lp, 1_000_000, 1_000_000).unwrap();
+        env.link_orderbook(None);
+        let buyer = env.trader(0, 0);
+        let lamports = env.bank.accounts[&buyer].lamports;
+        
+        // 0.1 SOL mints 0.1 sets; the 100k NO leg swaps into YES
+        let yes_out = 100_000 + get_amount_out(100_000, 1_000_000, 1_000_000, 30, 10_000).unwrap();
+        assert_eq!(env.buy_yes_with_sol(buyer, SOL / 10, yes_out + 1), err(ErrorCode::SlippageExceeded));
+        env.buy_yes_with_sol(buyer, SOL / 10, yes_out).unwrap();
+        
+        assert_eq!(take_return_data::<(u64, u64)>(), (yes_out, 100_000));
+        assert_eq!(env.token_balance(&env.token_account(buyer, env.yes_mint)), yes_out);
+        assert_eq!(env.token_balance(&env.token_account(buyer, env.no_mint)), 0);
+        assert_eq!(env.bank.accounts[&buyer].lamports, lamports - DOLLAR / 10);
+        assert_eq!(env.bank.accounts[&env.orderbook_vault()].lamports, 100 * SOL + DOLLAR / 10);
+    }
+
+    #[test]
+    fn an_lp_mint_past_u64_fails_cleanly() {
+        let mut env = setup();
+        let lp = env.trader(3_000_000, 3_000_000);
+        env.add_liquidity(lp, 1_000_000, 1_000_000).unwrap();
+        
+        // Doubling the reserves would mint the supply again, past u64::MAX
+        env.edit_pool(|pool| pool.total_supply = u64::MAX / 2 + 1);
+        assert_eq!(env.add_liquidity(lp, 2_000_000, 2_000_000), err(ErrorCode::MathOverflow));
+        let pool = env.pool();
+        assert_eq!((pool.yes_reserves, pool.total_supply), (1_000_000, u64::MAX / 2 + 1));
+        assert_eq!(env.token_balance(&env.token_account(lp, env.yes_mint)), 2_000_000);
+    }
 }
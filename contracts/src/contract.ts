@@ -53,6 +53,17 @@ export class MemeMarketContract {
     console.log('DEBUG: Program ID:', this.program.programId.toBase58());
   }
 
+  /**
+   * Derive program config PDA
+   * Debug: Holds the creation fee and treasury wallet
+   */
+  async getConfigPDA(): Promise<[PublicKey, number]> {
+    return PublicKey.findProgramAddressSync(
+      [Buffer.from('config')],
+      this.program.programId
+    );
+  }
+
   /**
    * Derive market PDA from seed
    * Debug: Markets are identified by a unique seed string
@@ -66,11 +77,20 @@ export class MemeMarketContract {
 
   /**
    * Derive user bet PDA for a specific market
-   * Debug: Each user has one bet account per market
+   * Debug: Each bet index gets its own account, encoded as a little-endian u64
    */
-  async getUserBetPDA(marketPubkey: PublicKey, userPubkey: PublicKey): Promise<[PublicKey, number]> {
+  async getUserBetPDA(
+    marketPubkey: PublicKey,
+    userPubkey: PublicKey,
+    betIndex: number = 0
+  ): Promise<[PublicKey, number]> {
     return PublicKey.findProgramAddressSync(
-      [Buffer.from('user_bet'), marketPubkey.toBuffer(), userPubkey.toBuffer()],
+      [
+        Buffer.from('user_bet'),
+        marketPubkey.toBuffer(),
+        userPubkey.toBuffer(),
+        new BN(betIndex).toArrayLike(Buffer, 'le', 8),
+      ],
       this.program.programId
     );
   }
@@ -86,15 +106,118 @@ export class MemeMarketContract {
     );
   }
 
+  /**
+   * Derive creation-fee escrow PDA for a market
+   * Debug: Holds the creation fee until refunded to the creator or collected to treasury
+   */
+  async getFeeEscrowPDA(marketPubkey: PublicKey): Promise<[PublicKey, number]> {
+    return PublicKey.findProgramAddressSync(
+      [Buffer.from('fee_escrow'), marketPubkey.toBuffer()],
+      this.program.programId
+    );
+  }
+
+  /**
+   * Derive market registry entry PDA for a creation index
+   * Debug: Index is encoded as a little-endian u64
+   */
+  async getRegistryEntryPDA(index: number): Promise<[PublicKey, number]> {
+    return PublicKey.findProgramAddressSync(
+      [Buffer.from('registry'), new BN(index).toArrayLike(Buffer, 'le', 8)],
+      this.program.programId
+    );
+  }
+
+  /**
+   * Derive categorical market PDA from seed
+   * Debug: Separate namespace from binary markets
+   */
+  async getCategoricalMarketPDA(marketSeed: string): Promise<[PublicKey, number]> {
+    return PublicKey.findProgramAddressSync(
+      [Buffer.from('categorical_market'), Buffer.from(marketSeed)],
+      this.program.programId
+    );
+  }
+
+  /**
+   * Derive categorical bet PDA for a specific market
+   * Debug: Each user has one bet account per categorical market
+   */
+  async getCategoricalBetPDA(marketPubkey: PublicKey, userPubkey: PublicKey): Promise<[PublicKey, number]> {
+    return PublicKey.findProgramAddressSync(
+      [Buffer.from('categorical_bet'), marketPubkey.toBuffer(), userPubkey.toBuffer()],
+      this.program.programId
+    );
+  }
+
+  /**
+   * Pause or unpause new markets and bets across the protocol (config authority only)
+   * Debug: Claims and refunds keep working while paused
+   * @param paused - true to block new markets and bets
+   */
+  async setPaused(paused: boolean): Promise<string> {
+    console.log('DEBUG: Setting protocol paused:', paused);
+    
+    try {
+      const [configPDA] = await this.getConfigPDA();
+      
+      const tx = await this.program.methods
+        .parimutuelSetPaused(paused)
+        .accounts({
+          config: configPDA,
+          authority: this.wallet.publicKey,
+        })
+        .rpc();
+
+      console.log('DEBUG: Paused flag updated with transaction:', tx);
+      return tx;
+    } catch (error) {
+      console.error('DEBUG: Error setting paused flag:', error);
+      throw error;
+    }
+  }
+
+  /**
+   * Reject bets once a heartbeat records the market cap at or above target (config authority only)
+   * Debug: Anti-sniping - stops losing-side bets while resolution is still pending
+   * @param enabled - true to block bets after the target is hit
+   */
+  async setBlockBetsAfterTarget(enabled: boolean): Promise<string> {
+    console.log('DEBUG: Setting block bets after target:', enabled);
+    
+    try {
+      const [configPDA] = await this.getConfigPDA();
+      
+      const tx = await this.program.methods
+        .parimutuelSetBlockBetsAfterTarget(enabled)
+        .accounts({
+          config: configPDA,
+          authority: this.wallet.publicKey,
+        })
+        .rpc();
+
+      console.log('DEBUG: Anti-sniping flag updated with transaction:', tx);
+      return tx;
+    } catch (error) {
+      console.error('DEBUG: Error setting anti-sniping flag:', error);
+      throw error;
+    }
+  }
+
   /**
    * Initialize a new parimutuel market (permissionless)
-   * Debug: Charges 0.015 SOL fee to treasury wallet
+   * Debug: Holds the 0.015 SOL fee in a refundable fee escrow
    * @param marketSeed - Unique seed string for the market PDA
    * @param oracleAuthority - Oracle pubkey that can resolve the market
    * @param tokenMint - Token to track market cap for
    * @param targetMarketCap - Target market cap in USD with 6 decimals (e.g., 1_000_000_000000 = $1M)
    * @param deadline - Unix timestamp when betting closes
-   * @param treasuryWallet - Wallet to receive the 0.015 SOL creation fee
+   * @param maxOracleSkewSecs - Allowed oracle timestamp skew (0 = program default of 300s)
+   * @param graceSecs - Seconds before the deadline at which betting closes
+   * @param claimWindowSecs - Seconds winners have to claim after resolution (0 = no deadline)
+   * @param title - Human-readable market title
+   * @param metadataUri - Off-chain JSON metadata URI
+   * @param deadlineWinsOnTie - Only count a target hit reported strictly before the deadline
    */
   async initializeMarket(
     marketSeed: string,
@@ -102,15 +225,26 @@ export class MemeMarketContract {
     tokenMint: PublicKey,
     targetMarketCap: number,
     deadline: number,
-    treasuryWallet: PublicKey = TREASURY_WALLET
+    maxOracleSkewSecs: number = 0,
+    graceSecs: number = 0,
+    claimWindowSecs: number = 0,
+    title: string = '',
+    metadataUri: string = '',
+    deadlineWinsOnTie: boolean = false
   ): Promise<string> {
     console.log('DEBUG: Initializing market with seed:', marketSeed);
+    console.log('DEBUG: Title:', title);
     console.log('DEBUG: Target market cap:', targetMarketCap);
     console.log('DEBUG: Deadline:', new Date(deadline * 1000).toISOString());
-    console.log('DEBUG: Treasury will receive 0.015 SOL fee');
+    console.log('DEBUG: 0.015 SOL fee held in escrow until the market sees a bet');
     
     try {
+      const [configPDA] = await this.getConfigPDA();
+      const config = await this.program.account.config.fetch(configPDA);
       const [marketPDA] = await this.getMarketPDA(marketSeed);
+      const [escrowPDA] = await this.getEscrowPDA(marketPDA);
+      const [feeEscrowPDA] = await this.getFeeEscrowPDA(marketPDA);
+      const [registryEntryPDA] = await this.getRegistryEntryPDA(Number(config.marketCount));
       console.log('DEBUG: Market PDA:', marketPDA.toBase58());
       
       const tx = await this.program.methods
@@ -119,11 +253,20 @@ export class MemeMarketContract {
           oracleAuthority,
           tokenMint,
           new BN(targetMarketCap),
-          new BN(deadline)
+          new BN(deadline),
+          new BN(maxOracleSkewSecs),
+          new BN(graceSecs),
+          new BN(claimWindowSecs),
+          title,
+          metadataUri,
+          deadlineWinsOnTie ? { deadlineWinsOnTie: {} } : { targetWinsOnTie: {} }
         )
         .accounts({
+          config: configPDA,
           market: marketPDA,
-          treasury: treasuryWallet,
+          escrow: escrowPDA,
+          feeEscrow: feeEscrowPDA,
+          registryEntry: registryEntryPDA,
           creator: this.wallet.publicKey,
           systemProgram: SystemProgram.programId,
         })
@@ -143,31 +286,37 @@ export class MemeMarketContract {
    * @param marketSeed - Market seed string
    * @param amount - Amount in lamports to bet
    * @param side - true = YES, false = NO
+   * @param betIndex - Unused index for this bet; each index is claimed independently
    */
   async placeBet(
     marketSeed: string,
     amount: number,
-    side: boolean
+    side: boolean,
+    betIndex: number = 0
   ): Promise<string> {
     console.log('DEBUG: Placing bet on', side ? 'YES' : 'NO');
     console.log('DEBUG: Amount:', amount / LAMPORTS_PER_SOL, 'SOL');
     
     try {
       const [marketPDA] = await this.getMarketPDA(marketSeed);
-      const [userBetPDA] = await this.getUserBetPDA(marketPDA, this.wallet.publicKey);
+      const [userBetPDA] = await this.getUserBetPDA(marketPDA, this.wallet.publicKey, betIndex);
       const [escrowPDA] = await this.getEscrowPDA(marketPDA);
       
       console.log('DEBUG: Market PDA:', marketPDA.toBase58());
       console.log('DEBUG: User bet PDA:', userBetPDA.toBase58());
       console.log('DEBUG: Escrow PDA:', escrowPDA.toBase58());
       
+      const [configPDA] = await this.getConfigPDA();
+      
       const tx = await this.program.methods
         .parimutuelPlaceBet(
           marketSeed,
+          new BN(betIndex),
           new BN(amount),
           side
         )
         .accounts({
+          config: configPDA,
           market: marketPDA,
           userBet: userBetPDA,
           escrow: escrowPDA,
@@ -190,11 +339,13 @@ export class MemeMarketContract {
    * @param marketSeed - Market seed string
    * @param currentMarketCap - Current market cap from oracle in USD with 6 decimals
    * @param timestamp - Oracle timestamp for verification
+   * @param push - Declare a push: every bettor is refunded their principal
    */
   async resolveMarket(
     marketSeed: string,
     currentMarketCap: number,
-    timestamp: number
+    timestamp: number,
+    push: boolean = false
   ): Promise<string> {
     console.log('DEBUG: Resolving market with seed:', marketSeed);
     console.log('DEBUG: Current market cap:', currentMarketCap);
@@ -207,7 +358,8 @@ export class MemeMarketContract {
         .parimutuelResolveMarket(
           marketSeed,
           new BN(currentMarketCap),
-          new BN(timestamp)
+          new BN(timestamp),
+          push ? { push: {} } : null
         )
         .accounts({
           market: marketPDA,
@@ -223,21 +375,108 @@ export class MemeMarketContract {
     }
   }
 
+  /**
+   * Force-resolve a market to NO once the oracle has missed the deadline by the grace period
+   * Debug: Permissionless - callable by any wallet after deadline + 7 days
+   * @param marketSeed - Market seed string
+   */
+  async forceResolveDeadline(marketSeed: string): Promise<string> {
+    console.log('DEBUG: Force-resolving market with seed:', marketSeed);
+    
+    try {
+      const [marketPDA] = await this.getMarketPDA(marketSeed);
+      
+      const tx = await this.program.methods
+        .parimutuelForceResolveDeadline(marketSeed)
+        .accounts({
+          market: marketPDA,
+          caller: this.wallet.publicKey,
+        })
+        .rpc();
+
+      console.log('DEBUG: Market force-resolved with transaction:', tx);
+      return tx;
+    } catch (error) {
+      console.error('DEBUG: Error force-resolving market:', error);
+      throw error;
+    }
+  }
+
+  /**
+   * Rotate the oracle authority of an unresolved market
+   * Debug: Wallet must be the market creator or the config authority
+   * @param marketSeed - Market seed string
+   * @param newOracle - Oracle allowed to resolve the market from now on
+   */
+  async setOracleAuthority(marketSeed: string, newOracle: PublicKey): Promise<string> {
+    console.log('DEBUG: Setting oracle authority to:', newOracle.toString());
+    
+    try {
+      const [configPDA] = await this.getConfigPDA();
+      const [marketPDA] = await this.getMarketPDA(marketSeed);
+      
+      const tx = await this.program.methods
+        .parimutuelSetOracleAuthority(marketSeed, newOracle)
+        .accounts({
+          config: configPDA,
+          market: marketPDA,
+          authority: this.wallet.publicKey,
+        })
+        .rpc();
+
+      console.log('DEBUG: Oracle authority changed with transaction:', tx);
+      return tx;
+    } catch (error) {
+      console.error('DEBUG: Error setting oracle authority:', error);
+      throw error;
+    }
+  }
+
+  /**
+   * Resolve a market from its designated Switchboard aggregator
+   * Debug: Permissionless - the feed must match market.switchboard_feed
+   * @param marketSeed - Market seed string
+   * @param feed - Switchboard aggregator account
+   */
+  async resolveMarketFromSwitchboard(marketSeed: string, feed: PublicKey): Promise<string> {
+    console.log('DEBUG: Resolving market from Switchboard feed:', feed.toString());
+    
+    try {
+      const [marketPDA] = await this.getMarketPDA(marketSeed);
+      
+      const tx = await this.program.methods
+        .parimutuelResolveMarketFromSwitchboard(marketSeed)
+        .accounts({
+          market: marketPDA,
+          feed,
+          caller: this.wallet.publicKey,
+        })
+        .rpc();
+
+      console.log('DEBUG: Market resolved from Switchboard with transaction:', tx);
+      return tx;
+    } catch (error) {
+      console.error('DEBUG: Error resolving market from Switchboard:', error);
+      throw error;
+    }
+  }
+
   /**
    * Claim reward after market resolution
    * Debug: Calculates proportional payout from total pool
    * @param marketSeed - Market seed string
+   * @param betIndex - Index the bet was placed at
    */
-  async claimReward(marketSeed: string): Promise<string> {
-    console.log('DEBUG: Claiming reward for market:', marketSeed);
+  async claimReward(marketSeed: string, betIndex: number = 0): Promise<string> {
+    console.log('DEBUG: Claiming reward for market:', marketSeed, 'bet index:', betIndex);
     
     try {
       const [marketPDA] = await this.getMarketPDA(marketSeed);
-      const [userBetPDA] = await this.getUserBetPDA(marketPDA, this.wallet.publicKey);
+      const [userBetPDA] = await this.getUserBetPDA(marketPDA, this.wallet.publicKey, betIndex);
       const [escrowPDA] = await this.getEscrowPDA(marketPDA);
       
       const tx = await this.program.methods
-        .parimutuelClaimReward(marketSeed)
+        .parimutuelClaimReward(marketSeed, new BN(betIndex))
         .accounts({
           market: marketPDA,
           userBet: userBetPDA,
@@ -255,6 +494,304 @@ export class MemeMarketContract {
     }
   }
 
+  /**
+   * Claim a resolved bet and re-bet the payout into another market in one transaction
+   * Debug: Fails without claiming if the target market no longer accepts bets
+   * @param sourceMarketSeed - Seed of the resolved market to claim from
+   * @param targetMarketSeed - Seed of the open market to bet into
+   * @param side - true = YES, false = NO
+   * @param amount - Lamports to reinvest; omit to reinvest the full payout
+   * @param sourceBetIndex - Index of the resolved bet being claimed
+   * @param targetBetIndex - Unused index for the new bet
+   */
+  async claimAndBet(
+    sourceMarketSeed: string,
+    targetMarketSeed: string,
+    side: boolean,
+    amount?: number,
+    sourceBetIndex: number = 0,
+    targetBetIndex: number = 0
+  ): Promise<string> {
+    console.log('DEBUG: Reinvesting winnings from', sourceMarketSeed, 'into', targetMarketSeed);
+
+    try {
+      const [configPDA] = await this.getConfigPDA();
+      const [sourceMarketPDA] = await this.getMarketPDA(sourceMarketSeed);
+      const [sourceUserBetPDA] = await this.getUserBetPDA(
+        sourceMarketPDA,
+        this.wallet.publicKey,
+        sourceBetIndex
+      );
+      const [sourceEscrowPDA] = await this.getEscrowPDA(sourceMarketPDA);
+      const [targetMarketPDA] = await this.getMarketPDA(targetMarketSeed);
+      const [targetUserBetPDA] = await this.getUserBetPDA(
+        targetMarketPDA,
+        this.wallet.publicKey,
+        targetBetIndex
+      );
+      const [targetEscrowPDA] = await this.getEscrowPDA(targetMarketPDA);
+
+      const tx = await this.program.methods
+        .parimutuelClaimAndBet(
+          sourceMarketSeed,
+          targetMarketSeed,
+          new BN(sourceBetIndex),
+          new BN(targetBetIndex),
+          amount === undefined ? null : new BN(amount),
+          side
+        )
+        .accounts({
+          config: configPDA,
+          sourceMarket: sourceMarketPDA,
+          sourceUserBet: sourceUserBetPDA,
+          sourceEscrow: sourceEscrowPDA,
+          targetMarket: targetMarketPDA,
+          targetUserBet: targetUserBetPDA,
+          targetEscrow: targetEscrowPDA,
+          user: this.wallet.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .rpc();
+
+      console.log('DEBUG: Winnings reinvested with transaction:', tx);
+      return tx;
+    } catch (error) {
+      console.error('DEBUG: Error reinvesting winnings:', error);
+      throw error;
+    }
+  }
+
+  /**
+   * Close a losing bet after resolution
+   * Debug: Returns the UserBet account rent to the wallet
+   * @param marketSeed - Market seed string
+   * @param betIndex - Index the bet was placed at
+   */
+  async closeLosingBet(marketSeed: string, betIndex: number = 0): Promise<string> {
+    console.log('DEBUG: Closing losing bet for market:', marketSeed);
+    
+    try {
+      const [marketPDA] = await this.getMarketPDA(marketSeed);
+      const [userBetPDA] = await this.getUserBetPDA(marketPDA, this.wallet.publicKey, betIndex);
+      
+      const tx = await this.program.methods
+        .parimutuelCloseLosingBet(marketSeed, new BN(betIndex))
+        .accounts({
+          market: marketPDA,
+          userBet: userBetPDA,
+          user: this.wallet.publicKey,
+        })
+        .rpc();
+
+      console.log('DEBUG: Losing bet closed with transaction:', tx);
+      return tx;
+    } catch (error) {
+      console.error('DEBUG: Error closing losing bet:', error);
+      throw error;
+    }
+  }
+
+  /**
+   * Initialize a categorical (N-outcome) market (permissionless)
+   * Debug: The creation fee is paid straight to the configured treasury
+   * @param marketSeed - Unique seed string for the market PDA
+   * @param oracleAuthority - Oracle pubkey that picks the winning outcome
+   * @param outcomeCount - Number of outcomes (2-8)
+   * @param deadline - Unix timestamp deadline for resolution
+   * @param graceSecs - Seconds before the deadline at which betting closes
+   * @param title - Human-readable market title
+   */
+  async initializeCategoricalMarket(
+    marketSeed: string,
+    oracleAuthority: PublicKey,
+    outcomeCount: number,
+    deadline: number,
+    graceSecs: number = 0,
+    title: string = ''
+  ): Promise<string> {
+    console.log('DEBUG: Initializing categorical market with seed:', marketSeed);
+    console.log('DEBUG: Outcomes:', outcomeCount);
+    
+    try {
+      const [configPDA] = await this.getConfigPDA();
+      const config = await this.program.account.config.fetch(configPDA);
+      const [marketPDA] = await this.getCategoricalMarketPDA(marketSeed);
+      const [escrowPDA] = await this.getEscrowPDA(marketPDA);
+      
+      const tx = await this.program.methods
+        .parimutuelInitializeCategoricalMarket(
+          marketSeed,
+          oracleAuthority,
+          outcomeCount,
+          new BN(deadline),
+          new BN(graceSecs),
+          title
+        )
+        .accounts({
+          config: configPDA,
+          market: marketPDA,
+          escrow: escrowPDA,
+          treasury: config.treasury,
+          creator: this.wallet.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .rpc();
+
+      console.log('DEBUG: Categorical market initialized with transaction:', tx);
+      return tx;
+    } catch (error) {
+      console.error('DEBUG: Error initializing categorical market:', error);
+      throw error;
+    }
+  }
+
+  /**
+   * Bet on one outcome of a categorical market
+   * @param marketSeed - Market seed string
+   * @param amount - Amount in lamports to bet
+   * @param outcomeIndex - Outcome to back (0-based)
+   */
+  async placeCategoricalBet(marketSeed: string, amount: number, outcomeIndex: number): Promise<string> {
+    console.log('DEBUG: Placing categorical bet on outcome', outcomeIndex);
+    
+    try {
+      const [configPDA] = await this.getConfigPDA();
+      const [marketPDA] = await this.getCategoricalMarketPDA(marketSeed);
+      const [userBetPDA] = await this.getCategoricalBetPDA(marketPDA, this.wallet.publicKey);
+      const [escrowPDA] = await this.getEscrowPDA(marketPDA);
+      
+      const tx = await this.program.methods
+        .parimutuelPlaceCategoricalBet(marketSeed, new BN(amount), outcomeIndex)
+        .accounts({
+          config: configPDA,
+          market: marketPDA,
+          userBet: userBetPDA,
+          escrow: escrowPDA,
+          user: this.wallet.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .rpc();
+
+      console.log('DEBUG: Categorical bet placed with transaction:', tx);
+      return tx;
+    } catch (error) {
+      console.error('DEBUG: Error placing categorical bet:', error);
+      throw error;
+    }
+  }
+
+  /**
+   * Resolve a categorical market (oracle only)
+   * @param marketSeed - Market seed string
+   * @param winningOutcome - Index of the winning outcome
+   */
+  async resolveCategoricalMarket(marketSeed: string, winningOutcome: number): Promise<string> {
+    console.log('DEBUG: Resolving categorical market to outcome', winningOutcome);
+    
+    try {
+      const [marketPDA] = await this.getCategoricalMarketPDA(marketSeed);
+      
+      const tx = await this.program.methods
+        .parimutuelResolveCategoricalMarket(marketSeed, winningOutcome)
+        .accounts({
+          market: marketPDA,
+          oracle: this.wallet.publicKey,
+        })
+        .rpc();
+
+      console.log('DEBUG: Categorical market resolved with transaction:', tx);
+      return tx;
+    } catch (error) {
+      console.error('DEBUG: Error resolving categorical market:', error);
+      throw error;
+    }
+  }
+
+  /**
+   * Claim a categorical bet after resolution
+   * Debug: Losing bets are closed with no payout, returning their rent
+   * @param marketSeed - Market seed string
+   */
+  async claimCategoricalReward(marketSeed: string): Promise<string> {
+    console.log('DEBUG: Claiming categorical reward for market:', marketSeed);
+    
+    try {
+      const [marketPDA] = await this.getCategoricalMarketPDA(marketSeed);
+      const [userBetPDA] = await this.getCategoricalBetPDA(marketPDA, this.wallet.publicKey);
+      const [escrowPDA] = await this.getEscrowPDA(marketPDA);
+      
+      const tx = await this.program.methods
+        .parimutuelClaimCategoricalReward(marketSeed)
+        .accounts({
+          market: marketPDA,
+          userBet: userBetPDA,
+          escrow: escrowPDA,
+          user: this.wallet.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .rpc();
+
+      console.log('DEBUG: Categorical reward claimed with transaction:', tx);
+      return tx;
+    } catch (error) {
+      console.error('DEBUG: Error claiming categorical reward:', error);
+      throw error;
+    }
+  }
+
+  /**
+   * Void a categorical market once the oracle has missed the deadline by the grace period
+   * Debug: Permissionless - callable by any wallet after deadline + 7 days; every bettor
+   * then reclaims their principal through claimCategoricalReward
+   * @param marketSeed - Market seed string
+   */
+  async forceVoidCategoricalMarket(marketSeed: string): Promise<string> {
+    console.log('DEBUG: Force-voiding categorical market with seed:', marketSeed);
+    
+    try {
+      const [marketPDA] = await this.getCategoricalMarketPDA(marketSeed);
+      
+      const tx = await this.program.methods
+        .parimutuelForceVoidCategoricalMarket(marketSeed)
+        .accounts({
+          market: marketPDA,
+          caller: this.wallet.publicKey,
+        })
+        .rpc();
+
+      console.log('DEBUG: Categorical market voided with transaction:', tx);
+      return tx;
+    } catch (error) {
+      console.error('DEBUG: Error force-voiding categorical market:', error);
+      throw error;
+    }
+  }
+
+  /**
+   * List every market in creation order from the on-chain registry
+   * Debug: Walks registry entries 0..config.marketCount - no getProgramAccounts scan
+   */
+  async listMarkets(): Promise<PublicKey[]> {
+    try {
+      const [configPDA] = await this.getConfigPDA();
+      const config = await this.program.account.config.fetch(configPDA);
+      const count = Number(config.marketCount);
+      
+      const entryPDAs = await Promise.all(
+        Array.from({ length: count }, async (_, index) => (await this.getRegistryEntryPDA(index))[0])
+      );
+      const entries = await this.program.account.marketRegistryEntry.fetchMultiple(entryPDAs);
+      
+      console.log('DEBUG: Registry lists', count, 'markets');
+      return entries
+        .filter((entry): entry is NonNullable<typeof entry> => entry !== null)
+        .map((entry) => entry.market);
+    } catch (error) {
+      console.error('DEBUG: Error listing markets:', error);
+      throw error;
+    }
+  }
+
   /**
    * Get market information
    * Debug: Fetches on-chain market account data
@@ -268,6 +805,7 @@ export class MemeMarketContract {
       console.log('DEBUG: Total YES pool:', Number(marketAccount.totalYesPool) / LAMPORTS_PER_SOL, 'SOL');
       console.log('DEBUG: Total NO pool:', Number(marketAccount.totalNoPool) / LAMPORTS_PER_SOL, 'SOL');
       console.log('DEBUG: Is resolved:', marketAccount.isResolved);
+      console.log('DEBUG: Created at:', new Date(Number(marketAccount.createdAt) * 1000).toISOString());
       
       return {
         creator: marketAccount.creator,
@@ -292,11 +830,11 @@ export class MemeMarketContract {
    * Get user's bet info for a specific market
    * Debug: Returns user's bet amount, side, and claim status
    */
-  async getUserBetInfo(marketSeed: string, userPubkey?: PublicKey) {
+  async getUserBetInfo(marketSeed: string, userPubkey?: PublicKey, betIndex: number = 0) {
     try {
       const user = userPubkey || this.wallet.publicKey;
       const [marketPDA] = await this.getMarketPDA(marketSeed);
-      const [userBetPDA] = await this.getUserBetPDA(marketPDA, user);
+      const [userBetPDA] = await this.getUserBetPDA(marketPDA, user, betIndex);
       
       const userBetAccount = await this.program.account.userBet.fetch(userBetPDA);
       
@@ -311,6 +849,7 @@ export class MemeMarketContract {
         amount: Number(userBetAccount.amount),
         side: userBetAccount.side,
         claimed: userBetAccount.claimed,
+        betIndex: Number(userBetAccount.betIndex),
       };
     } catch (error) {
       console.error('DEBUG: Error fetching user bet info:', error);
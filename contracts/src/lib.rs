@@ -8,8 +8,51 @@ declare_id!("5rw87nNYE9Ep7zv6Yv8adK86fP8MSSYRAMq276C8Dbkq");
 pub mod mememarket {
     use super::*;
 
+    /// Create the program config (fee + treasury)
+    pub fn parimutuel_initialize_config(
+        ctx: Context<parimutuel::InitializeConfig>,
+        treasury: Pubkey,
+        creation_fee_lamports: u64,
+    ) -> Result<()> {
+        parimutuel::initialize_config(ctx, treasury, creation_fee_lamports)
+    }
+
+    /// Update the allowed market duration window (config authority only)
+    pub fn parimutuel_set_market_duration_bounds(
+        ctx: Context<parimutuel::UpdateConfig>,
+        min_market_duration_secs: i64,
+        max_market_duration_secs: i64,
+    ) -> Result<()> {
+        parimutuel::set_market_duration_bounds(ctx, min_market_duration_secs, max_market_duration_secs)
+    }
+
+    /// Update the market creation fee (config authority only)
+    pub fn parimutuel_set_creation_fee(
+        ctx: Context<parimutuel::UpdateConfig>,
+        creation_fee_lamports: u64,
+    ) -> Result<()> {
+        parimutuel::set_creation_fee(ctx, creation_fee_lamports)
+    }
+
+    /// Pause or unpause new markets and bets (config authority only)
+    pub fn parimutuel_set_paused(
+        ctx: Context<parimutuel::UpdateConfig>,
+        paused: bool,
+    ) -> Result<()> {
+        parimutuel::set_paused(ctx, paused)
+    }
+
+    /// Block bets once the recorded market cap hits the target (config authority only)
+    pub fn parimutuel_set_block_bets_after_target(
+        ctx: Context<parimutuel::UpdateConfig>,
+        enabled: bool,
+    ) -> Result<()> {
+        parimutuel::set_block_bets_after_target(ctx, enabled)
+    }
+
     /// Initialize a new parimutuel market
-    /// Charges 0.015 SOL creation fee to treasury
+    /// Holds the configured creation fee in a refundable fee escrow
+    #[allow(clippy::too_many_arguments)]
     pub fn parimutuel_initialize_market(
         ctx: Context<parimutuel::InitializeMarket>,
         market_seed: String,
@@ -17,18 +60,38 @@ pub mod mememarket {
         token_mint: Pubkey,
         target_market_cap: u64,
         deadline: i64,
+        max_oracle_skew_secs: i64,
+        grace_secs: i64,
+        claim_window_secs: i64,
+        title: String,
+        metadata_uri: String,
+        resolution_policy: parimutuel::ResolutionPolicy,
     ) -> Result<()> {
-        parimutuel::initialize_market(ctx, market_seed, oracle_authority, token_mint, target_market_cap, deadline)
+        parimutuel::initialize_market(
+            ctx,
+            market_seed,
+            oracle_authority,
+            token_mint,
+            target_market_cap,
+            deadline,
+            max_oracle_skew_secs,
+            grace_secs,
+            claim_window_secs,
+            title,
+            metadata_uri,
+            resolution_policy,
+        )
     }
 
     /// Place a bet on YES or NO
     pub fn parimutuel_place_bet(
         ctx: Context<parimutuel::PlaceBet>,
         market_seed: String,
+        bet_index: u64,
         amount: u64,
         side: bool,
     ) -> Result<()> {
-        parimutuel::place_bet(ctx, market_seed, amount, side)
+        parimutuel::place_bet(ctx, market_seed, bet_index, amount, side)
     }
 
     /// Resolve market (oracle only)
@@ -37,15 +100,229 @@ pub mod mememarket {
         market_seed: String,
         current_market_cap: u64,
         timestamp: i64,
+        resolution: Option<parimutuel::Resolution>,
+    ) -> Result<()> {
+        parimutuel::resolve_market(ctx, market_seed, current_market_cap, timestamp, resolution)
+    }
+
+    /// Force-resolve NO once the oracle misses the deadline + grace (anyone)
+    pub fn parimutuel_force_resolve_deadline(
+        ctx: Context<parimutuel::ForceResolveDeadline>,
+        market_seed: String,
+    ) -> Result<()> {
+        parimutuel::force_resolve_deadline(ctx, market_seed)
+    }
+
+    /// Designate the Switchboard feed a market may resolve from (oracle only)
+    pub fn parimutuel_set_switchboard_feed(
+        ctx: Context<parimutuel::SetSwitchboardFeed>,
+        market_seed: String,
+        feed: Pubkey,
+    ) -> Result<()> {
+        parimutuel::set_switchboard_feed(ctx, market_seed, feed)
+    }
+
+    /// Rotate a market's oracle authority before resolution (creator or config authority)
+    pub fn parimutuel_set_oracle_authority(
+        ctx: Context<parimutuel::SetOracleAuthority>,
+        market_seed: String,
+        new_oracle: Pubkey,
+    ) -> Result<()> {
+        parimutuel::set_oracle_authority(ctx, market_seed, new_oracle)
+    }
+
+    /// Resolve a market from its Switchboard aggregator (anyone)
+    pub fn parimutuel_resolve_market_from_switchboard(
+        ctx: Context<parimutuel::ResolveFromSwitchboard>,
+        market_seed: String,
+    ) -> Result<()> {
+        parimutuel::resolve_market_from_switchboard(ctx, market_seed)
+    }
+
+    /// Sweep rewards unclaimed past the claim deadline to treasury (anyone)
+    pub fn parimutuel_sweep_expired_claims(
+        ctx: Context<parimutuel::SweepExpiredClaims>,
+        market_seed: String,
+    ) -> Result<()> {
+        parimutuel::sweep_expired_claims(ctx, market_seed)
+    }
+
+    /// Record latest market cap without resolving (oracle only)
+    pub fn parimutuel_update_oracle_heartbeat(
+        ctx: Context<parimutuel::UpdateOracleHeartbeat>,
+        market_seed: String,
+        current_market_cap: u64,
+        timestamp: i64,
     ) -> Result<()> {
-        parimutuel::resolve_market(ctx, market_seed, current_market_cap, timestamp)
+        parimutuel::update_oracle_heartbeat(ctx, market_seed, current_market_cap, timestamp)
+    }
+
+    /// Refund creation fee to creator if no bets were placed
+    pub fn parimutuel_refund_creation_fee(
+        ctx: Context<parimutuel::RefundCreationFee>,
+        market_seed: String,
+    ) -> Result<()> {
+        parimutuel::refund_creation_fee(ctx, market_seed)
+    }
+
+    /// Release creation fee to treasury once a bet exists
+    pub fn parimutuel_collect_creation_fee(
+        ctx: Context<parimutuel::CollectCreationFee>,
+        market_seed: String,
+    ) -> Result<()> {
+        parimutuel::collect_creation_fee(ctx, market_seed)
     }
 
     /// Claim reward after market resolution
     pub fn parimutuel_claim_reward(
         ctx: Context<parimutuel::ClaimReward>,
         market_seed: String,
+        bet_index: u64,
+    ) -> Result<()> {
+        parimutuel::claim_reward(ctx, market_seed, bet_index)
+    }
+
+    /// Claim a resolved bet and re-bet the payout into another market
+    #[allow(clippy::too_many_arguments)]
+    pub fn parimutuel_claim_and_bet(
+        ctx: Context<parimutuel::ClaimAndBet>,
+        source_market_seed: String,
+        target_market_seed: String,
+        source_bet_index: u64,
+        target_bet_index: u64,
+        amount: Option<u64>,
+        side: bool,
+    ) -> Result<()> {
+        parimutuel::claim_and_bet(
+            ctx,
+            source_market_seed,
+            target_market_seed,
+            source_bet_index,
+            target_bet_index,
+            amount,
+            side,
+        )
+    }
+
+    /// Close a losing bet after resolution and reclaim its rent
+    pub fn parimutuel_close_losing_bet(
+        ctx: Context<parimutuel::CloseLosingBet>,
+        market_seed: String,
+        bet_index: u64,
+    ) -> Result<()> {
+        parimutuel::close_losing_bet(ctx, market_seed, bet_index)
+    }
+
+    /// Report (deadline_passed, seconds_to_deadline, is_resolved) for keeper bots
+    pub fn parimutuel_is_resolvable(
+        ctx: Context<parimutuel::IsResolvable>,
+        market_seed: String,
+    ) -> Result<()> {
+        parimutuel::is_resolvable(ctx, market_seed)
+    }
+
+    /// Create a categorical (N-outcome) parimutuel market (permissionless)
+    pub fn parimutuel_initialize_categorical_market(
+        ctx: Context<parimutuel::InitializeCategoricalMarket>,
+        market_seed: String,
+        oracle_authority: Pubkey,
+        outcome_count: u8,
+        deadline: i64,
+        grace_secs: i64,
+        title: String,
+    ) -> Result<()> {
+        parimutuel::initialize_categorical_market(
+            ctx,
+            market_seed,
+            oracle_authority,
+            outcome_count,
+            deadline,
+            grace_secs,
+            title,
+        )
+    }
+
+    /// Bet on one outcome of a categorical market
+    pub fn parimutuel_place_categorical_bet(
+        ctx: Context<parimutuel::PlaceCategoricalBet>,
+        market_seed: String,
+        amount: u64,
+        outcome_index: u8,
+    ) -> Result<()> {
+        parimutuel::place_categorical_bet(ctx, market_seed, amount, outcome_index)
+    }
+
+    /// Resolve a categorical market to its winning outcome (oracle only)
+    pub fn parimutuel_resolve_categorical_market(
+        ctx: Context<parimutuel::ResolveCategoricalMarket>,
+        market_seed: String,
+        winning_outcome: u8,
+    ) -> Result<()> {
+        parimutuel::resolve_categorical_market(ctx, market_seed, winning_outcome)
+    }
+
+    /// Claim a categorical bet after resolution
+    pub fn parimutuel_claim_categorical_reward(
+        ctx: Context<parimutuel::ClaimCategoricalReward>,
+        market_seed: String,
+    ) -> Result<()> {
+        parimutuel::claim_categorical_reward(ctx, market_seed)
+    }
+
+    /// Void a categorical market once the oracle misses the deadline + grace (anyone)
+    pub fn parimutuel_force_void_categorical_market(
+        ctx: Context<parimutuel::ForceVoidCategoricalMarket>,
+        market_seed: String,
+    ) -> Result<()> {
+        parimutuel::force_void_categorical_market(ctx, market_seed)
+    }
+
+    /// Forfeit categorical rewards left unclaimed 180 days after resolution (creator only)
+    pub fn parimutuel_sweep_unclaimed_categorical(
+        ctx: Context<parimutuel::SweepUnclaimedCategorical>,
+        market_seed: String,
+    ) -> Result<()> {
+        parimutuel::sweep_unclaimed_categorical(ctx, market_seed)
+    }
+
+    /// Close a categorical market once every bet is claimed, sweeping rounding dust to treasury
+    pub fn parimutuel_close_categorical_market(
+        ctx: Context<parimutuel::CloseCategoricalMarket>,
+        market_seed: String,
+    ) -> Result<()> {
+        parimutuel::close_categorical_market(ctx, market_seed)
+    }
+
+    /// Forfeit rewards left unclaimed 180 days after resolution (creator only)
+    pub fn parimutuel_sweep_unclaimed(
+        ctx: Context<parimutuel::SweepUnclaimed>,
+        market_seed: String,
+    ) -> Result<()> {
+        parimutuel::sweep_unclaimed(ctx, market_seed)
+    }
+
+    /// Start the emergency withdrawal timelock for a market (config authority only)
+    pub fn parimutuel_propose_emergency_withdraw(
+        ctx: Context<parimutuel::ProposeEmergencyWithdraw>,
+        market_seed: String,
+        recovery: Pubkey,
+    ) -> Result<()> {
+        parimutuel::propose_emergency_withdraw(ctx, market_seed, recovery)
+    }
+
+    /// Drain escrow to the proposed recovery address after the timelock (config authority only)
+    pub fn parimutuel_emergency_withdraw(
+        ctx: Context<parimutuel::EmergencyWithdraw>,
+        market_seed: String,
+    ) -> Result<()> {
+        parimutuel::emergency_withdraw(ctx, market_seed)
+    }
+
+    /// Close a fully-claimed market, sweeping rounding dust to treasury
+    pub fn parimutuel_close_market(
+        ctx: Context<parimutuel::CloseMarket>,
+        market_seed: String,
     ) -> Result<()> {
-        parimutuel::claim_reward(ctx, market_seed)
+        parimutuel::close_market(ctx, market_seed)
     }
 }
@@ -1,5 +1,13 @@
 use anchor_lang::prelude::*;
+#[cfg(not(test))]
 use anchor_lang::system_program;
+#[cfg(not(test))]
+use anchor_lang::solana_program::program::set_return_data;
+#[cfg(not(test))]
+use anchor_spl::token;
+use anchor_spl::token::{Mint, Token, TokenAccount};
+#[cfg(test)]
+use tests::{set_return_data, system_program, token};
 
 declare_id!("MemeMarketCLOB111111111111111111111111111111");
 
@@ -11,17 +19,60 @@ pub const PRICE_PRECISION: u64 = 1_000_000;
 /// At ~$130/SOL: 1 SOL = 1_000_000_000 lamports, so $1 ≈ 7_692_308 lamports
 pub const DEFAULT_ONE_DOLLAR_LAMPORTS: u64 = 7_700_000; // ~$1 at $130/SOL
 
+/// Max share decimals: quantities are in base units of 10^-share_decimals shares
+pub const MAX_SHARE_DECIMALS: u8 = 9;
+
+/// Max orders `place_orders_batch` will create in one call
+/// Each order costs a create_account CPI plus a PDA derivation, so keep this small
+pub const MAX_BATCH_ORDERS: usize = 10;
+
+/// Max candidate orders `crank_match` will scan in one call (bounds compute)
+pub const MAX_CRANK_CANDIDATES: usize = 8;
+
+/// Basis point denominator for taker fees and maker rebates
+pub const BPS_DENOMINATOR: u64 = 10_000;
+
+/// Max taker fee the authority can set (5%)
+pub const MAX_TAKER_FEE_BPS: u16 = 500;
+
+/// Max settlement fee the authority can take from redemptions (2%)
+pub const MAX_REDEMPTION_FEE_BPS: u16 = 200;
+
+/// Max keys on an orderbook's matcher allowlist (stored inline in the account)
+pub const MAX_MATCHERS: usize = 8;
+
 #[program]
 pub mod orderbook {
     use super::*;
 
     /// Initialize the order book for a market
-    /// Debug: Creates order book with configurable SOL price
+    /// The authority also funds the collateral vault's rent-exempt reserve
+    /// `creator` earns `creator_fee_bps` of each fill's value out of collected taker fees;
+    /// taker fees start disabled, so a non-zero royalty is rejected here and is set with
+    /// `set_creator_fee` once `set_fee_schedule` has raised the taker fee
+    /// A non-zero `min_seed_shares` holds trading closed until the authority has seeded that
+    /// many complete sets and called `enable_trading`
+    /// Debug: Creates order book with configurable SOL price and opening YES price
+    #[allow(clippy::too_many_arguments)]
     pub fn initialize_orderbook(
         ctx: Context<InitializeOrderbook>,
         market_id: Pubkey,
         one_dollar_lamports: u64, // SOL equivalent of $1 in lamports
+        initial_yes_price: u64,   // Opening YES probability (PRICE_PRECISION units)
+        max_open_orders: u64,     // Cap on resting orders across both sides (0 = unlimited)
+        share_decimals: u8,       // Quantity granularity: 1 unit = 10^-share_decimals shares
+        creator: Pubkey,          // Market creator receiving the royalty
+        creator_fee_bps: u16,     // Creator royalty, in bps of fill value (paid from taker fees)
+        dust_threshold: u64,      // Auto-cancel partial fills left below this quantity (0 = disabled)
+        min_seed_shares: u64,     // Complete sets to seed before trading opens (0 = open at once)
     ) -> Result<()> {
+        require!(share_decimals <= MAX_SHARE_DECIMALS, ErrorCode::InvalidShareDecimals);
+        require!(creator_fee_bps <= MAX_TAKER_FEE_BPS, ErrorCode::FeeTooHigh);
+        require!(
+            initial_yes_price > 0 && initial_yes_price < PRICE_PRECISION,
+            ErrorCode::InvalidPrice
+        );
+        
         let orderbook = &mut ctx.accounts.orderbook;
         
         orderbook.authority = ctx.accounts.authority.key();
@@ -32,24 +83,165 @@ pub mod orderbook {
         orderbook.total_yes_shares = 0;
         orderbook.total_no_shares = 0;
         orderbook.total_volume_lamports = 0;
-        orderbook.last_yes_price = PRICE_PRECISION / 2; // Start at 50%
-        orderbook.last_no_price = PRICE_PRECISION / 2;  // Start at 50%
+        orderbook.last_yes_price = initial_yes_price; // Seed the first chart point
+        orderbook.last_no_price = PRICE_PRECISION - initial_yes_price;
         orderbook.created_at = Clock::get()?.unix_timestamp;
         orderbook.is_active = true;
+        orderbook.winning_outcome = None;
+        orderbook.resolved_at = 0;
+        orderbook.last_match_slot = 0;
+        orderbook.max_open_orders = max_open_orders;
+        orderbook.open_yes_orders = 0;
+        orderbook.open_no_orders = 0;
+        orderbook.open_buy_collateral_lamports = 0;
+        orderbook.share_decimals = share_decimals;
+        orderbook.yes_mint = Pubkey::default();
+        orderbook.no_mint = Pubkey::default();
+        orderbook.resolution_dollar_lamports = 0;
+        orderbook.taker_fee_bps = 0; // Fees and rebates are opt-in via set_fee_schedule
+        orderbook.maker_rebate_bps = 0;
+        orderbook.fee_pot_lamports = 0;
+        orderbook.creator = creator;
+        orderbook.creator_fee_bps = creator_fee_bps;
+        orderbook.creator_fees_lamports = 0;
+        orderbook.dust_threshold = dust_threshold;
+        orderbook.matcher_reward_lamports = 0; // Opt-in via set_matcher_reward
+        orderbook.resting_yes_notional = 0;
+        orderbook.resting_no_notional = 0;
+        orderbook.total_collateral_lamports = 0;
+        orderbook.redemption_fee_bps = 0;
+        orderbook.min_seed_shares = min_seed_shares;
+        orderbook.trading_enabled = min_seed_shares == 0;
+        orderbook.yes_volume_lamports = 0;
+        orderbook.no_volume_lamports = 0;
+        orderbook.min_order_lifetime_secs = 0; // Cancels are unrestricted until opted in
+        orderbook.set_collateral_lamports = 0;
+        orderbook.permissioned_matching = false; // Anyone can match until opted in
+        orderbook.matchers = Vec::new();
+        orderbook.bump = ctx.bumps.orderbook;
+        require_fees_funded(orderbook)?;
+        
+        let fee_vault = &mut ctx.accounts.fee_vault;
+        fee_vault.market_id = market_id;
+        fee_vault.total_accrued_lamports = 0;
+        fee_vault.total_withdrawn_lamports = 0;
+        fee_vault.bump = ctx.bumps.fee_vault;
+        
+        // Fund the vault's rent reserve up front: payouts never debit below it, so
+        // without it the last rent-exempt-minimum of collateral could never leave
+        let vault = &ctx.accounts.vault;
+        let rent_reserve = Rent::get()?
+            .minimum_balance(vault.data_len())
+            .saturating_sub(vault.lamports());
+        if rent_reserve > 0 {
+            let cpi_context = CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                system_program::Transfer {
+                    from: ctx.accounts.authority.to_account_info(),
+                    to: vault.to_account_info(),
+                },
+            );
+            system_program::transfer(cpi_context, rent_reserve)?;
+        }
         
         // Debug: Log orderbook initialization
         msg!("DEBUG: Orderbook initialized for market {:?}", market_id);
         msg!("DEBUG: 1 USD = {} lamports", one_dollar_lamports);
+        msg!("DEBUG: Initial YES price = {}", initial_yes_price);
+        msg!("DEBUG: Max open orders = {} (0 = unlimited)", max_open_orders);
+        msg!("DEBUG: Share decimals = {}", share_decimals);
+        msg!("DEBUG: Creator {:?} royalty = {} bps", creator, creator_fee_bps);
+        msg!("DEBUG: Dust threshold = {} (0 = disabled)", dust_threshold);
+        msg!("DEBUG: Min seed shares = {} (0 = trading open)", min_seed_shares);
+        msg!("DEBUG: Vault rent reserve funded with {} lamports", rent_reserve);
         
         emit!(OrderbookInitialized {
             market_id,
             one_dollar_lamports,
+            initial_yes_price,
             timestamp: orderbook.created_at,
         });
         
         Ok(())
     }
 
+    /// Mint complete sets to the authority to bootstrap a new market (authority only)
+    /// Deposits $1 per set at the current SOL rate and credits `quantity` YES and NO
+    /// shares to the authority's UserShares, counting toward `min_seed_shares`
+    /// Debug: Sets are fully collateralized, so check_collateralization stays exact
+    pub fn seed_complete_sets(
+        ctx: Context<SeedCompleteSets>,
+        quantity: u64,
+    ) -> Result<()> {
+        let orderbook = &mut ctx.accounts.orderbook;
+        let authority = &ctx.accounts.authority;
+        let authority_shares = &mut ctx.accounts.authority_shares;
+        
+        require!(authority.key() == orderbook.authority, ErrorCode::Unauthorized);
+        require!(orderbook.is_active, ErrorCode::OrderbookInactive);
+        require!(quantity > 0, ErrorCode::InvalidAmount);
+        
+        let cost_lamports = calculate_collateral_lamports(
+            PRICE_PRECISION,
+            quantity,
+            orderbook.one_dollar_lamports,
+            orderbook.share_decimals,
+        )?;
+        
+        let cpi_context = CpiContext::new(
+            ctx.accounts.system_program.to_account_info(),
+            system_program::Transfer {
+                from: authority.to_account_info(),
+                to: ctx.accounts.vault.to_account_info(),
+            },
+        );
+        system_program::transfer(cpi_context, cost_lamports)?;
+        add_vault_collateral(orderbook, cost_lamports)?;
+        
+        authority_shares.owner = authority.key();
+        authority_shares.market_id = orderbook.market_id;
+        authority_shares.yes_shares = authority_shares.yes_shares
+            .checked_add(quantity)
+            .ok_or(ErrorCode::MathOverflow)?;
+        authority_shares.no_shares = authority_shares.no_shares
+            .checked_add(quantity)
+            .ok_or(ErrorCode::MathOverflow)?;
+        orderbook.total_yes_shares = orderbook.total_yes_shares
+            .checked_add(quantity)
+            .ok_or(ErrorCode::MathOverflow)?;
+        orderbook.total_no_shares = orderbook.total_no_shares
+            .checked_add(quantity)
+            .ok_or(ErrorCode::MathOverflow)?;
+        add_set_collateral(orderbook, cost_lamports)?;
+        
+        // Debug: Log seeding
+        msg!("DEBUG: Seeded {} complete sets for {} lamports ({} of {} required)",
+            quantity, cost_lamports, orderbook.total_yes_shares, orderbook.min_seed_shares);
+        
+        Ok(())
+    }
+
+    /// Open trading once the seeded supply reaches `min_seed_shares` (authority only)
+    pub fn enable_trading(ctx: Context<UpdateSolPrice>) -> Result<()> {
+        let orderbook = &mut ctx.accounts.orderbook;
+        
+        require!(
+            ctx.accounts.authority.key() == orderbook.authority,
+            ErrorCode::Unauthorized
+        );
+        require!(
+            std::cmp::min(orderbook.total_yes_shares, orderbook.total_no_shares) >= orderbook.min_seed_shares,
+            ErrorCode::InsufficientSeedShares
+        );
+        
+        orderbook.trading_enabled = true;
+        
+        // Debug: Log trading enabled
+        msg!("DEBUG: Trading enabled for market {:?}", orderbook.market_id);
+        
+        Ok(())
+    }
+
     /// Update SOL price (called by oracle or admin)
     /// Debug: Allows updating the SOL/USD exchange rate
     pub fn update_sol_price(
@@ -80,38 +272,350 @@ pub mod orderbook {
         Ok(())
     }
 
+    /// Set the taker fee and the maker rebate paid out of it (authority only)
+    /// Rebates and the creator royalty are funded solely from collected taker fees,
+    /// so together they may not exceed the fee rate
+    /// Debug: Applies to orders placed after the change; resting orders keep
+    /// the taker fee they reserved at placement
+    pub fn set_fee_schedule(
+        ctx: Context<UpdateFeeSchedule>,
+        taker_fee_bps: u16,
+        maker_rebate_bps: u16,
+    ) -> Result<()> {
+        let orderbook = &mut ctx.accounts.orderbook;
+        
+        require!(
+            ctx.accounts.authority.key() == orderbook.authority,
+            ErrorCode::Unauthorized
+        );
+        require!(taker_fee_bps <= MAX_TAKER_FEE_BPS, ErrorCode::FeeTooHigh);
+        
+        orderbook.taker_fee_bps = taker_fee_bps;
+        orderbook.maker_rebate_bps = maker_rebate_bps;
+        require_fees_funded(orderbook)?;
+        
+        // Debug: Log fee schedule
+        msg!("DEBUG: Fee schedule set - taker fee: {} bps, maker rebate: {} bps",
+            taker_fee_bps, maker_rebate_bps);
+        
+        emit!(FeeScheduleUpdated {
+            market_id: orderbook.market_id,
+            taker_fee_bps,
+            maker_rebate_bps,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        
+        Ok(())
+    }
+
+    /// Set the creator royalty (authority only)
+    /// Debug: Like the maker rebate it is paid out of collected taker fees, so the two
+    /// together may not exceed the current taker fee
+    pub fn set_creator_fee(
+        ctx: Context<UpdateFeeSchedule>,
+        creator_fee_bps: u16,
+    ) -> Result<()> {
+        let orderbook = &mut ctx.accounts.orderbook;
+        
+        require!(
+            ctx.accounts.authority.key() == orderbook.authority,
+            ErrorCode::Unauthorized
+        );
+        
+        orderbook.creator_fee_bps = creator_fee_bps;
+        require_fees_funded(orderbook)?;
+        
+        // Debug: Log creator fee
+        msg!("DEBUG: Creator royalty set to {} bps", creator_fee_bps);
+        
+        Ok(())
+    }
+
+    /// Set the reward paid to the matcher of each successful `match_orders` or
+    /// `crank_match` (authority only)
+    /// Debug: Paid out of fee_pot_lamports and capped at its balance, so it can never
+    /// touch collateral backing orders or shares
+    pub fn set_matcher_reward(
+        ctx: Context<UpdateFeeSchedule>,
+        matcher_reward_lamports: u64,
+    ) -> Result<()> {
+        let orderbook = &mut ctx.accounts.orderbook;
+        
+        require!(
+            ctx.accounts.authority.key() == orderbook.authority,
+            ErrorCode::Unauthorized
+        );
+        
+        orderbook.matcher_reward_lamports = matcher_reward_lamports;
+        
+        // Debug: Log matcher reward
+        msg!("DEBUG: Matcher reward set to {} lamports per match", matcher_reward_lamports);
+        
+        Ok(())
+    }
+
+    /// Set how long an order must rest before its owner may cancel it (authority only)
+    /// Debug: Deters place-cancel cycles that spoof book depth; 0 disables the check,
+    /// and orders that have already been partially filled are always cancellable
+    pub fn set_min_order_lifetime(
+        ctx: Context<UpdateFeeSchedule>,
+        min_order_lifetime_secs: u64,
+    ) -> Result<()> {
+        let orderbook = &mut ctx.accounts.orderbook;
+        
+        require!(
+            ctx.accounts.authority.key() == orderbook.authority,
+            ErrorCode::Unauthorized
+        );
+        
+        orderbook.min_order_lifetime_secs = min_order_lifetime_secs;
+        
+        // Debug: Log minimum lifetime
+        msg!("DEBUG: Minimum order lifetime set to {} seconds", min_order_lifetime_secs);
+        
+        Ok(())
+    }
+
+    /// Restrict matching and cranking to the matcher allowlist, or reopen it (authority only)
+    /// Debug: false restores permissionless matching; the allowlist is kept either way
+    pub fn set_permissioned_matching(
+        ctx: Context<UpdateFeeSchedule>,
+        permissioned: bool,
+    ) -> Result<()> {
+        let orderbook = &mut ctx.accounts.orderbook;
+        
+        require!(
+            ctx.accounts.authority.key() == orderbook.authority,
+            ErrorCode::Unauthorized
+        );
+        
+        orderbook.permissioned_matching = permissioned;
+        
+        // Debug: Log matching mode
+        msg!("DEBUG: Permissioned matching {}", if permissioned { "on" } else { "off" });
+        
+        Ok(())
+    }
+
+    /// Add a key to the matcher allowlist (authority only)
+    pub fn add_matcher(
+        ctx: Context<UpdateFeeSchedule>,
+        matcher: Pubkey,
+    ) -> Result<()> {
+        let orderbook = &mut ctx.accounts.orderbook;
+        
+        require!(
+            ctx.accounts.authority.key() == orderbook.authority,
+            ErrorCode::Unauthorized
+        );
+        require!(!orderbook.matchers.contains(&matcher), ErrorCode::MatcherAlreadyAllowed);
+        require!(orderbook.matchers.len() < MAX_MATCHERS, ErrorCode::TooManyMatchers);
+        
+        orderbook.matchers.push(matcher);
+        
+        // Debug: Log allowlist change
+        msg!("DEBUG: Matcher {:?} allowed ({} of {})", matcher, orderbook.matchers.len(), MAX_MATCHERS);
+        
+        Ok(())
+    }
+
+    /// Remove a key from the matcher allowlist (authority only)
+    pub fn remove_matcher(
+        ctx: Context<UpdateFeeSchedule>,
+        matcher: Pubkey,
+    ) -> Result<()> {
+        let orderbook = &mut ctx.accounts.orderbook;
+        
+        require!(
+            ctx.accounts.authority.key() == orderbook.authority,
+            ErrorCode::Unauthorized
+        );
+        let position = orderbook.matchers
+            .iter()
+            .position(|key| *key == matcher)
+            .ok_or(ErrorCode::MatcherNotFound)?;
+        
+        orderbook.matchers.swap_remove(position);
+        
+        // Debug: Log allowlist change
+        msg!("DEBUG: Matcher {:?} removed", matcher);
+        
+        Ok(())
+    }
+
+    /// Set the settlement fee taken from winning redemptions (authority only)
+    /// Debug: Capped at MAX_REDEMPTION_FEE_BPS; 0 disables it
+    pub fn set_redemption_fee(
+        ctx: Context<UpdateFeeSchedule>,
+        redemption_fee_bps: u16,
+    ) -> Result<()> {
+        let orderbook = &mut ctx.accounts.orderbook;
+        
+        require!(
+            ctx.accounts.authority.key() == orderbook.authority,
+            ErrorCode::Unauthorized
+        );
+        require!(redemption_fee_bps <= MAX_REDEMPTION_FEE_BPS, ErrorCode::FeeTooHigh);
+        
+        orderbook.redemption_fee_bps = redemption_fee_bps;
+        
+        // Debug: Log redemption fee
+        msg!("DEBUG: Redemption fee set to {} bps", redemption_fee_bps);
+        
+        Ok(())
+    }
+
+    /// Withdraw the creator royalty accrued from matched volume (creator only)
+    /// Debug: Pays out creator_fees_lamports from the vault and zeroes it
+    pub fn claim_creator_fees(ctx: Context<ClaimCreatorFees>) -> Result<()> {
+        let orderbook = &mut ctx.accounts.orderbook;
+        let vault = VaultSigner {
+            vault: &ctx.accounts.vault,
+            bump: ctx.bumps.vault,
+            system_program: &ctx.accounts.system_program,
+        };
+        let creator = &ctx.accounts.creator;
+        
+        require!(creator.key() == orderbook.creator, ErrorCode::Unauthorized);
+        
+        let amount = orderbook.creator_fees_lamports;
+        require!(amount > 0, ErrorCode::InvalidAmount);
+        
+        orderbook.creator_fees_lamports = 0;
+        pay_from_vault(orderbook, &vault, creator, amount)?;
+        
+        // Debug: Log claim
+        msg!("DEBUG: Creator {:?} claimed {} lamports of royalties", creator.key(), amount);
+        
+        emit!(CreatorFeesClaimed {
+            market_id: orderbook.market_id,
+            creator: creator.key(),
+            amount_lamports: amount,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        
+        Ok(())
+    }
+
+    /// Withdraw protocol fees from the market's FeeVault (authority only)
+    /// First sweeps the taker fees still pooled in the collateral vault (fee_pot_lamports)
+    /// into the FeeVault, then pays `amount` (0 = everything accrued) to `recipient`
+    /// Debug: Only the FeeVault is ever debited here, never order or share collateral
+    pub fn withdraw_fees(ctx: Context<WithdrawFees>, amount: u64) -> Result<()> {
+        let orderbook = &mut ctx.accounts.orderbook;
+        let vault = VaultSigner {
+            vault: &ctx.accounts.vault,
+            bump: ctx.bumps.vault,
+            system_program: &ctx.accounts.system_program,
+        };
+        let fee_vault_info = ctx.accounts.fee_vault.to_account_info();
+        
+        require!(
+            ctx.accounts.authority.key() == orderbook.authority,
+            ErrorCode::Unauthorized
+        );
+        
+        // Move fees left after rebates, royalties and matcher rewards out of the collateral vault
+        let swept = orderbook.fee_pot_lamports;
+        if swept > 0 {
+            orderbook.fee_pot_lamports = 0;
+            pay_from_vault(orderbook, &vault, &fee_vault_info, swept)?;
+        }
+        
+        let fee_vault = &mut ctx.accounts.fee_vault;
+        fee_vault.total_accrued_lamports = fee_vault.total_accrued_lamports
+            .checked_add(swept)
+            .ok_or(ErrorCode::MathOverflow)?;
+        
+        let available = fee_vault.total_accrued_lamports - fee_vault.total_withdrawn_lamports;
+        let amount = if amount == 0 { available } else { amount };
+        require!(amount > 0, ErrorCode::InvalidAmount);
+        require!(amount <= available, ErrorCode::InsufficientFees);
+        
+        debit_pda(&fee_vault_info, &ctx.accounts.recipient, amount)?;
+        fee_vault.total_withdrawn_lamports = fee_vault.total_withdrawn_lamports
+            .checked_add(amount)
+            .ok_or(ErrorCode::MathOverflow)?;
+        
+        // Debug: Log withdrawal
+        msg!("DEBUG: Withdrew {} lamports of fees to {:?} (swept {} from fee pot, {} left)",
+            amount, ctx.accounts.recipient.key(), swept, available - amount);
+        
+        emit!(FeesWithdrawn {
+            market_id: orderbook.market_id,
+            recipient: ctx.accounts.recipient.key(),
+            amount_lamports: amount,
+            swept_lamports: swept,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        
+        Ok(())
+    }
+
     /// Place a limit order to buy YES or NO shares
     /// Core Polymarket rule: YES price + NO price = $1
+    /// The order PDA is `derive_order_pda(user, orderbook, nonce)`, and `nonce` must be the
+    /// user's next `UserShares.order_nonce`, so ids are user-scoped and cannot collide
+    /// `net` nets the order: `quantity` opposite shares are locked as collateral
+    /// instead of SOL (1 YES + 1 NO merge to $1), so only the fee reserve is paid
     /// Debug: Creates order and attempts matching
     pub fn place_order(
         ctx: Context<PlaceOrder>,
-        order_id: Pubkey,
+        nonce: u64,
         side: OrderSide,      // YES or NO
         price: u64,           // Price in PRICE_PRECISION units (0-1_000_000)
         quantity: u64,        // Number of shares to buy
+        net: bool,            // Post opposite shares instead of SOL collateral
     ) -> Result<()> {
         let orderbook = &mut ctx.accounts.orderbook;
         let order = &mut ctx.accounts.order;
+        let user_shares = &mut ctx.accounts.user_shares;
         let user = &ctx.accounts.user;
+        let order_id = order.key();
         
         require!(orderbook.is_active, ErrorCode::OrderbookInactive);
+        require!(orderbook.trading_enabled, ErrorCode::TradingNotEnabled);
         require!(price > 0 && price < PRICE_PRECISION, ErrorCode::InvalidPrice);
         require!(quantity > 0, ErrorCode::InvalidAmount);
         
-        // Calculate required SOL collateral for this order
-        // cost = (price / PRICE_PRECISION) * quantity * one_dollar_lamports
-        let cost_lamports = price
-            .checked_mul(quantity)
-            .ok_or(ErrorCode::MathOverflow)?
-            .checked_mul(orderbook.one_dollar_lamports)
-            .ok_or(ErrorCode::MathOverflow)?
-            .checked_div(PRICE_PRECISION)
+        require!(nonce == user_shares.order_nonce, ErrorCode::InvalidOrderNonce);
+        
+        open_order_slot(orderbook, &side)?;
+        
+        user_shares.owner = user.key();
+        user_shares.market_id = orderbook.market_id;
+        user_shares.order_nonce = nonce
+            .checked_add(1)
             .ok_or(ErrorCode::MathOverflow)?;
         
+        // Cross-margin: post offsetting shares in place of SOL collateral
+        let is_netted = net;
+        if is_netted {
+            require!(orderbook.yes_mint == Pubkey::default(), ErrorCode::ShareTokensEnabled);
+            lock_posted_shares(user_shares, &side, quantity)?;
+        }
+        
+        // Calculate required SOL collateral for this order, plus the taker fee
+        // reserved in case it fills as the taker; a netted order only pays the fee
+        let collateral_lamports = calculate_collateral_lamports(
+            price,
+            quantity,
+            orderbook.one_dollar_lamports,
+            orderbook.share_decimals,
+        )?;
+        let fee_reserve_lamports = calculate_fee_lamports(collateral_lamports, orderbook.taker_fee_bps)?;
+        let cost_lamports = if is_netted {
+            fee_reserve_lamports
+        } else {
+            collateral_lamports
+                .checked_add(fee_reserve_lamports)
+                .ok_or(ErrorCode::MathOverflow)?
+        };
+        
         // Debug: Log order details
-        msg!("DEBUG: Placing {} order - price: {}, qty: {}, cost: {} lamports", 
+        msg!("DEBUG: Placing {} order - price: {}, qty: {}, cost: {} lamports, netted: {}", 
             if side == OrderSide::Yes { "YES" } else { "NO" },
-            price, quantity, cost_lamports);
+            price, quantity, cost_lamports, is_netted);
         
         // Transfer SOL from user to orderbook vault
         let cpi_context = CpiContext::new(
@@ -122,6 +626,7 @@ pub mod orderbook {
             },
         );
         system_program::transfer(cpi_context, cost_lamports)?;
+        add_vault_collateral(orderbook, cost_lamports)?;
         
         // Initialize order
         order.order_id = order_id;
@@ -133,8 +638,16 @@ pub mod orderbook {
         order.filled_quantity = 0;
         order.remaining_quantity = quantity;
         order.lamports_deposited = cost_lamports;
+        order.surplus_lamports = 0;
         order.status = OrderStatus::Open;
         order.created_at = Clock::get()?.unix_timestamp;
+        order.taker_fee_bps = orderbook.taker_fee_bps;
+        order.is_netted = is_netted;
+        order.rate_at_placement = orderbook.one_dollar_lamports;
+        
+        adjust_open_collateral(orderbook, 0, cost_lamports)?;
+        let depth = resting_notional(orderbook, order)?;
+        adjust_resting_notional(orderbook, &side, 0, depth)?;
         
         // Update orderbook counts
         match side {
@@ -156,100 +669,685 @@ pub mod orderbook {
         Ok(())
     }
 
-    /// Match orders: When YES price + NO price = $1, mint shares
-    /// This is the core Polymarket mechanism
-    /// Debug: Matches two complementary orders and mints shares
-    pub fn match_orders(
-        ctx: Context<MatchOrders>,
-    ) -> Result<()> {
-        let orderbook = &mut ctx.accounts.orderbook;
-        let yes_order = &mut ctx.accounts.yes_order;
-        let no_order = &mut ctx.accounts.no_order;
-        
-        require!(orderbook.is_active, ErrorCode::OrderbookInactive);
-        require!(yes_order.side == OrderSide::Yes, ErrorCode::InvalidOrderSide);
-        require!(no_order.side == OrderSide::No, ErrorCode::InvalidOrderSide);
-        require!(yes_order.status == OrderStatus::Open, ErrorCode::OrderNotOpen);
-        require!(no_order.status == OrderStatus::Open, ErrorCode::OrderNotOpen);
-        require!(yes_order.market_id == no_order.market_id, ErrorCode::MarketMismatch);
-        
-        // Core rule: YES price + NO price must equal $1 (PRICE_PRECISION)
-        let combined_price = yes_order.price.checked_add(no_order.price)
-            .ok_or(ErrorCode::MathOverflow)?;
+    /// Grow a UserShares account created before `order_nonce` existed to UserShares::LEN
+    /// The added bytes are zeroed, so a migrated account's next order nonce is 0
+    /// Debug: Pre-nonce accounts are 8 bytes short and fail to deserialize as UserShares
+    /// until migrated; the user tops up the extra rent
+    pub fn migrate_user_shares(ctx: Context<MigrateUserShares>) -> Result<()> {
+        let user_shares = &ctx.accounts.user_shares;
+        let old_len = user_shares.data_len();
+        require!(old_len < UserShares::LEN, ErrorCode::UserSharesAlreadyMigrated);
         
-        require!(combined_price == PRICE_PRECISION, ErrorCode::PricesMustSumToOne);
+        let rent_due = Rent::get()?
+            .minimum_balance(UserShares::LEN)
+            .saturating_sub(user_shares.lamports());
+        if rent_due > 0 {
+            let cpi_context = CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                system_program::Transfer {
+                    from: ctx.accounts.user.to_account_info(),
+                    to: user_shares.to_account_info(),
+                },
+            );
+            system_program::transfer(cpi_context, rent_due)?;
+        }
+        user_shares.resize(UserShares::LEN)?;
         
-        // Calculate match quantity (minimum of both remaining quantities)
-        let match_quantity = std::cmp::min(
-            yes_order.remaining_quantity,
-            no_order.remaining_quantity
-        );
+        // Debug: Log migration
+        msg!("DEBUG: Migrated user shares {:?} from {} to {} bytes, {} lamports rent added",
+            user_shares.key(), old_len, UserShares::LEN, rent_due);
         
-        require!(match_quantity > 0, ErrorCode::NoMatchQuantity);
+        Ok(())
+    }
+
+    /// Place several buy orders with one aggregate collateral transfer
+    /// `remaining_accounts` holds the uninitialized Order PDA for each entry, in order,
+    /// at consecutive nonces from the user's `UserShares.order_nonce` (see derive_order_pda);
+    /// at most MAX_BATCH_ORDERS per call
+    /// Debug: Lets market makers quote a ladder of price levels in one transaction
+    pub fn place_orders_batch<'info>(
+        ctx: Context<'_, '_, 'info, 'info, PlaceOrdersBatch<'info>>,
+        orders: Vec<BatchOrderParams>,
+    ) -> Result<()> {
+        let orderbook_key = ctx.accounts.orderbook.key();
+        let orderbook = &mut ctx.accounts.orderbook;
+        let user_shares = &mut ctx.accounts.user_shares;
+        let user = &ctx.accounts.user;
+        let order_accounts = ctx.remaining_accounts;
         
-        // Debug: Log match details
-        msg!("DEBUG: Matching orders - YES price: {}, NO price: {}, qty: {}",
-            yes_order.price, no_order.price, match_quantity);
+        require!(orderbook.is_active, ErrorCode::OrderbookInactive);
+        require!(orderbook.trading_enabled, ErrorCode::TradingNotEnabled);
+        require!(!orders.is_empty(), ErrorCode::InvalidAmount);
+        require!(orders.len() <= MAX_BATCH_ORDERS, ErrorCode::TooManyBatchOrders);
+        require!(order_accounts.len() == orders.len(), ErrorCode::InvalidBatchAccounts);
         
-        // Update YES order
-        yes_order.filled_quantity += match_quantity;
-        yes_order.remaining_quantity -= match_quantity;
-        if yes_order.remaining_quantity == 0 {
-            yes_order.status = OrderStatus::Filled;
-        } else {
-            yes_order.status = OrderStatus::PartiallyFilled;
-        }
+        let rent_lamports = Rent::get()?.minimum_balance(Order::LEN);
+        let created_at = Clock::get()?.unix_timestamp;
+        let mut total_cost_lamports: u64 = 0;
+        let mut nonce = user_shares.order_nonce;
         
-        // Update NO order
-        no_order.filled_quantity += match_quantity;
-        no_order.remaining_quantity -= match_quantity;
-        if no_order.remaining_quantity == 0 {
-            no_order.status = OrderStatus::Filled;
-        } else {
-            no_order.status = OrderStatus::PartiallyFilled;
+        for (params, order_info) in orders.iter().zip(order_accounts.iter()) {
+            require!(
+                params.price > 0 && params.price < PRICE_PRECISION,
+                ErrorCode::InvalidPrice
+            );
+            require!(params.quantity > 0, ErrorCode::InvalidAmount);
+            
+            let (order_pda, order_bump) = derive_order_pda(&user.key(), &orderbook_key, nonce);
+            require!(order_info.key() == order_pda, ErrorCode::InvalidBatchAccounts);
+            require!(order_info.data_is_empty(), ErrorCode::InvalidBatchAccounts);
+            
+            open_order_slot(orderbook, &params.side)?;
+            
+            let cost_lamports = calculate_collateral_lamports(
+                params.price,
+                params.quantity,
+                orderbook.one_dollar_lamports,
+                orderbook.share_decimals,
+            )?;
+            let cost_lamports = cost_lamports
+                .checked_add(calculate_fee_lamports(cost_lamports, orderbook.taker_fee_bps)?)
+                .ok_or(ErrorCode::MathOverflow)?;
+            total_cost_lamports = total_cost_lamports
+                .checked_add(cost_lamports)
+                .ok_or(ErrorCode::MathOverflow)?;
+            
+            // Create the order PDA (user pays rent)
+            let user_key = user.key();
+            let nonce_bytes = nonce.to_le_bytes();
+            let order_seeds: &[&[u8]] = &[
+                b"order",
+                user_key.as_ref(),
+                orderbook_key.as_ref(),
+                nonce_bytes.as_ref(),
+                &[order_bump],
+            ];
+            system_program::create_account(
+                CpiContext::new_with_signer(
+                    ctx.accounts.system_program.to_account_info(),
+                    system_program::CreateAccount {
+                        from: user.to_account_info(),
+                        to: order_info.clone(),
+                    },
+                    &[order_seeds],
+                ),
+                rent_lamports,
+                Order::LEN as u64,
+                ctx.program_id,
+            )?;
+            
+            let order = Order {
+                order_id: order_pda,
+                owner: user.key(),
+                market_id: orderbook.market_id,
+                side: params.side.clone(),
+                price: params.price,
+                original_quantity: params.quantity,
+                filled_quantity: 0,
+                remaining_quantity: params.quantity,
+                lamports_deposited: cost_lamports,
+                surplus_lamports: 0,
+                status: OrderStatus::Open,
+                is_sell: false,
+                created_at,
+                taker_fee_bps: orderbook.taker_fee_bps,
+                is_netted: false,
+                rate_at_placement: orderbook.one_dollar_lamports,
+            };
+            order.try_serialize(&mut &mut order_info.try_borrow_mut_data()?[..])?;
+            
+            adjust_open_collateral(orderbook, 0, cost_lamports)?;
+            let depth = resting_notional(orderbook, &order)?;
+            adjust_resting_notional(orderbook, &params.side, 0, depth)?;
+            match params.side {
+                OrderSide::Yes => orderbook.yes_order_count += 1,
+                OrderSide::No => orderbook.no_order_count += 1,
+            }
+            
+            emit!(OrderPlaced {
+                order_id: order_pda,
+                owner: user.key(),
+                market_id: orderbook.market_id,
+                side: params.side.clone(),
+                price: params.price,
+                quantity: params.quantity,
+                cost_lamports,
+                timestamp: created_at,
+            });
+            
+            nonce = nonce.checked_add(1).ok_or(ErrorCode::MathOverflow)?;
         }
         
-        // Mint shares to respective owners
-        // YES shares go to yes_order.owner
-        // NO shares go to no_order.owner
-        let yes_user_shares = &mut ctx.accounts.yes_user_shares;
-        let no_user_shares = &mut ctx.accounts.no_user_shares;
+        user_shares.owner = user.key();
+        user_shares.market_id = orderbook.market_id;
+        user_shares.order_nonce = nonce;
         
-        yes_user_shares.owner = yes_order.owner;
-        yes_user_shares.market_id = orderbook.market_id;
-        yes_user_shares.yes_shares += match_quantity;
+        // Debug: Log batch
+        msg!("DEBUG: Placed {} orders, total cost: {} lamports", orders.len(), total_cost_lamports);
         
-        no_user_shares.owner = no_order.owner;
-        no_user_shares.market_id = orderbook.market_id;
-        no_user_shares.no_shares += match_quantity;
+        // Single aggregate collateral transfer into the vault
+        let cpi_context = CpiContext::new(
+            ctx.accounts.system_program.to_account_info(),
+            system_program::Transfer {
+                from: user.to_account_info(),
+                to: ctx.accounts.vault.to_account_info(),
+            },
+        );
+        system_program::transfer(cpi_context, total_cost_lamports)?;
+        add_vault_collateral(orderbook, total_cost_lamports)?;
         
-        // Update orderbook state
-        orderbook.total_yes_shares += match_quantity;
-        orderbook.total_no_shares += match_quantity;
-        orderbook.last_yes_price = yes_order.price;
-        orderbook.last_no_price = no_order.price;
+        Ok(())
+    }
+
+    /// Modify price and/or quantity of an unfilled buy order in place
+    /// Collects or refunds the collateral difference and resets time priority
+    /// Debug: Saves market makers a cancel + place round-trip when re-quoting
+    pub fn modify_order(
+        ctx: Context<ModifyOrder>,
+        new_price: u64,
+        new_quantity: u64,
+    ) -> Result<()> {
+        let orderbook = &mut ctx.accounts.orderbook;
+        let vault = VaultSigner {
+            vault: &ctx.accounts.vault,
+            bump: ctx.bumps.vault,
+            system_program: &ctx.accounts.system_program,
+        };
+        let order = &mut ctx.accounts.order;
+        let user = &ctx.accounts.user;
         
-        // Calculate volume in lamports
-        let volume = match_quantity
-            .checked_mul(orderbook.one_dollar_lamports)
+        require!(orderbook.is_active, ErrorCode::OrderbookInactive);
+        require!(orderbook.trading_enabled, ErrorCode::TradingNotEnabled);
+        require!(!order.is_sell && !order.is_netted, ErrorCode::OrderNotModifiable);
+        require!(
+            order.status == OrderStatus::Open && order.filled_quantity == 0,
+            ErrorCode::OrderNotModifiable
+        );
+        require!(new_price > 0 && new_price < PRICE_PRECISION, ErrorCode::InvalidPrice);
+        require!(new_quantity > 0, ErrorCode::InvalidAmount);
+        
+        let old_price = order.price;
+        let old_quantity = order.original_quantity;
+        let old_cost = order.lamports_deposited;
+        let old_depth = resting_notional(orderbook, order)?;
+        let new_cost = calculate_collateral_lamports(
+            new_price,
+            new_quantity,
+            orderbook.one_dollar_lamports,
+            orderbook.share_decimals,
+        )?;
+        let new_cost = new_cost
+            .checked_add(calculate_fee_lamports(new_cost, orderbook.taker_fee_bps)?)
             .ok_or(ErrorCode::MathOverflow)?;
-        orderbook.total_volume_lamports += volume;
         
-        emit!(OrdersMatched {
-            yes_order_id: yes_order.order_id,
-            no_order_id: no_order.order_id,
+        // Debug: Log modification
+        msg!("DEBUG: Modifying order {:?} - price: {} -> {}, qty: {} -> {}, cost: {} -> {} lamports",
+            order.order_id, old_price, new_price, old_quantity, new_quantity, old_cost, new_cost);
+        
+        if new_cost > old_cost {
+            // Collect additional collateral from user
+            let cpi_context = CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                system_program::Transfer {
+                    from: user.to_account_info(),
+                    to: ctx.accounts.vault.to_account_info(),
+                },
+            );
+            system_program::transfer(cpi_context, new_cost - old_cost)?;
+            add_vault_collateral(orderbook, new_cost - old_cost)?;
+        } else if new_cost < old_cost {
+            // Refund excess collateral from vault
+            let refund = old_cost - new_cost;
+            pay_from_vault(orderbook, &vault, user, refund)?;
+        }
+        
+        order.price = new_price;
+        order.original_quantity = new_quantity;
+        order.remaining_quantity = new_quantity;
+        order.lamports_deposited = new_cost;
+        order.created_at = Clock::get()?.unix_timestamp; // Loses time priority
+        order.taker_fee_bps = orderbook.taker_fee_bps;
+        order.rate_at_placement = orderbook.one_dollar_lamports; // new_cost was priced at this rate
+        
+        adjust_open_collateral(orderbook, old_cost, new_cost)?;
+        let new_depth = resting_notional(orderbook, order)?;
+        adjust_resting_notional(orderbook, &order.side, old_depth, new_depth)?;
+        
+        emit!(OrderModified {
+            order_id: order.order_id,
+            owner: user.key(),
             market_id: orderbook.market_id,
-            yes_owner: yes_order.owner,
-            no_owner: no_order.owner,
-            yes_price: yes_order.price,
-            no_price: no_order.price,
-            quantity: match_quantity,
+            old_price,
+            new_price,
+            old_quantity,
+            new_quantity,
+            old_cost_lamports: old_cost,
+            new_cost_lamports: new_cost,
+            timestamp: order.created_at,
+        });
+        
+        Ok(())
+    }
+
+    /// Match orders: When YES price + NO price = $1, mint shares
+    /// This is the core Polymarket mechanism
+    /// A side left partially filled below `dust_threshold` is cancelled and its residual
+    /// collateral refunded, which needs that side's owner account
+    /// The matcher earns `matcher_reward_lamports`, bounded by the collected fee pot
+    /// Debug: Matches two complementary orders and mints shares
+    pub fn match_orders(
+        ctx: Context<MatchOrders>,
+    ) -> Result<()> {
+        let orderbook = &mut ctx.accounts.orderbook;
+        let vault = VaultSigner {
+            vault: &ctx.accounts.vault,
+            bump: ctx.bumps.vault,
+            system_program: &ctx.accounts.system_program,
+        };
+        let yes_order = &mut ctx.accounts.yes_order;
+        let no_order = &mut ctx.accounts.no_order;
+        
+        require!(orderbook.is_active, ErrorCode::OrderbookInactive);
+        require_matcher(orderbook, &ctx.accounts.matcher.key())?;
+        require!(yes_order.side == OrderSide::Yes, ErrorCode::InvalidOrderSide);
+        require!(no_order.side == OrderSide::No, ErrorCode::InvalidOrderSide);
+        require!(!yes_order.is_sell && !no_order.is_sell, ErrorCode::InvalidOrderSide);
+        // A partially filled order keeps resting until its remainder is matched
+        require!(
+            yes_order.status == OrderStatus::Open || yes_order.status == OrderStatus::PartiallyFilled,
+            ErrorCode::OrderNotOpen
+        );
+        require!(
+            no_order.status == OrderStatus::Open || no_order.status == OrderStatus::PartiallyFilled,
+            ErrorCode::OrderNotOpen
+        );
+        require!(yes_order.market_id == no_order.market_id, ErrorCode::MarketMismatch);
+        
+        if orderbook.yes_mint == Pubkey::default() {
+            execute_match(
+                orderbook,
+                yes_order,
+                no_order,
+                Some((&mut ctx.accounts.yes_user_shares, &mut ctx.accounts.no_user_shares)),
+            )?;
+            finish_match(
+                orderbook,
+                yes_order,
+                no_order,
+                Some((&mut ctx.accounts.yes_user_shares, &mut ctx.accounts.no_user_shares)),
+                &vault,
+                (ctx.accounts.yes_owner.as_ref(), ctx.accounts.no_owner.as_ref()),
+                &ctx.accounts.matcher,
+            )?;
+            return Ok(());
+        }
+        
+        // Share tokens enabled: mint SPL YES/NO tokens instead of crediting counters
+        let match_quantity = execute_match(orderbook, yes_order, no_order, None)?;
+        
+        let (Some(yes_mint), Some(no_mint), Some(yes_token_account), Some(no_token_account), Some(token_program)) = (
+            ctx.accounts.yes_mint.as_ref(),
+            ctx.accounts.no_mint.as_ref(),
+            ctx.accounts.yes_owner_token_account.as_ref(),
+            ctx.accounts.no_owner_token_account.as_ref(),
+            ctx.accounts.token_program.as_ref(),
+        ) else {
+            return err!(ErrorCode::MissingShareTokenAccounts);
+        };
+        
+        require!(yes_mint.key() == orderbook.yes_mint, ErrorCode::InvalidShareMint);
+        require!(no_mint.key() == orderbook.no_mint, ErrorCode::InvalidShareMint);
+        require!(
+            yes_token_account.mint == orderbook.yes_mint && yes_token_account.owner == yes_order.owner,
+            ErrorCode::InvalidShareMint
+        );
+        require!(
+            no_token_account.mint == orderbook.no_mint && no_token_account.owner == no_order.owner,
+            ErrorCode::InvalidShareMint
+        );
+        
+        let market_id = orderbook.market_id;
+        let seeds = &[
+            b"orderbook",
+            market_id.as_ref(),
+            &[orderbook.bump],
+        ];
+        let signer = &[&seeds[..]];
+        
+        token::mint_to(
+            CpiContext::new_with_signer(
+                token_program.to_account_info(),
+                token::MintTo {
+                    mint: yes_mint.to_account_info(),
+                    to: yes_token_account.to_account_info(),
+                    authority: orderbook.to_account_info(),
+                },
+                signer,
+            ),
+            match_quantity,
+        )?;
+        token::mint_to(
+            CpiContext::new_with_signer(
+                token_program.to_account_info(),
+                token::MintTo {
+                    mint: no_mint.to_account_info(),
+                    to: no_token_account.to_account_info(),
+                    authority: orderbook.to_account_info(),
+                },
+                signer,
+            ),
+            match_quantity,
+        )?;
+        
+        // Debug: Log token mint
+        msg!("DEBUG: Minted {} YES/NO share tokens", match_quantity);
+        
+        finish_match(
+            orderbook,
+            yes_order,
+            no_order,
+            None,
+            &vault,
+            (ctx.accounts.yes_owner.as_ref(), ctx.accounts.no_owner.as_ref()),
+            &ctx.accounts.matcher,
+        )
+    }
+
+    /// Create SPL mints for YES/NO shares so matches mint transferable tokens (authority only)
+    /// Mint authority is the orderbook PDA; decimals follow `share_decimals`
+    /// Debug: Once enabled, match_orders mints tokens instead of crediting UserShares
+    /// counters, and token holders redeem through `redeem_share_tokens`
+    pub fn initialize_share_mints(ctx: Context<InitializeShareMints>) -> Result<()> {
+        let orderbook = &mut ctx.accounts.orderbook;
+        
+        require!(
+            ctx.accounts.authority.key() == orderbook.authority,
+            ErrorCode::Unauthorized
+        );
+        require!(
+            orderbook.yes_mint == Pubkey::default(),
+            ErrorCode::ShareTokensAlreadyEnabled
+        );
+        
+        orderbook.yes_mint = ctx.accounts.yes_mint.key();
+        orderbook.no_mint = ctx.accounts.no_mint.key();
+        
+        // Debug: Log mints
+        msg!("DEBUG: Share tokens enabled - YES mint {:?}, NO mint {:?}",
+            orderbook.yes_mint, orderbook.no_mint);
+        
+        Ok(())
+    }
+
+    /// Redeem winning SPL share tokens for $1 each after resolution
+    /// `quantity == 0` redeems the full token balance
+    /// Debug: Token counterpart of `redeem_shares`
+    pub fn redeem_share_tokens(
+        ctx: Context<RedeemShareTokens>,
+        winning_outcome: OrderSide,
+        quantity: u64,
+    ) -> Result<()> {
+        let orderbook = &mut ctx.accounts.orderbook;
+        let vault = VaultSigner {
+            vault: &ctx.accounts.vault,
+            bump: ctx.bumps.vault,
+            system_program: &ctx.accounts.system_program,
+        };
+        let user = &ctx.accounts.user;
+        
+        require!(!orderbook.is_active, ErrorCode::MarketStillActive);
+        require!(
+            orderbook.winning_outcome == Some(winning_outcome.clone()),
+            ErrorCode::InvalidOutcome
+        );
+        
+        let winning_mint = match winning_outcome {
+            OrderSide::Yes => orderbook.yes_mint,
+            OrderSide::No => orderbook.no_mint,
+        };
+        require!(winning_mint != Pubkey::default(), ErrorCode::ShareTokensNotEnabled);
+        require!(ctx.accounts.share_mint.key() == winning_mint, ErrorCode::InvalidShareMint);
+        
+        let tokens_held = ctx.accounts.user_token_account.amount;
+        require!(tokens_held > 0, ErrorCode::NoSharesToRedeem);
+        require!(quantity <= tokens_held, ErrorCode::InsufficientShares);
+        
+        let shares_to_redeem = if quantity == 0 { tokens_held } else { quantity };
+        
+        let gross_payout = calculate_cost_lamports(
+            PRICE_PRECISION,
+            shares_to_redeem,
+            orderbook.resolution_dollar_lamports,
+            orderbook.share_decimals,
+        )?;
+        let fee_lamports = calculate_fee_lamports(gross_payout, orderbook.redemption_fee_bps)?;
+        let payout = gross_payout - fee_lamports;
+        
+        // Debug: Log redemption
+        msg!("DEBUG: Redeeming {} share tokens for {} lamports ({} fee)",
+            shares_to_redeem,
+            payout,
+            fee_lamports);
+        
+        // Burn redeemed tokens
+        token::burn(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                token::Burn {
+                    mint: ctx.accounts.share_mint.to_account_info(),
+                    from: ctx.accounts.user_token_account.to_account_info(),
+                    authority: user.to_account_info(),
+                },
+            ),
+            shares_to_redeem,
+        )?;
+        
+        match winning_outcome {
+            OrderSide::Yes => {
+                orderbook.total_yes_shares = orderbook.total_yes_shares.saturating_sub(shares_to_redeem);
+            },
+            OrderSide::No => {
+                orderbook.total_no_shares = orderbook.total_no_shares.saturating_sub(shares_to_redeem);
+            },
+        }
+        
+        // Transfer payout
+        pay_from_vault(orderbook, &vault, user, payout)?;
+        if fee_lamports > 0 {
+            let fee_vault = &mut ctx.accounts.fee_vault;
+            pay_from_vault(orderbook, &vault, &fee_vault.to_account_info(), fee_lamports)?;
+            fee_vault.total_accrued_lamports = fee_vault.total_accrued_lamports
+                .checked_add(fee_lamports)
+                .ok_or(ErrorCode::MathOverflow)?;
+        }
+        
+        emit!(SharesRedeemed {
+            owner: user.key(),
+            market_id: orderbook.market_id,
+            winning_outcome,
+            shares_redeemed: shares_to_redeem,
+            payout_lamports: payout,
+            fee_lamports,
             timestamp: Clock::get()?.unix_timestamp,
         });
         
         Ok(())
     }
 
+    /// Permissionless crank: pair the best YES/NO buys from a batch of candidates
+    /// `remaining_accounts` holds (order, owner's UserShares) pairs, at most
+    /// MAX_CRANK_CANDIDATES of them; the pair with the highest combined price
+    /// covering $1 is matched, self-trades are skipped, and orders at the same
+    /// price are filled oldest first
+    /// Like `match_orders`, a side left below `dust_threshold` is auto-cancelled
+    /// (which needs that side's owner account) and the cranker earns the matcher reward
+    /// Debug: Returns the quantity matched (0 if no pair crosses)
+    pub fn crank_match<'info>(
+        ctx: Context<'_, '_, 'info, 'info, CrankMatch<'info>>,
+    ) -> Result<u64> {
+        let orderbook = &mut ctx.accounts.orderbook;
+        let vault = VaultSigner {
+            vault: &ctx.accounts.vault,
+            bump: ctx.bumps.vault,
+            system_program: &ctx.accounts.system_program,
+        };
+        let remaining = ctx.remaining_accounts;
+        
+        require!(orderbook.is_active, ErrorCode::OrderbookInactive);
+        require_matcher(orderbook, &ctx.accounts.cranker.key())?;
+        require!(orderbook.yes_mint == Pubkey::default(), ErrorCode::ShareTokensEnabled);
+        require!(remaining.len().is_multiple_of(2), ErrorCode::InvalidCrankAccounts);
+        require!(
+            remaining.len() / 2 <= MAX_CRANK_CANDIDATES,
+            ErrorCode::TooManyCrankCandidates
+        );
+        
+        // Load candidates, ignoring anything that cannot be matched right now
+        let mut candidates: Vec<(Account<'info, Order>, Account<'info, UserShares>)> = Vec::new();
+        for pair in remaining.chunks(2) {
+            let order = Account::<Order>::try_from(&pair[0])?;
+            let user_shares = Account::<UserShares>::try_from(&pair[1])?;
+            
+            require!(order.market_id == orderbook.market_id, ErrorCode::MarketMismatch);
+            require!(
+                user_shares.owner == order.owner && user_shares.market_id == orderbook.market_id,
+                ErrorCode::Unauthorized
+            );
+            
+            let is_resting = order.status == OrderStatus::Open
+                || order.status == OrderStatus::PartiallyFilled;
+            if order.is_sell || !is_resting || order.remaining_quantity == 0 {
+                continue;
+            }
+            candidates.push((order, user_shares));
+        }
+        
+        // Best pair: highest combined price covering $1; at equal prices the
+        // older YES order wins, then the older NO order (price-time priority)
+        let mut best: Option<(usize, usize, u64)> = None;
+        for (i, (yes_order, _)) in candidates.iter().enumerate() {
+            if yes_order.side != OrderSide::Yes {
+                continue;
+            }
+            for (j, (no_order, _)) in candidates.iter().enumerate() {
+                if no_order.side != OrderSide::No || no_order.owner == yes_order.owner {
+                    continue;
+                }
+                let combined_price = yes_order.price
+                    .checked_add(no_order.price)
+                    .ok_or(ErrorCode::MathOverflow)?;
+                if combined_price < PRICE_PRECISION {
+                    continue;
+                }
+                let is_better = match best {
+                    None => true,
+                    Some((best_i, best_j, best_price)) => {
+                        combined_price > best_price
+                            || (combined_price == best_price
+                                && (priority_key(yes_order), priority_key(no_order))
+                                    < (priority_key(&candidates[best_i].0),
+                                       priority_key(&candidates[best_j].0)))
+                    },
+                };
+                if is_better {
+                    best = Some((i, j, combined_price));
+                }
+            }
+        }
+        
+        let Some((yes_index, no_index, combined_price)) = best else {
+            msg!("DEBUG: Crank found no crossing pair among {} candidates", candidates.len());
+            return Ok(0);
+        };
+        
+        // FIFO: no older order at the same price may be skipped on either side
+        require_time_priority(&candidates, yes_index, &candidates[no_index].0.owner)?;
+        require_time_priority(&candidates, no_index, &candidates[yes_index].0.owner)?;
+        
+        // Debug: Log crank selection
+        msg!("DEBUG: Crank matching candidates {} and {} (combined price {})",
+            yes_index, no_index, combined_price);
+        
+        // Take the higher index first so the lower one is still valid
+        let (first, second) = if yes_index > no_index {
+            (yes_index, no_index)
+        } else {
+            (no_index, yes_index)
+        };
+        let taken_first = candidates.swap_remove(first);
+        let taken_second = candidates.swap_remove(second);
+        let ((mut yes_order, mut yes_user_shares), (mut no_order, mut no_user_shares)) =
+            if yes_index > no_index {
+                (taken_first, taken_second)
+            } else {
+                (taken_second, taken_first)
+            };
+        
+        let match_quantity = execute_match(
+            orderbook,
+            &mut yes_order,
+            &mut no_order,
+            Some((&mut yes_user_shares, &mut no_user_shares)),
+        )?;
+        finish_match(
+            orderbook,
+            &mut yes_order,
+            &mut no_order,
+            Some((&mut yes_user_shares, &mut no_user_shares)),
+            &vault,
+            (ctx.accounts.yes_owner.as_ref(), ctx.accounts.no_owner.as_ref()),
+            &ctx.accounts.cranker,
+        )?;
+        
+        // Persist the manually loaded accounts
+        yes_order.exit(&crate::ID)?;
+        no_order.exit(&crate::ID)?;
+        yes_user_shares.exit(&crate::ID)?;
+        no_user_shares.exit(&crate::ID)?;
+        
+        Ok(match_quantity)
+    }
+
+    /// Resolve the market and stop trading (authority only)
+    /// Ordering invariant: shares are only minted while `is_active`, only redeemed
+    /// once `!is_active`, and resolution must land in a later slot than the last
+    /// match - so no single transaction can mint shares and then redeem them
+    /// Debug: Records the winning outcome that `redeem_shares` pays against
+    pub fn resolve_orderbook(
+        ctx: Context<ResolveOrderbook>,
+        winning_outcome: OrderSide,
+    ) -> Result<()> {
+        let orderbook = &mut ctx.accounts.orderbook;
+        let clock = Clock::get()?;
+        
+        require!(
+            ctx.accounts.authority.key() == orderbook.authority,
+            ErrorCode::Unauthorized
+        );
+        require!(orderbook.is_active, ErrorCode::OrderbookInactive);
+        require!(clock.slot > orderbook.last_match_slot, ErrorCode::ResolutionTooSoon);
+        
+        orderbook.is_active = false;
+        orderbook.winning_outcome = Some(winning_outcome.clone());
+        orderbook.resolved_at = clock.unix_timestamp;
+        // Lock the SOL/$1 rate so later price updates cannot change payouts
+        orderbook.resolution_dollar_lamports = orderbook.one_dollar_lamports;
+        
+        // Debug: Log resolution
+        msg!("DEBUG: Orderbook resolved - winner: {}, redeeming at {} lamports/$1",
+            if winning_outcome == OrderSide::Yes { "YES" } else { "NO" },
+            orderbook.resolution_dollar_lamports);
+        
+        emit!(OrderbookResolved {
+            market_id: orderbook.market_id,
+            winning_outcome,
+            resolution_dollar_lamports: orderbook.resolution_dollar_lamports,
+            timestamp: orderbook.resolved_at,
+        });
+        
+        Ok(())
+    }
+
     /// Sell shares back (merge operation)
     /// When user sells YES and another sells NO at complementary prices,
     /// shares are burned and SOL is returned
@@ -266,18 +1364,11 @@ pub mod orderbook {
         let sell_order = &mut ctx.accounts.sell_order;
         
         require!(orderbook.is_active, ErrorCode::OrderbookInactive);
+        require!(orderbook.trading_enabled, ErrorCode::TradingNotEnabled);
         require!(price > 0 && price < PRICE_PRECISION, ErrorCode::InvalidPrice);
         require!(quantity > 0, ErrorCode::InvalidAmount);
         
-        // Verify user has enough shares
-        match side {
-            OrderSide::Yes => {
-                require!(user_shares.yes_shares >= quantity, ErrorCode::InsufficientShares);
-            },
-            OrderSide::No => {
-                require!(user_shares.no_shares >= quantity, ErrorCode::InsufficientShares);
-            },
-        }
+        open_order_slot(orderbook, &side)?;
         
         // Debug: Log sell order
         msg!("DEBUG: Selling {} {} shares at price {}",
@@ -295,19 +1386,16 @@ pub mod orderbook {
         sell_order.filled_quantity = 0;
         sell_order.remaining_quantity = quantity;
         sell_order.lamports_deposited = 0; // Seller deposits shares, not SOL
+        sell_order.surplus_lamports = 0;
         sell_order.status = OrderStatus::Open;
         sell_order.is_sell = true;
+        sell_order.is_netted = false;
+        sell_order.rate_at_placement = orderbook.one_dollar_lamports;
         sell_order.created_at = Clock::get()?.unix_timestamp;
         
-        // Lock the shares (mark as pending sale)
-        match side {
-            OrderSide::Yes => {
-                user_shares.yes_shares_locked += quantity;
-            },
-            OrderSide::No => {
-                user_shares.no_shares_locked += quantity;
-            },
-        }
+        // Lock the shares (mark as pending sale); shares already locked by other
+        // sell orders or netted buys cannot be listed again
+        lock_free_shares(user_shares, &side, quantity)?;
         
         emit!(SellOrderPlaced {
             order_id: sell_order_id,
@@ -324,17 +1412,25 @@ pub mod orderbook {
 
     /// Match sell orders (merge shares)
     /// When YES seller + NO seller prices sum to $1, burn shares and pay out SOL
+    /// The payout is the merged sets' collateral at the rate it was deposited, as for
+    /// `redeem_complete_set`, split between the sellers by price
     /// Debug: Burns shares from both parties and returns SOL
     pub fn match_sell_orders(
         ctx: Context<MatchSellOrders>,
     ) -> Result<()> {
         let orderbook = &mut ctx.accounts.orderbook;
+        let vault = VaultSigner {
+            vault: &ctx.accounts.vault,
+            bump: ctx.bumps.vault,
+            system_program: &ctx.accounts.system_program,
+        };
         let yes_sell_order = &mut ctx.accounts.yes_sell_order;
         let no_sell_order = &mut ctx.accounts.no_sell_order;
         let yes_user_shares = &mut ctx.accounts.yes_user_shares;
         let no_user_shares = &mut ctx.accounts.no_user_shares;
         
         require!(orderbook.is_active, ErrorCode::OrderbookInactive);
+        require_matcher(orderbook, &ctx.accounts.matcher.key())?;
         require!(yes_sell_order.is_sell && no_sell_order.is_sell, ErrorCode::NotASellOrder);
         require!(yes_sell_order.side == OrderSide::Yes, ErrorCode::InvalidOrderSide);
         require!(no_sell_order.side == OrderSide::No, ErrorCode::InvalidOrderSide);
@@ -358,51 +1454,60 @@ pub mod orderbook {
         msg!("DEBUG: Merging shares - YES price: {}, NO price: {}, qty: {}",
             yes_sell_order.price, no_sell_order.price, match_quantity);
         
-        // Calculate payouts
-        let yes_payout = yes_sell_order.price
-            .checked_mul(match_quantity)
+        // Calculate payouts: the merged sets release the SOL they were collateralized
+        // with, at whatever rate that was, split between the sellers by price
+        let released = retire_set_collateral(orderbook, match_quantity)?;
+        let yes_payout = (released as u128)
+            .checked_mul(yes_sell_order.price as u128)
             .ok_or(ErrorCode::MathOverflow)?
-            .checked_mul(orderbook.one_dollar_lamports)
-            .ok_or(ErrorCode::MathOverflow)?
-            .checked_div(PRICE_PRECISION)
-            .ok_or(ErrorCode::MathOverflow)?;
-        
-        let no_payout = no_sell_order.price
-            .checked_mul(match_quantity)
-            .ok_or(ErrorCode::MathOverflow)?
-            .checked_mul(orderbook.one_dollar_lamports)
-            .ok_or(ErrorCode::MathOverflow)?
-            .checked_div(PRICE_PRECISION)
-            .ok_or(ErrorCode::MathOverflow)?;
+            / PRICE_PRECISION as u128;
+        let yes_payout = u64::try_from(yes_payout).map_err(|_| ErrorCode::MathOverflow)?;
+        let no_payout = released - yes_payout;
         
         // Burn shares
-        yes_user_shares.yes_shares -= match_quantity;
-        yes_user_shares.yes_shares_locked -= match_quantity;
-        no_user_shares.no_shares -= match_quantity;
-        no_user_shares.no_shares_locked -= match_quantity;
+        yes_user_shares.yes_shares = yes_user_shares.yes_shares
+            .checked_sub(match_quantity)
+            .ok_or(ErrorCode::MathOverflow)?;
+        yes_user_shares.yes_shares_locked = yes_user_shares.yes_shares_locked
+            .checked_sub(match_quantity)
+            .ok_or(ErrorCode::MathOverflow)?;
+        no_user_shares.no_shares = no_user_shares.no_shares
+            .checked_sub(match_quantity)
+            .ok_or(ErrorCode::MathOverflow)?;
+        no_user_shares.no_shares_locked = no_user_shares.no_shares_locked
+            .checked_sub(match_quantity)
+            .ok_or(ErrorCode::MathOverflow)?;
         
         // Update orderbook
-        orderbook.total_yes_shares -= match_quantity;
-        orderbook.total_no_shares -= match_quantity;
+        orderbook.total_yes_shares = orderbook.total_yes_shares
+            .checked_sub(match_quantity)
+            .ok_or(ErrorCode::MathOverflow)?;
+        orderbook.total_no_shares = orderbook.total_no_shares
+            .checked_sub(match_quantity)
+            .ok_or(ErrorCode::MathOverflow)?;
         
         // Update orders
-        yes_sell_order.filled_quantity += match_quantity;
+        yes_sell_order.filled_quantity = yes_sell_order.filled_quantity
+            .checked_add(match_quantity)
+            .ok_or(ErrorCode::MathOverflow)?;
         yes_sell_order.remaining_quantity -= match_quantity;
         if yes_sell_order.remaining_quantity == 0 {
             yes_sell_order.status = OrderStatus::Filled;
+            close_order_slot(orderbook, &OrderSide::Yes);
         }
         
-        no_sell_order.filled_quantity += match_quantity;
+        no_sell_order.filled_quantity = no_sell_order.filled_quantity
+            .checked_add(match_quantity)
+            .ok_or(ErrorCode::MathOverflow)?;
         no_sell_order.remaining_quantity -= match_quantity;
         if no_sell_order.remaining_quantity == 0 {
             no_sell_order.status = OrderStatus::Filled;
+            close_order_slot(orderbook, &OrderSide::No);
         }
         
         // Transfer SOL from vault to sellers
-        // Note: In production, use proper PDA signing for vault transfers
-        **ctx.accounts.vault.try_borrow_mut_lamports()? -= yes_payout + no_payout;
-        **ctx.accounts.yes_seller.try_borrow_mut_lamports()? += yes_payout;
-        **ctx.accounts.no_seller.try_borrow_mut_lamports()? += no_payout;
+        pay_from_vault(orderbook, &vault, &ctx.accounts.yes_seller, yes_payout)?;
+        pay_from_vault(orderbook, &vault, &ctx.accounts.no_seller, no_payout)?;
         
         emit!(SharesMerged {
             yes_order_id: yes_sell_order.order_id,
@@ -420,32 +1525,46 @@ pub mod orderbook {
     }
 
     /// Cancel an open order and refund SOL
+    /// Refund = collateral for the unfilled portion + surplus from favorable fills
     /// Debug: Cancels order and returns deposited SOL
     pub fn cancel_order(
         ctx: Context<CancelOrder>,
     ) -> Result<()> {
+        let orderbook = &mut ctx.accounts.orderbook;
+        let vault = VaultSigner {
+            vault: &ctx.accounts.vault,
+            bump: ctx.bumps.vault,
+            system_program: &ctx.accounts.system_program,
+        };
         let order = &mut ctx.accounts.order;
         let user = &ctx.accounts.user;
         
         require!(order.owner == user.key(), ErrorCode::Unauthorized);
+        require!(order.market_id == orderbook.market_id, ErrorCode::MarketMismatch);
+        require!(!order.is_sell, ErrorCode::NotABuyOrder);
         require!(
             order.status == OrderStatus::Open || order.status == OrderStatus::PartiallyFilled,
             ErrorCode::OrderNotCancellable
         );
+        require_min_order_lifetime(orderbook, order)?;
         
-        // Calculate refund for unfilled portion
-        let refund_ratio = order.remaining_quantity as u128 * 1_000_000 / order.original_quantity as u128;
-        let refund_lamports = (order.lamports_deposited as u128 * refund_ratio / 1_000_000) as u64;
+        // Calculate refund for unfilled portion plus any fill surplus
+        let refund_lamports = calculate_refund_lamports(order)?;
         
         // Debug: Log cancellation
-        msg!("DEBUG: Cancelling order {:?}, refunding {} lamports", 
-            order.order_id, refund_lamports);
+        msg!("DEBUG: Cancelling order {:?}, refunding {} lamports (incl. {} surplus)", 
+            order.order_id, refund_lamports, order.surplus_lamports);
         
         // Transfer refund from vault to user
-        **ctx.accounts.vault.try_borrow_mut_lamports()? -= refund_lamports;
-        **user.try_borrow_mut_lamports()? += refund_lamports;
+        pay_from_vault(orderbook, &vault, user, refund_lamports)?;
+        release_posted_shares(order, ctx.accounts.user_shares.as_deref_mut())?;
         
+        let depth = resting_notional(orderbook, order)?;
+        order.surplus_lamports = 0;
         order.status = OrderStatus::Cancelled;
+        close_order_slot(orderbook, &order.side);
+        adjust_open_collateral(orderbook, refund_lamports, 0)?;
+        adjust_resting_notional(orderbook, &order.side, depth, 0)?;
         
         emit!(OrderCancelled {
             order_id: order.order_id,
@@ -457,434 +1576,5008 @@ pub mod orderbook {
         Ok(())
     }
 
-    /// Redeem winning shares after market resolution
-    /// Winners get $1 per share, losers get $0
-    /// Debug: Pays out winners after market resolution
-    pub fn redeem_shares(
-        ctx: Context<RedeemShares>,
-        winning_outcome: OrderSide,
+    /// Cancel an open sell order and unlock its unsold shares (owner only)
+    /// Only `remaining_quantity` is released: shares sold through `match_sell_orders`
+    /// were already burned out of both the balance and the lock
+    /// Debug: Sell counterpart of `cancel_order`; sell orders hold no SOL to refund
+    pub fn cancel_sell_order(
+        ctx: Context<CancelSellOrder>,
     ) -> Result<()> {
-        let orderbook = &ctx.accounts.orderbook;
+        let orderbook = &mut ctx.accounts.orderbook;
+        let order = &mut ctx.accounts.order;
         let user_shares = &mut ctx.accounts.user_shares;
         let user = &ctx.accounts.user;
         
-        require!(!orderbook.is_active, ErrorCode::MarketStillActive);
-        require!(user_shares.owner == user.key(), ErrorCode::Unauthorized);
+        require!(order.owner == user.key(), ErrorCode::Unauthorized);
+        require!(order.market_id == orderbook.market_id, ErrorCode::MarketMismatch);
+        require!(order.is_sell, ErrorCode::NotASellOrder);
+        require!(
+            order.status == OrderStatus::Open || order.status == OrderStatus::PartiallyFilled,
+            ErrorCode::OrderNotCancellable
+        );
+        require_min_order_lifetime(orderbook, order)?;
         
-        let shares_to_redeem = match winning_outcome {
-            OrderSide::Yes => user_shares.yes_shares,
-            OrderSide::No => user_shares.no_shares,
+        let shares_unlocked = order.remaining_quantity;
+        let locked = match order.side {
+            OrderSide::Yes => &mut user_shares.yes_shares_locked,
+            OrderSide::No => &mut user_shares.no_shares_locked,
         };
+        require!(*locked >= shares_unlocked, ErrorCode::InsufficientShares);
+        *locked -= shares_unlocked;
         
-        require!(shares_to_redeem > 0, ErrorCode::NoSharesToRedeem);
+        // Debug: Log cancellation
+        msg!("DEBUG: Cancelling sell order {:?}, unlocking {} of {} shares",
+            order.order_id, shares_unlocked, order.original_quantity);
         
-        // Winning shares are worth $1 each
-        let payout = shares_to_redeem
-            .checked_mul(orderbook.one_dollar_lamports)
-            .ok_or(ErrorCode::MathOverflow)?;
+        order.status = OrderStatus::Cancelled;
+        close_order_slot(orderbook, &order.side);
         
-        // Debug: Log redemption
-        msg!("DEBUG: Redeeming {} {} shares for {} lamports",
-            shares_to_redeem,
-            if winning_outcome == OrderSide::Yes { "YES" } else { "NO" },
-            payout);
+        emit!(SellOrderCancelled {
+            order_id: order.order_id,
+            owner: user.key(),
+            shares_unlocked,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
         
-        // Zero out shares
-        match winning_outcome {
-            OrderSide::Yes => user_shares.yes_shares = 0,
-            OrderSide::No => user_shares.no_shares = 0,
+        Ok(())
+    }
+
+    /// Withdraw the surplus left on a fully filled buy order (owner only)
+    /// Covers fills below the limit price and the proceeds of netted orders
+    pub fn claim_order_surplus(
+        ctx: Context<ClaimOrderSurplus>,
+    ) -> Result<()> {
+        let orderbook = &mut ctx.accounts.orderbook;
+        let vault = VaultSigner {
+            vault: &ctx.accounts.vault,
+            bump: ctx.bumps.vault,
+            system_program: &ctx.accounts.system_program,
+        };
+        let order = &mut ctx.accounts.order;
+        let user = &ctx.accounts.user;
+        
+        require!(order.owner == user.key(), ErrorCode::Unauthorized);
+        require!(order.market_id == orderbook.market_id, ErrorCode::MarketMismatch);
+        require!(order.status == OrderStatus::Filled, ErrorCode::OrderNotFilled);
+        
+        let surplus_lamports = order.surplus_lamports;
+        require!(surplus_lamports > 0, ErrorCode::InvalidAmount);
+        
+        order.surplus_lamports = 0;
+        pay_from_vault(orderbook, &vault, user, surplus_lamports)?;
+        adjust_open_collateral(orderbook, surplus_lamports, 0)?;
+        
+        // Debug: Log surplus claim
+        msg!("DEBUG: Order {:?} surplus of {} lamports claimed", order.order_id, surplus_lamports);
+        
+        Ok(())
+    }
+
+    /// Settle an order left open at resolution (permissionless crank)
+    /// Buy orders get their remaining collateral refunded, sell orders get their
+    /// locked shares released; either way the order ends up Cancelled
+    /// Debug: Lets the vault be fully reconciled after the market resolves
+    pub fn settle_open_order(
+        ctx: Context<SettleOpenOrder>,
+    ) -> Result<()> {
+        let orderbook = &mut ctx.accounts.orderbook;
+        let vault = VaultSigner {
+            vault: &ctx.accounts.vault,
+            bump: ctx.bumps.vault,
+            system_program: &ctx.accounts.system_program,
+        };
+        let order = &mut ctx.accounts.order;
+        
+        require!(!orderbook.is_active, ErrorCode::MarketStillActive);
+        require!(
+            order.status == OrderStatus::Open || order.status == OrderStatus::PartiallyFilled,
+            ErrorCode::OrderNotCancellable
+        );
+        
+        let mut refund_lamports = 0;
+        let mut shares_unlocked = 0;
+        
+        if order.is_sell {
+            let user_shares = ctx.accounts.user_shares
+                .as_mut()
+                .ok_or(ErrorCode::MissingUserShares)?;
+            
+            shares_unlocked = order.remaining_quantity;
+            match order.side {
+                OrderSide::Yes => {
+                    user_shares.yes_shares_locked = user_shares.yes_shares_locked
+                        .checked_sub(shares_unlocked)
+                        .ok_or(ErrorCode::MathOverflow)?;
+                },
+                OrderSide::No => {
+                    user_shares.no_shares_locked = user_shares.no_shares_locked
+                        .checked_sub(shares_unlocked)
+                        .ok_or(ErrorCode::MathOverflow)?;
+                },
+            }
+        } else {
+            refund_lamports = calculate_refund_lamports(order)?;
+            
+            pay_from_vault(orderbook, &vault, &ctx.accounts.owner, refund_lamports)?;
+            release_posted_shares(order, ctx.accounts.user_shares.as_deref_mut())?;
         }
         
-        // Transfer payout
-        **ctx.accounts.vault.try_borrow_mut_lamports()? -= payout;
-        **user.try_borrow_mut_lamports()? += payout;
+        // Debug: Log settlement
+        msg!("DEBUG: Settled order {:?} - refunded {} lamports, unlocked {} shares",
+            order.order_id, refund_lamports, shares_unlocked);
         
-        emit!(SharesRedeemed {
-            owner: user.key(),
+        let depth = resting_notional(orderbook, order)?;
+        order.surplus_lamports = 0;
+        order.status = OrderStatus::Cancelled;
+        close_order_slot(orderbook, &order.side);
+        adjust_open_collateral(orderbook, refund_lamports, 0)?;
+        adjust_resting_notional(orderbook, &order.side, depth, 0)?;
+        
+        emit!(OrderSettled {
+            order_id: order.order_id,
+            owner: order.owner,
             market_id: orderbook.market_id,
-            winning_outcome,
-            shares_redeemed: shares_to_redeem,
-            payout_lamports: payout,
+            refund_lamports,
+            shares_unlocked,
             timestamp: Clock::get()?.unix_timestamp,
         });
         
         Ok(())
     }
-}
-
-// ============================================================================
-// Account Structures
-// ============================================================================
 
-#[account]
-pub struct Orderbook {
-    pub authority: Pubkey,
-    pub market_id: Pubkey,
-    pub one_dollar_lamports: u64,    // SOL equivalent of $1
-    pub yes_order_count: u64,
-    pub no_order_count: u64,
-    pub total_yes_shares: u64,       // Total YES shares in circulation
-    pub total_no_shares: u64,        // Total NO shares in circulation
-    pub total_volume_lamports: u64,  // Total trading volume
-    pub last_yes_price: u64,         // Last matched YES price
-    pub last_no_price: u64,          // Last matched NO price
-    pub created_at: i64,
-    pub is_active: bool,
-}
+    /// Redeem winning shares after market resolution
+    /// Winners get $1 per share at the SOL rate locked on resolution, losers get $0
+    /// `redemption_fee_bps` of the payout accrues in the market's FeeVault
+    /// `quantity == 0` redeems the full free winning balance; shares locked by open
+    /// orders are redeemable once `settle_open_order` has released them
+    /// Debug: Pays out winners after market resolution
+    pub fn redeem_shares(
+        ctx: Context<RedeemShares>,
+        winning_outcome: OrderSide,
+        quantity: u64,
+    ) -> Result<()> {
+        let orderbook = &mut ctx.accounts.orderbook;
+        let vault = VaultSigner {
+            vault: &ctx.accounts.vault,
+            bump: ctx.bumps.vault,
+            system_program: &ctx.accounts.system_program,
+        };
+        let user_shares = &mut ctx.accounts.user_shares;
+        let user = &ctx.accounts.user;
+        
+        require!(!orderbook.is_active, ErrorCode::MarketStillActive);
+        require!(
+            orderbook.winning_outcome == Some(winning_outcome.clone()),
+            ErrorCode::InvalidOutcome
+        );
+        require!(user_shares.owner == user.key(), ErrorCode::Unauthorized);
+        
+        // Shares still locked by open sell orders or netted buys stay put until settled
+        let shares_held = match winning_outcome {
+            OrderSide::Yes => user_shares.yes_shares.saturating_sub(user_shares.yes_shares_locked),
+            OrderSide::No => user_shares.no_shares.saturating_sub(user_shares.no_shares_locked),
+        };
+        
+        require!(shares_held > 0, ErrorCode::NoSharesToRedeem);
+        require!(quantity <= shares_held, ErrorCode::InsufficientShares);
+        
+        let shares_to_redeem = if quantity == 0 { shares_held } else { quantity };
+        
+        // Winning shares are worth $1 each at the rate locked on resolution
+        let gross_payout = calculate_cost_lamports(
+            PRICE_PRECISION,
+            shares_to_redeem,
+            orderbook.resolution_dollar_lamports,
+            orderbook.share_decimals,
+        )?;
+        let fee_lamports = calculate_fee_lamports(gross_payout, orderbook.redemption_fee_bps)?;
+        let payout = gross_payout - fee_lamports;
+        
+        // Debug: Log redemption
+        msg!("DEBUG: Redeeming {} {} shares for {} lamports ({} fee)",
+            shares_to_redeem,
+            if winning_outcome == OrderSide::Yes { "YES" } else { "NO" },
+            payout,
+            fee_lamports);
+        
+        // Burn redeemed shares
+        match winning_outcome {
+            OrderSide::Yes => {
+                user_shares.yes_shares -= shares_to_redeem;
+                orderbook.total_yes_shares = orderbook.total_yes_shares.saturating_sub(shares_to_redeem);
+            },
+            OrderSide::No => {
+                user_shares.no_shares -= shares_to_redeem;
+                orderbook.total_no_shares = orderbook.total_no_shares.saturating_sub(shares_to_redeem);
+            },
+        }
+        
+        // Transfer payout
+        pay_from_vault(orderbook, &vault, user, payout)?;
+        if fee_lamports > 0 {
+            let fee_vault = &mut ctx.accounts.fee_vault;
+            pay_from_vault(orderbook, &vault, &fee_vault.to_account_info(), fee_lamports)?;
+            fee_vault.total_accrued_lamports = fee_vault.total_accrued_lamports
+                .checked_add(fee_lamports)
+                .ok_or(ErrorCode::MathOverflow)?;
+        }
+        
+        emit!(SharesRedeemed {
+            owner: user.key(),
+            market_id: orderbook.market_id,
+            winning_outcome,
+            shares_redeemed: shares_to_redeem,
+            payout_lamports: payout,
+            fee_lamports,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        
+        Ok(())
+    }
 
-#[account]
-pub struct Order {
-    pub order_id: Pubkey,
-    pub owner: Pubkey,
-    pub market_id: Pubkey,
-    pub side: OrderSide,
-    pub price: u64,                  // Price in PRICE_PRECISION units
-    pub original_quantity: u64,
-    pub filled_quantity: u64,
-    pub remaining_quantity: u64,
-    pub lamports_deposited: u64,
-    pub status: OrderStatus,
-    pub is_sell: bool,               // true if selling shares, false if buying
-    pub created_at: i64,
-}
+    /// Mint `quantity` complete sets (1 YES + 1 NO share token each) for $1 per set
+    /// Deposits quantity * one_dollar_lamports at the current SOL rate, rounded up, and
+    /// mints both sides to the user's token accounts; open to anyone once trading is enabled
+    /// Debug: Token counterpart of `seed_complete_sets`; the AMM's `buy_yes_with_sol` calls
+    /// this by CPI, so the account order is part of that interface
+    pub fn mint_complete_set_tokens(
+        ctx: Context<MintCompleteSetTokens>,
+        quantity: u64,
+    ) -> Result<()> {
+        let orderbook = &mut ctx.accounts.orderbook;
+        let user = &ctx.accounts.user;
+        
+        require!(orderbook.is_active, ErrorCode::OrderbookInactive);
+        require!(orderbook.trading_enabled, ErrorCode::TradingNotEnabled);
+        require!(orderbook.yes_mint != Pubkey::default(), ErrorCode::ShareTokensNotEnabled);
+        require!(quantity > 0, ErrorCode::InvalidAmount);
+        
+        let cost_lamports = calculate_collateral_lamports(
+            PRICE_PRECISION,
+            quantity,
+            orderbook.one_dollar_lamports,
+            orderbook.share_decimals,
+        )?;
+        
+        let cpi_context = CpiContext::new(
+            ctx.accounts.system_program.to_account_info(),
+            system_program::Transfer {
+                from: user.to_account_info(),
+                to: ctx.accounts.vault.to_account_info(),
+            },
+        );
+        system_program::transfer(cpi_context, cost_lamports)?;
+        add_vault_collateral(orderbook, cost_lamports)?;
+        
+        let market_id = orderbook.market_id;
+        let seeds = &[
+            b"orderbook",
+            market_id.as_ref(),
+            &[orderbook.bump],
+        ];
+        let signer = &[&seeds[..]];
+        
+        token::mint_to(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                token::MintTo {
+                    mint: ctx.accounts.yes_mint.to_account_info(),
+                    to: ctx.accounts.user_yes_token_account.to_account_info(),
+                    authority: orderbook.to_account_info(),
+                },
+                signer,
+            ),
+            quantity,
+        )?;
+        token::mint_to(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                token::MintTo {
+                    mint: ctx.accounts.no_mint.to_account_info(),
+                    to: ctx.accounts.user_no_token_account.to_account_info(),
+                    authority: orderbook.to_account_info(),
+                },
+                signer,
+            ),
+            quantity,
+        )?;
+        
+        orderbook.total_yes_shares = orderbook.total_yes_shares
+            .checked_add(quantity)
+            .ok_or(ErrorCode::MathOverflow)?;
+        orderbook.total_no_shares = orderbook.total_no_shares
+            .checked_add(quantity)
+            .ok_or(ErrorCode::MathOverflow)?;
+        add_set_collateral(orderbook, cost_lamports)?;
+        
+        // Debug: Log mint
+        msg!("DEBUG: Minted {} complete sets for {} lamports", quantity, cost_lamports);
+        
+        emit!(CompleteSetMinted {
+            owner: user.key(),
+            market_id,
+            quantity,
+            cost_lamports,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        
+        Ok(())
+    }
 
-#[account]
-pub struct UserShares {
-    pub owner: Pubkey,
-    pub market_id: Pubkey,
-    pub yes_shares: u64,
-    pub no_shares: u64,
-    pub yes_shares_locked: u64,      // Locked in pending sell orders
-    pub no_shares_locked: u64,       // Locked in pending sell orders
+    /// Unwind `quantity` complete sets (1 YES + 1 NO each) before resolution
+    /// Pays the sets' share of the SOL deposited to mint them (`set_collateral_lamports`),
+    /// not today's $1, with no redemption fee - unlike `redeem_shares`
+    /// Burns share counters, or the YES and NO tokens once share tokens are enabled
+    /// Debug: Only free shares count; those locked in sell orders or netted buys stay put
+    pub fn redeem_complete_set(
+        ctx: Context<RedeemCompleteSet>,
+        quantity: u64,
+    ) -> Result<()> {
+        let orderbook = &mut ctx.accounts.orderbook;
+        let vault = VaultSigner {
+            vault: &ctx.accounts.vault,
+            bump: ctx.bumps.vault,
+            system_program: &ctx.accounts.system_program,
+        };
+        let user = &ctx.accounts.user;
+        
+        require!(orderbook.is_active, ErrorCode::OrderbookInactive);
+        require!(quantity > 0, ErrorCode::InvalidAmount);
+        
+        // Burn one of each side
+        if orderbook.yes_mint == Pubkey::default() {
+            let user_shares = ctx.accounts.user_shares
+                .as_mut()
+                .ok_or(ErrorCode::MissingUserShares)?;
+            require!(
+                user_shares.yes_shares.saturating_sub(user_shares.yes_shares_locked) >= quantity
+                    && user_shares.no_shares.saturating_sub(user_shares.no_shares_locked) >= quantity,
+                ErrorCode::InsufficientShares
+            );
+            user_shares.yes_shares -= quantity;
+            user_shares.no_shares -= quantity;
+        } else {
+            let (Some(yes_mint), Some(no_mint), Some(yes_token_account), Some(no_token_account), Some(token_program)) = (
+                ctx.accounts.yes_mint.as_ref(),
+                ctx.accounts.no_mint.as_ref(),
+                ctx.accounts.user_yes_token_account.as_ref(),
+                ctx.accounts.user_no_token_account.as_ref(),
+                ctx.accounts.token_program.as_ref(),
+            ) else {
+                return err!(ErrorCode::MissingShareTokenAccounts);
+            };
+            require!(yes_mint.key() == orderbook.yes_mint, ErrorCode::InvalidShareMint);
+            require!(no_mint.key() == orderbook.no_mint, ErrorCode::InvalidShareMint);
+            
+            for (mint, token_account) in [(yes_mint, yes_token_account), (no_mint, no_token_account)] {
+                token::burn(
+                    CpiContext::new(
+                        token_program.to_account_info(),
+                        token::Burn {
+                            mint: mint.to_account_info(),
+                            from: token_account.to_account_info(),
+                            authority: user.to_account_info(),
+                        },
+                    ),
+                    quantity,
+                )?;
+            }
+        }
+        
+        let payout = retire_set_collateral(orderbook, quantity)?;
+        orderbook.total_yes_shares = orderbook.total_yes_shares.saturating_sub(quantity);
+        orderbook.total_no_shares = orderbook.total_no_shares.saturating_sub(quantity);
+        
+        // Debug: Log redemption
+        msg!("DEBUG: Redeeming {} complete sets for {} lamports ({} set collateral left)",
+            quantity, payout, orderbook.set_collateral_lamports);
+        
+        pay_from_vault(orderbook, &vault, user, payout)?;
+        
+        emit!(CompleteSetRedeemed {
+            owner: user.key(),
+            market_id: orderbook.market_id,
+            quantity,
+            payout_lamports: payout,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        
+        Ok(())
+    }
+
+    /// Quote the SOL a `place_order` with these parameters would pull from the user
+    /// Validates exactly like `place_order`, so an invalid order fails here too
+    /// Debug: Returns (cost_lamports, fee_lamports, total_lamports) via return data
+    pub fn quote_order(
+        ctx: Context<QuoteOrder>,
+        price: u64,
+        quantity: u64,
+    ) -> Result<()> {
+        let orderbook = &ctx.accounts.orderbook;
+        
+        require!(orderbook.is_active, ErrorCode::OrderbookInactive);
+        require!(price > 0 && price < PRICE_PRECISION, ErrorCode::InvalidPrice);
+        require!(quantity > 0, ErrorCode::InvalidAmount);
+        
+        let cost_lamports = calculate_collateral_lamports(
+            price,
+            quantity,
+            orderbook.one_dollar_lamports,
+            orderbook.share_decimals,
+        )?;
+        // Taker fee reserved at placement; refunded if the order fills as maker
+        let fee_lamports = calculate_fee_lamports(cost_lamports, orderbook.taker_fee_bps)?;
+        let total_lamports = cost_lamports
+            .checked_add(fee_lamports)
+            .ok_or(ErrorCode::MathOverflow)?;
+        
+        // Debug: Log quote
+        msg!("DEBUG: Quote price: {}, qty: {} -> cost: {}, fee: {} lamports",
+            price, quantity, cost_lamports, fee_lamports);
+        
+        set_return_data(&(cost_lamports, fee_lamports, total_lamports).try_to_vec()?);
+        
+        Ok(())
+    }
+
+    /// Check the vault holds enough SOL to honour every outstanding claim
+    /// Before resolution: required = min(total_yes_shares, total_no_shares) * one_dollar_lamports
+    ///                              / 10^share_decimals + open_buy_collateral_lamports
+    /// After resolution:  required = winning-side total shares * resolution_dollar_lamports
+    ///                              / 10^share_decimals + open_buy_collateral_lamports
+    /// Both also include fee_pot_lamports (taker fees held for maker rebates) and
+    /// creator_fees_lamports (unclaimed creator royalties)
+    /// Solvent when vault_balance - rent_exempt_minimum >= required, since the vault
+    /// can never be debited below its rent reserve
+    /// Debug: Returns (vault_balance, required_backing, is_solvent, total_collateral_lamports)
+    /// via return data; initialize_orderbook funds the rent reserve separately, so
+    /// total_collateral_lamports equals vault_balance - rent_exempt_minimum unless
+    /// lamports were sent to the vault directly
+    pub fn check_collateralization(ctx: Context<CheckCollateralization>) -> Result<()> {
+        let orderbook = &ctx.accounts.orderbook;
+        let vault = &ctx.accounts.vault;
+        
+        let (backed_sets, dollar_lamports) = match orderbook.winning_outcome {
+            Some(OrderSide::Yes) => (orderbook.total_yes_shares, orderbook.resolution_dollar_lamports),
+            Some(OrderSide::No) => (orderbook.total_no_shares, orderbook.resolution_dollar_lamports),
+            None => (
+                std::cmp::min(orderbook.total_yes_shares, orderbook.total_no_shares),
+                orderbook.one_dollar_lamports,
+            ),
+        };
+        let required_backing = calculate_cost_lamports(
+            PRICE_PRECISION,
+            backed_sets,
+            dollar_lamports,
+            orderbook.share_decimals,
+        )?
+            .checked_add(orderbook.open_buy_collateral_lamports)
+            .ok_or(ErrorCode::MathOverflow)?
+            .checked_add(orderbook.fee_pot_lamports)
+            .ok_or(ErrorCode::MathOverflow)?
+            .checked_add(orderbook.creator_fees_lamports)
+            .ok_or(ErrorCode::MathOverflow)?;
+        
+        let vault_balance = vault.lamports();
+        let rent_exempt_minimum = Rent::get()?.minimum_balance(vault.data_len());
+        let is_solvent = vault_balance.saturating_sub(rent_exempt_minimum) >= required_backing;
+        
+        // Debug: Log collateralization
+        msg!("DEBUG: Vault {} lamports, required {} lamports, solvent: {}",
+            vault_balance, required_backing, is_solvent);
+        msg!("DEBUG: Recorded collateral {} lamports, vault non-rent balance {} lamports",
+            orderbook.total_collateral_lamports, vault_balance.saturating_sub(rent_exempt_minimum));
+        
+        set_return_data(&(
+            vault_balance,
+            required_backing,
+            is_solvent,
+            orderbook.total_collateral_lamports,
+        ).try_to_vec()?);
+        
+        Ok(())
+    }
+
+    /// Get a user's share position (returned via return data)
+    /// Open orders are not aggregated here - enumerate them client-side with
+    /// getProgramAccounts filtered on `Order.owner`
+    /// Debug: Read-only view over the UserShares account
+    pub fn get_user_position(ctx: Context<GetUserPosition>) -> Result<UserPosition> {
+        let user_shares = &ctx.accounts.user_shares;
+        
+        Ok(UserPosition {
+            yes_shares: user_shares.yes_shares,
+            no_shares: user_shares.no_shares,
+            yes_shares_locked: user_shares.yes_shares_locked,
+            no_shares_locked: user_shares.no_shares_locked,
+        })
+    }
+
+    /// Live state of a single order, so clients don't decode the account layout
+    /// status: 0 = Open, 1 = PartiallyFilled, 2 = Filled, 3 = Cancelled
+    /// Debug: Returns (status, filled_quantity, remaining_quantity, lamports_deposited, is_sell)
+    /// via return data; a non-Order account fails Anchor's discriminator check
+    pub fn get_order_status(ctx: Context<GetOrderStatus>) -> Result<()> {
+        let order = &ctx.accounts.order;
+        
+        let status: u8 = match order.status {
+            OrderStatus::Open => 0,
+            OrderStatus::PartiallyFilled => 1,
+            OrderStatus::Filled => 2,
+            OrderStatus::Cancelled => 3,
+        };
+        
+        msg!("DEBUG: Order {} status {}, filled {}, remaining {}",
+            order.order_id, status, order.filled_quantity, order.remaining_quantity);
+        
+        set_return_data(&(
+            status,
+            order.filled_quantity,
+            order.remaining_quantity,
+            order.lamports_deposited,
+            order.is_sell,
+        ).try_to_vec()?);
+        
+        Ok(())
+    }
 }
 
 // ============================================================================
-// Enums
+// Helpers
 // ============================================================================
 
-#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq)]
-pub enum OrderSide {
-    Yes,
-    No,
+/// Value `quantity` base units at `price` in lamports, rounded down
+/// cost = price * quantity * one_dollar_lamports / (PRICE_PRECISION * 10^share_decimals)
+/// The product is formed in u128 (it overflows u64 for medium orders) and only the
+/// result is narrowed, failing with MathOverflow if it does not fit in u64
+/// Debug: Values payouts, refunds and backing - pass PRICE_PRECISION as price for
+/// $1 per share; collateral taken from users uses `calculate_collateral_lamports`
+fn calculate_cost_lamports(
+    price: u64,
+    quantity: u64,
+    one_dollar_lamports: u64,
+    share_decimals: u8,
+) -> Result<u64> {
+    let (numerator, denominator) =
+        cost_fraction(price, quantity, one_dollar_lamports, share_decimals)?;
+    u64::try_from(numerator / denominator).map_err(|_| ErrorCode::MathOverflow.into())
 }
 
-#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq)]
-pub enum OrderStatus {
-    Open,
-    PartiallyFilled,
-    Filled,
-    Cancelled,
+/// Calculate SOL collateral required to buy `quantity` base units at `price`
+/// Same formula as `calculate_cost_lamports` but rounded up, so deposits always
+/// cover the full notional while payouts and refunds round down
+fn calculate_collateral_lamports(
+    price: u64,
+    quantity: u64,
+    one_dollar_lamports: u64,
+    share_decimals: u8,
+) -> Result<u64> {
+    let (numerator, denominator) =
+        cost_fraction(price, quantity, one_dollar_lamports, share_decimals)?;
+    let cost = numerator
+        .checked_add(denominator - 1)
+        .ok_or(ErrorCode::MathOverflow)?
+        / denominator;
+    u64::try_from(cost).map_err(|_| ErrorCode::MathOverflow.into())
 }
 
-// ============================================================================
-// Context Structs
-// ============================================================================
+/// (price * quantity * one_dollar_lamports, PRICE_PRECISION * 10^share_decimals)
+fn cost_fraction(
+    price: u64,
+    quantity: u64,
+    one_dollar_lamports: u64,
+    share_decimals: u8,
+) -> Result<(u128, u128)> {
+    let denominator = (PRICE_PRECISION as u128)
+        .checked_mul(10u128.pow(share_decimals as u32))
+        .ok_or(ErrorCode::MathOverflow)?;
+    let numerator = (price as u128)
+        .checked_mul(quantity as u128)
+        .ok_or(ErrorCode::MathOverflow)?
+        .checked_mul(one_dollar_lamports as u128)
+        .ok_or(ErrorCode::MathOverflow)?;
+    Ok((numerator, denominator))
+}
 
-#[derive(Accounts)]
-#[instruction(market_id: Pubkey)]
-pub struct InitializeOrderbook<'info> {
-    #[account(mut)]
-    pub authority: Signer<'info>,
-    
-    #[account(
-        init,
-        payer = authority,
-        space = 8 + 32 + 32 + 8 + 8 + 8 + 8 + 8 + 8 + 8 + 8 + 8 + 1,
-        seeds = [b"orderbook", market_id.as_ref()],
-        bump
-    )]
-    pub orderbook: Account<'info, Orderbook>,
-    
-    /// CHECK: Vault PDA for holding SOL collateral
-    #[account(
-        mut,
-        seeds = [b"vault", market_id.as_ref()],
-        bump
-    )]
-    pub vault: AccountInfo<'info>,
-    
-    pub system_program: Program<'info, System>,
+/// Record lamports paid into the vault against `total_collateral_lamports`
+fn add_vault_collateral(orderbook: &mut Orderbook, amount: u64) -> Result<()> {
+    orderbook.total_collateral_lamports = orderbook.total_collateral_lamports
+        .checked_add(amount)
+        .ok_or(ErrorCode::MathOverflow)?;
+    Ok(())
 }
 
-#[derive(Accounts)]
-pub struct UpdateSolPrice<'info> {
-    pub authority: Signer<'info>,
-    
-    #[account(mut)]
-    pub orderbook: Account<'info, Orderbook>,
+/// The collateral vault plus what a payout out of it has to sign with
+/// Debug: The vault is a system-owned PDA, so only a System Program transfer
+/// signed with its seeds can debit it
+struct VaultSigner<'a, 'info> {
+    vault: &'a AccountInfo<'info>,
+    bump: u8,
+    system_program: &'a AccountInfo<'info>,
 }
 
-#[derive(Accounts)]
-#[instruction(order_id: Pubkey)]
-pub struct PlaceOrder<'info> {
-    #[account(mut)]
-    pub user: Signer<'info>,
+/// Pay lamports out of the vault and release them from `total_collateral_lamports`
+/// Debug: Every vault debit goes through here so the figure tracks the vault's
+/// non-rent balance; a payout larger than the recorded collateral is rejected
+fn pay_from_vault<'info>(
+    orderbook: &mut Orderbook,
+    vault: &VaultSigner<'_, 'info>,
+    to: &AccountInfo<'info>,
+    amount: u64,
+) -> Result<()> {
+    orderbook.total_collateral_lamports = orderbook.total_collateral_lamports
+        .checked_sub(amount)
+        .ok_or(ErrorCode::MathOverflow)?;
     
-    #[account(mut)]
-    pub orderbook: Account<'info, Orderbook>,
+    let rent_exempt_minimum = Rent::get()?.minimum_balance(vault.vault.data_len());
+    let remaining = vault.vault
+        .lamports()
+        .checked_sub(amount)
+        .ok_or(ErrorCode::WouldBreakRentExemption)?;
+    require!(remaining >= rent_exempt_minimum, ErrorCode::WouldBreakRentExemption);
     
-    #[account(
-        init,
-        payer = user,
-        space = 8 + 32 + 32 + 32 + 1 + 8 + 8 + 8 + 8 + 8 + 1 + 1 + 8,
-        seeds = [b"order", order_id.as_ref()],
-        bump
-    )]
-    pub order: Account<'info, Order>,
+    let market_id = orderbook.market_id;
+    let seeds = &[
+        b"vault",
+        market_id.as_ref(),
+        &[vault.bump],
+    ];
+    let signer = &[&seeds[..]];
     
-    /// CHECK: Vault for SOL collateral
-    #[account(mut)]
-    pub vault: AccountInfo<'info>,
+    system_program::transfer(
+        CpiContext::new_with_signer(
+            vault.system_program.clone(),
+            system_program::Transfer {
+                from: vault.vault.clone(),
+                to: to.clone(),
+            },
+            signer,
+        ),
+        amount,
+    )?;
+    Ok(())
+}
+
+/// Record SOL deposited behind newly minted complete sets
+fn add_set_collateral(orderbook: &mut Orderbook, amount: u64) -> Result<()> {
+    orderbook.set_collateral_lamports = orderbook.set_collateral_lamports
+        .checked_add(amount)
+        .ok_or(ErrorCode::MathOverflow)?;
+    Ok(())
+}
+
+/// Release `sets` complete sets' pro-rata share of `set_collateral_lamports`
+/// and return it: sets * set_collateral / outstanding sets, rounded down
+/// Debug: Call before total_yes_shares / total_no_shares drop by `sets`
+fn retire_set_collateral(orderbook: &mut Orderbook, sets: u64) -> Result<u64> {
+    let outstanding = std::cmp::min(orderbook.total_yes_shares, orderbook.total_no_shares);
+    require!(sets <= outstanding, ErrorCode::InsufficientShares);
+    let share = (orderbook.set_collateral_lamports as u128)
+        .checked_mul(sets as u128)
+        .ok_or(ErrorCode::MathOverflow)?
+        / outstanding as u128;
+    let share = u64::try_from(share).map_err(|_| ErrorCode::MathOverflow)?;
+    orderbook.set_collateral_lamports -= share;
+    Ok(share)
+}
+
+/// Move lamports out of a program-owned PDA without dropping it below rent exemption
+/// Debug: Only for accounts this program owns (the fee vault); the system-owned
+/// collateral vault pays out through `pay_from_vault`
+fn debit_pda<'info>(
+    from: &AccountInfo<'info>,
+    to: &AccountInfo<'info>,
+    amount: u64,
+) -> Result<()> {
+    let rent_exempt_minimum = Rent::get()?.minimum_balance(from.data_len());
+    let remaining = from
+        .lamports()
+        .checked_sub(amount)
+        .ok_or(ErrorCode::WouldBreakRentExemption)?;
+    require!(remaining >= rent_exempt_minimum, ErrorCode::WouldBreakRentExemption);
     
-    pub system_program: Program<'info, System>,
+    **from.try_borrow_mut_lamports()? = remaining;
+    **to.try_borrow_mut_lamports()? = to
+        .lamports()
+        .checked_add(amount)
+        .ok_or(ErrorCode::MathOverflow)?;
+    Ok(())
 }
 
-#[derive(Accounts)]
-pub struct MatchOrders<'info> {
-    #[account(mut)]
-    pub orderbook: Account<'info, Orderbook>,
+/// Reserve an open-order slot on `side`, enforcing `max_open_orders` across both sides
+fn open_order_slot(orderbook: &mut Orderbook, side: &OrderSide) -> Result<()> {
+    let open_orders = orderbook.open_yes_orders
+        .checked_add(orderbook.open_no_orders)
+        .ok_or(ErrorCode::MathOverflow)?;
+    require!(
+        orderbook.max_open_orders == 0 || open_orders < orderbook.max_open_orders,
+        ErrorCode::TooManyOpenOrders
+    );
     
-    #[account(mut)]
-    pub yes_order: Account<'info, Order>,
+    match side {
+        OrderSide::Yes => orderbook.open_yes_orders += 1,
+        OrderSide::No => orderbook.open_no_orders += 1,
+    }
+    Ok(())
+}
+
+/// Release an open-order slot once an order is filled, cancelled or settled
+fn close_order_slot(orderbook: &mut Orderbook, side: &OrderSide) {
+    match side {
+        OrderSide::Yes => orderbook.open_yes_orders = orderbook.open_yes_orders.saturating_sub(1),
+        OrderSide::No => orderbook.open_no_orders = orderbook.open_no_orders.saturating_sub(1),
+    }
+}
+
+/// Calculate SOL owed back on an order: unfilled collateral + fill surplus
+/// unfilled = lamports_deposited * remaining / original, in u128 and narrowed checked
+/// Both parts are at the order's `rate_at_placement`, never the current SOL price
+fn calculate_refund_lamports(order: &Order) -> Result<u64> {
+    if order.original_quantity == 0 {
+        return Ok(order.surplus_lamports);
+    }
+    let unfilled_refund = (order.lamports_deposited as u128)
+        .checked_mul(order.remaining_quantity as u128)
+        .ok_or(ErrorCode::MathOverflow)?
+        / order.original_quantity as u128;
+    let unfilled_refund = u64::try_from(unfilled_refund).map_err(|_| ErrorCode::MathOverflow)?;
+    let refund = unfilled_refund
+        .checked_add(order.surplus_lamports)
+        .ok_or(ErrorCode::MathOverflow)?;
+    Ok(refund)
+}
+
+/// Cancel a buy order a fill left below the orderbook's dust threshold and
+/// refund its residual collateral plus surplus to the owner
+/// Debug: No-op when the threshold is 0 or the order is not a dust remainder
+fn cancel_dust_order<'info>(
+    orderbook: &mut Orderbook,
+    order: &mut Order,
+    user_shares: Option<&mut UserShares>,
+    vault: &VaultSigner<'_, 'info>,
+    owner: Option<&AccountInfo<'info>>,
+) -> Result<()> {
+    let is_dust = orderbook.dust_threshold > 0
+        && order.status == OrderStatus::PartiallyFilled
+        && order.remaining_quantity < orderbook.dust_threshold;
+    if !is_dust {
+        return Ok(());
+    }
+    
+    let Some(owner) = owner.filter(|owner| owner.key() == order.owner) else {
+        return err!(ErrorCode::MissingOrderOwner);
+    };
+    
+    let refund_lamports = calculate_refund_lamports(order)?;
+    
+    msg!("DEBUG: Auto-cancelling dust order {:?} ({} remaining < {}), refunding {} lamports",
+        order.order_id, order.remaining_quantity, orderbook.dust_threshold, refund_lamports);
+    
+    pay_from_vault(orderbook, vault, owner, refund_lamports)?;
+    release_posted_shares(order, user_shares)?;
+    
+    let depth = resting_notional(orderbook, order)?;
+    order.surplus_lamports = 0;
+    order.status = OrderStatus::Cancelled;
+    close_order_slot(orderbook, &order.side);
+    adjust_open_collateral(orderbook, refund_lamports, 0)?;
+    adjust_resting_notional(orderbook, &order.side, depth, 0)?;
+    
+    emit!(OrderCancelled {
+        order_id: order.order_id,
+        owner: order.owner,
+        refund_lamports,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+    
+    Ok(())
+}
+
+/// Lock `quantity` of the user's free (held but not yet locked) `side` shares
+fn lock_free_shares(user_shares: &mut UserShares, side: &OrderSide, quantity: u64) -> Result<()> {
+    let (held, locked) = match side {
+        OrderSide::Yes => (user_shares.yes_shares, &mut user_shares.yes_shares_locked),
+        OrderSide::No => (user_shares.no_shares, &mut user_shares.no_shares_locked),
+    };
+    let free = held.saturating_sub(*locked);
+    require!(free >= quantity, ErrorCode::InsufficientShares);
+    *locked = locked.checked_add(quantity).ok_or(ErrorCode::MathOverflow)?;
+    Ok(())
+}
+
+/// Lock `quantity` shares opposite to `side` as collateral for a netted buy order
+fn lock_posted_shares(user_shares: &mut UserShares, side: &OrderSide, quantity: u64) -> Result<()> {
+    let posted_side = match side {
+        OrderSide::Yes => OrderSide::No,
+        OrderSide::No => OrderSide::Yes,
+    };
+    lock_free_shares(user_shares, &posted_side, quantity)
+}
+
+/// Unlock the shares still posted by a netted order that is being closed
+/// Debug: No-op for ordinary orders; a netted order needs its owner's UserShares
+fn release_posted_shares(order: &Order, user_shares: Option<&mut UserShares>) -> Result<()> {
+    if !order.is_netted {
+        return Ok(());
+    }
+    let user_shares = user_shares
+        .filter(|user_shares| user_shares.owner == order.owner && user_shares.market_id == order.market_id)
+        .ok_or(ErrorCode::MissingUserShares)?;
+    let locked = match order.side {
+        OrderSide::Yes => &mut user_shares.no_shares_locked,
+        OrderSide::No => &mut user_shares.yes_shares_locked,
+    };
+    *locked = locked
+        .checked_sub(order.remaining_quantity)
+        .ok_or(ErrorCode::MathOverflow)?;
+    Ok(())
+}
+
+/// Settle a netted order's fill: the minted share merges with a posted opposite share
+/// Debug: Burns the posted share instead of crediting a new one; the $1 the merged set
+/// released is credited as fill surplus by record_fill_surplus
+fn merge_posted_shares(user_shares: &mut UserShares, side: &OrderSide, quantity: u64) -> Result<()> {
+    let (held, locked) = match side {
+        OrderSide::Yes => (&mut user_shares.no_shares, &mut user_shares.no_shares_locked),
+        OrderSide::No => (&mut user_shares.yes_shares, &mut user_shares.yes_shares_locked),
+    };
+    *held = held.checked_sub(quantity).ok_or(ErrorCode::MathOverflow)?;
+    *locked = locked.checked_sub(quantity).ok_or(ErrorCode::MathOverflow)?;
+    Ok(())
+}
+
+/// Order PDA for a user's `nonce`-th buy order on `orderbook`
+/// Seeds are [b"order", user, orderbook, nonce as little-endian u64]; nonces come from
+/// `UserShares.order_nonce`, so ids are user-scoped and two users never collide
+pub fn derive_order_pda(user: &Pubkey, orderbook: &Pubkey, nonce: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[b"order", user.as_ref(), orderbook.as_ref(), &nonce.to_le_bytes()],
+        &ID,
+    )
+}
+
+/// Reject cancelling an unfilled order younger than the orderbook's minimum lifetime
+/// Debug: Orders with any fill are exempt, as is every order when the minimum is 0
+fn require_min_order_lifetime(orderbook: &Orderbook, order: &Order) -> Result<()> {
+    if order.filled_quantity == 0 && orderbook.min_order_lifetime_secs > 0 {
+        let age = Clock::get()?.unix_timestamp.saturating_sub(order.created_at);
+        require!(
+            u64::try_from(age).unwrap_or(0) >= orderbook.min_order_lifetime_secs,
+            ErrorCode::OrderTooYoung
+        );
+    }
+    Ok(())
+}
+
+/// Reject a matcher outside the allowlist while matching is permissioned
+/// Debug: Gates match_orders, match_sell_orders and crank_match alike
+fn require_matcher(orderbook: &Orderbook, matcher: &Pubkey) -> Result<()> {
+    require!(
+        !orderbook.permissioned_matching || orderbook.matchers.contains(matcher),
+        ErrorCode::MatcherNotAllowed
+    );
+    Ok(())
+}
+
+/// Maker rebates and the creator royalty are funded solely from taker fees
+/// Debug: Checked after every change to any of the three rates
+fn require_fees_funded(orderbook: &Orderbook) -> Result<()> {
+    require!(
+        (orderbook.maker_rebate_bps as u32) + (orderbook.creator_fee_bps as u32)
+            <= orderbook.taker_fee_bps as u32,
+        ErrorCode::RebateExceedsFee
+    );
+    Ok(())
+}
+
+/// Post-match step shared by `match_orders` and `crank_match`: auto-cancel either
+/// side left below the dust threshold, then pay the matcher its reward
+#[allow(clippy::too_many_arguments)]
+fn finish_match<'info>(
+    orderbook: &mut Orderbook,
+    yes_order: &mut Order,
+    no_order: &mut Order,
+    user_shares: Option<(&mut UserShares, &mut UserShares)>,
+    vault: &VaultSigner<'_, 'info>,
+    owners: (Option<&AccountInfo<'info>>, Option<&AccountInfo<'info>>),
+    matcher: &AccountInfo<'info>,
+) -> Result<()> {
+    let (yes_user_shares, no_user_shares) = match user_shares {
+        Some((yes_user_shares, no_user_shares)) => (Some(yes_user_shares), Some(no_user_shares)),
+        None => (None, None),
+    };
+    cancel_dust_order(orderbook, yes_order, yes_user_shares, vault, owners.0)?;
+    cancel_dust_order(orderbook, no_order, no_user_shares, vault, owners.1)?;
+    pay_matcher_reward(orderbook, vault, matcher)
+}
+
+/// Pay the matcher its reward out of the fee pot
+/// Debug: Capped at fee_pot_lamports, so a thin pot pays less (or nothing) rather
+/// than dipping into collateral
+fn pay_matcher_reward<'info>(
+    orderbook: &mut Orderbook,
+    vault: &VaultSigner<'_, 'info>,
+    matcher: &AccountInfo<'info>,
+) -> Result<()> {
+    let reward = std::cmp::min(orderbook.matcher_reward_lamports, orderbook.fee_pot_lamports);
+    if reward == 0 {
+        return Ok(());
+    }
+    
+    orderbook.fee_pot_lamports -= reward;
+    pay_from_vault(orderbook, vault, matcher, reward)?;
+    
+    msg!("DEBUG: Paid matcher {:?} {} lamports, fee pot now {}",
+        matcher.key(), reward, orderbook.fee_pot_lamports);
+    
+    Ok(())
+}
+
+/// Time priority of a resting order: older first, order id breaks same-slot ties
+fn priority_key(order: &Order) -> (i64, Pubkey) {
+    (order.created_at, order.order_id)
+}
+
+/// Reject a crank selection that skips an older order at the same price
+/// Debug: `counterparty` is the opposite order's owner; orders it owns are
+/// exempt since matching them would be a self-trade
+fn require_time_priority(
+    candidates: &[(Account<Order>, Account<UserShares>)],
+    selected: usize,
+    counterparty: &Pubkey,
+) -> Result<()> {
+    let chosen = &candidates[selected].0;
+    let skipped = candidates.iter().enumerate().any(|(k, (other, _))| {
+        k != selected
+            && other.side == chosen.side
+            && other.price == chosen.price
+            && other.owner != *counterparty
+            && priority_key(other) < priority_key(chosen)
+    });
+    if skipped {
+        msg!("DEBUG: Crank selection skips an older order at price {}", chosen.price);
+        return Err(ErrorCode::PriorityViolation.into());
+    }
+    Ok(())
+}
+
+/// Match a YES buy against a NO buy whose limits cover $1 and mint a complete set per share
+/// Callers validate sides, status and market; this enforces the collateral invariant
+/// `user_shares` = (YES owner, NO owner) counters to credit, or None when the caller
+/// mints SPL share tokens instead
+/// Debug: Shared by `match_orders` and `crank_match`, returns the quantity matched
+fn execute_match(
+    orderbook: &mut Orderbook,
+    yes_order: &mut Order,
+    no_order: &mut Order,
+    user_shares: Option<(&mut UserShares, &mut UserShares)>,
+) -> Result<u64> {
+    // Stamp the slot so resolution cannot land in the same slot as a mint
+    orderbook.last_match_slot = Clock::get()?.slot;
+    
+    // Hard invariant: YES price + NO price must cover $1 (PRICE_PRECISION)
+    // A sub-$1 match would mint complete sets backed by less than $1 of collateral
+    let combined_price = yes_order.price.checked_add(no_order.price)
+        .ok_or(ErrorCode::MathOverflow)?;
+    
+    require!(combined_price >= PRICE_PRECISION, ErrorCode::UndercollateralizedMatch);
+    
+    // Netted orders settle against share counters, which SPL share tokens bypass
+    require!(
+        user_shares.is_some() || !(yes_order.is_netted || no_order.is_netted),
+        ErrorCode::ShareTokensEnabled
+    );
+    
+    // Crossed book: split the excess evenly so both sides execute at or below their limits
+    // and execution prices sum to exactly $1; the excess is refunded as fill surplus
+    let (yes_execution_price, no_execution_price) =
+        calculate_execution_prices(yes_order.price, no_order.price)?;
+    
+    // Calculate match quantity (minimum of both remaining quantities)
+    let match_quantity = std::cmp::min(
+        yes_order.remaining_quantity,
+        no_order.remaining_quantity
+    );
+    
+    require!(match_quantity > 0, ErrorCode::NoMatchQuantity);
+    
+    // Debug: Log match details
+    msg!("DEBUG: Matching orders - YES price: {} (exec {}), NO price: {} (exec {}), qty: {}",
+        yes_order.price, yes_execution_price, no_order.price, no_execution_price, match_quantity);
+    
+    let collateral_before = outstanding_collateral(yes_order)?
+        .checked_add(outstanding_collateral(no_order)?)
+        .ok_or(ErrorCode::MathOverflow)?;
+    let yes_depth_before = resting_notional(orderbook, yes_order)?;
+    let no_depth_before = resting_notional(orderbook, no_order)?;
+    
+    // Record any collateral left over from filling below the limit price
+    record_fill_surplus(yes_order, yes_execution_price, match_quantity, orderbook)?;
+    record_fill_surplus(no_order, no_execution_price, match_quantity, orderbook)?;
+    
+    // The older order was resting (maker), the newer one crossed it (taker)
+    if priority_key(yes_order) <= priority_key(no_order) {
+        settle_match_fees(orderbook, yes_order, yes_execution_price, no_order, match_quantity)?;
+    } else {
+        settle_match_fees(orderbook, no_order, no_execution_price, yes_order, match_quantity)?;
+    }
+    
+    // Update YES order
+    yes_order.filled_quantity += match_quantity;
+    yes_order.remaining_quantity -= match_quantity;
+    if yes_order.remaining_quantity == 0 {
+        yes_order.status = OrderStatus::Filled;
+        close_order_slot(orderbook, &OrderSide::Yes);
+    } else {
+        yes_order.status = OrderStatus::PartiallyFilled;
+    }
+    
+    // Update NO order
+    no_order.filled_quantity += match_quantity;
+    no_order.remaining_quantity -= match_quantity;
+    if no_order.remaining_quantity == 0 {
+        no_order.status = OrderStatus::Filled;
+        close_order_slot(orderbook, &OrderSide::No);
+    } else {
+        no_order.status = OrderStatus::PartiallyFilled;
+    }
+    
+    // Filled collateral now backs the minted sets instead of resting orders
+    let collateral_after = outstanding_collateral(yes_order)?
+        .checked_add(outstanding_collateral(no_order)?)
+        .ok_or(ErrorCode::MathOverflow)?;
+    adjust_open_collateral(orderbook, collateral_before, collateral_after)?;
+    
+    // Matched quantity no longer rests on the book
+    let yes_depth_after = resting_notional(orderbook, yes_order)?;
+    let no_depth_after = resting_notional(orderbook, no_order)?;
+    adjust_resting_notional(orderbook, &OrderSide::Yes, yes_depth_before, yes_depth_after)?;
+    adjust_resting_notional(orderbook, &OrderSide::No, no_depth_before, no_depth_after)?;
+    
+    // Mint shares to respective owners
+    // YES shares go to yes_order.owner
+    // NO shares go to no_order.owner
+    // A netted side merges its new share with the one it posted instead
+    if let Some((yes_user_shares, no_user_shares)) = user_shares {
+        yes_user_shares.owner = yes_order.owner;
+        yes_user_shares.market_id = orderbook.market_id;
+        if yes_order.is_netted {
+            merge_posted_shares(yes_user_shares, &OrderSide::Yes, match_quantity)?;
+        } else {
+            yes_user_shares.yes_shares += match_quantity;
+        }
+        
+        no_user_shares.owner = no_order.owner;
+        no_user_shares.market_id = orderbook.market_id;
+        if no_order.is_netted {
+            merge_posted_shares(no_user_shares, &OrderSide::No, match_quantity)?;
+        } else {
+            no_user_shares.no_shares += match_quantity;
+        }
+    }
+    
+    // Update orderbook state - every merged pair retires one complete set
+    let merged_sets = match_quantity * (yes_order.is_netted as u64 + no_order.is_netted as u64);
+    orderbook.total_yes_shares = (orderbook.total_yes_shares + match_quantity)
+        .checked_sub(merged_sets)
+        .ok_or(ErrorCode::MathOverflow)?;
+    orderbook.total_no_shares = (orderbook.total_no_shares + match_quantity)
+        .checked_sub(merged_sets)
+        .ok_or(ErrorCode::MathOverflow)?;
+    orderbook.last_yes_price = yes_execution_price;
+    orderbook.last_no_price = no_execution_price;
+    
+    // Volume is the collateral each leg actually committed to the fill, so the
+    // per-side totals always sum to total_volume_lamports
+    let yes_volume = fill_volume(yes_order, yes_execution_price, match_quantity, orderbook)?;
+    let no_volume = fill_volume(no_order, no_execution_price, match_quantity, orderbook)?;
+    orderbook.yes_volume_lamports = orderbook.yes_volume_lamports
+        .checked_add(yes_volume)
+        .ok_or(ErrorCode::MathOverflow)?;
+    orderbook.no_volume_lamports = orderbook.no_volume_lamports
+        .checked_add(no_volume)
+        .ok_or(ErrorCode::MathOverflow)?;
+    orderbook.total_volume_lamports = orderbook.total_volume_lamports
+        .checked_add(yes_volume)
+        .and_then(|v| v.checked_add(no_volume))
+        .ok_or(ErrorCode::MathOverflow)?;
+    
+    // The fill deposits both legs' volume behind the new set; each merged set leaves
+    // at $1 today, the same value its netted leg's volume + surplus pays out
+    let merged_lamports = calculate_cost_lamports(
+        PRICE_PRECISION,
+        merged_sets,
+        orderbook.one_dollar_lamports,
+        orderbook.share_decimals,
+    )?;
+    add_set_collateral(orderbook, yes_volume.checked_add(no_volume).ok_or(ErrorCode::MathOverflow)?)?;
+    orderbook.set_collateral_lamports = orderbook.set_collateral_lamports.saturating_sub(merged_lamports);
+    
+    emit!(OrdersMatched {
+        yes_order_id: yes_order.order_id,
+        no_order_id: no_order.order_id,
+        market_id: orderbook.market_id,
+        yes_owner: yes_order.owner,
+        no_owner: no_order.owner,
+        yes_price: yes_execution_price,
+        no_price: no_execution_price,
+        quantity: match_quantity,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+    
+    Ok(match_quantity)
+}
+
+/// Execution prices for a YES/NO buy match whose limits sum to at least $1
+/// Any excess over PRICE_PRECISION is split evenly (odd unit to NO) so
+/// yes_exec + no_exec == PRICE_PRECISION, yes_exec <= yes_price, no_exec <= no_price
+fn calculate_execution_prices(yes_price: u64, no_price: u64) -> Result<(u64, u64)> {
+    let excess = yes_price
+        .checked_add(no_price)
+        .ok_or(ErrorCode::MathOverflow)?
+        .checked_sub(PRICE_PRECISION)
+        .ok_or(ErrorCode::UndercollateralizedMatch)?;
+    
+    let yes_execution_price = yes_price - excess / 2;
+    let no_execution_price = PRICE_PRECISION - yes_execution_price;
+    Ok((yes_execution_price, no_execution_price))
+}
+
+/// SOL an order can still reclaim from the vault: unfilled collateral + surplus
+/// Debug: Sell orders and cancelled orders hold no collateral
+fn outstanding_collateral(order: &Order) -> Result<u64> {
+    if order.is_sell || order.status == OrderStatus::Cancelled {
+        return Ok(0);
+    }
+    calculate_refund_lamports(order)
+}
+
+/// Move `open_buy_collateral_lamports` from an order's old claim to its new one
+fn adjust_open_collateral(orderbook: &mut Orderbook, before: u64, after: u64) -> Result<()> {
+    orderbook.open_buy_collateral_lamports = orderbook.open_buy_collateral_lamports
+        .checked_add(after)
+        .ok_or(ErrorCode::MathOverflow)?
+        .saturating_sub(before);
+    Ok(())
+}
+
+/// Lamport notional of an order's unfilled quantity at its limit price
+/// Debug: Sell orders and closed orders contribute no resting depth
+fn resting_notional(orderbook: &Orderbook, order: &Order) -> Result<u64> {
+    let is_resting = order.status == OrderStatus::Open || order.status == OrderStatus::PartiallyFilled;
+    if order.is_sell || !is_resting {
+        return Ok(0);
+    }
+    calculate_collateral_lamports(
+        order.price,
+        order.remaining_quantity,
+        order.rate_at_placement,
+        orderbook.share_decimals,
+    )
+}
+
+/// Move a side's resting notional from an order's old depth to its new one
+/// Debug: Depth is recomputed from remaining quantity each time, so partial fills
+/// telescope back to exactly what was added and the total never underflows
+fn adjust_resting_notional(
+    orderbook: &mut Orderbook,
+    side: &OrderSide,
+    before: u64,
+    after: u64,
+) -> Result<()> {
+    let notional = match side {
+        OrderSide::Yes => &mut orderbook.resting_yes_notional,
+        OrderSide::No => &mut orderbook.resting_no_notional,
+    };
+    *notional = notional
+        .checked_add(after)
+        .ok_or(ErrorCode::MathOverflow)?
+        .saturating_sub(before);
+    Ok(())
+}
+
+/// Basis-point fee on a lamport amount, rounded down
+fn calculate_fee_lamports(amount: u64, fee_bps: u16) -> Result<u64> {
+    let fee = (amount as u128)
+        .checked_mul(fee_bps as u128)
+        .ok_or(ErrorCode::MathOverflow)?
+        / BPS_DENOMINATOR as u128;
+    u64::try_from(fee).map_err(|_| ErrorCode::MathOverflow.into())
+}
+
+/// Move the taker's reserved fee for a fill into the fee pot, release the
+/// maker's unused reservation, then pay the maker rebate and the creator
+/// royalty out of the pot
+/// Both reservations are re-priced at each order's `rate_at_placement`, the rate
+/// they were deposited at
+/// Debug: Each payout is capped at the remaining pot balance, so rebates plus
+/// royalties can never exceed collected taker fees
+fn settle_match_fees(
+    orderbook: &mut Orderbook,
+    maker: &mut Order,
+    maker_execution_price: u64,
+    taker: &Order,
+    fill_quantity: u64,
+) -> Result<()> {
+    let taker_fee = calculate_fee_lamports(
+        calculate_cost_lamports(
+            taker.price,
+            fill_quantity,
+            taker.rate_at_placement,
+            orderbook.share_decimals,
+        )?,
+        taker.taker_fee_bps,
+    )?;
+    let maker_reserve = calculate_fee_lamports(
+        calculate_cost_lamports(
+            maker.price,
+            fill_quantity,
+            maker.rate_at_placement,
+            orderbook.share_decimals,
+        )?,
+        maker.taker_fee_bps,
+    )?;
+    
+    orderbook.fee_pot_lamports = orderbook.fee_pot_lamports
+        .checked_add(taker_fee)
+        .ok_or(ErrorCode::MathOverflow)?;
+    
+    let maker_rebate = std::cmp::min(
+        calculate_fee_lamports(
+            calculate_cost_lamports(
+                maker_execution_price,
+                fill_quantity,
+                orderbook.one_dollar_lamports,
+                orderbook.share_decimals,
+            )?,
+            orderbook.maker_rebate_bps,
+        )?,
+        orderbook.fee_pot_lamports,
+    );
+    orderbook.fee_pot_lamports -= maker_rebate;
+    
+    let creator_fee = std::cmp::min(
+        calculate_fee_lamports(
+            calculate_cost_lamports(
+                maker_execution_price,
+                fill_quantity,
+                orderbook.one_dollar_lamports,
+                orderbook.share_decimals,
+            )?,
+            orderbook.creator_fee_bps,
+        )?,
+        orderbook.fee_pot_lamports,
+    );
+    orderbook.fee_pot_lamports -= creator_fee;
+    orderbook.creator_fees_lamports = orderbook.creator_fees_lamports
+        .checked_add(creator_fee)
+        .ok_or(ErrorCode::MathOverflow)?;
+    
+    maker.surplus_lamports = maker.surplus_lamports
+        .checked_add(maker_reserve)
+        .ok_or(ErrorCode::MathOverflow)?
+        .checked_add(maker_rebate)
+        .ok_or(ErrorCode::MathOverflow)?;
+    
+    if taker_fee == 0 && maker_rebate == 0 && creator_fee == 0 {
+        return Ok(());
+    }
+    
+    msg!("DEBUG: Match fees - taker {:?} paid {} lamports, maker {:?} rebated {} lamports, creator {} lamports, pot {}",
+        taker.order_id, taker_fee, maker.order_id, maker_rebate, creator_fee, orderbook.fee_pot_lamports);
+    
+    emit!(MatchFeesSettled {
+        market_id: orderbook.market_id,
+        maker_order_id: maker.order_id,
+        taker_order_id: taker.order_id,
+        taker_fee_lamports: taker_fee,
+        maker_rebate_lamports: maker_rebate,
+        creator_fee_lamports: creator_fee,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
     
+    Ok(())
+}
+
+/// Credit an order with the collateral it no longer needs after a fill
+/// Buy orders lock `price * quantity` up front; filling at a lower execution
+/// price leaves the difference in the vault, refundable on `cancel_order`
+/// Priced at `rate_at_placement`, so a later update_sol_price cannot refund more
+/// (or less) than the order actually deposited
+/// Debug: No-op when execution_price == order.price
+fn record_fill_surplus(
+    order: &mut Order,
+    execution_price: u64,
+    fill_quantity: u64,
+    orderbook: &Orderbook,
+) -> Result<()> {
+    if order.is_sell || (!order.is_netted && execution_price >= order.price) {
+        return Ok(());
+    }
+    
+    // A netted order deposited no collateral: its merged set pays out $1 less the price
+    // at today's rate; a funded order frees collateral priced at its placement rate
+    let (surplus_price, dollar_lamports) = if order.is_netted {
+        (PRICE_PRECISION - execution_price, orderbook.one_dollar_lamports)
+    } else {
+        (order.price - execution_price, order.rate_at_placement)
+    };
+    let surplus = calculate_cost_lamports(
+        surplus_price,
+        fill_quantity,
+        dollar_lamports,
+        orderbook.share_decimals,
+    )?;
+    
+    order.surplus_lamports = order.surplus_lamports
+        .checked_add(surplus)
+        .ok_or(ErrorCode::MathOverflow)?;
+    
+    msg!("DEBUG: Order {:?} filled at {} (limit {}), surplus now {} lamports",
+        order.order_id, execution_price, order.price, order.surplus_lamports);
+    
+    Ok(())
+}
+
+/// Lamports of collateral a buy leg commits to a fill: execution price times quantity,
+/// priced at the same rate record_fill_surplus uses (placement rate for funded orders,
+/// today's rate for netted orders, whose merged set is valued at redemption)
+/// Debug: Mirrors record_fill_surplus, so volume + surplus covers the leg (up to rounding)
+fn fill_volume(
+    order: &Order,
+    execution_price: u64,
+    fill_quantity: u64,
+    orderbook: &Orderbook,
+) -> Result<u64> {
+    let dollar_lamports = if order.is_netted {
+        orderbook.one_dollar_lamports
+    } else {
+        order.rate_at_placement
+    };
+    calculate_cost_lamports(
+        execution_price,
+        fill_quantity,
+        dollar_lamports,
+        orderbook.share_decimals,
+    )
+}
+
+// ============================================================================
+// Account Structures
+// ============================================================================
+
+/// Per-market book, collateral ledger and fee configuration
+/// Debug: Fields were inserted mid-layout across releases and there is no in-place
+/// migration, so upgrading an existing deployment means redeploying and opening new books
+#[account]
+pub struct Orderbook {
+    pub authority: Pubkey,
+    pub market_id: Pubkey,
+    pub one_dollar_lamports: u64,    // SOL equivalent of $1
+    pub yes_order_count: u64,
+    pub no_order_count: u64,
+    pub total_yes_shares: u64,       // Total YES shares in circulation
+    pub total_no_shares: u64,        // Total NO shares in circulation
+    pub total_volume_lamports: u64,  // SOL committed by matched buy legs, always yes_volume + no_volume
+    pub last_yes_price: u64,         // Last matched YES price
+    pub last_no_price: u64,          // Last matched NO price
+    pub created_at: i64,
+    pub is_active: bool,
+    pub winning_outcome: Option<OrderSide>, // Set once resolved
+    pub resolved_at: i64,
+    pub last_match_slot: u64,        // Slot of the most recent match_orders
+    pub max_open_orders: u64,        // Cap on resting orders across both sides (0 = unlimited)
+    pub open_yes_orders: u64,        // YES orders currently open or partially filled
+    pub open_no_orders: u64,         // NO orders currently open or partially filled
+    pub open_buy_collateral_lamports: u64, // SOL still reclaimable by buy orders (unfilled + surplus)
+    pub share_decimals: u8,          // Quantities are in base units of 10^-share_decimals shares
+    pub yes_mint: Pubkey,            // SPL mint for YES share tokens (default = counters only)
+    pub no_mint: Pubkey,             // SPL mint for NO share tokens (default = counters only)
+    pub resolution_dollar_lamports: u64, // one_dollar_lamports locked at resolution, used for redemptions
+    pub taker_fee_bps: u16,          // Fee reserved by every buy order, charged when it fills as taker
+    pub maker_rebate_bps: u16,       // Rebate paid to the resting order, out of fee_pot_lamports
+    pub fee_pot_lamports: u64,       // Collected taker fees not yet paid out as rebates
+    pub creator: Pubkey,             // Market creator, earns creator_fee_bps of fill value
+    pub creator_fee_bps: u16,        // Creator royalty, carved out of collected taker fees
+    pub creator_fees_lamports: u64,  // Royalties accrued and not yet claimed
+    pub dust_threshold: u64,         // Partial fills left below this quantity auto-cancel (0 = off)
+    pub matcher_reward_lamports: u64, // Paid from fee_pot_lamports to each match_orders / crank_match matcher
+    pub resting_yes_notional: u64,   // Lamport notional of unfilled YES buy orders
+    pub resting_no_notional: u64,    // Lamport notional of unfilled NO buy orders
+    pub total_collateral_lamports: u64, // SOL the vault should hold above rent (deposits - payouts)
+    pub redemption_fee_bps: u16,     // Settlement fee on winning redemptions, accrued in the FeeVault
+    pub min_seed_shares: u64,        // Complete sets required before enable_trading (0 = none)
+    pub trading_enabled: bool,       // Order placement allowed
+    pub yes_volume_lamports: u64,    // SOL committed by matched YES buy legs
+    pub no_volume_lamports: u64,     // SOL committed by matched NO buy legs
+    pub min_order_lifetime_secs: u64, // Unfilled orders younger than this cannot be cancelled (0 = off)
+    pub set_collateral_lamports: u64, // SOL deposited for the complete sets still outstanding
+    pub permissioned_matching: bool, // Only `matchers` may match or crank (false = anyone)
+    pub matchers: Vec<Pubkey>,       // Matcher allowlist, at most MAX_MATCHERS keys
+    pub bump: u8,                    // Orderbook PDA bump, signs share token mints
+}
+
+/// A resting buy or sell order; buy orders live at `derive_order_pda`
+/// Debug: Orders from earlier releases use the old client-id seeds and layout and cannot
+/// be migrated; cancel or settle them before the program is redeployed
+#[account]
+pub struct Order {
+    pub order_id: Pubkey,
+    pub owner: Pubkey,
+    pub market_id: Pubkey,
+    pub side: OrderSide,
+    pub price: u64,                  // Price in PRICE_PRECISION units
+    pub original_quantity: u64,
+    pub filled_quantity: u64,
+    pub remaining_quantity: u64,
+    pub lamports_deposited: u64,
+    pub surplus_lamports: u64,       // Unused collateral from fills below the limit price
+    pub status: OrderStatus,
+    pub is_sell: bool,               // true if selling shares, false if buying
+    pub created_at: i64,
+    pub taker_fee_bps: u16,          // Taker fee rate reserved in lamports_deposited at placement
+    pub is_netted: bool,             // Buy backed by locked opposite shares instead of SOL
+    pub rate_at_placement: u64,      // one_dollar_lamports that priced lamports_deposited
+}
+
+impl Order {
+    /// 8 (discriminator) + 32 (order_id) + 32 (owner) + 32 (market_id) + 1 (side) + 8 (price)
+    /// + 8 (original) + 8 (filled) + 8 (remaining) + 8 (deposited) + 8 (surplus)
+    /// + 1 (status) + 1 (is_sell) + 8 (created_at) + 2 (taker_fee_bps) + 1 (is_netted)
+    /// + 8 (rate_at_placement)
+    pub const LEN: usize = 8 + 32 + 32 + 32 + 1 + 8 + 8 + 8 + 8 + 8 + 8 + 1 + 1 + 8 + 2 + 1 + 8;
+}
+
+/// Per-market protocol fee account, kept apart from the collateral vault
+/// Debug: Program-owned so withdraw_fees can debit it directly
+#[account]
+pub struct FeeVault {
+    pub market_id: Pubkey,
+    pub total_accrued_lamports: u64,   // Fees ever moved into this account
+    pub total_withdrawn_lamports: u64, // Fees paid out by withdraw_fees
+    pub bump: u8,
+}
+
+impl FeeVault {
+    /// 8 (discriminator) + 32 (market_id) + 8 (accrued) + 8 (withdrawn) + 1 (bump)
+    pub const LEN: usize = 8 + 32 + 8 + 8 + 1;
+}
+
+#[account]
+pub struct UserShares {
+    pub owner: Pubkey,
+    pub market_id: Pubkey,
+    pub yes_shares: u64,
+    pub no_shares: u64,
+    pub yes_shares_locked: u64,      // Locked in pending sell orders or posted by netted NO buys
+    pub no_shares_locked: u64,       // Locked in pending sell orders or posted by netted YES buys
+    pub order_nonce: u64,            // Nonce of this user's next buy order PDA (see derive_order_pda)
+}
+
+impl UserShares {
+    /// 8 (discriminator) + 32 (owner) + 32 (market_id) + 8 (yes_shares) + 8 (no_shares)
+    /// + 8 (yes_shares_locked) + 8 (no_shares_locked) + 8 (order_nonce)
+    pub const LEN: usize = 8 + 32 + 32 + 8 + 8 + 8 + 8 + 8;
+}
+
+/// One order in a `place_orders_batch` call
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct BatchOrderParams {
+    pub side: OrderSide,
+    pub price: u64,
+    pub quantity: u64,
+}
+
+/// Return value of `get_user_position`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct UserPosition {
+    pub yes_shares: u64,
+    pub no_shares: u64,
+    pub yes_shares_locked: u64,
+    pub no_shares_locked: u64,
+}
+
+// ============================================================================
+// Enums
+// ============================================================================
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq)]
+pub enum OrderSide {
+    Yes,
+    No,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq)]
+pub enum OrderStatus {
+    Open,
+    PartiallyFilled,
+    Filled,
+    Cancelled,
+}
+
+// ============================================================================
+// Context Structs
+// ============================================================================
+
+#[derive(Accounts)]
+#[instruction(market_id: Pubkey)]
+pub struct InitializeOrderbook<'info> {
     #[account(mut)]
-    pub no_order: Account<'info, Order>,
+    pub authority: Signer<'info>,
     
     #[account(
-        init_if_needed,
-        payer = matcher,
-        space = 8 + 32 + 32 + 8 + 8 + 8 + 8,
-        seeds = [b"shares", yes_order.owner.as_ref(), orderbook.market_id.as_ref()],
+        init,
+        payer = authority,
+        space = 8 + 32 + 32 + 8 + 8 + 8 + 8 + 8 + 8 + 8 + 8 + 8 + 1 + 2 + 8 + 8 + 8 + 8 + 8 + 8 + 1 + 32 + 32 + 8 + 2 + 2 + 8 + 32 + 2 + 8 + 8 + 8 + 8 + 8 + 8 + 2 + 8 + 1
+            + 8 + 8 + 8 + 8 + 1 + 4 + 32 * MAX_MATCHERS + 1,
+        seeds = [b"orderbook", market_id.as_ref()],
         bump
     )]
-    pub yes_user_shares: Account<'info, UserShares>,
+    pub orderbook: Account<'info, Orderbook>,
+    
+    /// CHECK: Vault PDA for holding SOL collateral
+    #[account(
+        mut,
+        seeds = [b"vault", market_id.as_ref()],
+        bump
+    )]
+    pub vault: AccountInfo<'info>,
+    
+    #[account(
+        init,
+        payer = authority,
+        space = FeeVault::LEN,
+        seeds = [b"fee_vault", market_id.as_ref()],
+        bump
+    )]
+    pub fee_vault: Account<'info, FeeVault>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateSolPrice<'info> {
+    pub authority: Signer<'info>,
+    
+    #[account(mut)]
+    pub orderbook: Account<'info, Orderbook>,
+}
+
+#[derive(Accounts)]
+pub struct SeedCompleteSets<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    
+    #[account(mut)]
+    pub orderbook: Account<'info, Orderbook>,
     
     #[account(
         init_if_needed,
-        payer = matcher,
-        space = 8 + 32 + 32 + 8 + 8 + 8 + 8,
-        seeds = [b"shares", no_order.owner.as_ref(), orderbook.market_id.as_ref()],
+        payer = authority,
+        space = UserShares::LEN,
+        seeds = [b"shares", authority.key().as_ref(), orderbook.market_id.as_ref()],
         bump
     )]
-    pub no_user_shares: Account<'info, UserShares>,
+    pub authority_shares: Account<'info, UserShares>,
+    
+    /// CHECK: Vault PDA for SOL collateral
+    #[account(
+        mut,
+        seeds = [b"vault", orderbook.market_id.as_ref()],
+        bump
+    )]
+    pub vault: AccountInfo<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateFeeSchedule<'info> {
+    pub authority: Signer<'info>,
+    
+    #[account(mut)]
+    pub orderbook: Account<'info, Orderbook>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawFees<'info> {
+    pub authority: Signer<'info>,
+    
+    #[account(mut)]
+    pub orderbook: Account<'info, Orderbook>,
+    
+    /// CHECK: Collateral vault PDA, only debited for fee_pot_lamports
+    #[account(
+        mut,
+        seeds = [b"vault", orderbook.market_id.as_ref()],
+        bump
+    )]
+    pub vault: AccountInfo<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"fee_vault", orderbook.market_id.as_ref()],
+        bump = fee_vault.bump
+    )]
+    pub fee_vault: Account<'info, FeeVault>,
+    
+    /// CHECK: Any account chosen by the authority to receive the fees
+    #[account(mut)]
+    pub recipient: AccountInfo<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimCreatorFees<'info> {
+    #[account(mut)]
+    pub creator: Signer<'info>,
+    
+    #[account(mut)]
+    pub orderbook: Account<'info, Orderbook>,
+    
+    /// CHECK: Vault PDA holding the accrued royalties
+    #[account(
+        mut,
+        seeds = [b"vault", orderbook.market_id.as_ref()],
+        bump
+    )]
+    pub vault: AccountInfo<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ResolveOrderbook<'info> {
+    pub authority: Signer<'info>,
+    
+    #[account(mut)]
+    pub orderbook: Account<'info, Orderbook>,
+}
+
+#[derive(Accounts)]
+#[instruction(nonce: u64)]
+pub struct PlaceOrder<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+    
+    #[account(mut)]
+    pub orderbook: Account<'info, Orderbook>,
+    
+    #[account(
+        init,
+        payer = user,
+        space = Order::LEN,
+        seeds = [b"order", user.key().as_ref(), orderbook.key().as_ref(), nonce.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub order: Account<'info, Order>,
+    
+    /// Holds the user's order nonce, and the opposite shares posted by a netted order
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = UserShares::LEN,
+        seeds = [b"shares", user.key().as_ref(), orderbook.market_id.as_ref()],
+        bump
+    )]
+    pub user_shares: Account<'info, UserShares>,
+    
+    /// CHECK: Vault PDA for SOL collateral
+    #[account(
+        mut,
+        seeds = [b"vault", orderbook.market_id.as_ref()],
+        bump
+    )]
+    pub vault: AccountInfo<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateUserShares<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+    
+    pub orderbook: Account<'info, Orderbook>,
+    
+    /// CHECK: Pre-nonce UserShares, too short to deserialize; bound by seeds and owner
+    #[account(
+        mut,
+        seeds = [b"shares", user.key().as_ref(), orderbook.market_id.as_ref()],
+        bump,
+        owner = ID
+    )]
+    pub user_shares: AccountInfo<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct PlaceOrdersBatch<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+    
+    #[account(mut)]
+    pub orderbook: Account<'info, Orderbook>,
+    
+    /// Holds the user's order nonce; the batch consumes one per order
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = UserShares::LEN,
+        seeds = [b"shares", user.key().as_ref(), orderbook.market_id.as_ref()],
+        bump
+    )]
+    pub user_shares: Account<'info, UserShares>,
+    
+    /// CHECK: Vault PDA for SOL collateral
+    #[account(
+        mut,
+        seeds = [b"vault", orderbook.market_id.as_ref()],
+        bump
+    )]
+    pub vault: AccountInfo<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ModifyOrder<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+    
+    #[account(mut)]
+    pub orderbook: Account<'info, Orderbook>,
+    
+    #[account(
+        mut,
+        constraint = order.owner == user.key() @ ErrorCode::Unauthorized,
+        constraint = order.market_id == orderbook.market_id @ ErrorCode::MarketMismatch
+    )]
+    pub order: Account<'info, Order>,
+    
+    /// CHECK: Vault PDA for SOL collateral
+    #[account(
+        mut,
+        seeds = [b"vault", orderbook.market_id.as_ref()],
+        bump
+    )]
+    pub vault: AccountInfo<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct MatchOrders<'info> {
+    #[account(mut)]
+    pub orderbook: Account<'info, Orderbook>,
+    
+    /// Debug: Both orders must belong to this market - dust refunds are paid from its vault
+    #[account(
+        mut,
+        constraint = yes_order.market_id == orderbook.market_id @ ErrorCode::MarketMismatch
+    )]
+    pub yes_order: Account<'info, Order>,
+    
+    /// Debug: Passing one account as both orders would deserialize it twice and let
+    /// the second write-back silently overwrite the first
+    #[account(
+        mut,
+        constraint = no_order.key() != yes_order.key() @ ErrorCode::SelfMatch,
+        constraint = no_order.market_id == orderbook.market_id @ ErrorCode::MarketMismatch
+    )]
+    pub no_order: Account<'info, Order>,
+    
+    #[account(
+        init_if_needed,
+        payer = matcher,
+        space = UserShares::LEN,
+        seeds = [b"shares", yes_order.owner.as_ref(), orderbook.market_id.as_ref()],
+        bump
+    )]
+    pub yes_user_shares: Account<'info, UserShares>,
+    
+    /// Debug: Same owner on both orders means the same shares PDA twice - rejected for
+    /// the same double-write reason (the crank already skips such pairs)
+    #[account(
+        init_if_needed,
+        payer = matcher,
+        space = UserShares::LEN,
+        seeds = [b"shares", no_order.owner.as_ref(), orderbook.market_id.as_ref()],
+        bump,
+        constraint = no_user_shares.key() != yes_user_shares.key() @ ErrorCode::SelfMatch
+    )]
+    pub no_user_shares: Account<'info, UserShares>,
+    
+    /// Share token accounts, required once `initialize_share_mints` has run
+    #[account(mut)]
+    pub yes_mint: Option<Box<Account<'info, Mint>>>,
+    
+    #[account(mut)]
+    pub no_mint: Option<Box<Account<'info, Mint>>>,
+    
+    #[account(mut)]
+    pub yes_owner_token_account: Option<Box<Account<'info, TokenAccount>>>,
+    
+    #[account(mut)]
+    pub no_owner_token_account: Option<Box<Account<'info, TokenAccount>>>,
+    
+    pub token_program: Option<Program<'info, Token>>,
+    
+    /// CHECK: Vault PDA, refunds dust remainders
+    #[account(
+        mut,
+        seeds = [b"vault", orderbook.market_id.as_ref()],
+        bump
+    )]
+    pub vault: AccountInfo<'info>,
+    
+    /// CHECK: YES order owner, required only if the YES side is auto-cancelled as dust
+    #[account(mut)]
+    pub yes_owner: Option<AccountInfo<'info>>,
+    
+    /// CHECK: NO order owner, required only if the NO side is auto-cancelled as dust
+    #[account(mut)]
+    pub no_owner: Option<AccountInfo<'info>>,
+    
+    #[account(mut)]
+    pub matcher: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(sell_order_id: Pubkey)]
+pub struct SellShares<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+    
+    #[account(mut)]
+    pub orderbook: Account<'info, Orderbook>,
+    
+    #[account(
+        mut,
+        seeds = [b"shares", user.key().as_ref(), orderbook.market_id.as_ref()],
+        bump
+    )]
+    pub user_shares: Account<'info, UserShares>,
+    
+    #[account(
+        init,
+        payer = user,
+        space = Order::LEN,
+        seeds = [b"sell_order", sell_order_id.as_ref()],
+        bump
+    )]
+    pub sell_order: Account<'info, Order>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeShareMints<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"orderbook", orderbook.market_id.as_ref()],
+        bump = orderbook.bump
+    )]
+    pub orderbook: Box<Account<'info, Orderbook>>,
+    
+    #[account(
+        init,
+        payer = authority,
+        seeds = [b"yes_mint", orderbook.market_id.as_ref()],
+        bump,
+        mint::decimals = orderbook.share_decimals,
+        mint::authority = orderbook,
+    )]
+    pub yes_mint: Box<Account<'info, Mint>>,
+    
+    #[account(
+        init,
+        payer = authority,
+        seeds = [b"no_mint", orderbook.market_id.as_ref()],
+        bump,
+        mint::decimals = orderbook.share_decimals,
+        mint::authority = orderbook,
+    )]
+    pub no_mint: Box<Account<'info, Mint>>,
+    
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RedeemShareTokens<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+    
+    #[account(mut)]
+    pub orderbook: Box<Account<'info, Orderbook>>,
+    
+    #[account(mut)]
+    pub share_mint: Box<Account<'info, Mint>>,
+    
+    #[account(
+        mut,
+        token::mint = share_mint,
+        token::authority = user,
+    )]
+    pub user_token_account: Box<Account<'info, TokenAccount>>,
+    
+    /// CHECK: Vault PDA for payout
+    #[account(
+        mut,
+        seeds = [b"vault", orderbook.market_id.as_ref()],
+        bump
+    )]
+    pub vault: AccountInfo<'info>,
+    
+    /// Receives the redemption fee
+    #[account(
+        mut,
+        seeds = [b"fee_vault", orderbook.market_id.as_ref()],
+        bump = fee_vault.bump
+    )]
+    pub fee_vault: Box<Account<'info, FeeVault>>,
+    
+    pub token_program: Program<'info, Token>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CrankMatch<'info> {
+    #[account(mut)]
+    pub orderbook: Account<'info, Orderbook>,
+    
+    /// CHECK: Vault PDA, pays the matcher reward and refunds dust remainders
+    #[account(
+        mut,
+        seeds = [b"vault", orderbook.market_id.as_ref()],
+        bump
+    )]
+    pub vault: AccountInfo<'info>,
+    
+    /// CHECK: Owner of the expected YES order, required only if it is auto-cancelled as dust
+    #[account(mut)]
+    pub yes_owner: Option<AccountInfo<'info>>,
+    
+    /// CHECK: Owner of the expected NO order, required only if it is auto-cancelled as dust
+    #[account(mut)]
+    pub no_owner: Option<AccountInfo<'info>>,
+    
+    #[account(mut)]
+    pub cranker: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct MatchSellOrders<'info> {
+    #[account(mut)]
+    pub orderbook: Account<'info, Orderbook>,
+    
+    #[account(
+        mut,
+        constraint = yes_sell_order.market_id == orderbook.market_id @ ErrorCode::MarketMismatch
+    )]
+    pub yes_sell_order: Account<'info, Order>,
+    
+    #[account(
+        mut,
+        constraint = no_sell_order.market_id == orderbook.market_id @ ErrorCode::MarketMismatch
+    )]
+    pub no_sell_order: Account<'info, Order>,
+    
+    #[account(
+        mut,
+        seeds = [b"shares", yes_sell_order.owner.as_ref(), orderbook.market_id.as_ref()],
+        bump
+    )]
+    pub yes_user_shares: Account<'info, UserShares>,
+    
+    /// Debug: One owner selling both sides means the same shares PDA twice, and the NO
+    /// write-back would undo the YES burn - rejected as in `MatchOrders`
+    #[account(
+        mut,
+        seeds = [b"shares", no_sell_order.owner.as_ref(), orderbook.market_id.as_ref()],
+        bump,
+        constraint = no_user_shares.key() != yes_user_shares.key() @ ErrorCode::SelfMatch
+    )]
+    pub no_user_shares: Account<'info, UserShares>,
+    
+    /// CHECK: Vault PDA for SOL
+    #[account(
+        mut,
+        seeds = [b"vault", orderbook.market_id.as_ref()],
+        bump
+    )]
+    pub vault: AccountInfo<'info>,
+    
+    /// CHECK: YES seller receives SOL
+    #[account(
+        mut,
+        address = yes_sell_order.owner @ ErrorCode::Unauthorized
+    )]
+    pub yes_seller: AccountInfo<'info>,
+    
+    /// CHECK: NO seller receives SOL
+    #[account(
+        mut,
+        address = no_sell_order.owner @ ErrorCode::Unauthorized
+    )]
+    pub no_seller: AccountInfo<'info>,
+    
+    #[account(mut)]
+    pub matcher: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CancelOrder<'info> {
+    #[account(mut)]
+    pub orderbook: Account<'info, Orderbook>,
+    
+    #[account(mut)]
+    pub user: Signer<'info>,
+    
+    #[account(mut)]
+    pub order: Account<'info, Order>,
+    
+    /// CHECK: Vault PDA for SOL refund
+    #[account(
+        mut,
+        seeds = [b"vault", orderbook.market_id.as_ref()],
+        bump
+    )]
+    pub vault: AccountInfo<'info>,
+    
+    /// Required when cancelling a netted order, to unlock its posted shares
+    #[account(
+        mut,
+        seeds = [b"shares", order.owner.as_ref(), orderbook.market_id.as_ref()],
+        bump
+    )]
+    pub user_shares: Option<Account<'info, UserShares>>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CancelSellOrder<'info> {
+    #[account(mut)]
+    pub orderbook: Account<'info, Orderbook>,
+    
+    pub user: Signer<'info>,
+    
+    #[account(mut)]
+    pub order: Account<'info, Order>,
+    
+    #[account(
+        mut,
+        seeds = [b"shares", user.key().as_ref(), orderbook.market_id.as_ref()],
+        bump
+    )]
+    pub user_shares: Account<'info, UserShares>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimOrderSurplus<'info> {
+    #[account(mut)]
+    pub orderbook: Account<'info, Orderbook>,
+    
+    #[account(mut)]
+    pub user: Signer<'info>,
+    
+    #[account(mut)]
+    pub order: Account<'info, Order>,
+    
+    /// CHECK: Vault PDA holding the surplus
+    #[account(
+        mut,
+        seeds = [b"vault", orderbook.market_id.as_ref()],
+        bump
+    )]
+    pub vault: AccountInfo<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SettleOpenOrder<'info> {
+    #[account(mut)]
+    pub orderbook: Account<'info, Orderbook>,
+    
+    #[account(
+        mut,
+        constraint = order.market_id == orderbook.market_id @ ErrorCode::MarketMismatch
+    )]
+    pub order: Account<'info, Order>,
+    
+    /// Required when settling a sell order or a netted buy order
+    #[account(
+        mut,
+        seeds = [b"shares", order.owner.as_ref(), orderbook.market_id.as_ref()],
+        bump
+    )]
+    pub user_shares: Option<Account<'info, UserShares>>,
+    
+    /// CHECK: Order owner receives the refund
+    #[account(
+        mut,
+        address = order.owner @ ErrorCode::Unauthorized
+    )]
+    pub owner: AccountInfo<'info>,
+    
+    /// CHECK: Vault PDA for SOL refund
+    #[account(
+        mut,
+        seeds = [b"vault", orderbook.market_id.as_ref()],
+        bump
+    )]
+    pub vault: AccountInfo<'info>,
+    
+    pub cranker: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RedeemShares<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+    
+    #[account(mut)]
+    pub orderbook: Account<'info, Orderbook>,
+    
+    #[account(
+        mut,
+        seeds = [b"shares", user.key().as_ref(), orderbook.market_id.as_ref()],
+        bump
+    )]
+    pub user_shares: Account<'info, UserShares>,
+    
+    /// CHECK: Vault PDA for payout
+    #[account(
+        mut,
+        seeds = [b"vault", orderbook.market_id.as_ref()],
+        bump
+    )]
+    pub vault: AccountInfo<'info>,
+    
+    /// Receives the redemption fee
+    #[account(
+        mut,
+        seeds = [b"fee_vault", orderbook.market_id.as_ref()],
+        bump = fee_vault.bump
+    )]
+    pub fee_vault: Account<'info, FeeVault>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct MintCompleteSetTokens<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+    
+    #[account(mut)]
+    pub orderbook: Box<Account<'info, Orderbook>>,
+    
+    #[account(mut, address = orderbook.yes_mint @ ErrorCode::InvalidShareMint)]
+    pub yes_mint: Box<Account<'info, Mint>>,
+    
+    #[account(mut, address = orderbook.no_mint @ ErrorCode::InvalidShareMint)]
+    pub no_mint: Box<Account<'info, Mint>>,
+    
+    #[account(
+        mut,
+        token::mint = yes_mint,
+        token::authority = user,
+    )]
+    pub user_yes_token_account: Box<Account<'info, TokenAccount>>,
+    
+    #[account(
+        mut,
+        token::mint = no_mint,
+        token::authority = user,
+    )]
+    pub user_no_token_account: Box<Account<'info, TokenAccount>>,
+    
+    /// CHECK: Vault PDA that holds the sets' collateral
+    #[account(
+        mut,
+        seeds = [b"vault", orderbook.market_id.as_ref()],
+        bump
+    )]
+    pub vault: AccountInfo<'info>,
+    
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RedeemCompleteSet<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+    
+    #[account(mut)]
+    pub orderbook: Account<'info, Orderbook>,
+    
+    /// Required while shares are counters
+    #[account(
+        mut,
+        seeds = [b"shares", user.key().as_ref(), orderbook.market_id.as_ref()],
+        bump
+    )]
+    pub user_shares: Option<Account<'info, UserShares>>,
+    
+    /// Share token accounts, required once `initialize_share_mints` has run
+    #[account(mut)]
+    pub yes_mint: Option<Box<Account<'info, Mint>>>,
+    
+    #[account(mut)]
+    pub no_mint: Option<Box<Account<'info, Mint>>>,
+    
+    #[account(mut, token::authority = user)]
+    pub user_yes_token_account: Option<Box<Account<'info, TokenAccount>>>,
+    
+    #[account(mut, token::authority = user)]
+    pub user_no_token_account: Option<Box<Account<'info, TokenAccount>>>,
+    
+    pub token_program: Option<Program<'info, Token>>,
+    
+    /// CHECK: Vault PDA that returns the set's collateral
+    #[account(
+        mut,
+        seeds = [b"vault", orderbook.market_id.as_ref()],
+        bump
+    )]
+    pub vault: AccountInfo<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct QuoteOrder<'info> {
+    pub orderbook: Account<'info, Orderbook>,
+}
+
+#[derive(Accounts)]
+pub struct CheckCollateralization<'info> {
+    pub orderbook: Account<'info, Orderbook>,
+    
+    /// CHECK: Vault PDA whose balance is checked
+    #[account(
+        seeds = [b"vault", orderbook.market_id.as_ref()],
+        bump
+    )]
+    pub vault: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct GetUserPosition<'info> {
+    pub user_shares: Account<'info, UserShares>,
+}
+
+#[derive(Accounts)]
+pub struct GetOrderStatus<'info> {
+    pub order: Account<'info, Order>,
+}
+
+// ============================================================================
+// Error Codes
+// ============================================================================
+
+#[error_code]
+pub enum ErrorCode {
+    #[msg("Unauthorized access")]
+    Unauthorized,
+    #[msg("Invalid amount")]
+    InvalidAmount,
+    #[msg("Invalid price - must be between 0 and 1")]
+    InvalidPrice,
+    #[msg("YES price + NO price must equal $1")]
+    PricesMustSumToOne,
+    #[msg("Orderbook is inactive")]
+    OrderbookInactive,
+    #[msg("Trading has not been enabled on this orderbook")]
+    TradingNotEnabled,
+    #[msg("Seeded complete sets are below min_seed_shares")]
+    InsufficientSeedShares,
+    #[msg("Not enough accrued fees")]
+    InsufficientFees,
+    #[msg("Order is not fully filled")]
+    OrderNotFilled,
+    #[msg("Order is not open")]
+    OrderNotOpen,
+    #[msg("Order is not cancellable")]
+    OrderNotCancellable,
+    #[msg("Invalid order side")]
+    InvalidOrderSide,
+    #[msg("Market mismatch")]
+    MarketMismatch,
+    #[msg("No quantity to match")]
+    NoMatchQuantity,
+    #[msg("Insufficient shares")]
+    InsufficientShares,
+    #[msg("Not a sell order")]
+    NotASellOrder,
+    #[msg("Market is still active")]
+    MarketStillActive,
+    #[msg("No shares to redeem")]
+    NoSharesToRedeem,
+    #[msg("Math overflow")]
+    MathOverflow,
+    #[msg("Only unfilled buy orders can be modified")]
+    OrderNotModifiable,
+    #[msg("Cannot resolve in the same slot as a match")]
+    ResolutionTooSoon,
+    #[msg("Outcome does not match the resolved winner")]
+    InvalidOutcome,
+    #[msg("User shares account required to settle a sell order")]
+    MissingUserShares,
+    #[msg("Transfer would leave the vault below rent exemption")]
+    WouldBreakRentExemption,
+    #[msg("Too many open orders on this orderbook")]
+    TooManyOpenOrders,
+    #[msg("YES price + NO price below $1: match would be undercollateralized")]
+    UndercollateralizedMatch,
+    #[msg("Crank accounts must be (order, user shares) pairs")]
+    InvalidCrankAccounts,
+    #[msg("Too many crank candidates")]
+    TooManyCrankCandidates,
+    #[msg("An older order at the same price must be matched first")]
+    PriorityViolation,
+    #[msg("Share decimals exceed the maximum")]
+    InvalidShareDecimals,
+    #[msg("Too many orders in batch")]
+    TooManyBatchOrders,
+    #[msg("Batch accounts must be one uninitialized order PDA per entry")]
+    InvalidBatchAccounts,
+    #[msg("Share tokens are already enabled")]
+    ShareTokensAlreadyEnabled,
+    #[msg("Fee exceeds the maximum")]
+    FeeTooHigh,
+    #[msg("Maker rebate plus creator royalty cannot exceed the taker fee")]
+    RebateExceedsFee,
+    #[msg("Share tokens are not enabled for this orderbook")]
+    ShareTokensNotEnabled,
+    #[msg("Share tokens are enabled: use match_orders with token accounts")]
+    ShareTokensEnabled,
+    #[msg("Share token accounts required when share tokens are enabled")]
+    MissingShareTokenAccounts,
+    #[msg("Order owner account required to refund a dust remainder")]
+    MissingOrderOwner,
+    #[msg("Share mint or token account does not match the orderbook")]
+    InvalidShareMint,
+    #[msg("Cannot match an order or owner against itself")]
+    SelfMatch,
+    #[msg("Matcher is not on this orderbook's allowlist")]
+    MatcherNotAllowed,
+    #[msg("Matcher allowlist is full")]
+    TooManyMatchers,
+    #[msg("Matcher is already on the allowlist")]
+    MatcherAlreadyAllowed,
+    #[msg("Matcher is not on the allowlist")]
+    MatcherNotFound,
+    #[msg("Order has not rested for the minimum lifetime and cannot be cancelled yet")]
+    OrderTooYoung,
+    #[msg("Sell orders are cancelled with cancel_sell_order")]
+    NotABuyOrder,
+    #[msg("Order nonce must be the user's next UserShares.order_nonce")]
+    InvalidOrderNonce,
+    #[msg("User shares account is already at the current size")]
+    UserSharesAlreadyMigrated,
+}
+
+// ============================================================================
+// Events
+// ============================================================================
+
+#[event]
+pub struct OrderbookInitialized {
+    pub market_id: Pubkey,
+    pub one_dollar_lamports: u64,
+    pub initial_yes_price: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct SolPriceUpdated {
+    pub market_id: Pubkey,
+    pub old_lamports_per_dollar: u64,
+    pub new_lamports_per_dollar: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct OrderPlaced {
+    pub order_id: Pubkey,
+    pub owner: Pubkey,
+    pub market_id: Pubkey,
+    pub side: OrderSide,
+    pub price: u64,
+    pub quantity: u64,
+    pub cost_lamports: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct OrderModified {
+    pub order_id: Pubkey,
+    pub owner: Pubkey,
+    pub market_id: Pubkey,
+    pub old_price: u64,
+    pub new_price: u64,
+    pub old_quantity: u64,
+    pub new_quantity: u64,
+    pub old_cost_lamports: u64,
+    pub new_cost_lamports: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct OrdersMatched {
+    pub yes_order_id: Pubkey,
+    pub no_order_id: Pubkey,
+    pub market_id: Pubkey,
+    pub yes_owner: Pubkey,
+    pub no_owner: Pubkey,
+    pub yes_price: u64,
+    pub no_price: u64,
+    pub quantity: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct FeeScheduleUpdated {
+    pub market_id: Pubkey,
+    pub taker_fee_bps: u16,
+    pub maker_rebate_bps: u16,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct MatchFeesSettled {
+    pub market_id: Pubkey,
+    pub maker_order_id: Pubkey,
+    pub taker_order_id: Pubkey,
+    pub taker_fee_lamports: u64,
+    pub maker_rebate_lamports: u64,
+    pub creator_fee_lamports: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct CreatorFeesClaimed {
+    pub market_id: Pubkey,
+    pub creator: Pubkey,
+    pub amount_lamports: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct FeesWithdrawn {
+    pub market_id: Pubkey,
+    pub recipient: Pubkey,
+    pub amount_lamports: u64,
+    pub swept_lamports: u64,         // Moved from fee_pot_lamports as part of this withdrawal
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct OrderbookResolved {
+    pub market_id: Pubkey,
+    pub winning_outcome: OrderSide,
+    pub resolution_dollar_lamports: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct SellOrderPlaced {
+    pub order_id: Pubkey,
+    pub owner: Pubkey,
+    pub market_id: Pubkey,
+    pub side: OrderSide,
+    pub price: u64,
+    pub quantity: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct SharesMerged {
+    pub yes_order_id: Pubkey,
+    pub no_order_id: Pubkey,
+    pub market_id: Pubkey,
+    pub yes_seller: Pubkey,
+    pub no_seller: Pubkey,
+    pub quantity: u64,
+    pub yes_payout: u64,
+    pub no_payout: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct SellOrderCancelled {
+    pub order_id: Pubkey,
+    pub owner: Pubkey,
+    pub shares_unlocked: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct OrderCancelled {
+    pub order_id: Pubkey,
+    pub owner: Pubkey,
+    pub refund_lamports: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct OrderSettled {
+    pub order_id: Pubkey,
+    pub owner: Pubkey,
+    pub market_id: Pubkey,
+    pub refund_lamports: u64,
+    pub shares_unlocked: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct SharesRedeemed {
+    pub owner: Pubkey,
+    pub market_id: Pubkey,
+    pub winning_outcome: OrderSide,
+    pub shares_redeemed: u64,
+    pub payout_lamports: u64,        // Net of the redemption fee
+    pub fee_lamports: u64,           // Redemption fee accrued in the FeeVault
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct CompleteSetMinted {
+    pub owner: Pubkey,
+    pub market_id: Pubkey,
+    pub quantity: u64,               // Sets minted (one YES + one NO token each)
+    pub cost_lamports: u64,          // quantity * one_dollar_lamports, rounded up
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct CompleteSetRedeemed {
+    pub owner: Pubkey,
+    pub market_id: Pubkey,
+    pub quantity: u64,               // Sets burned (one YES + one NO each)
+    pub payout_lamports: u64,        // Fee-free: the sets' share of set_collateral_lamports
+    pub timestamp: i64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anchor_lang::solana_program::entrypoint::{ProgramResult, MAX_PERMITTED_DATA_INCREASE};
+    use anchor_lang::solana_program::instruction::AccountMeta;
+    use anchor_lang::solana_program::program_error::ProgramError;
+    use anchor_lang::solana_program::program_option::COption;
+    use anchor_lang::solana_program::program_pack::Pack;
+    use anchor_lang::{Discriminator, InstructionData, ToAccountMetas};
+    use anchor_spl::token::spl_token;
+    use solana_sysvar::program_stubs::{set_syscall_stubs, SyscallStubs};
+    use std::cell::{Cell, RefCell};
+    use std::collections::HashMap;
+    use std::sync::Once;
+    
+    const SOL: u64 = 1_000_000_000;
+    const DOLLAR: u64 = SOL; // one_dollar_lamports in the Env markets: $1 = 1 SOL
+    const START: i64 = 1_700_000_000;
+    
+    thread_local! {
+        static NOW: Cell<i64> = const { Cell::new(0) };
+        static SLOT: Cell<u64> = const { Cell::new(0) };
+        static RETURN_DATA: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+    }
+    
+    /// Sysvars for off-chain runs, with the clock kept per test thread
+    struct Stubs;
+    
+    impl SyscallStubs for Stubs {
+        fn sol_get_clock_sysvar(&self, var_addr: *mut u8) -> u64 {
+            let clock = Clock {
+                slot: SLOT.with(Cell::get),
+                unix_timestamp: NOW.with(Cell::get),
+                ..Clock::default()
+            };
+            unsafe { std::ptr::write(var_addr as *mut Clock, clock) };
+            0
+        }
+        
+        fn sol_get_rent_sysvar(&self, var_addr: *mut u8) -> u64 {
+            unsafe { std::ptr::write(var_addr as *mut Rent, Rent::default()) };
+            0
+        }
+    }
+    
+    fn set_now(unix_timestamp: i64) {
+        NOW.with(|now| now.set(unix_timestamp));
+    }
+    
+    fn next_slot() {
+        SLOT.with(|slot| slot.set(slot.get() + 1));
+    }
+    
+    /// Test double for `set_return_data`, which is a no-op off-chain
+    pub(super) fn set_return_data(data: &[u8]) {
+        RETURN_DATA.with(|return_data| *return_data.borrow_mut() = data.to_vec());
+    }
+    
+    /// Decode what the last instruction passed to `set_return_data`
+    fn take_return_data<T: AnchorDeserialize>() -> T {
+        RETURN_DATA.with(|return_data| T::try_from_slice(&return_data.borrow_mut().split_off(0)).unwrap())
+    }
+    
+    /// Whether `account` signed directly or through one of this program's PDA seeds
+    fn is_signed(account: &AccountInfo, signer_seeds: &[&[&[u8]]]) -> bool {
+        account.is_signer
+            || signer_seeds.iter().any(|seeds| {
+                Pubkey::create_program_address(seeds, &ID).is_ok_and(|pda| pda == *account.key)
+            })
+    }
+    
+    /// `anchor_lang::system_program` with test doubles for the CPIs the orderbook makes
+    /// Debug: CPIs cannot run off-chain, so each double applies the checks the runtime and
+    /// system program would, then moves the lamports (and data) itself
+    pub(super) mod system_program {
+        pub use anchor_lang::system_program::*;
+        use super::is_signed;
+        use anchor_lang::prelude::*;
+        use anchor_lang::solana_program::program_error::ProgramError;
+        
+        pub fn transfer<'info>(
+            ctx: CpiContext<'_, '_, '_, 'info, Transfer<'info>>,
+            lamports: u64,
+        ) -> Result<()> {
+            let Transfer { from, to } = ctx.accounts;
+            if !is_signed(&from, ctx.signer_seeds) {
+                return Err(ProgramError::MissingRequiredSignature.into());
+            }
+            if *from.owner != ID {
+                return Err(ProgramError::InvalidAccountOwner.into());
+            }
+            if from.lamports() < lamports {
+                return Err(ProgramError::InsufficientFunds.into());
+            }
+            
+            **from.try_borrow_mut_lamports()? -= lamports;
+            **to.try_borrow_mut_lamports()? += lamports;
+            Ok(())
+        }
+        
+        pub fn create_account<'info>(
+            ctx: CpiContext<'_, '_, '_, 'info, CreateAccount<'info>>,
+            lamports: u64,
+            space: u64,
+            owner: &Pubkey,
+        ) -> Result<()> {
+            let CreateAccount { from, to } = ctx.accounts;
+            if !is_signed(&from, ctx.signer_seeds) || !is_signed(&to, ctx.signer_seeds) {
+                return Err(ProgramError::MissingRequiredSignature.into());
+            }
+            if to.lamports() > 0 || !to.data_is_empty() {
+                return Err(ProgramError::AccountAlreadyInitialized.into());
+            }
+            if from.lamports() < lamports {
+                return Err(ProgramError::InsufficientFunds.into());
+            }
+            
+            **from.try_borrow_mut_lamports()? -= lamports;
+            **to.try_borrow_mut_lamports()? += lamports;
+            to.resize(space as usize)?;
+            to.assign(owner);
+            Ok(())
+        }
+    }
+    
+    /// `anchor_spl::token` with test doubles for the share token CPIs
+    pub(super) mod token {
+        pub use anchor_spl::token::*;
+        use super::is_signed;
+        use anchor_lang::prelude::*;
+        use anchor_lang::solana_program::program_error::ProgramError;
+        use anchor_lang::solana_program::program_option::COption;
+        use anchor_lang::solana_program::program_pack::Pack;
+        use spl_token::state::{Account as TokenState, Mint as MintState};
+        
+        pub fn mint_to<'info>(
+            ctx: CpiContext<'_, '_, '_, 'info, MintTo<'info>>,
+            amount: u64,
+        ) -> Result<()> {
+            let MintTo { mint, to, authority } = ctx.accounts;
+            let mut mint_state = MintState::unpack(&mint.try_borrow_data()?)?;
+            let mut to_state = TokenState::unpack(&to.try_borrow_data()?)?;
+            if mint_state.mint_authority != COption::Some(*authority.key)
+                || !is_signed(&authority, ctx.signer_seeds)
+            {
+                return Err(ProgramError::MissingRequiredSignature.into());
+            }
+            if to_state.mint != *mint.key {
+                return Err(ProgramError::InvalidAccountData.into());
+            }
+            
+            mint_state.supply = mint_state.supply.checked_add(amount).ok_or(ProgramError::ArithmeticOverflow)?;
+            to_state.amount = to_state.amount.checked_add(amount).ok_or(ProgramError::ArithmeticOverflow)?;
+            MintState::pack(mint_state, &mut mint.try_borrow_mut_data()?)?;
+            TokenState::pack(to_state, &mut to.try_borrow_mut_data()?)?;
+            Ok(())
+        }
+        
+        pub fn burn<'info>(
+            ctx: CpiContext<'_, '_, '_, 'info, Burn<'info>>,
+            amount: u64,
+        ) -> Result<()> {
+            let Burn { mint, from, authority } = ctx.accounts;
+            let mut mint_state = MintState::unpack(&mint.try_borrow_data()?)?;
+            let mut from_state = TokenState::unpack(&from.try_borrow_data()?)?;
+            if from_state.owner != *authority.key || !is_signed(&authority, ctx.signer_seeds) {
+                return Err(ProgramError::MissingRequiredSignature.into());
+            }
+            if from_state.mint != *mint.key {
+                return Err(ProgramError::InvalidAccountData.into());
+            }
+            
+            from_state.amount = from_state.amount.checked_sub(amount).ok_or(ProgramError::InsufficientFunds)?;
+            mint_state.supply = mint_state.supply.checked_sub(amount).ok_or(ProgramError::InsufficientFunds)?;
+            MintState::pack(mint_state, &mut mint.try_borrow_mut_data()?)?;
+            TokenState::pack(from_state, &mut from.try_borrow_mut_data()?)?;
+            Ok(())
+        }
+    }
+
+    /// Keys of a market with one open YES buy order, and an attacker-controlled
+    /// system account standing in for its vault
+    struct Fixture {
+        market_id: Pubkey,
+        user: Pubkey,
+        orderbook: Pubkey,
+        order: Pubkey,
+        user_shares: Pubkey,
+        fee_vault: Pubkey,
+        fake_vault: Pubkey,
+    }
+
+    fn fixture() -> Fixture {
+        let market_id = Pubkey::new_unique();
+        let user = Pubkey::new_unique();
+        let (user_shares, _) = Pubkey::find_program_address(
+            &[b"shares", user.as_ref(), market_id.as_ref()],
+            &ID,
+        );
+        Fixture {
+            market_id,
+            user,
+            orderbook: Pubkey::new_unique(),
+            order: Pubkey::new_unique(),
+            user_shares,
+            fee_vault: Pubkey::new_unique(),
+            fake_vault: Pubkey::new_unique(),
+        }
+    }
+
+    fn leak<T>(value: T) -> &'static mut T {
+        Box::leak(Box::new(value))
+    }
+
+    fn serialized<T: AccountSerialize>(value: &T) -> Vec<u8> {
+        let mut data = Vec::new();
+        value.try_serialize(&mut data).unwrap();
+        data
+    }
+
+    fn test_orderbook(market_id: Pubkey) -> Orderbook {
+        Orderbook {
+            authority: Pubkey::new_unique(),
+            market_id,
+            one_dollar_lamports: 1_000_000_000,
+            yes_order_count: 1,
+            no_order_count: 0,
+            total_yes_shares: 0,
+            total_no_shares: 0,
+            total_volume_lamports: 0,
+            last_yes_price: 0,
+            last_no_price: 0,
+            created_at: 0,
+            is_active: true,
+            winning_outcome: None,
+            resolved_at: 0,
+            last_match_slot: 0,
+            max_open_orders: 0,
+            open_yes_orders: 1,
+            open_no_orders: 0,
+            open_buy_collateral_lamports: 500_000_000,
+            share_decimals: 0,
+            yes_mint: Pubkey::default(),
+            no_mint: Pubkey::default(),
+            resolution_dollar_lamports: 0,
+            taker_fee_bps: 0,
+            maker_rebate_bps: 0,
+            fee_pot_lamports: 0,
+            creator: Pubkey::new_unique(),
+            creator_fee_bps: 0,
+            creator_fees_lamports: 0,
+            dust_threshold: 0,
+            matcher_reward_lamports: 0,
+            resting_yes_notional: 500_000_000,
+            resting_no_notional: 0,
+            total_collateral_lamports: 500_000_000,
+            redemption_fee_bps: 0,
+            min_seed_shares: 0,
+            trading_enabled: true,
+            yes_volume_lamports: 0,
+            no_volume_lamports: 0,
+            min_order_lifetime_secs: 0,
+            set_collateral_lamports: 0,
+            permissioned_matching: false,
+            matchers: Vec::new(),
+            bump: 255,
+        }
+    }
+
+    fn test_order(owner: Pubkey, market_id: Pubkey) -> Order {
+        Order {
+            order_id: Pubkey::new_unique(),
+            owner,
+            market_id,
+            side: OrderSide::Yes,
+            price: 500_000,
+            original_quantity: 1,
+            filled_quantity: 0,
+            remaining_quantity: 1,
+            lamports_deposited: 500_000_000,
+            surplus_lamports: 0,
+            status: OrderStatus::Open,
+            is_sell: false,
+            created_at: 0,
+            taker_fee_bps: 0,
+            is_netted: false,
+            rate_at_placement: 1_000_000_000,
+        }
+    }
+
+    fn test_user_shares(owner: Pubkey, market_id: Pubkey) -> UserShares {
+        UserShares {
+            owner,
+            market_id,
+            yes_shares: 1,
+            no_shares: 1,
+            yes_shares_locked: 0,
+            no_shares_locked: 0,
+            order_nonce: 1,
+        }
+    }
+
+    /// Run `data` against `metas`, backing each key with the fixture's account of that role
+    fn run(fixture: &Fixture, metas: Vec<AccountMeta>, data: Vec<u8>) -> ProgramResult {
+        let infos: Vec<AccountInfo> = metas
+            .iter()
+            .map(|meta| {
+                let (owner, data) = if meta.pubkey == fixture.orderbook {
+                    (ID, serialized(&test_orderbook(fixture.market_id)))
+                } else if meta.pubkey == fixture.order {
+                    (ID, serialized(&test_order(fixture.user, fixture.market_id)))
+                } else if meta.pubkey == fixture.user_shares {
+                    (ID, serialized(&test_user_shares(fixture.user, fixture.market_id)))
+                } else if meta.pubkey == fixture.fee_vault {
+                    let fee_vault = FeeVault {
+                        market_id: fixture.market_id,
+                        total_accrued_lamports: 0,
+                        total_withdrawn_lamports: 0,
+                        bump: 255,
+                    };
+                    (ID, serialized(&fee_vault))
+                } else {
+                    (system_program::ID, Vec::new())
+                };
+                AccountInfo::new(
+                    leak(meta.pubkey),
+                    meta.is_signer,
+                    meta.is_writable,
+                    leak(10_000_000_000),
+                    leak(data).as_mut_slice(),
+                    leak(owner),
+                    meta.pubkey == system_program::ID,
+                    0,
+                )
+            })
+            .collect();
+        
+        crate::entry(&ID, leak(infos).as_slice(), &data)
+    }
+
+    fn assert_vault_rejected(result: ProgramResult) {
+        assert_eq!(
+            result,
+            Err(ProgramError::Custom(anchor_lang::error::ErrorCode::ConstraintSeeds.into()))
+        );
+    }
+
+    #[test]
+    fn cancel_order_rejects_a_substitute_vault() {
+        let f = fixture();
+        let metas = crate::accounts::CancelOrder {
+            orderbook: f.orderbook,
+            user: f.user,
+            order: f.order,
+            vault: f.fake_vault,
+            user_shares: None,
+            system_program: system_program::ID,
+        }
+        .to_account_metas(None);
+        
+        assert_vault_rejected(run(&f, metas, crate::instruction::CancelOrder {}.data()));
+    }
+
+    #[test]
+    fn modify_order_rejects_a_substitute_vault() {
+        let f = fixture();
+        let metas = crate::accounts::ModifyOrder {
+            user: f.user,
+            orderbook: f.orderbook,
+            order: f.order,
+            vault: f.fake_vault,
+            system_program: system_program::ID,
+        }
+        .to_account_metas(None);
+        let data = crate::instruction::ModifyOrder { new_price: 1, new_quantity: 1 }.data();
+        
+        assert_vault_rejected(run(&f, metas, data));
+    }
+
+    #[test]
+    fn claim_order_surplus_rejects_a_substitute_vault() {
+        let f = fixture();
+        let metas = crate::accounts::ClaimOrderSurplus {
+            orderbook: f.orderbook,
+            user: f.user,
+            order: f.order,
+            vault: f.fake_vault,
+            system_program: system_program::ID,
+        }
+        .to_account_metas(None);
+        
+        assert_vault_rejected(run(&f, metas, crate::instruction::ClaimOrderSurplus {}.data()));
+    }
+
+    #[test]
+    fn settle_open_order_rejects_a_substitute_vault() {
+        let f = fixture();
+        let metas = crate::accounts::SettleOpenOrder {
+            orderbook: f.orderbook,
+            order: f.order,
+            user_shares: Some(f.user_shares),
+            owner: f.user,
+            vault: f.fake_vault,
+            cranker: Pubkey::new_unique(),
+            system_program: system_program::ID,
+        }
+        .to_account_metas(None);
+        
+        assert_vault_rejected(run(&f, metas, crate::instruction::SettleOpenOrder {}.data()));
+    }
+
+    #[test]
+    fn redeem_shares_rejects_a_substitute_vault() {
+        let f = fixture();
+        let metas = crate::accounts::RedeemShares {
+            user: f.user,
+            orderbook: f.orderbook,
+            user_shares: f.user_shares,
+            vault: f.fake_vault,
+            fee_vault: f.fee_vault,
+            system_program: system_program::ID,
+        }
+        .to_account_metas(None);
+        
+        let data = crate::instruction::RedeemShares { winning_outcome: OrderSide::Yes, quantity: 1 }.data();
+        
+        assert_vault_rejected(run(&f, metas, data));
+    }
+
+    #[test]
+    fn complete_sets_redeem_at_their_deposit_rate() {
+        let mut orderbook = test_orderbook(Pubkey::new_unique());
+        orderbook.total_yes_shares = 10;
+        orderbook.total_no_shares = 10;
+        add_set_collateral(&mut orderbook, 10_000_000_000).unwrap();
+        
+        // SOL rallies: $1 is now half the lamports, but the sets were paid for at the old rate
+        orderbook.one_dollar_lamports = 500_000_000;
+        assert_eq!(retire_set_collateral(&mut orderbook, 4).unwrap(), 4_000_000_000);
+        assert_eq!(orderbook.set_collateral_lamports, 6_000_000_000);
+        
+        orderbook.total_yes_shares = 6;
+        orderbook.total_no_shares = 6;
+        assert_eq!(retire_set_collateral(&mut orderbook, 6).unwrap(), 6_000_000_000);
+        assert_eq!(orderbook.set_collateral_lamports, 0);
+        assert!(retire_set_collateral(&mut orderbook, 7).is_err());
+    }
+
+    #[test]
+    fn the_real_vault_passes_the_account_checks() {
+        let f = fixture();
+        let (vault, _) = Pubkey::find_program_address(&[b"vault", f.market_id.as_ref()], &ID);
+        let metas = crate::accounts::CancelOrder {
+            orderbook: f.orderbook,
+            user: Pubkey::new_unique(),
+            order: f.order,
+            vault,
+            user_shares: None,
+            system_program: system_program::ID,
+        }
+        .to_account_metas(None);
+        
+        // A stranger cancelling the order now fails in the handler, not on the vault
+        let result = run(&f, metas, crate::instruction::CancelOrder {}.data());
+        
+        assert_eq!(result, Err(ProgramError::Custom(ErrorCode::Unauthorized.into())));
+    }
+
+    fn rent(space: usize) -> u64 {
+        Rent::default().minimum_balance(space)
+    }
+    
+    fn pda(seeds: &[&[u8]]) -> Pubkey {
+        Pubkey::find_program_address(seeds, &ID).0
+    }
+    
+    fn err(error: ErrorCode) -> ProgramResult {
+        Err(Error::from(error).into())
+    }
+    
+    /// An account as the runtime stores it between instructions
+    #[derive(Clone, Default)]
+    struct TestAccount {
+        owner: Pubkey,
+        lamports: u64,
+        data: Vec<u8>,
+    }
+    
+    /// Leak `account` laid out like the runtime's input buffer: `realloc` writes the new
+    /// data length into the 8 bytes before the data and reads the original length from
+    /// the 4 bytes before the key, and the data may grow by MAX_PERMITTED_DATA_INCREASE
+    fn account_info(meta: &AccountMeta, account: TestAccount) -> AccountInfo<'static> {
+        #[repr(C)]
+        struct KeySlot {
+            original_data_len: u32,
+            key: Pubkey,
+        }
+        
+        let slot = leak(KeySlot {
+            original_data_len: account.data.len() as u32,
+            key: meta.pubkey,
+        });
+        let capacity = account.data.len() + MAX_PERMITTED_DATA_INCREASE;
+        let words = leak(vec![0u64; 1 + capacity.div_ceil(8)]);
+        let data = unsafe {
+            std::slice::from_raw_parts_mut(words.as_mut_ptr().add(1) as *mut u8, account.data.len())
+        };
+        data.copy_from_slice(&account.data);
+        
+        AccountInfo::new(
+            &slot.key,
+            meta.is_signer,
+            meta.is_writable,
+            leak(account.lamports),
+            data,
+            leak(account.owner),
+            meta.pubkey == system_program::ID || meta.pubkey == token::ID,
+            0,
+        )
+    }
+    
+    /// Minimal in-memory runtime: accounts persist across instructions and an
+    /// instruction's writes are kept only if it succeeds
+    #[derive(Default)]
+    struct Bank {
+        accounts: HashMap<Pubkey, TestAccount>,
+    }
+    
+    impl Bank {
+        fn fund(&mut self, key: Pubkey, lamports: u64) {
+            self.accounts.entry(key).or_default().lamports += lamports;
+        }
+        
+        fn lamports(&self, key: &Pubkey) -> u64 {
+            self.accounts.get(key).map_or(0, |account| account.lamports)
+        }
+        
+        fn exists(&self, key: &Pubkey) -> bool {
+            self.lamports(key) > 0
+        }
+        
+        /// Create a rent-exempt account outside any instruction
+        fn put(&mut self, key: Pubkey, owner: Pubkey, data: Vec<u8>) {
+            self.accounts.insert(key, TestAccount { owner, lamports: rent(data.len()), data });
+        }
+        
+        fn get<T: AccountDeserialize>(&self, key: &Pubkey) -> T {
+            T::try_deserialize(&mut self.accounts[key].data.as_slice()).unwrap()
+        }
+        
+        /// Run `f` over the accounts of `metas`, first creating each `init` key with the
+        /// given owner and data, rent paid by `payer` - the state Anchor's `init` leaves
+        /// behind, which cannot run here since it needs a CPI
+        fn transact(
+            &mut self,
+            metas: &[AccountMeta],
+            init: &[(Pubkey, Pubkey, Vec<u8>)],
+            payer: Pubkey,
+            f: impl FnOnce(&'static [AccountInfo<'static>]) -> ProgramResult,
+        ) -> ProgramResult {
+            let mut staged = self.accounts.clone();
+            for (key, owner, data) in init {
+                if staged.get(key).is_some_and(|account| account.lamports > 0) {
+                    return Err(ProgramError::AccountAlreadyInitialized);
+                }
+                let payer = staged.entry(payer).or_default();
+                payer.lamports = payer.lamports
+                    .checked_sub(rent(data.len()))
+                    .ok_or(ProgramError::InsufficientFunds)?;
+                staged.insert(*key, TestAccount {
+                    owner: *owner,
+                    lamports: rent(data.len()),
+                    data: data.clone(),
+                });
+            }
+            
+            let infos: &'static [AccountInfo<'static>] = leak(
+                metas
+                    .iter()
+                    .map(|meta| account_info(meta, staged.get(&meta.pubkey).cloned().unwrap_or_default()))
+                    .collect::<Vec<_>>(),
+            );
+            f(infos)?;
+            
+            for info in infos {
+                staged.insert(*info.key, TestAccount {
+                    owner: *info.owner,
+                    lamports: info.lamports(),
+                    data: info.data.borrow().to_vec(),
+                });
+            }
+            self.accounts = staged;
+            Ok(())
+        }
+        
+        /// Run an instruction through the program entrypoint, staging `init` accounts first
+        fn process(
+            &mut self,
+            metas: Vec<AccountMeta>,
+            data: Vec<u8>,
+            init: &[(Pubkey, Pubkey, Vec<u8>)],
+            payer: Pubkey,
+        ) -> ProgramResult {
+            self.transact(&metas, init, payer, |infos| crate::entry(&ID, infos, &data))
+        }
+    }
+    
+    /// Serialized size of a resolved orderbook with a full matcher allowlist
+    fn orderbook_space() -> usize {
+        let mut orderbook = test_orderbook(Pubkey::default());
+        orderbook.winning_outcome = Some(OrderSide::Yes);
+        orderbook.matchers = vec![Pubkey::default(); MAX_MATCHERS];
+        serialized(&orderbook).len()
+    }
+    
+    /// A zeroed program account of `space` bytes under `T`'s discriminator: what `init`
+    /// leaves behind, and what lets an `init_if_needed` account pass Anchor's checks
+    /// through the entrypoint, which cannot create it here
+    fn init<T: Discriminator>(key: Pubkey, space: usize) -> (Pubkey, Pubkey, Vec<u8>) {
+        let mut data = vec![0; space];
+        data[..8].copy_from_slice(T::DISCRIMINATOR);
+        (key, ID, data)
+    }
+    
+    /// An orderbook at $1 = 1 SOL, its authority, creator and a funded matcher
+    struct Env {
+        bank: Bank,
+        market_id: Pubkey,
+        authority: Pubkey,
+        creator: Pubkey,
+        matcher: Pubkey,
+    }
+    
+    fn orderbook_args(market_id: Pubkey) -> crate::instruction::InitializeOrderbook {
+        crate::instruction::InitializeOrderbook {
+            market_id,
+            one_dollar_lamports: DOLLAR,
+            initial_yes_price: PRICE_PRECISION / 2,
+            max_open_orders: 0,
+            share_decimals: 0,
+            creator: Pubkey::default(),
+            creator_fee_bps: 0,
+            dust_threshold: 0,
+            min_seed_shares: 0,
+        }
+    }
+    
+    /// Funded actors and no orderbook yet, clock at START
+    fn uninitialized() -> Env {
+        static INSTALL_STUBS: Once = Once::new();
+        INSTALL_STUBS.call_once(|| {
+            set_syscall_stubs(Box::new(Stubs));
+        });
+        set_now(START);
+        
+        let mut env = Env {
+            bank: Bank::default(),
+            market_id: Pubkey::new_unique(),
+            authority: Pubkey::new_unique(),
+            creator: Pubkey::new_unique(),
+            matcher: Pubkey::new_unique(),
+        };
+        env.bank.fund(env.authority, 100 * SOL);
+        env.bank.fund(env.matcher, SOL);
+        env
+    }
+    
+    /// `uninitialized` plus an orderbook with the default arguments
+    fn setup() -> Env {
+        let mut env = uninitialized();
+        let args = orderbook_args(env.market_id);
+        env.initialize(args).unwrap();
+        env
+    }
+    
+    impl Env {
+        fn orderbook_key(&self) -> Pubkey {
+            pda(&[b"orderbook", self.market_id.as_ref()])
+        }
+        
+        fn vault(&self) -> Pubkey {
+            pda(&[b"vault", self.market_id.as_ref()])
+        }
+        
+        fn fee_vault(&self) -> Pubkey {
+            pda(&[b"fee_vault", self.market_id.as_ref()])
+        }
+        
+        fn shares_key(&self, user: Pubkey) -> Pubkey {
+            pda(&[b"shares", user.as_ref(), self.market_id.as_ref()])
+        }
+        
+        fn orderbook(&self) -> Orderbook {
+            self.bank.get(&self.orderbook_key())
+        }
+        
+        fn order(&self, key: Pubkey) -> Order {
+            self.bank.get(&key)
+        }
+        
+        fn shares(&self, user: Pubkey) -> UserShares {
+            self.bank.get(&self.shares_key(user))
+        }
+        
+        fn trader(&mut self) -> Pubkey {
+            let user = Pubkey::new_unique();
+            self.bank.fund(user, 100 * SOL);
+            user
+        }
+        
+        /// `init_if_needed` staging for a user's shares account
+        fn init_shares(&self, user: Pubkey) -> Vec<(Pubkey, Pubkey, Vec<u8>)> {
+            let key = self.shares_key(user);
+            if self.bank.exists(&key) {
+                Vec::new()
+            } else {
+                vec![init::<UserShares>(key, UserShares::LEN)]
+            }
+        }
+        
+        fn initialize(&mut self, args: crate::instruction::InitializeOrderbook) -> ProgramResult {
+            let metas = crate::accounts::InitializeOrderbook {
+                authority: self.authority,
+                orderbook: self.orderbook_key(),
+                vault: self.vault(),
+                fee_vault: self.fee_vault(),
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None);
+            let creator = if args.creator == Pubkey::default() { self.creator } else { args.creator };
+            let init = [
+                init::<Orderbook>(self.orderbook_key(), orderbook_space()),
+                init::<FeeVault>(self.fee_vault(), FeeVault::LEN),
+            ];
+            let market_id = self.market_id;
+            
+            self.bank.transact(&metas, &init, self.authority, |infos| {
+                let mut accounts = InitializeOrderbook {
+                    authority: Signer::try_from(&infos[0])?,
+                    orderbook: Account::try_from_unchecked(&infos[1])?,
+                    vault: infos[2].clone(),
+                    fee_vault: Account::try_from_unchecked(&infos[3])?,
+                    system_program: Program::try_from(&infos[4])?,
+                };
+                let bumps = InitializeOrderbookBumps {
+                    orderbook: Pubkey::find_program_address(&[b"orderbook", market_id.as_ref()], &ID).1,
+                    vault: Pubkey::find_program_address(&[b"vault", market_id.as_ref()], &ID).1,
+                    fee_vault: Pubkey::find_program_address(&[b"fee_vault", market_id.as_ref()], &ID).1,
+                };
+                orderbook::initialize_orderbook(
+                    Context::new(&ID, &mut accounts, &[], bumps),
+                    args.market_id,
+                    args.one_dollar_lamports,
+                    args.initial_yes_price,
+                    args.max_open_orders,
+                    args.share_decimals,
+                    creator,
+                    args.creator_fee_bps,
+                    args.dust_threshold,
+                    args.min_seed_shares,
+                )?;
+                accounts.exit(&ID)?;
+                Ok(())
+            })
+        }
+        
+        /// Place a buy order at the user's next nonce and return its key
+        fn place(&mut self, user: Pubkey, side: OrderSide, price: u64, quantity: u64) -> std::result::Result<Pubkey, ProgramError> {
+            self.place_order(user, side, price, quantity, false)
+        }
+        
+        fn place_order(
+            &mut self,
+            user: Pubkey,
+            side: OrderSide,
+            price: u64,
+            quantity: u64,
+            net: bool,
+        ) -> std::result::Result<Pubkey, ProgramError> {
+            let nonce = if self.bank.exists(&self.shares_key(user)) {
+                self.shares(user).order_nonce
+            } else {
+                0
+            };
+            let order = derive_order_pda(&user, &self.orderbook_key(), nonce).0;
+            let metas = crate::accounts::PlaceOrder {
+                user,
+                orderbook: self.orderbook_key(),
+                order,
+                user_shares: self.shares_key(user),
+                vault: self.vault(),
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None);
+            let mut init = vec![init::<Order>(order, Order::LEN)];
+            init.extend(self.init_shares(user));
+            
+            self.bank.transact(&metas, &init, user, |infos| {
+                let mut accounts = PlaceOrder {
+                    user: Signer::try_from(&infos[0])?,
+                    orderbook: Account::try_from(&infos[1])?,
+                    order: Account::try_from_unchecked(&infos[2])?,
+                    user_shares: Account::try_from(&infos[3])?,
+                    vault: infos[4].clone(),
+                    system_program: Program::try_from(&infos[5])?,
+                };
+                orderbook::place_order(
+                    Context::new(&ID, &mut accounts, &[], PlaceOrderBumps::default()),
+                    nonce,
+                    side,
+                    price,
+                    quantity,
+                    net,
+                )?;
+                accounts.exit(&ID)?;
+                Ok(())
+            })?;
+            Ok(order)
+        }
+        
+        fn match_orders(&mut self, yes_order: Pubkey, no_order: Pubkey) -> ProgramResult {
+            let matcher = self.matcher;
+            self.match_orders_by(matcher, yes_order, no_order)
+        }
+        
+        fn match_orders_by(&mut self, matcher: Pubkey, yes_order: Pubkey, no_order: Pubkey) -> ProgramResult {
+            let yes_owner = self.order(yes_order).owner;
+            let no_owner = self.order(no_order).owner;
+            let metas = crate::accounts::MatchOrders {
+                orderbook: self.orderbook_key(),
+                yes_order,
+                no_order,
+                yes_user_shares: self.shares_key(yes_owner),
+                no_user_shares: self.shares_key(no_owner),
+                yes_mint: None,
+                no_mint: None,
+                yes_owner_token_account: None,
+                no_owner_token_account: None,
+                token_program: None,
+                vault: self.vault(),
+                yes_owner: Some(yes_owner),
+                no_owner: Some(no_owner),
+                matcher,
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None);
+            let mut init = self.init_shares(yes_owner);
+            init.extend(self.init_shares(no_owner));
+            self.bank.process(metas, crate::instruction::MatchOrders {}.data(), &init, matcher)
+        }
+        
+        /// Buy `quantity` YES for `yes_user` and NO for `no_user` at 0.60 / 0.40
+        fn mint_shares(&mut self, yes_user: Pubkey, no_user: Pubkey, quantity: u64) {
+            let yes_order = self.place(yes_user, OrderSide::Yes, 600_000, quantity).unwrap();
+            let no_order = self.place(no_user, OrderSide::No, 400_000, quantity).unwrap();
+            self.match_orders(yes_order, no_order).unwrap();
+        }
+        
+        /// List `quantity` shares for sale and return the sell order's key
+        fn sell(&mut self, user: Pubkey, side: OrderSide, price: u64, quantity: u64) -> std::result::Result<Pubkey, ProgramError> {
+            let sell_order_id = Pubkey::new_unique();
+            let sell_order = pda(&[b"sell_order", sell_order_id.as_ref()]);
+            let metas = crate::accounts::SellShares {
+                user,
+                orderbook: self.orderbook_key(),
+                user_shares: self.shares_key(user),
+                sell_order,
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None);
+            
+            self.bank.transact(&metas, &[init::<Order>(sell_order, Order::LEN)], user, |infos| {
+                let mut accounts = SellShares {
+                    user: Signer::try_from(&infos[0])?,
+                    orderbook: Account::try_from(&infos[1])?,
+                    user_shares: Account::try_from(&infos[2])?,
+                    sell_order: Account::try_from_unchecked(&infos[3])?,
+                    system_program: Program::try_from(&infos[4])?,
+                };
+                orderbook::sell_shares(
+                    Context::new(&ID, &mut accounts, &[], SellSharesBumps::default()),
+                    sell_order_id,
+                    side,
+                    price,
+                    quantity,
+                )?;
+                accounts.exit(&ID)?;
+                Ok(())
+            })?;
+            Ok(sell_order)
+        }
+        
+        fn match_sells_paying(
+            &mut self,
+            yes_sell_order: Pubkey,
+            no_sell_order: Pubkey,
+            yes_seller: Pubkey,
+            no_seller: Pubkey,
+        ) -> ProgramResult {
+            let metas = crate::accounts::MatchSellOrders {
+                orderbook: self.orderbook_key(),
+                yes_sell_order,
+                no_sell_order,
+                yes_user_shares: self.shares_key(self.order(yes_sell_order).owner),
+                no_user_shares: self.shares_key(self.order(no_sell_order).owner),
+                vault: self.vault(),
+                yes_seller,
+                no_seller,
+                matcher: self.matcher,
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None);
+            self.bank.process(metas, crate::instruction::MatchSellOrders {}.data(), &[], self.matcher)
+        }
+        
+        fn match_sells(&mut self, yes_sell_order: Pubkey, no_sell_order: Pubkey) -> ProgramResult {
+            let yes_seller = self.order(yes_sell_order).owner;
+            let no_seller = self.order(no_sell_order).owner;
+            self.match_sells_paying(yes_sell_order, no_sell_order, yes_seller, no_seller)
+        }
+        
+        fn cancel_sell(&mut self, user: Pubkey, order: Pubkey) -> ProgramResult {
+            let metas = crate::accounts::CancelSellOrder {
+                orderbook: self.orderbook_key(),
+                user,
+                order,
+                user_shares: self.shares_key(user),
+            }
+            .to_account_metas(None);
+            self.bank.process(metas, crate::instruction::CancelSellOrder {}.data(), &[], user)
+        }
+        
+        fn set_fee_schedule(&mut self, taker_fee_bps: u16, maker_rebate_bps: u16) -> ProgramResult {
+            let metas = crate::accounts::UpdateFeeSchedule {
+                authority: self.authority,
+                orderbook: self.orderbook_key(),
+            }
+            .to_account_metas(None);
+            let data = crate::instruction::SetFeeSchedule { taker_fee_bps, maker_rebate_bps }.data();
+            self.bank.process(metas, data, &[], self.authority)
+        }
+        
+        fn set_matcher_reward(&mut self, matcher_reward_lamports: u64) -> ProgramResult {
+            let metas = crate::accounts::UpdateFeeSchedule {
+                authority: self.authority,
+                orderbook: self.orderbook_key(),
+            }
+            .to_account_metas(None);
+            let data = crate::instruction::SetMatcherReward { matcher_reward_lamports }.data();
+            self.bank.process(metas, data, &[], self.authority)
+        }
+        
+        fn set_creator_fee(&mut self, creator_fee_bps: u16) -> ProgramResult {
+            let metas = crate::accounts::UpdateFeeSchedule {
+                authority: self.authority,
+                orderbook: self.orderbook_key(),
+            }
+            .to_account_metas(None);
+            let data = crate::instruction::SetCreatorFee { creator_fee_bps }.data();
+            self.bank.process(metas, data, &[], self.authority)
+        }
+        
+        fn set_min_order_lifetime(&mut self, min_order_lifetime_secs: u64) -> ProgramResult {
+            let metas = crate::accounts::UpdateFeeSchedule {
+                authority: self.authority,
+                orderbook: self.orderbook_key(),
+            }
+            .to_account_metas(None);
+            let data = crate::instruction::SetMinOrderLifetime { min_order_lifetime_secs }.data();
+            self.bank.process(metas, data, &[], self.authority)
+        }
+        
+        fn withdraw_fees(&mut self, amount: u64, recipient: Pubkey) -> ProgramResult {
+            let metas = crate::accounts::WithdrawFees {
+                authority: self.authority,
+                orderbook: self.orderbook_key(),
+                vault: self.vault(),
+                fee_vault: self.fee_vault(),
+                recipient,
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None);
+            let data = crate::instruction::WithdrawFees { amount }.data();
+            self.bank.process(metas, data, &[], self.authority)
+        }
+        
+        fn claim_creator_fees(&mut self) -> ProgramResult {
+            let metas = crate::accounts::ClaimCreatorFees {
+                creator: self.creator,
+                orderbook: self.orderbook_key(),
+                vault: self.vault(),
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None);
+            self.bank.process(metas, crate::instruction::ClaimCreatorFees {}.data(), &[], self.creator)
+        }
+        
+        fn claim_surplus(&mut self, user: Pubkey, order: Pubkey) -> ProgramResult {
+            let metas = crate::accounts::ClaimOrderSurplus {
+                orderbook: self.orderbook_key(),
+                user,
+                order,
+                vault: self.vault(),
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None);
+            self.bank.process(metas, crate::instruction::ClaimOrderSurplus {}.data(), &[], user)
+        }
+        
+        /// The vault's non-rent balance is exactly the collateral on the books
+        fn assert_backed(&self) {
+            assert_eq!(
+                self.orderbook().total_collateral_lamports,
+                self.bank.lamports(&self.vault()) - rent(0)
+            );
+        }
+        
+        fn update_sol_price(&mut self, new_one_dollar_lamports: u64) -> ProgramResult {
+            let metas = crate::accounts::UpdateSolPrice {
+                authority: self.authority,
+                orderbook: self.orderbook_key(),
+            }
+            .to_account_metas(None);
+            let data = crate::instruction::UpdateSolPrice { new_one_dollar_lamports }.data();
+            self.bank.process(metas, data, &[], self.authority)
+        }
+        
+        fn seed(&mut self, quantity: u64) -> ProgramResult {
+            let metas = crate::accounts::SeedCompleteSets {
+                authority: self.authority,
+                orderbook: self.orderbook_key(),
+                authority_shares: self.shares_key(self.authority),
+                vault: self.vault(),
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None);
+            let init = self.init_shares(self.authority);
+            let data = crate::instruction::SeedCompleteSets { quantity }.data();
+            self.bank.process(metas, data, &init, self.authority)
+        }
+        
+        fn enable_trading(&mut self) -> ProgramResult {
+            let metas = crate::accounts::UpdateSolPrice {
+                authority: self.authority,
+                orderbook: self.orderbook_key(),
+            }
+            .to_account_metas(None);
+            self.bank.process(metas, crate::instruction::EnableTrading {}.data(), &[], self.authority)
+        }
+        
+        fn modify(&mut self, user: Pubkey, order: Pubkey, new_price: u64, new_quantity: u64) -> ProgramResult {
+            let metas = crate::accounts::ModifyOrder {
+                user,
+                orderbook: self.orderbook_key(),
+                order,
+                vault: self.vault(),
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None);
+            let data = crate::instruction::ModifyOrder { new_price, new_quantity }.data();
+            self.bank.process(metas, data, &[], user)
+        }
+        
+        /// `get_user_position`'s return value, read straight from the handler since the
+        /// Anchor-generated `set_return_data` is a no-op off-chain
+        fn position(&mut self, user: Pubkey) -> UserPosition {
+            let metas = crate::accounts::GetUserPosition { user_shares: self.shares_key(user) }.to_account_metas(None);
+            let position = RefCell::new(None);
+            self.bank.transact(&metas, &[], user, |infos| {
+                let mut accounts = GetUserPosition { user_shares: Account::try_from(&infos[0])? };
+                let context = Context::new(&ID, &mut accounts, &[], GetUserPositionBumps {});
+                *position.borrow_mut() = Some(orderbook::get_user_position(context)?);
+                Ok(())
+            })
+            .unwrap();
+            position.into_inner().unwrap()
+        }
+        
+        fn settle(&mut self, order: Pubkey) -> ProgramResult {
+            let owner = self.order(order).owner;
+            let metas = crate::accounts::SettleOpenOrder {
+                orderbook: self.orderbook_key(),
+                order,
+                user_shares: Some(self.shares_key(owner)),
+                owner,
+                vault: self.vault(),
+                cranker: self.matcher,
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None);
+            let matcher = self.matcher;
+            self.bank.process(metas, crate::instruction::SettleOpenOrder {}.data(), &[], matcher)
+        }
+        
+        /// (vault_balance, required_backing, is_solvent, total_collateral_lamports)
+        fn collateralization(&mut self) -> (u64, u64, bool, u64) {
+            let metas = crate::accounts::CheckCollateralization {
+                orderbook: self.orderbook_key(),
+                vault: self.vault(),
+            }
+            .to_account_metas(None);
+            let data = crate::instruction::CheckCollateralization {}.data();
+            let authority = self.authority;
+            self.bank.process(metas, data, &[], authority).unwrap();
+            take_return_data()
+        }
+        
+        /// Place `orders` in one batch and return their keys
+        fn place_batch(&mut self, user: Pubkey, orders: Vec<BatchOrderParams>) -> std::result::Result<Vec<Pubkey>, ProgramError> {
+            let nonce = if self.bank.exists(&self.shares_key(user)) {
+                self.shares(user).order_nonce
+            } else {
+                0
+            };
+            let keys: Vec<Pubkey> = (nonce..nonce + orders.len() as u64)
+                .map(|nonce| derive_order_pda(&user, &self.orderbook_key(), nonce).0)
+                .collect();
+            let mut metas = crate::accounts::PlaceOrdersBatch {
+                user,
+                orderbook: self.orderbook_key(),
+                user_shares: self.shares_key(user),
+                vault: self.vault(),
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None);
+            metas.extend(keys.iter().map(|key| AccountMeta::new(*key, false)));
+            let init = self.init_shares(user);
+            let data = crate::instruction::PlaceOrdersBatch { orders }.data();
+            self.bank.process(metas, data, &init, user)?;
+            Ok(keys)
+        }
+        
+        fn share_mints(&self) -> (Pubkey, Pubkey) {
+            (
+                pda(&[b"yes_mint", self.market_id.as_ref()]),
+                pda(&[b"no_mint", self.market_id.as_ref()]),
+            )
+        }
+        
+        /// Run `initialize_share_mints` over mints staged as `init` would leave them
+        fn enable_share_tokens(&mut self) -> ProgramResult {
+            let (yes_mint, no_mint) = self.share_mints();
+            let metas = crate::accounts::InitializeShareMints {
+                authority: self.authority,
+                orderbook: self.orderbook_key(),
+                yes_mint,
+                no_mint,
+                token_program: token::ID,
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None);
+            let mint = |key| {
+                let mut data = vec![0; spl_token::state::Mint::LEN];
+                spl_token::state::Mint {
+                    mint_authority: COption::Some(self.orderbook_key()),
+                    decimals: self.orderbook().share_decimals,
+                    is_initialized: true,
+                    ..Default::default()
+                }
+                .pack_into_slice(&mut data);
+                (key, token::ID, data)
+            };
+            let init = [mint(yes_mint), mint(no_mint)];
+            
+            self.bank.transact(&metas, &init, self.authority, |infos| {
+                let mut accounts = InitializeShareMints {
+                    authority: Signer::try_from(&infos[0])?,
+                    orderbook: Box::new(Account::try_from(&infos[1])?),
+                    yes_mint: Box::new(Account::try_from(&infos[2])?),
+                    no_mint: Box::new(Account::try_from(&infos[3])?),
+                    token_program: Program::try_from(&infos[4])?,
+                    system_program: Program::try_from(&infos[5])?,
+                };
+                orderbook::initialize_share_mints(Context::new(&ID, &mut accounts, &[], InitializeShareMintsBumps::default()))?;
+                accounts.exit(&ID)?;
+                Ok(())
+            })
+        }
+        
+        /// A fresh token account of `mint` held by `owner`
+        fn token_account(&mut self, mint: Pubkey, owner: Pubkey) -> Pubkey {
+            let key = Pubkey::new_unique();
+            let mut data = vec![0; spl_token::state::Account::LEN];
+            spl_token::state::Account {
+                mint,
+                owner,
+                state: spl_token::state::AccountState::Initialized,
+                ..Default::default()
+            }
+            .pack_into_slice(&mut data);
+            self.bank.put(key, token::ID, data);
+            key
+        }
+        
+        fn token_balance(&self, key: &Pubkey) -> u64 {
+            spl_token::state::Account::unpack(&self.bank.accounts[key].data).unwrap().amount
+        }
+        
+        /// `match_orders` on a market with share tokens, minting into the given accounts
+        fn match_orders_minting(
+            &mut self,
+            yes_order: Pubkey,
+            no_order: Pubkey,
+            yes_token_account: Pubkey,
+            no_token_account: Pubkey,
+        ) -> ProgramResult {
+            let (yes_owner, no_owner) = (self.order(yes_order).owner, self.order(no_order).owner);
+            let (yes_mint, no_mint) = self.share_mints();
+            let metas = crate::accounts::MatchOrders {
+                orderbook: self.orderbook_key(),
+                yes_order,
+                no_order,
+                yes_user_shares: self.shares_key(yes_owner),
+                no_user_shares: self.shares_key(no_owner),
+                yes_mint: Some(yes_mint),
+                no_mint: Some(no_mint),
+                yes_owner_token_account: Some(yes_token_account),
+                no_owner_token_account: Some(no_token_account),
+                token_program: Some(token::ID),
+                vault: self.vault(),
+                yes_owner: Some(yes_owner),
+                no_owner: Some(no_owner),
+                matcher: self.matcher,
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None);
+            let mut init = self.init_shares(yes_owner);
+            init.extend(self.init_shares(no_owner));
+            let matcher = self.matcher;
+            self.bank.process(metas, crate::instruction::MatchOrders {}.data(), &init, matcher)
+        }
+        
+        /// (cost_lamports, fee_lamports, total_lamports)
+        fn quote(&mut self, price: u64, quantity: u64) -> std::result::Result<(u64, u64, u64), ProgramError> {
+            let metas = crate::accounts::QuoteOrder { orderbook: self.orderbook_key() }.to_account_metas(None);
+            let data = crate::instruction::QuoteOrder { price, quantity }.data();
+            let authority = self.authority;
+            self.bank.process(metas, data, &[], authority)?;
+            Ok(take_return_data())
+        }
+        
+        fn set_redemption_fee(&mut self, redemption_fee_bps: u16) -> ProgramResult {
+            let metas = crate::accounts::UpdateFeeSchedule {
+                authority: self.authority,
+                orderbook: self.orderbook_key(),
+            }
+            .to_account_metas(None);
+            let data = crate::instruction::SetRedemptionFee { redemption_fee_bps }.data();
+            self.bank.process(metas, data, &[], self.authority)
+        }
+        
+        fn fee_vault_state(&self) -> FeeVault {
+            self.bank.get(&self.fee_vault())
+        }
+        
+        /// (status, filled_quantity, remaining_quantity, lamports_deposited, is_sell)
+        fn order_status(&mut self, order: Pubkey) -> std::result::Result<(u8, u64, u64, u64, bool), ProgramError> {
+            let metas = crate::accounts::GetOrderStatus { order }.to_account_metas(None);
+            let authority = self.authority;
+            self.bank.process(metas, crate::instruction::GetOrderStatus {}.data(), &[], authority)?;
+            Ok(take_return_data())
+        }
+        
+        fn set_permissioned_matching(&mut self, permissioned: bool) -> ProgramResult {
+            let metas = crate::accounts::UpdateFeeSchedule {
+                authority: self.authority,
+                orderbook: self.orderbook_key(),
+            }
+            .to_account_metas(None);
+            let data = crate::instruction::SetPermissionedMatching { permissioned }.data();
+            self.bank.process(metas, data, &[], self.authority)
+        }
+        
+        fn add_matcher(&mut self, matcher: Pubkey) -> ProgramResult {
+            let metas = crate::accounts::UpdateFeeSchedule {
+                authority: self.authority,
+                orderbook: self.orderbook_key(),
+            }
+            .to_account_metas(None);
+            let data = crate::instruction::AddMatcher { matcher }.data();
+            self.bank.process(metas, data, &[], self.authority)
+        }
+        
+        /// Crank `orders` as the matcher, passing each with its owner's shares account;
+        /// `owners` are the expected pair's owners, for a dust auto-cancel
+        fn crank(&mut self, orders: &[Pubkey], owners: (Option<Pubkey>, Option<Pubkey>)) -> ProgramResult {
+            let mut metas = crate::accounts::CrankMatch {
+                orderbook: self.orderbook_key(),
+                vault: self.vault(),
+                yes_owner: owners.0,
+                no_owner: owners.1,
+                cranker: self.matcher,
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None);
+            for order in orders {
+                metas.push(AccountMeta::new(*order, false));
+                metas.push(AccountMeta::new(self.shares_key(self.order(*order).owner), false));
+            }
+            let matcher = self.matcher;
+            self.bank.process(metas, crate::instruction::CrankMatch {}.data(), &[], matcher)
+        }
+        
+        /// Resolve in a later slot than the last match
+        fn resolve(&mut self, winning_outcome: OrderSide) -> ProgramResult {
+            next_slot();
+            let metas = crate::accounts::ResolveOrderbook {
+                authority: self.authority,
+                orderbook: self.orderbook_key(),
+            }
+            .to_account_metas(None);
+            let data = crate::instruction::ResolveOrderbook { winning_outcome }.data();
+            self.bank.process(metas, data, &[], self.authority)
+        }
+        
+        fn redeem(&mut self, user: Pubkey, winning_outcome: OrderSide, quantity: u64) -> ProgramResult {
+            let metas = crate::accounts::RedeemShares {
+                user,
+                orderbook: self.orderbook_key(),
+                user_shares: self.shares_key(user),
+                vault: self.vault(),
+                fee_vault: self.fee_vault(),
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None);
+            let data = crate::instruction::RedeemShares { winning_outcome, quantity }.data();
+            self.bank.process(metas, data, &[], user)
+        }
+        
+        fn redeem_tokens(&mut self, user: Pubkey, user_token_account: Pubkey, winning_outcome: OrderSide) -> ProgramResult {
+            let (yes_mint, no_mint) = self.share_mints();
+            let metas = crate::accounts::RedeemShareTokens {
+                user,
+                orderbook: self.orderbook_key(),
+                share_mint: if winning_outcome == OrderSide::Yes { yes_mint } else { no_mint },
+                user_token_account,
+                vault: self.vault(),
+                fee_vault: self.fee_vault(),
+                token_program: token::ID,
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None);
+            let data = crate::instruction::RedeemShareTokens { winning_outcome, quantity: 0 }.data();
+            self.bank.process(metas, data, &[], user)
+        }
+        
+        fn cancel(&mut self, user: Pubkey, order: Pubkey) -> ProgramResult {
+            let metas = crate::accounts::CancelOrder {
+                orderbook: self.orderbook_key(),
+                user,
+                order,
+                vault: self.vault(),
+                user_shares: Some(self.shares_key(user)),
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None);
+            self.bank.process(metas, crate::instruction::CancelOrder {}.data(), &[], user)
+        }
+    }
+    
+    #[test]
+    fn match_sell_orders_rejects_a_substitute_seller() {
+        let mut env = setup();
+        let (alice, bob, mallory) = (env.trader(), env.trader(), env.trader());
+        env.mint_shares(alice, bob, 10);
+        let yes_sell = env.sell(alice, OrderSide::Yes, 600_000, 10).unwrap();
+        let no_sell = env.sell(bob, OrderSide::No, 400_000, 10).unwrap();
+        
+        // The matcher tries to route Alice's proceeds to Mallory
+        assert_eq!(env.match_sells_paying(yes_sell, no_sell, mallory, bob), err(ErrorCode::Unauthorized));
+        assert_eq!(env.match_sells_paying(yes_sell, no_sell, alice, mallory), err(ErrorCode::Unauthorized));
+        
+        let (alice_before, bob_before) = (env.bank.lamports(&alice), env.bank.lamports(&bob));
+        env.match_sells(yes_sell, no_sell).unwrap();
+        assert_eq!(env.bank.lamports(&alice) - alice_before, 6 * SOL);
+        assert_eq!(env.bank.lamports(&bob) - bob_before, 4 * SOL);
+        assert_eq!(env.shares(alice).yes_shares, 0);
+        assert_eq!(env.shares(bob).no_shares, 0);
+    }
+    
+    #[test]
+    fn match_sell_orders_rejects_another_users_shares() {
+        let mut env = setup();
+        let (alice, bob, carol) = (env.trader(), env.trader(), env.trader());
+        env.mint_shares(alice, bob, 10);
+        env.mint_shares(carol, bob, 10);
+        let yes_sell = env.sell(alice, OrderSide::Yes, 600_000, 10).unwrap();
+        let no_sell = env.sell(bob, OrderSide::No, 400_000, 10).unwrap();
+        
+        // Burning Carol's YES shares for Alice's sell order fails the shares PDA check
+        let mut metas = crate::accounts::MatchSellOrders {
+            orderbook: env.orderbook_key(),
+            yes_sell_order: yes_sell,
+            no_sell_order: no_sell,
+            yes_user_shares: env.shares_key(carol),
+            no_user_shares: env.shares_key(bob),
+            vault: env.vault(),
+            yes_seller: alice,
+            no_seller: bob,
+            matcher: env.matcher,
+            system_program: system_program::ID,
+        }
+        .to_account_metas(None);
+        let result = env.bank.process(metas.clone(), crate::instruction::MatchSellOrders {}.data(), &[], env.matcher);
+        assert_eq!(
+            result,
+            Err(ProgramError::Custom(anchor_lang::error::ErrorCode::ConstraintSeeds.into()))
+        );
+        assert_eq!(env.shares(carol).yes_shares, 10);
+        
+        metas[3].pubkey = env.shares_key(alice);
+        env.bank.process(metas, crate::instruction::MatchSellOrders {}.data(), &[], env.matcher).unwrap();
+        assert_eq!(env.shares(alice).yes_shares, 0);
+        assert_eq!(env.shares(carol).yes_shares, 10);
+    }
+    
+    #[test]
+    fn the_vault_pays_out_its_last_lamport_of_collateral() {
+        let mut env = setup();
+        let alice = env.trader();
+        assert_eq!(env.bank.lamports(&env.vault()), rent(0));
+        
+        let order = env.place(alice, OrderSide::Yes, 300_000, 2).unwrap();
+        assert_eq!(env.bank.lamports(&env.vault()), rent(0) + 600_000_000);
+        
+        // The refund takes the vault right down to its rent reserve
+        env.cancel(alice, order).unwrap();
+        assert_eq!(env.bank.lamports(&env.vault()), rent(0));
+        assert_eq!(env.orderbook().total_collateral_lamports, 0);
+        assert_eq!(env.bank.lamports(&alice), 100 * SOL - rent(Order::LEN) - rent(UserShares::LEN));
+    }
+    
+    #[test]
+    fn winners_redeem_half_then_the_rest() {
+        let mut env = setup();
+        let (alice, bob) = (env.trader(), env.trader());
+        env.mint_shares(alice, bob, 10);
+        env.resolve(OrderSide::Yes).unwrap();
+        
+        let before = env.bank.lamports(&alice);
+        env.redeem(alice, OrderSide::Yes, 5).unwrap();
+        assert_eq!(env.bank.lamports(&alice) - before, 5 * SOL);
+        assert_eq!(env.shares(alice).yes_shares, 5);
+        assert_eq!(env.orderbook().total_yes_shares, 5);
+        
+        assert_eq!(env.redeem(alice, OrderSide::Yes, 6), err(ErrorCode::InsufficientShares));
+        env.redeem(alice, OrderSide::Yes, 0).unwrap();
+        assert_eq!(env.bank.lamports(&alice) - before, 10 * SOL);
+        assert_eq!(env.shares(alice).yes_shares, 0);
+        assert_eq!(env.redeem(alice, OrderSide::Yes, 0), err(ErrorCode::NoSharesToRedeem));
+        assert_eq!(env.bank.lamports(&env.vault()), rent(0));
+    }
+    
+    #[test]
+    fn redeem_shares_skips_locked_shares_and_other_users_accounts() {
+        let mut env = setup();
+        let (alice, bob, mallory) = (env.trader(), env.trader(), env.trader());
+        env.mint_shares(alice, bob, 10);
+        env.sell(alice, OrderSide::Yes, 700_000, 4).unwrap();
+        env.resolve(OrderSide::Yes).unwrap();
+        
+        // Mallory cannot redeem out of Alice's shares account
+        let metas = crate::accounts::RedeemShares {
+            user: mallory,
+            orderbook: env.orderbook_key(),
+            user_shares: env.shares_key(alice),
+            vault: env.vault(),
+            fee_vault: env.fee_vault(),
+            system_program: system_program::ID,
+        }
+        .to_account_metas(None);
+        let data = crate::instruction::RedeemShares { winning_outcome: OrderSide::Yes, quantity: 0 }.data();
+        assert_eq!(
+            env.bank.process(metas, data, &[], mallory),
+            Err(ProgramError::Custom(anchor_lang::error::ErrorCode::ConstraintSeeds.into()))
+        );
+        
+        // The 4 shares still listed for sale are not redeemable
+        assert_eq!(env.redeem(alice, OrderSide::Yes, 7), err(ErrorCode::InsufficientShares));
+        let before = env.bank.lamports(&alice);
+        env.redeem(alice, OrderSide::Yes, 0).unwrap();
+        assert_eq!(env.bank.lamports(&alice) - before, 6 * SOL);
+        assert_eq!(env.shares(alice).yes_shares, 4);
+        assert_eq!(env.shares(alice).yes_shares_locked, 4);
+    }
+    
+    #[test]
+    fn posted_shares_reduce_the_collateral_of_a_netted_buy() {
+        let mut env = setup();
+        let (alice, bob) = (env.trader(), env.trader());
+        env.mint_shares(alice, bob, 10);
+        
+        // Alice holds 10 YES: a netted NO buy posts them instead of 0.40 SOL each
+        let before = env.bank.lamports(&alice);
+        let netted = env.place_order(alice, OrderSide::No, 400_000, 10, true).unwrap();
+        assert_eq!(before - env.bank.lamports(&alice), rent(Order::LEN));
+        assert_eq!(env.order(netted).lamports_deposited, 0);
+        assert_eq!(env.shares(alice).yes_shares_locked, 10);
+        
+        // The same buy without netting pulls the full collateral
+        let carol = env.trader();
+        let before = env.bank.lamports(&carol);
+        let plain = env.place(carol, OrderSide::No, 400_000, 10).unwrap();
+        assert_eq!(env.order(plain).lamports_deposited, 4 * SOL);
+        assert_eq!(before - env.bank.lamports(&carol), 4 * SOL + rent(Order::LEN) + rent(UserShares::LEN));
+    }
+    
+    #[test]
+    fn shares_cannot_be_locked_twice() {
+        let mut env = setup();
+        let (alice, bob) = (env.trader(), env.trader());
+        env.mint_shares(alice, bob, 10);
+        
+        env.place_order(alice, OrderSide::No, 400_000, 6, true).unwrap();
+        assert_eq!(env.sell(alice, OrderSide::Yes, 600_000, 5).map(drop), err(ErrorCode::InsufficientShares));
+        env.sell(alice, OrderSide::Yes, 600_000, 4).unwrap();
+        assert_eq!(
+            env.place_order(alice, OrderSide::No, 400_000, 1, true).map(drop),
+            err(ErrorCode::InsufficientShares)
+        );
+        assert_eq!(env.shares(alice).yes_shares_locked, 10);
+    }
+    
+    #[test]
+    fn cancel_order_rejects_another_users_shares() {
+        let mut env = setup();
+        let (alice, bob) = (env.trader(), env.trader());
+        env.mint_shares(alice, bob, 10);
+        let netted = env.place_order(alice, OrderSide::No, 400_000, 10, true).unwrap();
+        
+        // Bob's shares account cannot stand in for Alice's
+        let metas = crate::accounts::CancelOrder {
+            orderbook: env.orderbook_key(),
+            user: alice,
+            order: netted,
+            vault: env.vault(),
+            user_shares: Some(env.shares_key(bob)),
+            system_program: system_program::ID,
+        }
+        .to_account_metas(None);
+        assert_eq!(
+            env.bank.process(metas, crate::instruction::CancelOrder {}.data(), &[], alice),
+            Err(ProgramError::Custom(anchor_lang::error::ErrorCode::ConstraintSeeds.into()))
+        );
+        
+        env.cancel(alice, netted).unwrap();
+        assert_eq!(env.shares(alice).yes_shares_locked, 0);
+        assert!(env.order(netted).status == OrderStatus::Cancelled);
+    }
+    
+    #[test]
+    fn a_partially_filled_order_matches_again() {
+        let mut env = setup();
+        let (alice, bob, carol) = (env.trader(), env.trader(), env.trader());
+        let yes_order = env.place(alice, OrderSide::Yes, 600_000, 10).unwrap();
+        let first_no = env.place(bob, OrderSide::No, 400_000, 4).unwrap();
+        env.match_orders(yes_order, first_no).unwrap();
+        assert!(env.order(yes_order).status == OrderStatus::PartiallyFilled);
+        
+        let second_no = env.place(carol, OrderSide::No, 400_000, 6).unwrap();
+        env.match_orders(yes_order, second_no).unwrap();
+        assert!(env.order(yes_order).status == OrderStatus::Filled);
+        assert_eq!(env.shares(alice).yes_shares, 10);
+        assert_eq!(env.shares(carol).no_shares, 6);
+        
+        // A filled order is done
+        let third_no = env.place(carol, OrderSide::No, 400_000, 1).unwrap();
+        assert_eq!(env.match_orders(yes_order, third_no), err(ErrorCode::OrderNotOpen));
+    }
+    
+    #[test]
+    fn crank_match_pays_the_matcher_reward_out_of_fees() {
+        let mut env = setup();
+        env.set_fee_schedule(100, 0).unwrap();
+        env.set_matcher_reward(SOL).unwrap();
+        let (alice, bob) = (env.trader(), env.trader());
+        let yes_order = env.place(alice, OrderSide::Yes, 600_000, 10).unwrap();
+        set_now(START + 1);
+        let no_order = env.place(bob, OrderSide::No, 400_000, 10).unwrap();
+        
+        // Bob's NO order is the taker: 1% of 4 SOL goes to the fee pot, and the
+        // 1 SOL reward is capped at that
+        let before = env.bank.lamports(&env.matcher);
+        env.crank(&[yes_order, no_order], (None, None)).unwrap();
+        assert_eq!(env.shares(alice).yes_shares, 10);
+        assert_eq!(env.bank.lamports(&env.matcher) - before, 4 * SOL / 100);
+        assert_eq!(env.orderbook().fee_pot_lamports, 0);
+    }
+    
+    #[test]
+    fn crank_match_auto_cancels_a_dust_remainder() {
+        let mut env = uninitialized();
+        let mut args = orderbook_args(env.market_id);
+        args.dust_threshold = 3;
+        env.initialize(args).unwrap();
+        let (alice, bob) = (env.trader(), env.trader());
+        let yes_order = env.place(alice, OrderSide::Yes, 600_000, 10).unwrap();
+        let no_order = env.place(bob, OrderSide::No, 400_000, 8).unwrap();
+        
+        // Alice's 2 unfilled YES would be dust: the crank needs her account to refund them
+        assert_eq!(env.crank(&[yes_order, no_order], (None, None)), err(ErrorCode::MissingOrderOwner));
+        
+        let before = env.bank.lamports(&alice);
+        env.crank(&[yes_order, no_order], (Some(alice), None)).unwrap();
+        assert!(env.order(yes_order).status == OrderStatus::Cancelled);
+        assert_eq!(env.bank.lamports(&alice) - before, 2 * 600_000_000);
+        assert_eq!(env.orderbook().open_yes_orders, 0);
+    }
+    
+    #[test]
+    fn the_creator_royalty_cannot_exceed_the_taker_fee() {
+        let mut env = uninitialized();
+        let mut args = orderbook_args(env.market_id);
+        args.creator_fee_bps = 50;
+        assert_eq!(env.initialize(args), err(ErrorCode::RebateExceedsFee));
+        
+        let args = orderbook_args(env.market_id);
+        env.initialize(args).unwrap();
+        assert_eq!(env.set_creator_fee(50), err(ErrorCode::RebateExceedsFee));
+        env.set_fee_schedule(100, 50).unwrap();
+        assert_eq!(env.set_creator_fee(51), err(ErrorCode::RebateExceedsFee));
+        env.set_creator_fee(50).unwrap();
+        assert_eq!(env.set_fee_schedule(99, 50), err(ErrorCode::RebateExceedsFee));
+    }
+    
+    #[test]
+    fn the_creator_accrues_in_proportion_to_matched_volume() {
+        let mut env = setup();
+        env.set_fee_schedule(100, 0).unwrap();
+        env.set_creator_fee(50).unwrap();
+        let (alice, bob) = (env.trader(), env.trader());
+        
+        // 0.5% of the 6 SOL the YES maker paid: carved out of Bob's 0.04 SOL taker fee
+        let yes_order = env.place(alice, OrderSide::Yes, 600_000, 10).unwrap();
+        set_now(START + 1);
+        let no_order = env.place(bob, OrderSide::No, 400_000, 10).unwrap();
+        env.match_orders(yes_order, no_order).unwrap();
+        assert_eq!(env.orderbook().creator_fees_lamports, 30_000_000);
+        
+        // Twice the volume, twice the royalty
+        let yes_order = env.place(alice, OrderSide::Yes, 600_000, 20).unwrap();
+        set_now(START + 2);
+        let no_order = env.place(bob, OrderSide::No, 400_000, 20).unwrap();
+        env.match_orders(yes_order, no_order).unwrap();
+        assert_eq!(env.orderbook().creator_fees_lamports, 90_000_000);
+        assert_eq!(env.orderbook().total_volume_lamports, 30 * SOL);
+    }
+    
+    #[test]
+    fn collateral_tracks_the_vault_through_a_market_lifecycle() {
+        let mut env = setup();
+        env.set_fee_schedule(100, 20).unwrap();
+        env.set_creator_fee(30).unwrap();
+        env.set_matcher_reward(1_000_000).unwrap();
+        let (alice, bob) = (env.trader(), env.trader());
+        env.assert_backed();
+        
+        let yes_order = env.place(alice, OrderSide::Yes, 600_000, 10).unwrap();
+        set_now(START + 1);
+        let no_order = env.place(bob, OrderSide::No, 450_000, 6).unwrap();
+        env.assert_backed();
+        env.match_orders(yes_order, no_order).unwrap();
+        env.assert_backed();
+        env.cancel(alice, yes_order).unwrap();
+        env.assert_backed();
+        env.claim_surplus(bob, no_order).unwrap();
+        env.assert_backed();
+        
+        let yes_sell = env.sell(alice, OrderSide::Yes, 550_000, 3).unwrap();
+        let no_sell = env.sell(bob, OrderSide::No, 450_000, 3).unwrap();
+        env.match_sells(yes_sell, no_sell).unwrap();
+        env.assert_backed();
+        
+        env.resolve(OrderSide::Yes).unwrap();
+        env.redeem(alice, OrderSide::Yes, 0).unwrap();
+        env.assert_backed();
+        env.claim_creator_fees().unwrap();
+        env.assert_backed();
+        let treasury = Pubkey::new_unique();
+        env.withdraw_fees(0, treasury).unwrap();
+        env.assert_backed();
+    }
+    
+    #[test]
+    fn a_cancel_after_a_sol_price_change_refunds_at_the_placement_rate() {
+        let mut env = setup();
+        let alice = env.trader();
+        let before = env.bank.lamports(&alice);
+        let order = env.place(alice, OrderSide::Yes, 600_000, 10).unwrap();
+        assert_eq!(env.order(order).rate_at_placement, DOLLAR);
+        
+        // SOL doubles: $6 is now 3 SOL, but Alice deposited 6 SOL
+        env.update_sol_price(DOLLAR / 2).unwrap();
+        env.cancel(alice, order).unwrap();
+        assert_eq!(before - env.bank.lamports(&alice), rent(Order::LEN) + rent(UserShares::LEN));
+        env.assert_backed();
+    }
+    
+    #[test]
+    fn merged_sells_pay_out_the_collateral_the_sets_were_minted_with() {
+        let mut env = setup();
+        let (alice, bob) = (env.trader(), env.trader());
+        env.mint_shares(alice, bob, 10);
+        assert_eq!(env.orderbook().set_collateral_lamports, 10 * SOL);
+        
+        // SOL halves: at the new rate the sets would pay 20 SOL, but only 10 backs them
+        env.update_sol_price(2 * DOLLAR).unwrap();
+        let yes_sell = env.sell(alice, OrderSide::Yes, 700_000, 10).unwrap();
+        let no_sell = env.sell(bob, OrderSide::No, 300_000, 10).unwrap();
+        let (alice_before, bob_before) = (env.bank.lamports(&alice), env.bank.lamports(&bob));
+        env.match_sells(yes_sell, no_sell).unwrap();
+        assert_eq!(env.bank.lamports(&alice) - alice_before, 7 * SOL);
+        assert_eq!(env.bank.lamports(&bob) - bob_before, 3 * SOL);
+        assert_eq!(env.orderbook().set_collateral_lamports, 0);
+        env.assert_backed();
+    }
+    
+    #[test]
+    fn trading_opens_only_after_the_minimum_seed() {
+        let mut env = uninitialized();
+        let mut args = orderbook_args(env.market_id);
+        args.min_seed_shares = 10;
+        env.initialize(args).unwrap();
+        let alice = env.trader();
+        
+        assert_eq!(env.place(alice, OrderSide::Yes, 600_000, 1).map(drop), err(ErrorCode::TradingNotEnabled));
+        env.seed(6).unwrap();
+        assert_eq!(env.enable_trading(), err(ErrorCode::InsufficientSeedShares));
+        
+        env.seed(4).unwrap();
+        assert_eq!(env.shares(env.authority).yes_shares, 10);
+        assert_eq!(env.orderbook().total_no_shares, 10);
+        env.enable_trading().unwrap();
+        env.place(alice, OrderSide::Yes, 600_000, 1).unwrap();
+        env.assert_backed();
+    }
+    
+    #[test]
+    fn cancelling_a_favorably_filled_order_refunds_the_surplus() {
+        let mut env = setup();
+        let (alice, bob) = (env.trader(), env.trader());
+        let no_order = env.place(bob, OrderSide::No, 450_000, 4).unwrap();
+        set_now(START + 1);
+        let before = env.bank.lamports(&alice);
+        let yes_order = env.place(alice, OrderSide::Yes, 600_000, 10).unwrap();
+        
+        // 0.60 + 0.45 overshoots $1 by 0.05, split evenly: Alice fills 4 at 0.575
+        env.match_orders(yes_order, no_order).unwrap();
+        assert_eq!(env.order(yes_order).surplus_lamports, 4 * 25_000_000);
+        
+        // Refund: 6 unfilled at 0.60 plus 4 x 0.025 surplus, so only 4 x 0.575 was spent
+        let refund_before = env.bank.lamports(&alice);
+        env.cancel(alice, yes_order).unwrap();
+        assert_eq!(env.bank.lamports(&alice) - refund_before, 6 * 600_000_000 + 4 * 25_000_000);
+        assert_eq!(
+            before - env.bank.lamports(&alice),
+            4 * 575_000_000 + rent(Order::LEN) + rent(UserShares::LEN)
+        );
+        assert_eq!(env.order(yes_order).surplus_lamports, 0);
+        env.assert_backed();
+    }
+    
+    #[test]
+    fn modify_order_collects_on_a_price_rise_and_refunds_on_a_smaller_size() {
+        let mut env = setup();
+        let alice = env.trader();
+        let order = env.place(alice, OrderSide::Yes, 500_000, 10).unwrap();
+        
+        let before = env.bank.lamports(&alice);
+        env.modify(alice, order, 600_000, 10).unwrap();
+        assert_eq!(before - env.bank.lamports(&alice), SOL);
+        assert_eq!(env.order(order).lamports_deposited, 6 * SOL);
+        env.assert_backed();
+        
+        let before = env.bank.lamports(&alice);
+        env.modify(alice, order, 600_000, 4).unwrap();
+        assert_eq!(env.bank.lamports(&alice) - before, 6 * 600_000_000);
+        assert_eq!(env.order(order).remaining_quantity, 4);
+        assert_eq!(env.orderbook().resting_yes_notional, 4 * 600_000_000);
+        env.assert_backed();
+        
+        // Once part of it has filled the order can only be cancelled
+        let bob = env.trader();
+        let no_order = env.place(bob, OrderSide::No, 400_000, 1).unwrap();
+        env.match_orders(order, no_order).unwrap();
+        assert_eq!(env.modify(alice, order, 650_000, 3), err(ErrorCode::OrderNotModifiable));
+    }
+    
+    #[test]
+    fn shares_cannot_be_minted_and_redeemed_in_one_slot() {
+        let mut env = setup();
+        let (alice, bob) = (env.trader(), env.trader());
+        
+        // Match, resolve and redeem as one transaction would: all in the match's slot
+        env.mint_shares(alice, bob, 10);
+        assert_eq!(env.redeem(alice, OrderSide::Yes, 0), err(ErrorCode::MarketStillActive));
+        let metas = crate::accounts::ResolveOrderbook {
+            authority: env.authority,
+            orderbook: env.orderbook_key(),
+        }
+        .to_account_metas(None);
+        let data = crate::instruction::ResolveOrderbook { winning_outcome: OrderSide::Yes }.data();
+        assert_eq!(env.bank.process(metas, data, &[], env.authority), err(ErrorCode::ResolutionTooSoon));
+        
+        // Once resolved in a later slot, no more shares can be minted into the market
+        env.resolve(OrderSide::Yes).unwrap();
+        let yes_order = env.place(alice, OrderSide::Yes, 600_000, 1).map(drop);
+        assert_eq!(yes_order, err(ErrorCode::OrderbookInactive));
+        env.redeem(alice, OrderSide::Yes, 0).unwrap();
+    }
+    
+    #[test]
+    fn get_user_position_reports_held_and_locked_shares() {
+        let mut env = setup();
+        let (alice, bob) = (env.trader(), env.trader());
+        env.mint_shares(alice, bob, 10);
+        
+        let position = env.position(alice);
+        assert_eq!(
+            (position.yes_shares, position.no_shares, position.yes_shares_locked, position.no_shares_locked),
+            (10, 0, 0, 0)
+        );
+        
+        env.sell(alice, OrderSide::Yes, 700_000, 4).unwrap();
+        let position = env.position(alice);
+        assert_eq!(
+            (position.yes_shares, position.no_shares, position.yes_shares_locked, position.no_shares_locked),
+            (10, 0, 4, 0)
+        );
+        let position = env.position(bob);
+        assert_eq!((position.yes_shares, position.no_shares), (0, 10));
+    }
+    
+    #[test]
+    fn settle_open_order_refunds_buys_and_unlocks_sells_after_resolution() {
+        let mut env = setup();
+        let (alice, bob, carol) = (env.trader(), env.trader(), env.trader());
+        env.mint_shares(alice, bob, 10);
+        let buy = env.place(carol, OrderSide::Yes, 300_000, 5).unwrap();
+        let sell = env.sell(alice, OrderSide::Yes, 700_000, 4).unwrap();
+        assert_eq!(env.settle(buy), err(ErrorCode::MarketStillActive));
+        env.resolve(OrderSide::Yes).unwrap();
+        
+        let before = env.bank.lamports(&carol);
+        env.settle(buy).unwrap();
+        assert_eq!(env.bank.lamports(&carol) - before, 5 * 300_000_000);
+        assert!(env.order(buy).status == OrderStatus::Cancelled);
+        
+        env.settle(sell).unwrap();
+        assert_eq!(env.shares(alice).yes_shares_locked, 0);
+        assert!(env.order(sell).status == OrderStatus::Cancelled);
+        assert_eq!(env.settle(sell), err(ErrorCode::OrderNotCancellable));
+        
+        // With nothing left open, all of Alice's shares redeem and the vault reconciles
+        let orderbook = env.orderbook();
+        assert_eq!((orderbook.open_yes_orders, orderbook.open_buy_collateral_lamports), (0, 0));
+        env.redeem(alice, OrderSide::Yes, 0).unwrap();
+        assert_eq!(env.shares(alice).yes_shares, 0);
+        env.assert_backed();
+    }
+    
+    #[test]
+    fn initialize_orderbook_seeds_the_first_chart_point() {
+        let mut env = uninitialized();
+        let mut args = orderbook_args(env.market_id);
+        args.initial_yes_price = 200_000;
+        env.initialize(args).unwrap();
+        
+        let orderbook = env.orderbook();
+        assert_eq!((orderbook.last_yes_price, orderbook.last_no_price), (200_000, 800_000));
+        
+        for initial_yes_price in [0, PRICE_PRECISION] {
+            let mut env = uninitialized();
+            let mut args = orderbook_args(env.market_id);
+            args.initial_yes_price = initial_yes_price;
+            assert_eq!(env.initialize(args), err(ErrorCode::InvalidPrice));
+        }
+    }
+    
+    #[test]
+    fn orders_past_the_open_order_cap_are_rejected() {
+        let mut env = uninitialized();
+        let mut args = orderbook_args(env.market_id);
+        args.max_open_orders = 3;
+        env.initialize(args).unwrap();
+        let (alice, bob) = (env.trader(), env.trader());
+        
+        let yes_order = env.place(alice, OrderSide::Yes, 600_000, 5).unwrap();
+        env.place(alice, OrderSide::Yes, 550_000, 5).unwrap();
+        let no_order = env.place(bob, OrderSide::No, 400_000, 5).unwrap();
+        assert_eq!(env.place(bob, OrderSide::No, 400_000, 5).map(drop), err(ErrorCode::TooManyOpenOrders));
+        assert_eq!(env.sell(alice, OrderSide::Yes, 600_000, 1).map(drop), err(ErrorCode::TooManyOpenOrders));
+        
+        // A fill frees both slots
+        env.match_orders(yes_order, no_order).unwrap();
+        let orderbook = env.orderbook();
+        assert_eq!((orderbook.open_yes_orders, orderbook.open_no_orders), (1, 0));
+        env.place(bob, OrderSide::No, 400_000, 5).unwrap();
+        env.sell(alice, OrderSide::Yes, 600_000, 1).unwrap();
+        assert_eq!(env.place(bob, OrderSide::No, 400_000, 5).map(drop), err(ErrorCode::TooManyOpenOrders));
+    }
+    
+    #[test]
+    fn a_match_below_one_dollar_is_rejected() {
+        let mut env = setup();
+        let (alice, bob) = (env.trader(), env.trader());
+        let yes_order = env.place(alice, OrderSide::Yes, 400_000, 10).unwrap();
+        let no_order = env.place(bob, OrderSide::No, 400_000, 10).unwrap();
+        
+        // 0.40 + 0.40 would mint sets backed by 80 cents
+        assert_eq!(env.match_orders(yes_order, no_order), err(ErrorCode::UndercollateralizedMatch));
+        env.crank(&[yes_order, no_order], (None, None)).unwrap();
+        assert_eq!(env.order(yes_order).filled_quantity, 0);
+        assert_eq!(env.orderbook().total_yes_shares, 0);
+    }
     
-    #[account(mut)]
-    pub matcher: Signer<'info>,
+    #[test]
+    fn check_collateralization_flags_an_underfunded_vault() {
+        let mut env = setup();
+        let (alice, bob, carol) = (env.trader(), env.trader(), env.trader());
+        env.mint_shares(alice, bob, 10);
+        env.place(carol, OrderSide::Yes, 300_000, 5).unwrap();
+        
+        // 10 sets at $1 plus Carol's 1.5 SOL resting bid, all held above the rent reserve
+        let (vault_balance, required_backing, is_solvent, _) = env.collateralization();
+        assert_eq!(required_backing, 10 * SOL + 1_500_000_000);
+        assert_eq!(vault_balance, required_backing + rent(0));
+        assert!(is_solvent);
+        
+        // Drain one lamport behind the program's back
+        let vault = env.vault();
+        env.bank.accounts.get_mut(&vault).unwrap().lamports -= 1;
+        let (_, _, is_solvent, total_collateral_lamports) = env.collateralization();
+        assert!(!is_solvent);
+        assert_eq!(total_collateral_lamports, required_backing);
+    }
     
-    pub system_program: Program<'info, System>,
-}
-
-#[derive(Accounts)]
-#[instruction(sell_order_id: Pubkey)]
-pub struct SellShares<'info> {
-    #[account(mut)]
-    pub user: Signer<'info>,
+    #[test]
+    fn crank_match_pairs_the_crossing_orders_out_of_four() {
+        let mut env = setup();
+        let (alice, bob, carol, dave) = (env.trader(), env.trader(), env.trader(), env.trader());
+        let high_yes = env.place(alice, OrderSide::Yes, 600_000, 10).unwrap();
+        let low_no = env.place(bob, OrderSide::No, 200_000, 10).unwrap();
+        let low_yes = env.place(carol, OrderSide::Yes, 300_000, 10).unwrap();
+        let high_no = env.place(dave, OrderSide::No, 450_000, 6).unwrap();
+        
+        // Only 0.60 YES + 0.45 NO covers $1
+        env.crank(&[low_yes, high_no, low_no, high_yes], (None, None)).unwrap();
+        assert_eq!(env.order(high_yes).filled_quantity, 6);
+        assert!(env.order(high_no).status == OrderStatus::Filled);
+        assert_eq!(env.order(low_yes).filled_quantity, 0);
+        assert_eq!(env.order(low_no).filled_quantity, 0);
+        assert_eq!((env.shares(alice).yes_shares, env.shares(dave).no_shares), (6, 6));
+        
+        // Nothing else crosses, so another crank matches nothing
+        env.crank(&[low_yes, low_no, high_yes], (None, None)).unwrap();
+        assert_eq!(env.orderbook().total_yes_shares, 6);
+    }
     
-    #[account(mut)]
-    pub orderbook: Account<'info, Orderbook>,
+    #[test]
+    fn fractional_shares_cost_and_redeem_in_base_units() {
+        let mut env = uninitialized();
+        let mut args = orderbook_args(env.market_id);
+        args.share_decimals = 2;
+        env.initialize(args).unwrap();
+        let (alice, bob) = (env.trader(), env.trader());
+        
+        // 150 base units = 1.5 shares: 0.90 SOL of YES at 0.60, 0.60 SOL of NO at 0.40
+        let yes_order = env.place(alice, OrderSide::Yes, 600_000, 150).unwrap();
+        let no_order = env.place(bob, OrderSide::No, 400_000, 150).unwrap();
+        assert_eq!(env.order(yes_order).lamports_deposited, 900_000_000);
+        assert_eq!(env.order(no_order).lamports_deposited, 600_000_000);
+        env.match_orders(yes_order, no_order).unwrap();
+        assert_eq!(env.shares(alice).yes_shares, 150);
+        
+        env.resolve(OrderSide::Yes).unwrap();
+        let before = env.bank.lamports(&alice);
+        env.redeem(alice, OrderSide::Yes, 0).unwrap();
+        assert_eq!(env.bank.lamports(&alice) - before, 1_500_000_000);
+        env.assert_backed();
+    }
     
-    #[account(
-        mut,
-        seeds = [b"shares", user.key().as_ref(), orderbook.market_id.as_ref()],
-        bump
-    )]
-    pub user_shares: Account<'info, UserShares>,
+    #[test]
+    fn place_orders_batch_opens_every_order_with_one_deposit() {
+        let mut env = setup();
+        let alice = env.trader();
+        let ladder = [(OrderSide::Yes, 500_000, 10), (OrderSide::Yes, 450_000, 20), (OrderSide::No, 300_000, 5)];
+        let orders = ladder
+            .iter()
+            .map(|(side, price, quantity)| BatchOrderParams { side: side.clone(), price: *price, quantity: *quantity })
+            .collect();
+        
+        let before = env.bank.lamports(&alice);
+        let keys = env.place_batch(alice, orders).unwrap();
+        
+        let collateral = 5 * SOL + 9 * SOL + 1_500_000_000;
+        assert_eq!(env.bank.lamports(&env.vault()) - rent(0), collateral);
+        assert_eq!(before - env.bank.lamports(&alice), collateral + 3 * rent(Order::LEN) + rent(UserShares::LEN));
+        for (key, (side, price, quantity)) in keys.iter().zip(ladder) {
+            let order = env.order(*key);
+            assert!(order.status == OrderStatus::Open && order.side == side);
+            assert_eq!((order.price, order.remaining_quantity, order.owner), (price, quantity, alice));
+        }
+        assert_eq!(env.shares(alice).order_nonce, 3);
+        let orderbook = env.orderbook();
+        assert_eq!((orderbook.open_yes_orders, orderbook.open_no_orders), (2, 1));
+        env.assert_backed();
+    }
     
-    #[account(
-        init,
-        payer = user,
-        space = 8 + 32 + 32 + 32 + 1 + 8 + 8 + 8 + 8 + 8 + 1 + 1 + 8,
-        seeds = [b"sell_order", sell_order_id.as_ref()],
-        bump
-    )]
-    pub sell_order: Account<'info, Order>,
+    #[test]
+    fn matching_with_share_tokens_mints_spl_balances() {
+        let mut env = setup();
+        env.enable_share_tokens().unwrap();
+        assert_eq!(env.enable_share_tokens(), Err(ProgramError::AccountAlreadyInitialized));
+        let (alice, bob) = (env.trader(), env.trader());
+        let (yes_mint, no_mint) = env.share_mints();
+        let alice_yes = env.token_account(yes_mint, alice);
+        let bob_no = env.token_account(no_mint, bob);
+        
+        let yes_order = env.place(alice, OrderSide::Yes, 600_000, 10).unwrap();
+        let no_order = env.place(bob, OrderSide::No, 400_000, 10).unwrap();
+        
+        // Share tokens have to be minted: without the token accounts the match fails
+        assert_eq!(env.match_orders(yes_order, no_order), err(ErrorCode::MissingShareTokenAccounts));
+        assert_eq!(
+            env.match_orders_minting(yes_order, no_order, bob_no, alice_yes),
+            err(ErrorCode::InvalidShareMint)
+        );
+        
+        env.match_orders_minting(yes_order, no_order, alice_yes, bob_no).unwrap();
+        assert_eq!((env.token_balance(&alice_yes), env.token_balance(&bob_no)), (10, 10));
+        assert_eq!((env.shares(alice).yes_shares, env.shares(bob).no_shares), (0, 0));
+        assert_eq!(env.orderbook().total_yes_shares, 10);
+        env.assert_backed();
+    }
     
-    pub system_program: Program<'info, System>,
-}
-
-#[derive(Accounts)]
-pub struct MatchSellOrders<'info> {
-    #[account(mut)]
-    pub orderbook: Account<'info, Orderbook>,
+    #[test]
+    fn crank_match_fills_the_older_of_two_same_price_orders_first() {
+        let mut env = setup();
+        let (alice, bob, carol) = (env.trader(), env.trader(), env.trader());
+        let older = env.place(alice, OrderSide::Yes, 600_000, 10).unwrap();
+        set_now(START + 1);
+        let newer = env.place(carol, OrderSide::Yes, 600_000, 10).unwrap();
+        let no_order = env.place(bob, OrderSide::No, 400_000, 10).unwrap();
+        
+        env.crank(&[newer, no_order, older], (None, None)).unwrap();
+        assert!(env.order(older).status == OrderStatus::Filled);
+        assert_eq!(env.order(newer).filled_quantity, 0);
+        
+        // The newer order is next in line
+        let no_order = env.place(bob, OrderSide::No, 400_000, 10).unwrap();
+        env.crank(&[newer, no_order], (None, None)).unwrap();
+        assert!(env.order(newer).status == OrderStatus::Filled);
+    }
     
-    #[account(mut)]
-    pub yes_sell_order: Account<'info, Order>,
+    #[test]
+    fn redemptions_pay_the_rate_locked_at_resolution() {
+        let mut env = setup();
+        let (alice, bob) = (env.trader(), env.trader());
+        env.mint_shares(alice, bob, 10);
+        env.update_sol_price(800_000_000).unwrap();
+        env.resolve(OrderSide::Yes).unwrap();
+        assert_eq!(env.orderbook().resolution_dollar_lamports, 800_000_000);
+        
+        // SOL crashes after resolution: the payout stays at the locked 0.8 SOL per share
+        env.update_sol_price(2 * DOLLAR).unwrap();
+        let before = env.bank.lamports(&alice);
+        env.redeem(alice, OrderSide::Yes, 0).unwrap();
+        assert_eq!(env.bank.lamports(&alice) - before, 8 * SOL);
+        env.assert_backed();
+    }
     
-    #[account(mut)]
-    pub no_sell_order: Account<'info, Order>,
+    #[test]
+    fn quote_order_matches_what_place_order_pulls() {
+        let mut env = setup();
+        env.set_fee_schedule(100, 0).unwrap();
+        let alice = env.trader();
+        
+        let (cost_lamports, fee_lamports, total_lamports) = env.quote(333_333, 7).unwrap();
+        assert_eq!((cost_lamports, fee_lamports), (2_333_331_000, 23_333_310));
+        assert_eq!(total_lamports, cost_lamports + fee_lamports);
+        
+        let before = env.bank.lamports(&alice);
+        let order = env.place(alice, OrderSide::Yes, 333_333, 7).unwrap();
+        assert_eq!(before - env.bank.lamports(&alice), total_lamports + rent(Order::LEN) + rent(UserShares::LEN));
+        assert_eq!(env.order(order).lamports_deposited, total_lamports);
+        
+        assert_eq!(env.quote(PRICE_PRECISION, 7).map(drop), err(ErrorCode::InvalidPrice));
+        assert_eq!(env.quote(333_333, 0).map(drop), err(ErrorCode::InvalidAmount));
+    }
     
-    #[account(mut)]
-    pub yes_user_shares: Account<'info, UserShares>,
+    #[test]
+    fn a_filled_maker_receives_its_rebate() {
+        let mut env = setup();
+        env.set_fee_schedule(100, 50).unwrap();
+        let (alice, bob) = (env.trader(), env.trader());
+        let maker = env.place(alice, OrderSide::Yes, 600_000, 10).unwrap();
+        set_now(START + 1);
+        let taker = env.place(bob, OrderSide::No, 400_000, 10).unwrap();
+        env.match_orders(maker, taker).unwrap();
+        
+        // Bob pays 1% of 4 SOL; Alice gets her 0.06 SOL fee reserve back plus
+        // 0.5% of 6 SOL out of Bob's fee
+        assert_eq!(env.order(taker).surplus_lamports, 0);
+        assert_eq!(env.order(maker).surplus_lamports, 60_000_000 + 30_000_000);
+        assert_eq!(env.orderbook().fee_pot_lamports, 40_000_000 - 30_000_000);
+        
+        let before = env.bank.lamports(&alice);
+        env.claim_surplus(alice, maker).unwrap();
+        assert_eq!(env.bank.lamports(&alice) - before, 90_000_000);
+        env.assert_backed();
+    }
     
-    #[account(mut)]
-    pub no_user_shares: Account<'info, UserShares>,
+    #[test]
+    fn a_large_order_does_not_overflow_the_cost_formula() {
+        let mut env = setup();
+        let whale = Pubkey::new_unique();
+        env.bank.fund(whale, 1_000_000 * SOL);
+        
+        // 500_000 x 1_000_000 x 1e9 is far past u64::MAX before the division
+        let order = env.place(whale, OrderSide::Yes, 500_000, 1_000_000).unwrap();
+        assert_eq!(env.order(order).lamports_deposited, 500_000 * SOL);
+        env.assert_backed();
+    }
     
-    /// CHECK: Vault for SOL
-    #[account(mut)]
-    pub vault: AccountInfo<'info>,
+    #[test]
+    fn a_fractional_lamport_of_collateral_rounds_up() {
+        let mut env = setup();
+        env.update_sol_price(DOLLAR + 1).unwrap();
+        let alice = env.trader();
+        
+        // 0.333333 x 1_000_000_001 = 333_333_000.333333 lamports
+        let order = env.place(alice, OrderSide::Yes, 333_333, 1).unwrap();
+        assert_eq!(env.order(order).lamports_deposited, 333_333_001);
+        assert_eq!(calculate_cost_lamports(333_333, 1, DOLLAR + 1, 0).unwrap(), 333_333_000);
+        env.assert_backed();
+    }
     
-    /// CHECK: YES seller receives SOL
-    #[account(mut)]
-    pub yes_seller: AccountInfo<'info>,
+    #[test]
+    fn resting_depth_follows_place_partial_fill_and_cancel() {
+        let mut env = setup();
+        let (alice, bob, carol) = (env.trader(), env.trader(), env.trader());
+        let yes_order = env.place(alice, OrderSide::Yes, 600_000, 10).unwrap();
+        env.place(carol, OrderSide::Yes, 550_000, 2).unwrap();
+        assert_eq!(env.orderbook().resting_yes_notional, 6 * SOL + 1_100_000_000);
+        
+        let no_order = env.place(bob, OrderSide::No, 400_000, 4).unwrap();
+        assert_eq!(env.orderbook().resting_no_notional, 1_600_000_000);
+        env.match_orders(yes_order, no_order).unwrap();
+        let orderbook = env.orderbook();
+        assert_eq!(orderbook.resting_yes_notional, 6 * 600_000_000 + 1_100_000_000);
+        assert_eq!(orderbook.resting_no_notional, 0);
+        
+        env.cancel(alice, yes_order).unwrap();
+        assert_eq!(env.orderbook().resting_yes_notional, 1_100_000_000);
+    }
     
-    /// CHECK: NO seller receives SOL
-    #[account(mut)]
-    pub no_seller: AccountInfo<'info>,
+    #[test]
+    fn the_redemption_fee_accrues_to_the_fee_vault() {
+        let mut env = setup();
+        assert_eq!(env.set_redemption_fee(MAX_REDEMPTION_FEE_BPS + 1), err(ErrorCode::FeeTooHigh));
+        env.set_redemption_fee(100).unwrap();
+        let (alice, bob) = (env.trader(), env.trader());
+        env.mint_shares(alice, bob, 10);
+        env.resolve(OrderSide::Yes).unwrap();
+        
+        let fee_vault_before = env.bank.lamports(&env.fee_vault());
+        let before = env.bank.lamports(&alice);
+        env.redeem(alice, OrderSide::Yes, 0).unwrap();
+        assert_eq!(env.bank.lamports(&alice) - before, 9_900_000_000);
+        assert_eq!(env.bank.lamports(&env.fee_vault()) - fee_vault_before, 100_000_000);
+        assert_eq!(env.fee_vault_state().total_accrued_lamports, 100_000_000);
+        env.assert_backed();
+    }
     
-    #[account(mut)]
-    pub matcher: Signer<'info>,
+    #[test]
+    fn redeeming_share_tokens_charges_the_redemption_fee() {
+        let mut env = setup();
+        env.enable_share_tokens().unwrap();
+        env.set_redemption_fee(100).unwrap();
+        let (alice, bob) = (env.trader(), env.trader());
+        let (yes_mint, no_mint) = env.share_mints();
+        let alice_yes = env.token_account(yes_mint, alice);
+        let bob_no = env.token_account(no_mint, bob);
+        let yes_order = env.place(alice, OrderSide::Yes, 600_000, 10).unwrap();
+        let no_order = env.place(bob, OrderSide::No, 400_000, 10).unwrap();
+        env.match_orders_minting(yes_order, no_order, alice_yes, bob_no).unwrap();
+        env.resolve(OrderSide::Yes).unwrap();
+        
+        // Same 1% as a counter redemption
+        let fee_vault_before = env.bank.lamports(&env.fee_vault());
+        let before = env.bank.lamports(&alice);
+        env.redeem_tokens(alice, alice_yes, OrderSide::Yes).unwrap();
+        assert_eq!(env.bank.lamports(&alice) - before, 9_900_000_000);
+        assert_eq!(env.bank.lamports(&env.fee_vault()) - fee_vault_before, 100_000_000);
+        assert_eq!(env.fee_vault_state().total_accrued_lamports, 100_000_000);
+        assert_eq!(env.token_balance(&alice_yes), 0);
+        env.assert_backed();
+    }
     
-    pub system_program: Program<'info, System>,
-}
-
-#[derive(Accounts)]
-pub struct CancelOrder<'info> {
-    #[account(mut)]
-    pub user: Signer<'info>,
+    #[test]
+    fn withdraw_fees_pays_exactly_what_the_trades_accrued() {
+        let mut env = setup();
+        env.set_fee_schedule(100, 0).unwrap();
+        let (alice, bob) = (env.trader(), env.trader());
+        
+        // Each taker pays 1% of its own leg: 4 SOL, then 11 SOL
+        let maker = env.place(alice, OrderSide::Yes, 600_000, 10).unwrap();
+        set_now(START + 1);
+        let taker = env.place(bob, OrderSide::No, 400_000, 10).unwrap();
+        env.match_orders(maker, taker).unwrap();
+        let maker = env.place(bob, OrderSide::No, 450_000, 20).unwrap();
+        set_now(START + 2);
+        let taker = env.place(alice, OrderSide::Yes, 550_000, 20).unwrap();
+        env.match_orders(taker, maker).unwrap();
+        let accrued = 40_000_000 + 110_000_000;
+        assert_eq!(env.orderbook().fee_pot_lamports, accrued);
+        
+        let treasury = Pubkey::new_unique();
+        assert_eq!(env.withdraw_fees(accrued + 1, treasury), err(ErrorCode::InsufficientFees));
+        env.withdraw_fees(accrued, treasury).unwrap();
+        assert_eq!(env.bank.lamports(&treasury), accrued);
+        assert_eq!(env.bank.lamports(&env.fee_vault()), rent(FeeVault::LEN));
+        assert_eq!(env.withdraw_fees(0, treasury), err(ErrorCode::InvalidAmount));
+        env.assert_backed();
+    }
     
-    #[account(mut)]
-    pub order: Account<'info, Order>,
+    #[test]
+    fn get_order_status_reports_fill_progress() {
+        let mut env = setup();
+        let (alice, bob) = (env.trader(), env.trader());
+        let yes_order = env.place(alice, OrderSide::Yes, 600_000, 10).unwrap();
+        assert_eq!(env.order_status(yes_order).unwrap(), (0, 0, 10, 6 * SOL, false));
+        
+        let no_order = env.place(bob, OrderSide::No, 400_000, 4).unwrap();
+        env.match_orders(yes_order, no_order).unwrap();
+        assert_eq!(env.order_status(yes_order).unwrap(), (1, 4, 6, 6 * SOL, false));
+        assert_eq!(env.order_status(no_order).unwrap(), (2, 4, 0, 1_600_000_000, false));
+        
+        // A shares account is not an order
+        let shares = env.shares_key(alice);
+        assert_eq!(
+            env.order_status(shares).map(drop),
+            Err(ProgramError::Custom(anchor_lang::error::ErrorCode::AccountDiscriminatorMismatch.into()))
+        );
+    }
     
-    /// CHECK: Vault for SOL refund
-    #[account(mut)]
-    pub vault: AccountInfo<'info>,
-}
-
-#[derive(Accounts)]
-pub struct RedeemShares<'info> {
-    #[account(mut)]
-    pub user: Signer<'info>,
+    #[test]
+    fn matching_an_order_against_itself_is_rejected() {
+        let mut env = setup();
+        let (alice, bob) = (env.trader(), env.trader());
+        let yes_order = env.place(alice, OrderSide::Yes, 600_000, 10).unwrap();
+        assert_eq!(env.match_orders(yes_order, yes_order), err(ErrorCode::SelfMatch));
+        
+        // Alice on both sides shares one UserShares account: the crank skips the pair
+        let own_no = env.place(alice, OrderSide::No, 400_000, 10).unwrap();
+        env.crank(&[yes_order, own_no], (None, None)).unwrap();
+        assert_eq!(env.order(yes_order).filled_quantity, 0);
+        
+        let no_order = env.place(bob, OrderSide::No, 400_000, 10).unwrap();
+        env.match_orders(yes_order, no_order).unwrap();
+        assert_eq!(env.shares(alice).yes_shares, 10);
+    }
     
-    pub orderbook: Account<'info, Orderbook>,
+    #[test]
+    fn orders_from_another_market_cannot_be_matched_here() {
+        let mut env = setup();
+        let (alice, bob) = (env.trader(), env.trader());
+        env.mint_shares(alice, bob, 10);
+        let home = env.market_id;
+        env.market_id = Pubkey::new_unique();
+        env.initialize(orderbook_args(env.market_id)).unwrap();
+        let yes_order = env.place(alice, OrderSide::Yes, 600_000, 10).unwrap();
+        let no_order = env.place(bob, OrderSide::No, 400_000, 10).unwrap();
+        env.mint_shares(alice, bob, 10);
+        let yes_sell = env.sell(alice, OrderSide::Yes, 500_000, 10).unwrap();
+        let no_sell = env.sell(bob, OrderSide::No, 500_000, 10).unwrap();
+        
+        // Their collateral sits in the other market's vault
+        env.market_id = home;
+        assert_eq!(env.match_orders(yes_order, no_order), err(ErrorCode::MarketMismatch));
+        assert_eq!(env.match_sells(yes_sell, no_sell), err(ErrorCode::MarketMismatch));
+        assert_eq!(env.orderbook().total_yes_shares, 10);
+    }
     
-    #[account(mut)]
-    pub user_shares: Account<'info, UserShares>,
+    #[test]
+    fn merging_one_owners_yes_and_no_sell_orders_is_rejected() {
+        let mut env = setup();
+        let (alice, bob) = (env.trader(), env.trader());
+        env.mint_shares(alice, bob, 10);
+        env.mint_shares(bob, alice, 10);
+        let yes_sell = env.sell(alice, OrderSide::Yes, 500_000, 10).unwrap();
+        let no_sell = env.sell(alice, OrderSide::No, 500_000, 10).unwrap();
+        let lamports = env.bank.accounts[&alice].lamports;
+        
+        // Both legs would load Alice's one UserShares account
+        assert_eq!(env.match_sells(yes_sell, no_sell), err(ErrorCode::SelfMatch));
+        let shares = env.shares(alice);
+        assert_eq!((shares.yes_shares, shares.no_shares), (10, 10));
+        assert_eq!(env.bank.accounts[&alice].lamports, lamports);
+        assert!(env.order(yes_sell).status == OrderStatus::Open);
+    }
     
-    /// CHECK: Vault for payout
-    #[account(mut)]
-    pub vault: AccountInfo<'info>,
-}
-
-// ============================================================================
-// Error Codes
-// ============================================================================
-
-#[error_code]
-pub enum ErrorCode {
-    #[msg("Unauthorized access")]
-    Unauthorized,
-    #[msg("Invalid amount")]
-    InvalidAmount,
-    #[msg("Invalid price - must be between 0 and 1")]
-    InvalidPrice,
-    #[msg("YES price + NO price must equal $1")]
-    PricesMustSumToOne,
-    #[msg("Orderbook is inactive")]
-    OrderbookInactive,
-    #[msg("Order is not open")]
-    OrderNotOpen,
-    #[msg("Order is not cancellable")]
-    OrderNotCancellable,
-    #[msg("Invalid order side")]
-    InvalidOrderSide,
-    #[msg("Market mismatch")]
-    MarketMismatch,
-    #[msg("No quantity to match")]
-    NoMatchQuantity,
-    #[msg("Insufficient shares")]
-    InsufficientShares,
-    #[msg("Not a sell order")]
-    NotASellOrder,
-    #[msg("Market is still active")]
-    MarketStillActive,
-    #[msg("No shares to redeem")]
-    NoSharesToRedeem,
-    #[msg("Math overflow")]
-    MathOverflow,
-}
+    #[test]
+    fn permissioned_matching_admits_only_allowlisted_matchers() {
+        let mut env = setup();
+        env.set_permissioned_matching(true).unwrap();
+        let (alice, bob) = (env.trader(), env.trader());
+        let yes_order = env.place(alice, OrderSide::Yes, 600_000, 10).unwrap();
+        let no_order = env.place(bob, OrderSide::No, 400_000, 10).unwrap();
+        
+        assert_eq!(env.match_orders(yes_order, no_order), err(ErrorCode::MatcherNotAllowed));
+        assert_eq!(env.crank(&[yes_order, no_order], (None, None)), err(ErrorCode::MatcherNotAllowed));
+        
+        env.add_matcher(env.matcher).unwrap();
+        assert_eq!(env.add_matcher(env.matcher), err(ErrorCode::MatcherAlreadyAllowed));
+        env.match_orders(yes_order, no_order).unwrap();
+        assert_eq!(env.shares(alice).yes_shares, 10);
+        
+        // Any other key is still turned away
+        let (outsider, carol, dave) = (env.trader(), env.trader(), env.trader());
+        let yes_order = env.place(carol, OrderSide::Yes, 600_000, 1).unwrap();
+        let no_order = env.place(dave, OrderSide::No, 400_000, 1).unwrap();
+        assert_eq!(env.match_orders_by(outsider, yes_order, no_order), err(ErrorCode::MatcherNotAllowed));
+    }
+    
+    #[test]
+    fn volume_is_tracked_per_side() {
+        let mut env = setup();
+        let (alice, bob) = (env.trader(), env.trader());
+        env.mint_shares(alice, bob, 10);
+        
+        // 0.65 + 0.45 overshoots by 0.10, so the legs execute at 0.60 / 0.40
+        let yes_order = env.place(alice, OrderSide::Yes, 650_000, 4).unwrap();
+        let no_order = env.place(bob, OrderSide::No, 450_000, 4).unwrap();
+        env.match_orders(yes_order, no_order).unwrap();
+        
+        let orderbook = env.orderbook();
+        assert_eq!(orderbook.yes_volume_lamports, 6 * SOL + 2_400_000_000);
+        assert_eq!(orderbook.no_volume_lamports, 4 * SOL + 1_600_000_000);
+        assert_eq!(orderbook.total_volume_lamports, 14 * SOL);
+    }
 
-// ============================================================================
-// Events
-// ============================================================================
+    #[test]
+    fn volume_is_the_sol_both_legs_spent() {
+        let mut env = setup();
+        let (alice, bob) = (env.trader(), env.trader());
+        let (alice_before, bob_before) = (env.bank.lamports(&alice), env.bank.lamports(&bob));
+        let yes_order = env.place(alice, OrderSide::Yes, 700_000, 10).unwrap();
+        let no_order = env.place(bob, OrderSide::No, 400_000, 10).unwrap();
+        env.match_orders(yes_order, no_order).unwrap();
+        env.claim_surplus(alice, yes_order).unwrap();
+        env.claim_surplus(bob, no_order).unwrap();
 
-#[event]
-pub struct OrderbookInitialized {
-    pub market_id: Pubkey,
-    pub one_dollar_lamports: u64,
-    pub timestamp: i64,
-}
+        // What left the traders' wallets, less the rent they can reclaim
+        let rents = rent(Order::LEN) + rent(UserShares::LEN);
+        let alice_spent = alice_before - env.bank.lamports(&alice) - rents;
+        let bob_spent = bob_before - env.bank.lamports(&bob) - rents;
+        let orderbook = env.orderbook();
+        assert_eq!(orderbook.yes_volume_lamports, alice_spent);
+        assert_eq!(orderbook.no_volume_lamports, bob_spent);
+        assert_eq!(orderbook.total_volume_lamports, alice_spent + bob_spent);
+        assert_eq!(orderbook.total_volume_lamports, 10 * SOL);
+    }
 
-#[event]
-pub struct SolPriceUpdated {
-    pub market_id: Pubkey,
-    pub old_lamports_per_dollar: u64,
-    pub new_lamports_per_dollar: u64,
-    pub timestamp: i64,
-}
+    #[test]
+    fn young_orders_cannot_be_cancelled_until_the_minimum_lifetime() {
+        let mut env = setup();
+        let alice = env.trader();
+        env.set_min_order_lifetime(30).unwrap();
+        let order = env.place(alice, OrderSide::Yes, 500_000, 1).unwrap();
 
-#[event]
-pub struct OrderPlaced {
-    pub order_id: Pubkey,
-    pub owner: Pubkey,
-    pub market_id: Pubkey,
-    pub side: OrderSide,
-    pub price: u64,
-    pub quantity: u64,
-    pub cost_lamports: u64,
-    pub timestamp: i64,
-}
+        set_now(START + 29);
+        assert_eq!(env.cancel(alice, order), err(ErrorCode::OrderTooYoung));
+        set_now(START + 30);
+        env.cancel(alice, order).unwrap();
+        assert!(env.order(order).status == OrderStatus::Cancelled);
+    }
 
-#[event]
-pub struct OrdersMatched {
-    pub yes_order_id: Pubkey,
-    pub no_order_id: Pubkey,
-    pub market_id: Pubkey,
-    pub yes_owner: Pubkey,
-    pub no_owner: Pubkey,
-    pub yes_price: u64,
-    pub no_price: u64,
-    pub quantity: u64,
-    pub timestamp: i64,
-}
+    #[test]
+    fn partially_filled_orders_skip_the_minimum_lifetime() {
+        let mut env = setup();
+        let (alice, bob) = (env.trader(), env.trader());
+        env.set_min_order_lifetime(30).unwrap();
+        let yes_order = env.place(alice, OrderSide::Yes, 500_000, 4).unwrap();
+        let no_order = env.place(bob, OrderSide::No, 500_000, 1).unwrap();
+        env.match_orders(yes_order, no_order).unwrap();
+        env.cancel(alice, yes_order).unwrap();
+    }
 
-#[event]
-pub struct SellOrderPlaced {
-    pub order_id: Pubkey,
-    pub owner: Pubkey,
-    pub market_id: Pubkey,
-    pub side: OrderSide,
-    pub price: u64,
-    pub quantity: u64,
-    pub timestamp: i64,
-}
+    #[test]
+    fn cancelling_a_half_merged_sell_unlocks_only_the_remainder() {
+        let mut env = setup();
+        let (alice, bob) = (env.trader(), env.trader());
+        env.mint_shares(alice, bob, 10);
+        let yes_sell = env.sell(alice, OrderSide::Yes, 600_000, 10).unwrap();
+        let no_sell = env.sell(bob, OrderSide::No, 400_000, 5).unwrap();
+        env.match_sells(yes_sell, no_sell).unwrap();
+        assert_eq!(env.order(yes_sell).remaining_quantity, 5);
+        let shares = env.shares(alice);
+        assert_eq!((shares.yes_shares, shares.yes_shares_locked), (5, 5));
 
-#[event]
-pub struct SharesMerged {
-    pub yes_order_id: Pubkey,
-    pub no_order_id: Pubkey,
-    pub market_id: Pubkey,
-    pub yes_seller: Pubkey,
-    pub no_seller: Pubkey,
-    pub quantity: u64,
-    pub yes_payout: u64,
-    pub no_payout: u64,
-    pub timestamp: i64,
-}
+        env.cancel_sell(alice, yes_sell).unwrap();
+        let shares = env.shares(alice);
+        assert_eq!((shares.yes_shares, shares.yes_shares_locked), (5, 0));
+        assert!(env.order(yes_sell).status == OrderStatus::Cancelled);
+    }
 
-#[event]
-pub struct OrderCancelled {
-    pub order_id: Pubkey,
-    pub owner: Pubkey,
-    pub refund_lamports: u64,
-    pub timestamp: i64,
-}
+    #[test]
+    fn two_users_place_at_the_same_nonce_without_colliding() {
+        let mut env = setup();
+        let (alice, bob) = (env.trader(), env.trader());
+        let alice_order = env.place(alice, OrderSide::Yes, 500_000, 1).unwrap();
+        let bob_order = env.place(bob, OrderSide::No, 500_000, 1).unwrap();
 
-#[event]
-pub struct SharesRedeemed {
-    pub owner: Pubkey,
-    pub market_id: Pubkey,
-    pub winning_outcome: OrderSide,
-    pub shares_redeemed: u64,
-    pub payout_lamports: u64,
-    pub timestamp: i64,
+        assert_ne!(alice_order, bob_order);
+        assert_eq!(alice_order, derive_order_pda(&alice, &env.orderbook_key(), 0).0);
+        assert_eq!(bob_order, derive_order_pda(&bob, &env.orderbook_key(), 0).0);
+        assert_eq!((env.order(alice_order).owner, env.order(bob_order).owner), (alice, bob));
+        assert_eq!((env.shares(alice).order_nonce, env.shares(bob).order_nonce), (1, 1));
+    }
 }